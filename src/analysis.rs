@@ -1,79 +1,1109 @@
+//! Monotone framework of the dataflow analyses, with the reaching definitions, sign and interval analyses.
+
 use crate::worklist::Worklist;
 use crate::worklist::FifoWorklist;
-use crate::flow_graph::{FlowGraph, Action};
-use std::{collections::HashMap, cmp::PartialEq};
-use petgraph::{graph::NodeIndex, Direction};
+use crate::flow_graph::{FlowGraph, Action, Call, Origin};
+use crate::microc::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, ops::{ArithmeticOp, BinaryOp, RelationalOp}, stmt::Statement, visit::{Visitor, walk_arex, walk_lvalue}};
+use crate::{assertions, safety};
+use crate::points_to::{self, PointsTo, andersen, targets, written};
+use crate::lexer::{Span, keyword::Type, literal::IntegerLiteral};
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, cmp::PartialEq, fmt::{self, Display, Formatter}, marker::PhantomData};
+use petgraph::{graph::{EdgeIndex, NodeIndex}, visit::EdgeRef, Direction};
+
+/// Number of updates of a node before its value is widened.
+const WIDENING_DELAY: usize = 3;
+
+/// Instance of the monotone framework over program graphs.
+pub trait Analyzer<R: Clone + PartialEq> {
+	/// Least element of the lattice, holding at every node before the analysis.
+	fn bottom(&self) -> R;
+
+	/// Extremal value, holding at the initial node (final node for backward analyses).
+	fn initial(&self) -> R;
+
+	/// Transfer function of an edge.
+	fn map(&self, a: &Action, r: &R) -> R;
+
+	/// Transfer function of a given edge, for analyses depending on more than its action.
+	fn map_edge(&self, _edge: EdgeIndex, a: &Action, r: &R) -> R {
+		self.map(a, r)
+	}
+
+	/// Transfer function of a return edge of forward analyses, from the values at its call site and at the final node of the procedure.
+	///
+	/// Defaults to joining the value at the call site with the one returned, which is sound but loses that the locals of the caller are kept.
+	fn map_return(&self, edge: EdgeIndex, a: &Action, call: &R, exit: &R) -> R {
+		if *exit == self.bottom() {
+			exit.clone()
+		} else {
+			self.join(call, &self.map_edge(edge, a, exit))
+		}
+	}
+
+	/// Least upper bound of two values.
+	fn join(&self, r1: &R, r2: &R) -> R;
+
+	/// Widening operator, only needed by lattices of infinite height.
+	fn widen(&self, old: &R, new: &R) -> R {
+		self.join(old, new)
+	}
+
+	/// `Outgoing` for forward analyses, `Incoming` for backward analyses.
+	fn direction(&self) -> Direction {
+		Direction::Outgoing
+	}
+}
+
+/// Solves the equations of `specification` on the program graph by chaotic iteration.
+pub fn worklist<W: Worklist<NodeIndex>, R: Clone + PartialEq, A: Analyzer<R>>(program: &FlowGraph, specification: &A) -> HashMap<NodeIndex, R> {
+	let (graph, start, end) = program;
+	let direction = specification.direction();
+	let extremal = if direction == Direction::Outgoing { *start } else { *end };
+
+	let mut res: HashMap<NodeIndex, R> = graph.node_indices().map(|node| (node, specification.bottom())).collect();
+	let mut updates = HashMap::<NodeIndex, usize>::new();
+	let mut wl = W::default();
+	// call site of every return edge, whose value changing makes the return edge taken again
+	let mut sites = HashMap::<usize, NodeIndex>::new();
+	let mut returns = HashMap::<NodeIndex, Vec<EdgeIndex>>::new();
+
+	if direction == Direction::Outgoing {
+		for edge in graph.edge_references() {
+			if let Action::Call(call) = edge.weight() {
+				sites.insert(call.site, edge.source());
+			}
+		}
 
-trait Analyzer<R> {
-	fn map(&self, a: Action) -> R;
+		for edge in graph.edge_references() {
+			if let Action::Return(call) = edge.weight() {
+				returns.entry(sites[&call.site]).or_default().push(edge.id());
+			}
+		}
+	}
+
+	res.insert(extremal, specification.initial());
+
+	for node in graph.node_indices() {
+		wl.insert(node);
+	}
+
+	while let Some(node) = wl.extract() {
+		let edges: Vec<EdgeIndex> = graph.edges_directed(node, direction).map(|edge| edge.id()).chain(returns.get(&node).into_iter().flatten().copied()).collect();
+
+		for edge in edges {
+			let (source, target) = graph.edge_endpoints(edge).unwrap();
+			let (source, target) = if direction == Direction::Outgoing { (source, target) } else { (target, source) };
+			let value = match &graph[edge] {
+				a @ Action::Return(call) if direction == Direction::Outgoing => specification.map_return(edge, a, &res[&sites[&call.site]], &res[&source]),
+				a => specification.map_edge(edge, a, &res[&source]),
+			};
+			let old = &res[&target];
+			let new = specification.join(old, &value);
+
+			if *old != new {
+				let count = updates.entry(target).or_insert(0);
+				*count += 1;
+
+				let new = if WIDENING_DELAY < *count { specification.widen(old, &new) } else { new };
+
+				res.insert(target, new);
+				wl.insert(target);
+			}
+		}
+	}
+
+	res
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Sign {
-	Plus,
-	Minus,
-	Zero,
+/// Variables introduced by a declaration, with record members named `record.member`.
+pub fn variables(decl: &Declaration) -> Vec<String> {
+	use Declaration::*;
+
+	match decl {
+		Var(_, id) | Array(_, _, id) | Pointer(_, id) => vec![id.clone()],
+		Record(decls, id) => decls.iter().flat_map(variables).map(|member| format!("{id}.{member}")).collect(),
+		Procedure(..) => vec![],
+	}
 }
 
-struct SignDetecter {}
+/// Variable written or read through an lvalue, arrays being summarized by a single variable.
+///
+/// Dereferences are named `*pointer`, the variables they stand for being given by a points-to analysis.
+pub fn location(lvalue: &LvalueExpr) -> String {
+	use LvalueExpr::*;
+
+	match lvalue {
+		Variable(id) | ArrayIndex(id, _) => id.clone(),
+		RecordMember(id, member) => format!("{id}.{member}"),
+		Deref(id) => format!("*{id}"),
+	}
+}
+
+/// Collector of the variables read by expressions.
+#[derive(Default)]
+struct Reads(BTreeSet<String>);
+
+impl Visitor for Reads {
+	/// Taking an address reads none of the variables of the lvalue.
+	fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+		match arex {
+			ArithmeticExpr::Reference(lvalue) => walk_lvalue(self, lvalue),
+			_ => walk_arex(self, arex),
+		}
+	}
+
+	fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+		self.0.insert(location(lvalue));
+		walk_lvalue(self, lvalue);
+	}
+}
+
+/// Variables read by an arithmetic expression.
+pub fn arex_variables(arex: &ArithmeticExpr) -> BTreeSet<String> {
+	let mut reads = Reads::default();
+
+	reads.visit_arex(arex);
+	reads.0
+}
+
+/// Variables read by a boolean expression.
+pub fn boolex_variables(boolex: &BooleanExpr) -> BTreeSet<String> {
+	let mut reads = Reads::default();
+
+	reads.visit_boolex(boolex);
+	reads.0
+}
+
+/// Variables read to locate an lvalue, that is the ones of its array indexes or its pointer.
+pub fn lvalue_variables(lvalue: &LvalueExpr) -> BTreeSet<String> {
+	let mut reads = Reads::default();
+
+	walk_lvalue(&mut reads, lvalue);
+
+	if let LvalueExpr::Deref(pointer) = lvalue {
+		reads.0.insert(pointer.clone());
+	}
+
+	reads.0
+}
+
+/// Members of every record declared in the program, in declaration order.
+pub fn records(program: &FlowGraph) -> HashMap<String, Vec<String>> {
+	program.0.raw_edges().iter().filter_map(|edge| match &edge.weight {
+		Action::Declaration(decl @ Declaration::Record(_, id)) => Some((id.clone(), variables(decl))),
+		_ => None,
+	}).collect()
+}
+
+/// Float variables introduced by a declaration, with the dereferences `*pointer` of the pointers to floats.
+fn float_variables(decl: &Declaration) -> Vec<String> {
+	use Declaration::*;
+
+	match decl {
+		Var(Type::Float, id) | Array(Type::Float, _, id) => vec![id.clone()],
+		Pointer(Type::Float, id) => vec![format!("*{id}")],
+		Record(decls, id) => decls.iter().flat_map(float_variables).map(|member| format!("{id}.{member}")).collect(),
+		_ => vec![],
+	}
+}
+
+/// Float variables declared in the program, whose arithmetic the numeric domains do not model.
+pub fn floats(program: &FlowGraph) -> BTreeSet<String> {
+	program.0.raw_edges().iter().flat_map(|edge| match &edge.weight {
+		Action::Declaration(decl) => float_variables(decl),
+		_ => vec![],
+	}).collect()
+}
+
+/// Whether an arithmetic expression may have a float value, given the float variables.
+pub fn is_float(arex: &ArithmeticExpr, floats: &BTreeSet<String>) -> bool {
+	use ArithmeticExpr::*;
+
+	match arex {
+		Literal(literal) => matches!(literal, ArithmeticLiteral::Float(_)),
+		LvalueExpr(lvalue) => floats.contains(&location(lvalue)),
+		Reference(_) => false,
+		ArithmeticOperation(op) => is_float(&op.0, floats) || is_float(&op.2, floats),
+	}
+}
+
+/// Abstract value of a numeric domain.
+pub trait Value: Clone + PartialEq + Display {
+	/// Value of every number.
+	fn top() -> Self;
+
+	/// Value of no number, for unreachable code.
+	fn bottom() -> Self;
+
+	/// Least upper bound.
+	fn join(&self, other: &Self) -> Self;
+
+	/// Greatest lower bound.
+	fn meet(&self, other: &Self) -> Self;
+
+	/// Widening operator, only needed by domains of infinite height.
+	fn widen(&self, other: &Self) -> Self {
+		self.join(other)
+	}
+
+	/// Value of a literal.
+	fn literal(literal: &ArithmeticLiteral) -> Self;
+
+	/// Result of `self op other`, bottom if it always fails.
+	fn arithmetic(&self, op: &ArithmeticOp, other: &Self) -> Self;
+
+	/// Refines both operands of `self op other` under the assumption that the comparison holds.
+	fn compare(&self, op: &RelationalOp, other: &Self) -> (Self, Self);
+}
+
+/// Abstract memory, `None` being the unreachable state.
+pub type Memory<V> = Option<BTreeMap<String, V>>;
+
+/// Abstract value of an arithmetic expression, the targets of pointers being given by `points_to`.
+///
+/// Dereferencing a pointer that points nowhere fails, addresses are not numbers, and the float expressions may take any value.
+pub fn evaluate<V: Value>(memory: &BTreeMap<String, V>, arex: &ArithmeticExpr, points_to: &PointsTo, floats: &BTreeSet<String>) -> V {
+	use ArithmeticExpr::*;
+
+	let read = |var: &String| memory.get(var).cloned().unwrap_or_else(V::top);
+
+	match arex {
+		_ if is_float(arex, floats) => V::top(),
+		Literal(literal) => V::literal(literal),
+		LvalueExpr(self::LvalueExpr::Deref(pointer)) => targets(points_to, pointer).fold(V::bottom(), |value, var| value.join(&read(var))),
+		LvalueExpr(lvalue) => read(&location(lvalue)),
+		Reference(_) => V::top(),
+		ArithmeticOperation(op) => evaluate(memory, &op.0, points_to, floats).arithmetic(&op.1, &evaluate(memory, &op.2, points_to, floats)),
+	}
+}
+
+/// Forward analysis binding every variable to a value of the domain `V`.
+pub struct ValueAnalysis<V> {
+	records: HashMap<String, Vec<String>>,
+	points_to: PointsTo,
+	floats: BTreeSet<String>,
+	domain: PhantomData<V>,
+}
+
+impl<V: Value> ValueAnalysis<V> {
+	/// Analysis of the given program, whose record declarations are needed to assign whole records, and pointers to assign through them.
+	pub fn new(program: &FlowGraph) -> Self {
+		ValueAnalysis { records: records(program), points_to: andersen(program), floats: floats(program), domain: PhantomData }
+	}
+
+	fn evaluate(&self, memory: &BTreeMap<String, V>, arex: &ArithmeticExpr) -> V {
+		evaluate(memory, arex, &self.points_to, &self.floats)
+	}
+
+	/// Assigns a value to the variables written by an lvalue, none of them meaning that it dereferences a pointer pointing nowhere.
+	fn assign(&self, mut memory: BTreeMap<String, V>, lvalue: &LvalueExpr, value: V) -> Memory<V> {
+		let written = written(&self.points_to, lvalue);
+
+		if value == V::bottom() || written.is_empty() {
+			return None;
+		}
+
+		for (var, weak) in written {
+			let value = match (weak, memory.get(&var)) {
+				(true, Some(old)) => old.join(&value),
+				_ => value.clone(),
+			};
+
+			memory.insert(var, value);
+		}
+
+		Some(memory)
+	}
+
+	/// Assigns the values of the result parameters at the final node of a procedure to the lvalues of the results of its call.
+	fn results(&self, mut memory: BTreeMap<String, V>, call: &Call, exit: &BTreeMap<String, V>) -> Memory<V> {
+		for (lvalue, output) in call.results.iter().zip(&call.outputs) {
+			memory = self.assign(memory, lvalue, exit.get(output).cloned().unwrap_or_else(V::top))?;
+		}
+
+		Some(memory)
+	}
+
+	/// Restricts the memory to the states in which `boolex` evaluates to `holds`.
+	pub fn refine(&self, memory: BTreeMap<String, V>, boolex: &BooleanExpr, holds: bool) -> Memory<V> {
+		use BooleanExpr::*;
+
+		match boolex {
+			BooleanLiteral(b) => if *b == holds { Some(memory) } else { None },
+			NotOperation(boolex) => self.refine(memory, boolex, !holds),
+			BinaryOperation(b1, op, b2) => match (op, holds) {
+				(BinaryOp::BitAnd, true) | (BinaryOp::BitOr, false) => self.refine(memory, b1, holds).and_then(|m| self.refine(m, b2, holds)),
+				(BinaryOp::BitAnd, false) | (BinaryOp::BitOr, true) => self.join(&self.refine(memory.clone(), b1, holds), &self.refine(memory, b2, holds)),
+				(BinaryOp::BitXor, _) => self.join(
+					&self.refine(memory.clone(), b1, true).and_then(|m| self.refine(m, b2, !holds)),
+					&self.refine(memory, b1, false).and_then(|m| self.refine(m, b2, holds)),
+				),
+				_ => Some(memory),
+			},
+			// comparisons of floats do not bound the integers of the domains
+			RelationalOperation(arex1, _, arex2) if is_float(arex1, &self.floats) || is_float(arex2, &self.floats) => Some(memory),
+			RelationalOperation(arex1, op, arex2) => {
+				let op = if holds { op.clone() } else { !op.clone() };
+				let (v1, v2) = self.evaluate(&memory, arex1).compare(&op, &self.evaluate(&memory, arex2));
+
+				if v1 == V::bottom() || v2 == V::bottom() {
+					return None;
+				}
+
+				let mut memory = memory;
+
+				for (arex, value) in [(arex1, v1), (arex2, v2)] {
+					if let ArithmeticExpr::LvalueExpr(lvalue @ (LvalueExpr::Variable(_) | LvalueExpr::RecordMember(_, _))) = arex {
+						memory.insert(location(lvalue), value);
+					}
+				}
+
+				Some(memory)
+			},
+		}
+	}
+}
+
+impl<V: Value> Analyzer<Memory<V>> for ValueAnalysis<V> {
+	fn bottom(&self) -> Memory<V> {
+		None
+	}
+
+	fn initial(&self) -> Memory<V> {
+		Some(BTreeMap::new())
+	}
+
+	fn map(&self, a: &Action, r: &Memory<V>) -> Memory<V> {
+		let mut memory = r.clone()?;
 
-impl Analyzer<Sign> for SignDetecter {
-	fn map(&self, a: Action) -> Sign {
 		match a {
-			_ => Sign::Plus,
+			Action::Declaration(decl) => {
+				for var in variables(decl) {
+					memory.insert(var, V::literal(&ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(0))));
+				}
+
+				Some(memory)
+			},
+			Action::Statement(stmt) => match stmt {
+				Statement::LvalueAssign(lvalue, arex) => {
+					let value = self.evaluate(&memory, arex);
+
+					self.assign(memory, lvalue, value)
+				},
+				Statement::RecordAssign(id, arexs) => {
+					let values: Vec<V> = arexs.iter().map(|arex| self.evaluate(&memory, arex)).collect();
+
+					if values.contains(&V::bottom()) {
+						return None;
+					}
+
+					for (member, value) in self.records.get(id).into_iter().flatten().zip(values) {
+						memory.insert(member.clone(), value);
+					}
+
+					Some(memory)
+				},
+				Statement::Read(lvalue) => self.assign(memory, lvalue, V::top()),
+				// the runs going past a failing assertion stop
+				Statement::Assert(boolex) | Statement::Assume(boolex) => self.refine(memory, boolex, true),
+				_ => Some(memory),
+			},
+			Action::Condition(boolex) => self.refine(memory, boolex, true),
+			Action::Call(call) => {
+				let values: Vec<V> = call.arguments.iter().map(|arex| self.evaluate(&memory, arex)).collect();
+
+				if values.contains(&V::bottom()) {
+					return None;
+				}
+
+				memory.extend(call.values.iter().cloned().zip(values));
+
+				for output in &call.outputs {
+					memory.insert(output.clone(), V::literal(&ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(0))));
+				}
+
+				Some(memory)
+			},
+			Action::Return(call) => self.results(memory.clone(), call, &memory),
+		}
+	}
+
+	/// Restores the locals of the caller, then assigns the result parameters to the lvalues of the results.
+	fn map_return(&self, _edge: EdgeIndex, a: &Action, call: &Memory<V>, exit: &Memory<V>) -> Memory<V> {
+		match (a, call, exit) {
+			(Action::Return(site), Some(caller), Some(exit)) => {
+				let mut memory = exit.clone();
+
+				for var in &site.locals {
+					match caller.get(var) {
+						Some(value) => memory.insert(var.clone(), value.clone()),
+						None => memory.remove(var),
+					};
+				}
+
+				self.results(memory, site, exit)
+			},
+			_ => None,
+		}
+	}
+
+	fn join(&self, r1: &Memory<V>, r2: &Memory<V>) -> Memory<V> {
+		match (r1, r2) {
+			(None, r) | (r, None) => r.clone(),
+			(Some(m1), Some(m2)) => {
+				let mut memory = m1.clone();
+
+				for (var, value) in m2 {
+					let joined = memory.get(var).map_or_else(|| value.clone(), |old| old.join(value));
+					memory.insert(var.clone(), joined);
+				}
+
+				Some(memory)
+			},
+		}
+	}
+
+	fn widen(&self, old: &Memory<V>, new: &Memory<V>) -> Memory<V> {
+		match (old, new) {
+			(None, r) | (r, None) => r.clone(),
+			(Some(m1), Some(m2)) => {
+				let mut memory = m1.clone();
+
+				for (var, value) in m2 {
+					let widened = memory.get(var).map_or_else(|| value.clone(), |old| old.widen(value));
+					memory.insert(var.clone(), widened);
+				}
+
+				Some(memory)
+			},
+		}
+	}
+}
+
+/// Formats an abstract memory.
+pub struct MemoryDisplay<'a, V>(pub &'a Memory<V>);
+
+impl<V: Value> Display for MemoryDisplay<'_, V> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self.0 {
+			None => write!(f, "unreachable"),
+			Some(memory) => write!(f, "{{{}}}", memory.iter().map(|(var, value)| format!("{var}: {value}")).collect::<Vec<String>>().join(", ")),
+		}
+	}
+}
+
+/// Interval domain, bounds being saturated at the extremal `isize` values.
+pub mod interval {
+	use super::{FlowGraph, Memory, Value, ValueAnalysis, context};
+	use crate::microc::{expr::ArithmeticLiteral, ops::{ArithmeticOp, RelationalOp}};
+	use petgraph::graph::NodeIndex;
+	use std::{cmp::{max, min}, collections::HashMap, fmt::{self, Display, Formatter}};
+
+	/// Integer interval, `isize::MIN` and `isize::MAX` standing for the infinite bounds.
+	#[derive(Debug, Clone, Copy, PartialEq)]
+	pub enum Interval {
+		/// Interval of no integer.
+		Empty,
+		/// Integers from the first bound to the second one, both included.
+		Range(isize, isize),
+	}
+
+	/// Infinite lower bound.
+	pub const NEG_INF: isize = isize::MIN;
+	/// Infinite upper bound.
+	pub const POS_INF: isize = isize::MAX;
+
+	fn clamp(n: i128) -> isize {
+		n.clamp(NEG_INF as i128, POS_INF as i128) as isize
+	}
+
+	fn add(a: isize, b: isize, infinity: isize) -> isize {
+		if a == infinity || b == infinity {
+			infinity
+		} else {
+			clamp(a as i128 + b as i128)
+		}
+	}
+
+	fn negate(n: isize) -> isize {
+		match n {
+			NEG_INF => POS_INF,
+			POS_INF => NEG_INF,
+			_ => -n,
+		}
+	}
+
+	fn mul(a: isize, b: isize) -> isize {
+		clamp(a as i128 * b as i128)
+	}
+
+	/// Truncated division of two bounds, the divisor being non-zero.
+	fn div(a: isize, b: isize) -> isize {
+		if b == NEG_INF || b == POS_INF {
+			0
+		} else if a == NEG_INF || a == POS_INF {
+			if (a < 0) == (b < 0) { POS_INF } else { NEG_INF }
+		} else {
+			a / b
+		}
+	}
+
+	impl Interval {
+		/// Interval holding only `n`.
+		pub fn constant(n: isize) -> Self {
+			Interval::Range(n, n)
+		}
+
+		/// Whether `n` is in the interval.
+		pub fn contains(&self, n: isize) -> bool {
+			matches!(*self, Interval::Range(low, high) if low <= n && n <= high)
+		}
+
+		/// Whether every value of the interval lies within `other`.
+		pub fn within(&self, other: &Self) -> bool {
+			self.meet(other) == *self
+		}
+
+		/// Interval from `low` to `high`, empty if `low` exceeds `high`.
+		pub fn range(low: isize, high: isize) -> Self {
+			if low <= high { Interval::Range(low, high) } else { Interval::Empty }
+		}
+
+		fn corners(&self, other: &Self, f: fn(isize, isize) -> isize) -> Self {
+			match (*self, *other) {
+				(Interval::Range(l1, h1), Interval::Range(l2, h2)) => {
+					let corners = [f(l1, l2), f(l1, h2), f(h1, l2), f(h1, h2)];
+
+					Interval::range(*corners.iter().min().unwrap(), *corners.iter().max().unwrap())
+				},
+				_ => Interval::Empty,
+			}
+		}
+
+		fn div(&self, other: &Self) -> Self {
+			let negative = other.meet(&Interval::Range(NEG_INF, -1));
+			let positive = other.meet(&Interval::Range(1, POS_INF));
+
+			self.corners(&negative, div).join(&self.corners(&positive, div))
+		}
+
+		fn rem(&self, other: &Self) -> Self {
+			match (*self, *other) {
+				(Interval::Range(l1, h1), Interval::Range(l2, h2)) if *other != Interval::constant(0) => {
+					let bound = max(negate(l2), h2);
+					let bound = if bound == POS_INF { POS_INF } else { bound - 1 };
+
+					Interval::range(
+						if l1 < 0 { max(l1, negate(bound)) } else { 0 },
+						if 0 < h1 { min(h1, bound) } else { 0 },
+					)
+				},
+				_ => Interval::Empty,
+			}
+		}
+	}
+
+	impl Display for Interval {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			use Interval::*;
+
+			match *self {
+				Empty => write!(f, "empty"),
+				Range(low, high) => write!(f, "[{}, {}]",
+					if low == NEG_INF { "-inf".to_string() } else { low.to_string() },
+					if high == POS_INF { "+inf".to_string() } else { high.to_string() },
+				),
+			}
 		}
 	}
+
+	impl Value for Interval {
+		fn top() -> Self {
+			Interval::Range(NEG_INF, POS_INF)
+		}
+
+		fn bottom() -> Self {
+			Interval::Empty
+		}
+
+		fn join(&self, other: &Self) -> Self {
+			match (*self, *other) {
+				(Interval::Empty, i) | (i, Interval::Empty) => i,
+				(Interval::Range(l1, h1), Interval::Range(l2, h2)) => Interval::Range(min(l1, l2), max(h1, h2)),
+			}
+		}
+
+		fn meet(&self, other: &Self) -> Self {
+			match (*self, *other) {
+				(Interval::Range(l1, h1), Interval::Range(l2, h2)) => Interval::range(max(l1, l2), min(h1, h2)),
+				_ => Interval::Empty,
+			}
+		}
+
+		fn widen(&self, other: &Self) -> Self {
+			match (*self, *other) {
+				(Interval::Empty, i) | (i, Interval::Empty) => i,
+				(Interval::Range(l1, h1), Interval::Range(l2, h2)) => Interval::Range(
+					if l2 < l1 { NEG_INF } else { l1 },
+					if h1 < h2 { POS_INF } else { h1 },
+				),
+			}
+		}
+
+		fn literal(literal: &ArithmeticLiteral) -> Self {
+			match *literal {
+				ArithmeticLiteral::Int(n) => Interval::constant(isize::from(n)),
+				ArithmeticLiteral::Float(_) => Interval::top(),
+			}
+		}
+
+		fn arithmetic(&self, op: &ArithmeticOp, other: &Self) -> Self {
+			use ArithmeticOp::*;
+
+			match (self, other) {
+				(Interval::Range(l1, h1), Interval::Range(l2, h2)) => match op {
+					Add => Interval::Range(add(*l1, *l2, NEG_INF), add(*h1, *h2, POS_INF)),
+					Sub | Neg => Interval::Range(add(*l1, negate(*h2), NEG_INF), add(*h1, negate(*l2), POS_INF)),
+					Mul => self.corners(other, mul),
+					Div => self.div(other),
+					Rem => self.rem(other),
+				},
+				_ => Interval::Empty,
+			}
+		}
+
+		fn compare(&self, op: &RelationalOp, other: &Self) -> (Self, Self) {
+			use RelationalOp::*;
+
+			match (*self, *other) {
+				(Interval::Range(l1, h1), Interval::Range(l2, h2)) => match op {
+					Lt => (self.meet(&Interval::Range(NEG_INF, add(h2, -1, POS_INF))), other.meet(&Interval::Range(add(l1, 1, NEG_INF), POS_INF))),
+					Leq => (self.meet(&Interval::Range(NEG_INF, h2)), other.meet(&Interval::Range(l1, POS_INF))),
+					Gt => {
+						let (i2, i1) = other.compare(&Lt, self);
+						(i1, i2)
+					},
+					Geq => {
+						let (i2, i1) = other.compare(&Leq, self);
+						(i1, i2)
+					},
+					Eq => (self.meet(other), other.meet(self)),
+					Neq => match (l1 == h1, l2 == h2) {
+						(true, true) if l1 == l2 => (Interval::Empty, Interval::Empty),
+						(_, true) => (self.exclude(l2), *other),
+						(true, _) => (*self, other.exclude(l1)),
+						_ => (*self, *other),
+					},
+				},
+				_ => (Interval::Empty, Interval::Empty),
+			}
+		}
+	}
+
+	impl Interval {
+		/// Removes `n` from the interval when it is one of its bounds.
+		fn exclude(&self, n: isize) -> Self {
+			match *self {
+				Interval::Range(low, high) if low == n => Interval::range(add(low, 1, NEG_INF), high),
+				Interval::Range(low, high) if high == n => Interval::range(low, add(high, -1, POS_INF)),
+				i => i,
+			}
+		}
+	}
+
+	/// Interval analysis of the program.
+	pub fn analyze(program: &FlowGraph) -> HashMap<NodeIndex, Memory<Interval>> {
+		context::solve(program, &ValueAnalysis::<Interval>::new(program), context::DEPTH)
+	}
 }
 
-fn builder<W: Worklist<NodeIndex> + Default>(worklist: &W, program: FlowGraph) -> W {
-	let mut new_worklist = W::default();
+/// Sign domain, as sets of signs.
+pub mod sign {
+	use super::{FlowGraph, Memory, Value, ValueAnalysis, context, interval::{Interval, NEG_INF, POS_INF}};
+	use crate::microc::{expr::ArithmeticLiteral, ops::{ArithmeticOp, RelationalOp}};
+	use petgraph::graph::NodeIndex;
+	use std::{collections::{BTreeSet, HashMap}, fmt::{self, Display, Formatter}};
+
+	/// Sign of an integer.
+	#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+	pub enum Sign {
+		/// Negative.
+		Minus,
+		/// Zero.
+		Zero,
+		/// Positive.
+		Plus,
+	}
+
+	impl Sign {
+		/// Integers of the sign.
+		fn interval(&self) -> Interval {
+			match *self {
+				Sign::Minus => Interval::Range(NEG_INF, -1),
+				Sign::Zero => Interval::constant(0),
+				Sign::Plus => Interval::Range(1, POS_INF),
+			}
+		}
+	}
 
-	new_worklist.insert(program.1);
+	impl Display for Sign {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			match *self {
+				Sign::Minus => write!(f, "-"),
+				Sign::Zero => write!(f, "0"),
+				Sign::Plus => write!(f, "+"),
+			}
+		}
+	}
+
+	/// Set of the possible signs of a value.
+	#[derive(Debug, Clone, PartialEq)]
+	pub struct Signs(pub BTreeSet<Sign>);
+
+	impl From<Interval> for Signs {
+		fn from(interval: Interval) -> Self {
+			Signs([Sign::Minus, Sign::Zero, Sign::Plus].iter().filter(|s| s.interval().meet(&interval) != Interval::Empty).copied().collect())
+		}
+	}
 
-	for child in program.0.neighbors_directed(program.1, Direction::Outgoing) {
-		if !worklist.contains(child) {
-			builder::<W>(worklist, (program.0.clone(), child));
+	impl Signs {
+		/// Lifts an interval operation to signs, through their interval representatives.
+		fn lift(&self, other: &Self, f: impl Fn(&Interval, &Interval) -> Signs) -> Self {
+			Signs(self.0.iter().flat_map(|s1| other.0.iter().flat_map(|s2| f(&s1.interval(), &s2.interval()).0).collect::<Vec<Sign>>()).collect())
 		}
 	}
 
-	new_worklist
+	impl Display for Signs {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			write!(f, "{{{}}}", self.0.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(", "))
+		}
+	}
+
+	impl Value for Signs {
+		fn top() -> Self {
+			Signs([Sign::Minus, Sign::Zero, Sign::Plus].iter().copied().collect())
+		}
+
+		fn bottom() -> Self {
+			Signs(BTreeSet::new())
+		}
+
+		fn join(&self, other: &Self) -> Self {
+			Signs(self.0.union(&other.0).copied().collect())
+		}
+
+		fn meet(&self, other: &Self) -> Self {
+			Signs(self.0.intersection(&other.0).copied().collect())
+		}
+
+		fn literal(literal: &ArithmeticLiteral) -> Self {
+			match *literal {
+				ArithmeticLiteral::Int(n) => Signs::from(Interval::constant(isize::from(n))),
+				ArithmeticLiteral::Float(_) => Signs::top(),
+			}
+		}
+
+		fn arithmetic(&self, op: &ArithmeticOp, other: &Self) -> Self {
+			self.lift(other, |i1, i2| Signs::from(i1.arithmetic(op, i2)))
+		}
+
+		fn compare(&self, op: &RelationalOp, other: &Self) -> (Self, Self) {
+			(
+				self.lift(other, |i1, i2| Signs::from(i1.compare(op, i2).0)),
+				other.lift(self, |i2, i1| Signs::from(i1.compare(op, i2).1)),
+			)
+		}
+	}
+
+	/// Sign analysis of the program.
+	pub fn analyze(program: &FlowGraph) -> HashMap<NodeIndex, Memory<Signs>> {
+		context::solve(program, &ValueAnalysis::<Signs>::new(program), context::DEPTH)
+	}
 }
 
-fn worklist<W: Worklist<NodeIndex>, R: std::cmp::PartialEq, A: Analyzer<R>>(program: FlowGraph, specification: A) -> HashMap<NodeIndex, R> {
-	let mut wl = W::default();
-	let mut res = HashMap::<NodeIndex, R>::new();
-	let (graph, start) = program;
+/// Reaching definitions, the edges that may have last defined each variable.
+pub mod reaching {
+	use super::{Analyzer, FlowGraph, context, records, variables};
+	use crate::flow_graph::Action;
+	use crate::microc::stmt::Statement;
+	use crate::points_to::{PointsTo, andersen, written};
+	use petgraph::graph::{EdgeIndex, NodeIndex};
+	use std::collections::{BTreeSet, HashMap};
 
-	let mut children = builder::<W>(&wl, (graph.clone(), start));
+	/// Variables with the edges that may have last defined them.
+	pub type Definitions = BTreeSet<(String, EdgeIndex)>;
 
-	while let Some(node) = children.extract() {
-		if wl.insert(node).is_some() {
-			res.insert(start, specification.map(graph[start].clone()));
+	/// Reaching definitions analysis, arrays being weakly updated.
+	pub struct ReachingDefinitions {
+		records: HashMap<String, Vec<String>>,
+		points_to: PointsTo,
+	}
+
+	impl ReachingDefinitions {
+		/// Analysis of the given program, whose record declarations are needed to define whole records, and pointers to define through them.
+		pub fn new(program: &FlowGraph) -> Self {
+			ReachingDefinitions { records: records(program), points_to: andersen(program) }
+		}
+
+		/// Variables defined by an action, with whether the definition is weak.
+		pub fn defined(&self, a: &Action) -> Vec<(String, bool)> {
+			match a {
+				Action::Declaration(decl) => variables(decl).into_iter().map(|var| (var, false)).collect(),
+				Action::Statement(Statement::LvalueAssign(lvalue, _)) | Action::Statement(Statement::Read(lvalue)) => written(&self.points_to, lvalue),
+				Action::Statement(Statement::RecordAssign(id, _)) => self.records.get(id).into_iter().flatten().map(|member| (member.clone(), false)).collect(),
+				Action::Call(call) => call.values.iter().chain(&call.outputs).map(|param| (param.clone(), false)).collect(),
+				Action::Return(call) => call.results.iter().flat_map(|lvalue| written(&self.points_to, lvalue)).collect(),
+				_ => vec![],
+			}
+		}
+
+		fn transfer(&self, edge: Option<EdgeIndex>, a: &Action, r: &Definitions) -> Definitions {
+			let mut res = r.clone();
+
+			for (var, weak) in self.defined(a) {
+				if !weak {
+					res.retain(|(defined, _)| *defined != var);
+				}
+
+				if let Some(edge) = edge {
+					res.insert((var, edge));
+				}
+			}
+
+			res
 		}
 	}
 
-	while let Some(node) = wl.extract() {
-		let old = res.get(&node).unwrap();
-		let new = specification.map(graph[node].clone());
+	impl Analyzer<Definitions> for ReachingDefinitions {
+		fn bottom(&self) -> Definitions {
+			Definitions::new()
+		}
+
+		fn initial(&self) -> Definitions {
+			Definitions::new()
+		}
+
+		/// Only kills the definitions, generating them requires the edge.
+		fn map(&self, a: &Action, r: &Definitions) -> Definitions {
+			self.transfer(None, a, r)
+		}
+
+		fn map_edge(&self, edge: EdgeIndex, a: &Action, r: &Definitions) -> Definitions {
+			self.transfer(Some(edge), a, r)
+		}
+
+		/// Takes the definitions of the locals of the procedure from the call site, then defines the results.
+		fn map_return(&self, edge: EdgeIndex, a: &Action, call: &Definitions, exit: &Definitions) -> Definitions {
+			let locals: &[String] = if let Action::Return(site) = a { &site.locals } else { &[] };
+			let definitions = exit.iter().filter(|(var, _)| !locals.contains(var))
+				.chain(call.iter().filter(|(var, _)| locals.contains(var)))
+				.cloned().collect();
+
+			self.transfer(Some(edge), a, &definitions)
+		}
+
+		fn join(&self, r1: &Definitions, r2: &Definitions) -> Definitions {
+			r1.union(r2).cloned().collect()
+		}
+	}
+
+	/// Reaching definitions analysis of the program.
+	pub fn analyze(program: &FlowGraph) -> HashMap<NodeIndex, Definitions> {
+		context::solve(program, &ReachingDefinitions::new(program), context::DEPTH)
+	}
+
+	/// Formats definitions with the program graph edges they come from.
+	pub fn display(program: &FlowGraph, definitions: &Definitions) -> String {
+		let definitions: Vec<String> = definitions.iter().map(|(var, edge)| {
+			let (source, target) = program.0.edge_endpoints(*edge).unwrap();
+
+			format!("({var}, q{} -> q{})", source.index(), target.index())
+		}).collect();
+
+		format!("{{{}}}", definitions.join(", "))
+	}
+}
+
+/// Call strings, keeping apart the values of an analysis reaching procedures from different call sites.
+///
+/// Only the last `depth` call sites are kept, the values of the call strings agreeing on them being joined.
+pub mod context {
+	use super::{Analyzer, FifoWorklist, FlowGraph, worklist};
+	use crate::flow_graph::Action;
+	use petgraph::graph::{EdgeIndex, NodeIndex};
+	use std::{collections::{BTreeMap, HashMap}, marker::PhantomData};
+
+	/// Length of the call strings of the analyses of the program.
+	pub const DEPTH: usize = 2;
+
+	/// Last call sites of the calls leading to a node, the innermost one last.
+	pub type CallString = Vec<usize>;
+
+	/// Value of an analysis in every call string reaching a node.
+	pub type Contexts<R> = BTreeMap<CallString, R>;
+
+	/// Lifting of an analysis to call strings of length at most `depth`.
+	pub struct CallStrings<'a, R, A> {
+		analysis: &'a A,
+		depth: usize,
+		value: PhantomData<R>,
+	}
+
+	impl<'a, R: Clone + PartialEq, A: Analyzer<R>> CallStrings<'a, R, A> {
+		/// Lifts the analysis to call strings of length at most `depth`.
+		pub fn new(analysis: &'a A, depth: usize) -> Self {
+			CallStrings { analysis, depth, value: PhantomData }
+		}
+
+		/// Call string of a call at `site` in the call string `context`.
+		fn push(&self, context: &[usize], site: usize) -> CallString {
+			let mut context = [context, &[site]].concat();
+
+			context.drain(..context.len().saturating_sub(self.depth));
+			context
+		}
 
-		if *old != new {
-			for child in graph.neighbors_directed(node, Direction::Outgoing) {
-				wl.insert(child);
+		/// Adds the value of a call string, joining it with the one already there.
+		fn add(&self, contexts: &mut Contexts<R>, context: CallString, value: R) {
+			let value = match contexts.get(&context) {
+				Some(old) => self.analysis.join(old, &value),
+				None => value,
+			};
+
+			contexts.insert(context, value);
+		}
+
+		fn transfer(&self, a: &Action, r: &Contexts<R>, map: impl Fn(&R) -> R) -> Contexts<R> {
+			let mut contexts = Contexts::new();
+
+			for (context, value) in r {
+				match a {
+					Action::Call(call) => self.add(&mut contexts, self.push(context, call.site), map(value)),
+					// only taken on its own by backward analyses, the call string the call was made from is that of the final node without its last site
+					Action::Return(call) => if context.last() == Some(&call.site) {
+						self.add(&mut contexts, context[..context.len() - 1].to_vec(), map(value));
+					},
+					_ => self.add(&mut contexts, context.clone(), map(value)),
+				}
 			}
+
+			contexts
 		}
 	}
 
-	res
+	impl<R: Clone + PartialEq, A: Analyzer<R>> Analyzer<Contexts<R>> for CallStrings<'_, R, A> {
+		fn bottom(&self) -> Contexts<R> {
+			Contexts::new()
+		}
+
+		fn initial(&self) -> Contexts<R> {
+			std::iter::once((vec![], self.analysis.initial())).collect()
+		}
+
+		fn map(&self, a: &Action, r: &Contexts<R>) -> Contexts<R> {
+			self.transfer(a, r, |value| self.analysis.map(a, value))
+		}
+
+		fn map_edge(&self, edge: EdgeIndex, a: &Action, r: &Contexts<R>) -> Contexts<R> {
+			self.transfer(a, r, |value| self.analysis.map_edge(edge, a, value))
+		}
+
+		/// Returns to every call string of the call site the value of the final node in the call string of the call.
+		fn map_return(&self, edge: EdgeIndex, a: &Action, call: &Contexts<R>, exit: &Contexts<R>) -> Contexts<R> {
+			let mut contexts = Contexts::new();
+
+			if let Action::Return(site) = a {
+				for (context, value) in call {
+					if let Some(returned) = exit.get(&self.push(context, site.site)) {
+						self.add(&mut contexts, context.clone(), self.analysis.map_return(edge, a, value, returned));
+					}
+				}
+			}
+
+			contexts
+		}
+
+		fn join(&self, r1: &Contexts<R>, r2: &Contexts<R>) -> Contexts<R> {
+			let mut contexts = r1.clone();
+
+			for (context, value) in r2 {
+				self.add(&mut contexts, context.clone(), value.clone());
+			}
+
+			contexts
+		}
+
+		fn widen(&self, old: &Contexts<R>, new: &Contexts<R>) -> Contexts<R> {
+			let mut contexts = old.clone();
+
+			for (context, value) in new {
+				let widened = match old.get(context) {
+					Some(old) => self.analysis.widen(old, value),
+					None => value.clone(),
+				};
+
+				contexts.insert(context.clone(), widened);
+			}
+
+			contexts
+		}
+
+		fn direction(&self) -> petgraph::Direction {
+			self.analysis.direction()
+		}
+	}
+
+	/// Solves an analysis with call strings of length at most `depth`, giving the join of the values of every call string at each node.
+	pub fn solve<R: Clone + PartialEq, A: Analyzer<R>>(program: &FlowGraph, analysis: &A, depth: usize) -> HashMap<NodeIndex, R> {
+		worklist::<FifoWorklist<NodeIndex>, _, _>(program, &CallStrings::new(analysis, depth)).into_iter()
+			.map(|(node, contexts)| (node, contexts.values().fold(analysis.bottom(), |joined, value| analysis.join(&joined, value))))
+			.collect()
+	}
+}
+
+/// Formats the result of an analysis, node by node.
+fn report<R>(program: &FlowGraph, result: &HashMap<NodeIndex, R>, display: impl Fn(&R) -> String) -> String {
+	program.0.node_indices().map(|node| format!("q{}: {}", node.index(), display(&result[&node]))).collect::<Vec<String>>().join("\n")
 }
 
-pub fn analyze(program: FlowGraph, analysis: String) -> Result<HashMap<NodeIndex, Sign>, String> {
+/// Runs the analysis named by its pattern on the program graph, formatting its result with diagnostics located by the spans of the edges.
+pub fn analyze(program: FlowGraph, analysis: String, origins: &HashMap<EdgeIndex, Origin>, spans: &HashMap<EdgeIndex, Span>) -> Result<String, String> {
 	if program.0.node_count() == 0 {
 		Err("The flow graph is empty.".to_string())
 	} else {
-		let sd = SignDetecter {};
+		match analysis.as_str() {
+			"rd" => Ok(report(&program, &reaching::analyze(&program), |d| reaching::display(&program, d))),
+			"sa" => Ok(report(&program, &sign::analyze(&program), |m| MemoryDisplay(m).to_string())),
+			"ia" => Ok(report(&program, &interval::analyze(&program), |m| MemoryDisplay(m).to_string())),
+			"pt" => Ok(report(&program, &points_to::analyze(&program), points_to::display)),
+			"andersen" => Ok(points_to::display(&andersen(&program))),
+			"safety" => Ok(safety::report(&program, &safety::check(&program, origins), spans)),
+			"asserts" => Ok(assertions::report(&program, &assertions::check(&program), spans)),
+			_ => Err(format!("Unknown analysis '{analysis}'.")),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ValueAnalysis, context, interval, sign::Signs, MemoryDisplay};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+
+	#[test]
+	fn call_strings_keep_call_sites_apart() {
+		let program = flow(parse(lex_str("int x;\nint y;\nproc id(int a; int b) {\n\tb := a;\n}\ncall id(1; x);\ncall id(0 - 1; y);").unwrap()).unwrap());
+		let signs = |depth: usize| MemoryDisplay(&context::solve(&program, &ValueAnalysis::<Signs>::new(&program), depth)[&program.2]).to_string();
+
+		assert_eq!(signs(context::DEPTH), "{x: {+}, y: {-}}");
+		// without call strings, the returned values of both calls are joined
+		assert_eq!(signs(0), "{x: {-, 0, +}, y: {-, +}}");
+	}
 
-		let result = worklist::<FifoWorklist<NodeIndex>, Sign, SignDetecter>(program, sd);
+	#[test]
+	fn floats_take_any_value() {
+		let program = flow(parse(lex_str("float f;\nint y;\nf := 0.5;\ny := 1 / f;\nif f < 1 {\n\ty := 2;\n}").unwrap()).unwrap());
 
-		Ok(result)
+		// the integer division would give 1 and the integer comparison f <= 0, but 1 / 0.5 is 2
+		assert_eq!(MemoryDisplay(&interval::analyze(&program)[&program.2]).to_string(), "{f: [-inf, +inf], y: [-inf, +inf]}");
 	}
 }
@@ -0,0 +1,134 @@
+//! Checks of the `assert` statements with the interval and sign analyses.
+
+use crate::analysis::{Memory, Value, ValueAnalysis, boolex_variables, interval::{self, Interval}, sign::{self, Signs}};
+use crate::flow_graph::{Action, FlowGraph, locate};
+use crate::lexer::Span;
+use crate::microc::{expr::BooleanExpr, stmt::Statement};
+use petgraph::graph::EdgeIndex;
+use std::{collections::{BTreeMap, HashMap}, fmt::{self, Display, Formatter}};
+
+/// Verdict on an assertion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+	/// Holds whenever it is reached, or is never reached.
+	Proven,
+	/// Fails whenever it is reached.
+	Violated,
+	/// May hold or fail.
+	Unknown,
+}
+
+impl Display for Verdict {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Verdict::*;
+
+		match *self {
+			Proven => write!(f, "proven"),
+			Violated => write!(f, "violated"),
+			Unknown => write!(f, "unknown"),
+		}
+	}
+}
+
+/// Verdict on an assertion, with the intervals of its variables when it is reached.
+#[derive(Debug, Clone)]
+pub struct Check {
+	/// Edge of the program graph of the assertion.
+	pub edge: EdgeIndex,
+	/// Condition asserted.
+	pub assertion: BooleanExpr,
+	/// Verdict on the assertion.
+	pub verdict: Verdict,
+	/// Intervals of the variables of the assertion when it is reached.
+	pub values: BTreeMap<String, Interval>,
+}
+
+impl Display for Check {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: `{}`", self.verdict, self.assertion)?;
+
+		if !self.values.is_empty() {
+			write!(f, " with {}", self.values.iter().map(|(var, value)| format!("{var} in {value}")).collect::<Vec<String>>().join(", "))?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Classifies an assertion with the states of a domain reaching it, the assertion being proven if no state falsifies it.
+fn classify<V: Value>(analysis: &ValueAnalysis<V>, memory: &Memory<V>, assertion: &BooleanExpr) -> Verdict {
+	match memory {
+		None => Verdict::Proven,
+		Some(memory) if analysis.refine(memory.clone(), assertion, false).is_none() => Verdict::Proven,
+		Some(memory) if analysis.refine(memory.clone(), assertion, true).is_none() => Verdict::Violated,
+		Some(_) => Verdict::Unknown,
+	}
+}
+
+/// Checks every assertion of the program with the interval and sign analyses, assumptions refining their states like guards.
+pub fn check(program: &FlowGraph) -> Vec<Check> {
+	let intervals = interval::analyze(program);
+	let signs = sign::analyze(program);
+	let (interval_analysis, sign_analysis) = (ValueAnalysis::<Interval>::new(program), ValueAnalysis::<Signs>::new(program));
+
+	program.0.edge_indices().filter_map(|edge| match &program.0[edge] {
+		Action::Statement(Statement::Assert(assertion)) => {
+			let (source, _) = program.0.edge_endpoints(edge).unwrap();
+			// both domains are sound, so that the most precise verdict wins
+			let verdict = match (classify(&interval_analysis, &intervals[&source], assertion), classify(&sign_analysis, &signs[&source], assertion)) {
+				(Verdict::Proven, _) | (_, Verdict::Proven) => Verdict::Proven,
+				(Verdict::Violated, _) | (_, Verdict::Violated) => Verdict::Violated,
+				_ => Verdict::Unknown,
+			};
+			let values = match &intervals[&source] {
+				Some(memory) => boolex_variables(assertion).into_iter().map(|var| {
+					let value = memory.get(&var).cloned().unwrap_or_else(Interval::top);
+
+					(var, value)
+				}).collect(),
+				None => BTreeMap::new(),
+			};
+
+			Some(Check { edge, assertion: assertion.clone(), verdict, values })
+		},
+		_ => None,
+	}).collect()
+}
+
+/// Formats the checks with the program graph edges of the assertions, located with the spans of the edges.
+pub fn report(program: &FlowGraph, checks: &[Check], spans: &HashMap<EdgeIndex, Span>) -> String {
+	checks.iter().map(|check| {
+		let (source, target) = program.0.edge_endpoints(check.edge).unwrap();
+
+		format!("{}q{} -> q{}: {check}", locate(spans, check.edge), source.index(), target.index())
+	}).collect::<Vec<String>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Verdict, check};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+
+	fn verdicts(source: &str) -> Vec<String> {
+		check(&flow(parse(lex_str(source).unwrap()).unwrap())).iter().map(|check| check.to_string()).collect()
+	}
+
+	#[test]
+	fn assumptions_refine_like_guards() {
+		let source = "int x;\nint y;\nread x;\nassume x > 0 && x < 10;\ny := x * 2;\nassert y > 0;\nassert y < 18;\nassert y == 0;";
+
+		// the runs going past an assertion satisfy it
+		assert_eq!(verdicts(source), [
+			"proven: `y > 0` with y in [2, 18]",
+			"unknown: `y < 18` with y in [2, 18]",
+			"violated: `y == 0` with y in [2, 17]",
+		]);
+	}
+
+	#[test]
+	fn unreachable_assertions_hold() {
+		let program = flow(parse(lex_str("int x;\nread x;\nassume x < 0;\nif x > 0 {\n\tassert false;\n}").unwrap()).unwrap());
+
+		assert_eq!(check(&program)[0].verdict, Verdict::Proven);
+	}
+}
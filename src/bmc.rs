@@ -0,0 +1,721 @@
+//! Bounded model checking of the assertions, by reduction to propositional satisfiability.
+//!
+//! The program graph is unrolled up to a bound on the number of edges taken, the integers being words of a fixed width in two's
+//! complement whose arithmetic wraps around. The node of every step is one-hot encoded, every step takes at most one enabled
+//! edge, keeping the state otherwise so that the shorter runs are covered, and the clauses require an assertion to be
+//! reached with its condition false. The models of the clauses are traces of failing runs.
+
+use crate::analysis::{boolex_variables, location, records};
+use crate::flow_graph::{Action, FlowGraph, locate};
+use crate::lexer::{Span, keyword::Type};
+use crate::microc::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, ops::{ArithmeticOp, BinaryOp, RelationalOp}, stmt::Statement, visit::{Visitor, walk_arex, walk_lvalue}};
+use crate::sat::{Lit, Solver};
+use petgraph::graph::EdgeIndex;
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, convert::TryFrom};
+
+/// Integer in two's complement, least significant bit first.
+type Word = Vec<Lit>;
+
+/// Words of the variables and of the array elements, named `a[i, j]`.
+type State = BTreeMap<String, Word>;
+
+/// Variable or array element written by an edge, with the condition of its being written and its new word.
+type Write = (String, Lit, Word);
+
+/// Edge taken by a failing run, with the values it writes.
+#[derive(Debug, Clone)]
+pub struct Step {
+	/// Edge taken.
+	pub edge: EdgeIndex,
+	/// Values written by the edge.
+	pub values: Vec<(String, i128)>,
+}
+
+/// Run making an assertion fail, with the values of the variables of the assertion when it is reached.
+#[derive(Debug, Clone)]
+pub struct Counterexample {
+	/// Edges taken from the initial node to the assertion.
+	pub steps: Vec<Step>,
+	/// Edge of the assertion.
+	pub assertion: EdgeIndex,
+	/// Values of the variables of the assertion.
+	pub values: Vec<(String, i128)>,
+}
+
+/// Boolean circuit whose gates are defined by clauses, constant inputs being folded.
+struct Circuit {
+	solver: Solver,
+	/// Literal that is always true.
+	truth: Lit,
+}
+
+impl Circuit {
+	fn new() -> Self {
+		let mut solver = Solver::new();
+		let truth = Lit::new(solver.var(), false);
+
+		solver.clause(&[truth]);
+		Circuit { solver, truth }
+	}
+
+	fn fresh(&mut self) -> Lit {
+		Lit::new(self.solver.var(), false)
+	}
+
+	fn constant(&self, value: bool) -> Lit {
+		if value { self.truth } else { !self.truth }
+	}
+
+	fn and(&mut self, a: Lit, b: Lit) -> Lit {
+		if a == !self.truth || b == !self.truth || a == !b {
+			return !self.truth;
+		}
+
+		if a == self.truth || a == b {
+			return b;
+		}
+
+		if b == self.truth {
+			return a;
+		}
+
+		let gate = self.fresh();
+
+		self.solver.clause(&[!gate, a]);
+		self.solver.clause(&[!gate, b]);
+		self.solver.clause(&[gate, !a, !b]);
+		gate
+	}
+
+	fn or(&mut self, a: Lit, b: Lit) -> Lit {
+		!self.and(!a, !b)
+	}
+
+	fn all(&mut self, lits: impl IntoIterator<Item = Lit>) -> Lit {
+		lits.into_iter().fold(self.truth, |all, lit| self.and(all, lit))
+	}
+
+	fn any(&mut self, lits: impl IntoIterator<Item = Lit>) -> Lit {
+		lits.into_iter().fold(!self.truth, |any, lit| self.or(any, lit))
+	}
+
+	fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+		if a == self.truth || a == !self.truth {
+			return if a == self.truth { !b } else { b };
+		}
+
+		if b == self.truth || b == !self.truth {
+			return self.xor(b, a);
+		}
+
+		if a == b || a == !b {
+			return self.constant(a == !b);
+		}
+
+		let gate = self.fresh();
+
+		self.solver.clause(&[!gate, a, b]);
+		self.solver.clause(&[!gate, !a, !b]);
+		self.solver.clause(&[gate, !a, b]);
+		self.solver.clause(&[gate, a, !b]);
+		gate
+	}
+
+	/// `a` if `condition` holds, `b` otherwise.
+	fn ite(&mut self, condition: Lit, a: Lit, b: Lit) -> Lit {
+		if condition == self.truth || a == b {
+			return a;
+		}
+
+		if condition == !self.truth {
+			return b;
+		}
+
+		let gate = self.fresh();
+
+		self.solver.clause(&[!condition, !a, gate]);
+		self.solver.clause(&[!condition, a, !gate]);
+		self.solver.clause(&[condition, !b, gate]);
+		self.solver.clause(&[condition, b, !gate]);
+		gate
+	}
+
+	/// Literal true for at most one of the literals, given as the one true if any.
+	fn one(&mut self, lits: &[Lit]) -> Lit {
+		let mut any = !self.truth;
+
+		for lit in lits {
+			self.solver.clause(&[!*lit, !any]);
+			any = self.or(any, *lit);
+		}
+
+		any
+	}
+
+	fn word(&self, n: i128, width: usize) -> Word {
+		(0..width).map(|i| self.constant((n >> i.min(127)) & 1 == 1)).collect()
+	}
+
+	fn fresh_word(&mut self, width: usize) -> Word {
+		(0..width).map(|_| self.fresh()).collect()
+	}
+
+	fn select(&mut self, condition: Lit, a: &[Lit], b: &[Lit]) -> Word {
+		a.iter().zip(b).map(|(a, b)| self.ite(condition, *a, *b)).collect()
+	}
+
+	/// Sum of two words and of a carry, with the carry out.
+	fn adder(&mut self, a: &[Lit], b: &[Lit], mut carry: Lit) -> (Word, Lit) {
+		let mut sum = vec![];
+
+		for (a, b) in a.iter().zip(b) {
+			let half = self.xor(*a, *b);
+			let (both, propagated) = (self.and(*a, *b), self.and(carry, half));
+
+			sum.push(self.xor(half, carry));
+			carry = self.or(both, propagated);
+		}
+
+		(sum, carry)
+	}
+
+	fn add(&mut self, a: &[Lit], b: &[Lit]) -> Word {
+		self.adder(a, b, !self.truth).0
+	}
+
+	fn sub(&mut self, a: &[Lit], b: &[Lit]) -> Word {
+		let complement: Word = b.iter().map(|b| !*b).collect();
+
+		self.adder(a, &complement, self.truth).0
+	}
+
+	fn negate(&mut self, a: &[Lit]) -> Word {
+		let zero = self.word(0, a.len());
+
+		self.sub(&zero, a)
+	}
+
+	fn mul(&mut self, a: &[Lit], b: &[Lit]) -> Word {
+		let mut product = self.word(0, a.len());
+
+		for (i, b) in b.iter().enumerate() {
+			let partial: Word = (0..a.len()).map(|k| if k < i { !self.truth } else { self.and(a[k - i], *b) }).collect();
+
+			product = self.add(&product, &partial);
+		}
+
+		product
+	}
+
+	/// Quotient and remainder of the division truncating toward zero, when the divisor is not zero.
+	fn divide(&mut self, a: &[Lit], b: &[Lit]) -> (Word, Word) {
+		let width = a.len();
+		let (negative_a, negative_b) = (a[width - 1], b[width - 1]);
+		let (minus_a, minus_b) = (self.negate(a), self.negate(b));
+		let (dividend, divisor) = (self.select(negative_a, &minus_a, a), self.select(negative_b, &minus_b, b));
+		let complement: Word = divisor.iter().map(|b| !*b).collect();
+		let mut quotient = vec![!self.truth; width];
+		let mut remainder = self.word(0, width);
+
+		// restoring division of the magnitudes, the remainder staying below the divisor and thus fitting the shift
+		for i in (0..width).rev() {
+			remainder = std::iter::once(dividend[i]).chain(remainder[..width - 1].iter().copied()).collect();
+
+			let (difference, fits) = self.adder(&remainder, &complement, self.truth);
+
+			quotient[i] = fits;
+			remainder = self.select(fits, &difference, &remainder);
+		}
+
+		let (minus_quotient, minus_remainder) = (self.negate(&quotient), self.negate(&remainder));
+		let negative = self.xor(negative_a, negative_b);
+
+		(self.select(negative, &minus_quotient, &quotient), self.select(negative_a, &minus_remainder, &remainder))
+	}
+
+	fn equal(&mut self, a: &[Lit], b: &[Lit]) -> Lit {
+		let equal: Vec<Lit> = a.iter().zip(b).map(|(a, b)| !self.xor(*a, *b)).collect();
+
+		self.all(equal)
+	}
+
+	/// Signed comparison `a < b`, from the sign of the difference and its overflow.
+	fn less(&mut self, a: &[Lit], b: &[Lit]) -> Lit {
+		let sign = a.len() - 1;
+		let difference = self.sub(a, b);
+		let (signs, flipped) = (self.xor(a[sign], b[sign]), self.xor(difference[sign], a[sign]));
+		let overflow = self.and(signs, flipped);
+
+		self.xor(difference[sign], overflow)
+	}
+
+	fn decode(&self, word: &[Lit]) -> i128 {
+		let unsigned = word.iter().enumerate().fold(0i128, |n, (i, lit)| n | (((self.solver.model(lit.var()) != lit.negated()) as i128) << i));
+
+		if word.len() < 128 && unsigned >> (word.len() - 1) == 1 { unsigned - (1 << word.len()) } else { unsigned }
+	}
+}
+
+/// Variables and arrays of the program, with the constructs the encoding does not support.
+#[derive(Default)]
+struct Shapes {
+	variables: BTreeSet<String>,
+	arrays: BTreeMap<String, Vec<usize>>,
+	unsupported: Option<String>,
+}
+
+impl Shapes {
+	fn declare(&mut self, decl: &Declaration, prefix: &str) {
+		match decl {
+			Declaration::Var(Type::Float, id) => self.unsupported = Some(format!("Float variable '{prefix}{id}' is not supported.")),
+			Declaration::Var(_, id) => {
+				self.variables.insert(format!("{prefix}{id}"));
+			},
+			Declaration::Pointer(_, id) => self.unsupported = Some(format!("Pointer '{prefix}{id}' is not supported.")),
+			Declaration::Array(_, sizes, id) => {
+				let dims: Vec<usize> = sizes.iter().map(|size| usize::try_from(isize::from(*size)).unwrap_or(0)).collect();
+
+				match self.arrays.insert(format!("{prefix}{id}"), dims.clone()) {
+					Some(previous) if previous != dims => self.unsupported = Some(format!("Array '{prefix}{id}' is declared with different dimensions.")),
+					_ => (),
+				}
+			},
+			Declaration::Record(decls, id) => decls.iter().for_each(|member| self.declare(member, &format!("{prefix}{id}."))),
+			Declaration::Procedure(..) => (),
+		}
+	}
+}
+
+impl Visitor for Shapes {
+	fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+		match arex {
+			ArithmeticExpr::Literal(ArithmeticLiteral::Float(_)) => self.unsupported = Some(format!("Float `{arex}` is not supported.")),
+			ArithmeticExpr::Reference(_) => self.unsupported = Some(format!("Pointers are not supported, got `{arex}`.")),
+			_ => walk_arex(self, arex),
+		}
+	}
+
+	fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+		match lvalue {
+			LvalueExpr::Variable(_) | LvalueExpr::RecordMember(..) => {
+				self.variables.insert(location(lvalue));
+			},
+			LvalueExpr::Deref(_) => self.unsupported = Some(format!("Pointers are not supported, got `{lvalue}`.")),
+			LvalueExpr::ArrayIndex(..) => (),
+		}
+
+		walk_lvalue(self, lvalue);
+	}
+}
+
+/// Indexes of every element of an array, in row-major order.
+fn elements(dims: &[usize]) -> Vec<Vec<usize>> {
+	dims.iter().fold(vec![vec![]], |prefixes, size| prefixes.iter().flat_map(|prefix| (0..*size).map(move |i| [prefix.clone(), vec![i]].concat())).collect())
+}
+
+fn element(id: &str, indexes: &[usize]) -> String {
+	format!("{id}[{}]", indexes.iter().map(usize::to_string).collect::<Vec<String>>().join(", "))
+}
+
+/// Encoding of an edge from a state: whether it can be taken, the words it writes with when they are written, and whether it fails.
+struct Effect {
+	enabled: Lit,
+	writes: Vec<Write>,
+	failure: Option<Lit>,
+}
+
+/// Unrolling of a program graph into clauses.
+struct Checker<'a> {
+	program: &'a FlowGraph,
+	circuit: Circuit,
+	width: usize,
+	arrays: BTreeMap<String, Vec<usize>>,
+	records: HashMap<String, Vec<String>>,
+}
+
+impl Checker<'_> {
+	/// Word of an arithmetic expression, with the condition of its evaluation not failing.
+	fn arex(&mut self, state: &State, arex: &ArithmeticExpr) -> Result<(Word, Lit), String> {
+		match arex {
+			ArithmeticExpr::Literal(ArithmeticLiteral::Int(n)) => Ok((self.circuit.word(isize::from(*n) as i128, self.width), self.circuit.truth)),
+			ArithmeticExpr::LvalueExpr(lvalue) => self.load(state, lvalue),
+			ArithmeticExpr::ArithmeticOperation(operation) => {
+				let (a, defined_a) = self.arex(state, &operation.0)?;
+				let (b, defined_b) = self.arex(state, &operation.2)?;
+				let defined = self.circuit.and(defined_a, defined_b);
+
+				Ok(match operation.1 {
+					ArithmeticOp::Add => (self.circuit.add(&a, &b), defined),
+					ArithmeticOp::Sub | ArithmeticOp::Neg => (self.circuit.sub(&a, &b), defined),
+					ArithmeticOp::Mul => (self.circuit.mul(&a, &b), defined),
+					ArithmeticOp::Div | ArithmeticOp::Rem => {
+						let zero = self.circuit.word(0, self.width);
+						let nonzero = !self.circuit.equal(&b, &zero);
+						let (quotient, remainder) = self.circuit.divide(&a, &b);
+						let defined = self.circuit.and(defined, nonzero);
+
+						(if operation.1 == ArithmeticOp::Div { quotient } else { remainder }, defined)
+					},
+				})
+			},
+			_ => Err(format!("`{arex}` is not supported.")),
+		}
+	}
+
+	fn boolex(&mut self, state: &State, boolex: &BooleanExpr) -> Result<(Lit, Lit), String> {
+		match boolex {
+			BooleanExpr::BooleanLiteral(b) => Ok((self.circuit.constant(*b), self.circuit.truth)),
+			BooleanExpr::NotOperation(boolex) => self.boolex(state, boolex).map(|(value, defined)| (!value, defined)),
+			BooleanExpr::RelationalOperation(arex1, op, arex2) => {
+				let (a, defined_a) = self.arex(state, arex1)?;
+				let (b, defined_b) = self.arex(state, arex2)?;
+				let value = match op {
+					RelationalOp::Lt => self.circuit.less(&a, &b),
+					RelationalOp::Leq => !self.circuit.less(&b, &a),
+					RelationalOp::Gt => self.circuit.less(&b, &a),
+					RelationalOp::Geq => !self.circuit.less(&a, &b),
+					RelationalOp::Eq => self.circuit.equal(&a, &b),
+					RelationalOp::Neq => !self.circuit.equal(&a, &b),
+				};
+
+				Ok((value, self.circuit.and(defined_a, defined_b)))
+			},
+			BooleanExpr::BinaryOperation(boolex1, op, boolex2) => {
+				let (a, defined_a) = self.boolex(state, boolex1)?;
+				let (b, defined_b) = self.boolex(state, boolex2)?;
+				let value = match op {
+					BinaryOp::BitAnd => self.circuit.and(a, b),
+					BinaryOp::BitOr => self.circuit.or(a, b),
+					BinaryOp::BitXor => self.circuit.xor(a, b),
+					_ => return Err(format!("Invalid boolean operator '{op}'.")),
+				};
+
+				Ok((value, self.circuit.and(defined_a, defined_b)))
+			},
+		}
+	}
+
+	/// Elements an lvalue may designate, with the conditions under which it does, and the condition of its indexes being within bounds.
+	fn designate(&mut self, state: &State, lvalue: &LvalueExpr) -> Result<(Vec<(String, Lit)>, Lit), String> {
+		match lvalue {
+			LvalueExpr::ArrayIndex(id, indexes) => {
+				let dims = self.arrays.get(id).cloned().ok_or(format!("Undeclared array '{id}'."))?;
+
+				if dims.len() != indexes.len() {
+					return Err(format!("Array '{id}' has {} dimensions, `{lvalue}` indexes {}.", dims.len(), indexes.len()));
+				}
+
+				let mut words = vec![];
+				let mut defined = self.circuit.truth;
+
+				for arex in indexes {
+					let (word, defined_index) = self.arex(state, arex)?;
+
+					defined = self.circuit.and(defined, defined_index);
+					words.push(word);
+				}
+
+				let mut designated = vec![];
+
+				for indexes in elements(&dims) {
+					let equal: Vec<Lit> = indexes.iter().zip(&words).map(|(i, word)| {
+						let i = self.circuit.word(*i as i128, self.width);
+
+						self.circuit.equal(&i, word)
+					}).collect();
+					let selected = self.circuit.all(equal);
+
+					designated.push((element(id, &indexes), selected));
+				}
+
+				let within = self.circuit.any(designated.iter().map(|(_, selected)| *selected).collect::<Vec<Lit>>());
+
+				Ok((designated, self.circuit.and(defined, within)))
+			},
+			_ => Ok((vec![(location(lvalue), self.circuit.truth)], self.circuit.truth)),
+		}
+	}
+
+	fn load(&mut self, state: &State, lvalue: &LvalueExpr) -> Result<(Word, Lit), String> {
+		let (designated, defined) = self.designate(state, lvalue)?;
+		let mut value = self.circuit.word(0, self.width);
+
+		for (var, selected) in designated {
+			let word = state.get(&var).ok_or(format!("Undeclared variable '{var}'."))?;
+
+			value = self.circuit.select(selected, word, &value);
+		}
+
+		Ok((value, defined))
+	}
+
+	fn store(&mut self, state: &State, lvalue: &LvalueExpr, value: Word) -> Result<(Vec<Write>, Lit), String> {
+		let (designated, defined) = self.designate(state, lvalue)?;
+
+		Ok((designated.into_iter().map(|(var, selected)| (var, selected, value.clone())).collect(), defined))
+	}
+
+	/// Words written by a declaration, that is zero for each of its variables and array elements.
+	fn declare(&self, decl: &Declaration, prefix: &str) -> Vec<Write> {
+		let zero = self.circuit.word(0, self.width);
+
+		match decl {
+			Declaration::Var(_, id) => vec![(format!("{prefix}{id}"), self.circuit.truth, zero)],
+			Declaration::Array(_, _, id) => {
+				let id = format!("{prefix}{id}");
+
+				elements(&self.arrays[&id]).iter().map(|indexes| (element(&id, indexes), self.circuit.truth, zero.clone())).collect()
+			},
+			Declaration::Record(decls, id) => decls.iter().flat_map(|member| self.declare(member, &format!("{prefix}{id}."))).collect(),
+			_ => vec![],
+		}
+	}
+
+	/// Encodes an edge taken from a state, `at` being the literal of the state being at its source.
+	fn effect(&mut self, state: &State, edge: EdgeIndex, at: Lit) -> Result<Effect, String> {
+		let truth = self.circuit.truth;
+		let (enabled, writes, failure) = match &self.program.0[edge] {
+			Action::Declaration(decl) => (truth, self.declare(decl, ""), None),
+			Action::Condition(boolex) => {
+				let (value, defined) = self.boolex(state, boolex)?;
+
+				(self.circuit.and(value, defined), vec![], None)
+			},
+			Action::Statement(stmt) => match stmt {
+				Statement::LvalueAssign(lvalue, arex) => {
+					let (value, defined) = self.arex(state, arex)?;
+					let (writes, designated) = self.store(state, lvalue, value)?;
+
+					(self.circuit.and(defined, designated), writes, None)
+				},
+				Statement::RecordAssign(id, arexs) => {
+					let members = self.records.get(id).cloned().ok_or(format!("Undeclared record '{id}'."))?;
+
+					if members.len() != arexs.len() {
+						return Err(format!("Record '{id}' has {} members, {} values given.", members.len(), arexs.len()));
+					}
+
+					let mut enabled = truth;
+					let mut writes = vec![];
+
+					for (member, arex) in members.into_iter().zip(arexs) {
+						let (value, defined) = self.arex(state, arex)?;
+
+						enabled = self.circuit.and(enabled, defined);
+						writes.push((member, truth, value));
+					}
+
+					(enabled, writes, None)
+				},
+				Statement::Read(lvalue) => {
+					let value = self.circuit.fresh_word(self.width);
+					let (writes, designated) = self.store(state, lvalue, value)?;
+
+					(designated, writes, None)
+				},
+				Statement::Write(arex) => (self.arex(state, arex)?.1, vec![], None),
+				Statement::Assert(boolex) => {
+					let (value, defined) = self.boolex(state, boolex)?;
+
+					(self.circuit.and(value, defined), vec![], Some(self.circuit.and(!value, defined)))
+				},
+				Statement::Assume(boolex) => {
+					let (value, defined) = self.boolex(state, boolex)?;
+
+					(self.circuit.and(value, defined), vec![], None)
+				},
+				_ => (truth, vec![], None),
+			},
+			action @ (Action::Call(_) | Action::Return(_)) => return Err(format!("Procedures are not supported, got `{action}`.")),
+		};
+
+		Ok(Effect {
+			enabled: self.circuit.and(at, enabled),
+			writes,
+			failure: failure.map(|failure| self.circuit.and(at, failure)),
+		})
+	}
+}
+
+/// Looks for a run of at most `bound` edges making an assertion fail, the integers having `width` bits.
+pub fn check(program: &FlowGraph, bound: usize, width: usize) -> Result<Option<Counterexample>, String> {
+	if !(2..=64).contains(&width) {
+		return Err(format!("Width {width} is not between 2 and 64 bits."));
+	}
+
+	let (graph, start, _) = program;
+	let mut shapes = Shapes::default();
+
+	for edge in graph.edge_indices() {
+		match &graph[edge] {
+			Action::Declaration(decl) => shapes.declare(decl, ""),
+			Action::Condition(boolex) => shapes.visit_boolex(boolex),
+			Action::Statement(stmt) => shapes.visit_stmt(stmt),
+			action => return Err(format!("Procedures are not supported, got `{action}`.")),
+		}
+	}
+
+	if let Some(e) = shapes.unsupported {
+		return Err(e);
+	}
+
+	let mut checker = Checker { program, circuit: Circuit::new(), width, arrays: shapes.arrays, records: records(program) };
+	let names: Vec<String> = shapes.variables.into_iter()
+		.chain(checker.arrays.iter().flat_map(|(id, dims)| elements(dims).into_iter().map(move |indexes| element(id, &indexes))))
+		.collect();
+	// variables start with arbitrary values, MicroC declarations setting them to zero
+	let mut states: Vec<State> = vec![names.into_iter().map(|var| (var, checker.circuit.fresh_word(width))).collect()];
+	let mut at: Vec<Lit> = graph.node_indices().map(|node| checker.circuit.constant(node == *start)).collect();
+	let mut taken: Vec<Vec<(EdgeIndex, Lit, Vec<Write>)>> = vec![];
+	let mut failures: Vec<(usize, EdgeIndex, Lit)> = vec![];
+
+	for step in 0..=bound {
+		let state = states[step].clone();
+		let mut edges = vec![];
+
+		for edge in graph.edge_indices() {
+			let source = graph.edge_endpoints(edge).unwrap().0;
+
+			// nodes not reachable in as many steps are folded away
+			if at[source.index()] == !checker.circuit.truth {
+				continue;
+			}
+
+			let effect = checker.effect(&state, edge, at[source.index()])?;
+
+			if let Some(failure) = effect.failure {
+				failures.push((step, edge, failure));
+			}
+
+			if step < bound && effect.enabled != !checker.circuit.truth {
+				let chosen = checker.circuit.fresh();
+
+				checker.circuit.solver.clause(&[!chosen, effect.enabled]);
+				edges.push((edge, chosen, effect.writes));
+			}
+		}
+
+		if step == bound {
+			break;
+		}
+
+		let any = checker.circuit.one(&edges.iter().map(|(_, chosen, _)| *chosen).collect::<Vec<Lit>>());
+		let mut next = state.clone();
+
+		for (_, chosen, writes) in &edges {
+			for (var, written, value) in writes {
+				let written = checker.circuit.and(*chosen, *written);
+				let word = checker.circuit.select(written, value, &next[var]);
+
+				next.insert(var.clone(), word);
+			}
+		}
+
+		at = graph.node_indices().map(|node| {
+			let entering: Vec<Lit> = edges.iter().filter(|(edge, _, _)| graph.edge_endpoints(*edge).unwrap().1 == node).map(|(_, chosen, _)| *chosen).collect();
+			let entered = checker.circuit.any(entering);
+			let stays = checker.circuit.and(!any, at[node.index()]);
+
+			checker.circuit.or(entered, stays)
+		}).collect();
+		states.push(next);
+		taken.push(edges);
+	}
+
+	let fails: Vec<Lit> = failures.iter().map(|(_, _, failure)| *failure).collect();
+
+	checker.circuit.solver.clause(&fails);
+
+	if !checker.circuit.solver.solve() {
+		return Ok(None);
+	}
+
+	let circuit = &checker.circuit;
+	let holds = |lit: Lit| circuit.solver.model(lit.var()) != lit.negated();
+	let (step, assertion, _) = *failures.iter().filter(|(_, _, failure)| holds(*failure)).min_by_key(|(step, _, _)| *step).unwrap();
+	let steps = taken[..step].iter().enumerate().filter_map(|(k, edges)| edges.iter().find(|(_, chosen, _)| holds(*chosen)).map(|(edge, _, writes)| Step {
+		edge: *edge,
+		values: writes.iter().filter(|(_, written, _)| holds(*written)).map(|(var, _, _)| (var.clone(), circuit.decode(&states[k + 1][var]))).collect(),
+	})).collect();
+	let values = match &graph[assertion] {
+		Action::Statement(Statement::Assert(boolex)) => boolex_variables(boolex).into_iter().filter_map(|var| states[step].get(&var).map(|word| (var, circuit.decode(word)))).collect(),
+		_ => vec![],
+	};
+
+	Ok(Some(Counterexample { steps, assertion, values }))
+}
+
+/// Formats the counterexample, or the absence of one within the bound, located with the spans of the edges.
+pub fn report(program: &FlowGraph, counterexample: &Option<Counterexample>, bound: usize, width: usize, spans: &HashMap<EdgeIndex, Span>) -> String {
+	let edge = |edge: EdgeIndex| {
+		let (source, target) = program.0.edge_endpoints(edge).unwrap();
+
+		format!("{}q{} -> q{} `{}`", locate(spans, edge), source.index(), target.index(), program.0[edge])
+	};
+	let values = |values: &[(String, i128)]| match values.is_empty() {
+		true => String::new(),
+		false => format!(" with {}", values.iter().map(|(var, value)| format!("{var} = {value}")).collect::<Vec<String>>().join(", ")),
+	};
+
+	match counterexample {
+		None => format!("no assertion fails within {bound} steps with {width}-bit integers"),
+		Some(counterexample) => counterexample.steps.iter().enumerate()
+			.map(|(i, step)| format!("step {}: {}{}", i + 1, edge(step.edge), values(&step.values)))
+			.chain(std::iter::once(format!("assertion failure: {}{}", edge(counterexample.assertion), values(&counterexample.values))))
+			.collect::<Vec<String>>()
+			.join("\n"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{check, report};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+	use std::collections::HashMap;
+
+	fn bmc(source: &str, bound: usize, width: usize) -> String {
+		let program = flow(parse(lex_str(source).unwrap()).unwrap());
+
+		report(&program, &check(&program, bound, width).unwrap(), bound, width, &HashMap::new())
+	}
+
+	#[test]
+	fn counterexamples_reach_failing_assertions() {
+		let source = "int n;\nint i;\nint s;\nread n;\nwhile i < n {\n\ts := s + 2;\n\ti := i + 1;\n}\nassert s != 4;";
+
+		assert_eq!(bmc(source, 20, 5), [
+			"step 1: q0 -> q2 `int n;` with n = 0",
+			"step 2: q2 -> q3 `int i;` with i = 0",
+			"step 3: q3 -> q4 `int s;` with s = 0",
+			"step 4: q4 -> q5 `read n;` with n = 2",
+			"step 5: q5 -> q7 `i < n`",
+			"step 6: q7 -> q8 `s := s + 2;` with s = 2",
+			"step 7: q8 -> q5 `i := i + 1;` with i = 1",
+			"step 8: q5 -> q7 `i < n`",
+			"step 9: q7 -> q8 `s := s + 2;` with s = 4",
+			"step 10: q8 -> q5 `i := i + 1;` with i = 2",
+			"step 11: q5 -> q6 `!(i < n)`",
+			"assertion failure: q6 -> q1 `assert s != 4;` with s = 4",
+		].join("\n"));
+		// the failing run needs 12 steps
+		assert_eq!(bmc(source, 10, 5), "no assertion fails within 10 steps with 5-bit integers");
+	}
+
+	#[test]
+	fn integers_wrap_around_at_the_width() {
+		let source = "int x;\nint y;\nread x;\nassume x > 0;\ny := x * 2 / 2;\nassert y == x;";
+
+		// `x * 2` overflows for the positive `x` above 3 with 4 bits
+		assert!(bmc(source, 10, 4).ends_with("step 5: q5 -> q6 `y := x * 2 / 2;` with y = -4\nassertion failure: q6 -> q1 `assert y == x;` with x = 4, y = -4"));
+		assert!(bmc("int x;\nread x;\nassume x < 0;\nx := x % 5;\nassert x > -4;", 10, 6).ends_with("assertion failure: q5 -> q1 `assert x > -4;` with x = -4"));
+		assert!(bmc("int x;\nint[3] a;\nread x;\nassume x >= 0 && x < 3;\na[x] := 7 % (x + 2);\nassert a[0] + a[1] + a[2] != 1;", 10, 4).contains("with a[0] = 1\n"));
+	}
+
+	#[test]
+	fn procedures_are_not_supported() {
+		let program = flow(parse(lex_str("proc id(int a; int b) {\n\tb := a;\n}\nint x;\ncall id(1; x);").unwrap()).unwrap());
+
+		assert!(check(&program, 10, 8).unwrap_err().starts_with("Procedures are not supported"));
+		assert!(check(&program, 10, 1).is_err());
+	}
+}
@@ -0,0 +1,256 @@
+//! Races between the components of `par` statements and exploration of their interleavings.
+
+use crate::analysis::{Analyzer, Memory, ValueAnalysis, interval::{self, Interval}, reaching::ReachingDefinitions};
+use crate::flow_graph::{Action, FlowGraph, Origin, Position, locate};
+use crate::lexer::Span;
+use crate::microc::{decl::Declaration, stmt::{Scope, Statement}};
+use crate::parser::Ast;
+use crate::points_to::{PointsTo, andersen, targets};
+use crate::slicing::used;
+use petgraph::{graph::{EdgeIndex, NodeIndex}, visit::EdgeRef};
+use std::{collections::{BTreeSet, HashMap, HashSet}, fmt::{self, Display, Formatter}};
+
+/// Component of a `par` statement, as the position of the statement and the index of the component.
+pub type Thread = (Position, usize);
+
+/// Positions of the `par` statements of a scope and its nested ones.
+fn pars(scope: &Scope, position: &[usize], res: &mut Vec<Position>) {
+	let at = |i: usize| [position, &[i]].concat();
+
+	for (i, decl) in scope.0.iter().enumerate() {
+		if let Declaration::Procedure(_, _, _, body) = decl {
+			pars(body, &[at(i), vec![0]].concat(), res);
+		}
+	}
+
+	for (i, stmt) in scope.1.iter().enumerate() {
+		let position = at(scope.0.len() + i);
+		let scopes: Vec<&Scope> = match stmt {
+			Statement::If(_, scope) | Statement::While(_, scope) => vec![scope],
+			Statement::IfElse(_, scope1, scope2) => vec![scope1, scope2],
+			Statement::Par(scope1, scope2) => {
+				res.push(position.clone());
+				vec![scope1, scope2]
+			},
+			Statement::Scope(scope) => vec![scope],
+			_ => vec![],
+		};
+
+		for (k, scope) in scopes.into_iter().enumerate() {
+			pars(scope, &[position.clone(), vec![k]].concat(), res);
+		}
+	}
+}
+
+/// Components of `par` every edge runs in, outermost first, found from the positions of the items the edges come from.
+pub fn threads(ast: &Ast, origins: &HashMap<EdgeIndex, Origin>) -> HashMap<EdgeIndex, Vec<Thread>> {
+	let mut positions = vec![];
+
+	pars(ast, &[], &mut positions);
+	positions.sort_by_key(Vec::len);
+
+	origins.iter().map(|(edge, (position, _))| {
+		let threads = positions.iter()
+			.filter(|par| par.len() < position.len() && position.starts_with(par))
+			.map(|par| (par.clone(), position[par.len()]))
+			.collect();
+
+		(*edge, threads)
+	}).collect()
+}
+
+/// Whether two edges run in different components of the same `par`.
+fn concurrent(threads1: &[Thread], threads2: &[Thread]) -> bool {
+	threads1.iter().any(|(par, k)| threads2.iter().any(|(other, l)| par == other && k != l))
+}
+
+/// Variables read and written by the actions, dereferences standing for the variables their pointer may point to.
+struct Accesses {
+	points_to: PointsTo,
+	definitions: ReachingDefinitions,
+}
+
+impl Accesses {
+	fn new(program: &FlowGraph) -> Self {
+		Accesses { points_to: andersen(program), definitions: ReachingDefinitions::new(program) }
+	}
+
+	fn reads(&self, a: &Action) -> BTreeSet<String> {
+		used(a).into_iter().flat_map(|var| match var.strip_prefix('*') {
+			Some(pointer) => targets(&self.points_to, pointer).cloned().collect(),
+			None => vec![var],
+		}).collect()
+	}
+
+	fn writes(&self, a: &Action) -> BTreeSet<String> {
+		self.definitions.defined(a).into_iter().map(|(var, _)| var).collect()
+	}
+
+	/// Variables one action writes and the other reads or writes.
+	fn conflicts(&self, a1: &Action, a2: &Action) -> BTreeSet<String> {
+		let (reads1, writes1, reads2, writes2) = (self.reads(a1), self.writes(a1), self.reads(a2), self.writes(a2));
+
+		writes1.intersection(&reads2.union(&writes2).cloned().collect()).cloned()
+			.chain(writes2.intersection(&reads1).cloned())
+			.collect()
+	}
+}
+
+/// Nodes reached from the initial node, following at every node only the edges of a single component when they conflict with no action of the other components it runs concurrently with and `reduce` is set.
+///
+/// The reduction keeps the final node reachable if it is, edges closing a cycle of the search being always followed with the others.
+pub fn explore(program: &FlowGraph, threads: &HashMap<EdgeIndex, Vec<Thread>>, reduce: bool) -> HashSet<NodeIndex> {
+	let (graph, start, _) = program;
+	let accesses = Accesses::new(program);
+	let mut components = HashMap::<&Thread, Vec<EdgeIndex>>::new();
+
+	for (edge, threads) in threads {
+		for thread in threads {
+			components.entry(thread).or_default().push(*edge);
+		}
+	}
+
+	// an edge is independent when no edge of a sibling component conflicts with it
+	let independent = |edge: EdgeIndex| threads.get(&edge).is_none_or(|own| own.iter().all(|(par, k)| {
+		components.iter()
+			.filter(|((other, l), _)| other == par && l != k)
+			.all(|(_, edges)| edges.iter().all(|other| accesses.conflicts(&graph[edge], &graph[*other]).is_empty()))
+	}));
+	let independent: HashSet<EdgeIndex> = graph.edge_indices().filter(|edge| independent(*edge)).collect();
+
+	let successors = |node: NodeIndex, path: &[(NodeIndex, Vec<NodeIndex>)]| -> Vec<NodeIndex> {
+		let edges: Vec<_> = graph.edges(node).collect();
+		let thread = |edge: EdgeIndex| threads.get(&edge).cloned().unwrap_or_default();
+		let ample = edges.iter().find_map(|first| {
+			let ample: Vec<_> = edges.iter().filter(|edge| thread(edge.id()) == thread(first.id())).collect();
+			let closes_cycle = ample.iter().any(|edge| edge.target() == node || path.iter().any(|(on_path, _)| *on_path == edge.target()));
+
+			if ample.len() < edges.len() && ample.iter().all(|edge| independent.contains(&edge.id())) && !closes_cycle {
+				Some(ample.iter().map(|edge| edge.target()).collect())
+			} else {
+				None
+			}
+		});
+
+		match ample {
+			Some(ample) if reduce => ample,
+			_ => edges.iter().map(|edge| edge.target()).collect(),
+		}
+	};
+
+	// depth-first search, the path holding the nodes being explored with their successors left
+	let mut visited: HashSet<NodeIndex> = vec![*start].into_iter().collect();
+	let mut path = vec![(*start, successors(*start, &[]))];
+
+	while let Some((_, left)) = path.last_mut() {
+		match left.pop() {
+			Some(next) => if visited.insert(next) {
+				let left = successors(next, &path);
+
+				path.push((next, left));
+			},
+			None => {
+				path.pop();
+			},
+		}
+	}
+
+	visited
+}
+
+/// Accesses of two edges of different components of a `par` that are both enabled at the node they leave, one writing variables the other reads or writes.
+#[derive(Debug, Clone)]
+pub struct Race {
+	/// Node both edges leave.
+	pub node: NodeIndex,
+	/// Edges of the two components.
+	pub edges: (EdgeIndex, EdgeIndex),
+	/// Variables one edge writes and the other reads or writes.
+	pub variables: BTreeSet<String>,
+}
+
+impl Display for Race {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "race on {} at q{}", self.variables.iter().cloned().collect::<Vec<String>>().join(", "), self.node.index())
+	}
+}
+
+/// Races of the program, the edges being enabled when the interval analysis does not refute their guards at a reachable node.
+///
+/// The race of two items is only reported at the first node it occurs at.
+pub fn races(program: &FlowGraph, origins: &HashMap<EdgeIndex, Origin>, threads: &HashMap<EdgeIndex, Vec<Thread>>) -> Vec<Race> {
+	let graph = &program.0;
+	let intervals = interval::analyze(program);
+	let analysis = ValueAnalysis::<Interval>::new(program);
+	let accesses = Accesses::new(program);
+	let mut reported = HashSet::new();
+	let mut races = vec![];
+
+	for node in graph.node_indices() {
+		let memory: &Memory<Interval> = &intervals[&node];
+
+		if memory.is_none() {
+			continue;
+		}
+
+		let enabled: Vec<EdgeIndex> = graph.edges(node).filter(|edge| analysis.map(edge.weight(), memory).is_some()).map(|edge| edge.id()).collect();
+
+		for (i, edge1) in enabled.iter().enumerate() {
+			for edge2 in &enabled[i + 1..] {
+				let (threads1, threads2) = (threads.get(edge1).cloned().unwrap_or_default(), threads.get(edge2).cloned().unwrap_or_default());
+				let variables = accesses.conflicts(&graph[*edge1], &graph[*edge2]);
+
+				if concurrent(&threads1, &threads2) && !variables.is_empty() && reported.insert((origins[edge1].1, origins[edge2].1)) {
+					races.push(Race { node, edges: (*edge1, *edge2), variables });
+				}
+			}
+		}
+	}
+
+	races
+}
+
+/// Formats the races with the program graph edges they occur on, located with the spans of the edges.
+pub fn report(program: &FlowGraph, races: &[Race], spans: &HashMap<EdgeIndex, Span>) -> String {
+	races.iter().map(|race| {
+		let (edge1, edge2) = race.edges;
+
+		format!("{}`{}` and {}`{}`: {race}", locate(spans, edge1), program.0[edge1], locate(spans, edge2), program.0[edge2])
+	}).collect::<Vec<String>>().join("\n")
+}
+
+/// Formats the number of nodes explored with and without partial order reduction, and whether the final node is reachable.
+pub fn interleavings(program: &FlowGraph, threads: &HashMap<EdgeIndex, Vec<Thread>>) -> String {
+	let reduced = explore(program, threads, true);
+	let reachable = if reduced.contains(&program.2) { "reachable" } else { "unreachable" };
+
+	format!("{} of {} nodes explored with partial order reduction, the final node is {reachable}", reduced.len(), explore(program, threads, false).len())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{explore, interleavings, races, threads};
+	use crate::{flow_graph::build, lexer::lex_str, parser::parse};
+
+	#[test]
+	fn busy_waiting_orders_the_accesses() {
+		let ast = parse(lex_str("int x;\nint y;\nint flag;\npar {\n\tx := 1;\n\tflag := 1;\n} and {\n\twhile flag == 0 { }\n\ty := x;\n}\npar { x := 1; } and { x := x + 1; }").unwrap()).unwrap();
+		let (program, origins) = build(&ast);
+		let races: Vec<String> = races(&program, &origins, &threads(&ast, &origins)).iter().map(|race| {
+			format!("{} | {}: {}", program.0[race.edges.0], program.0[race.edges.1], race.variables.iter().cloned().collect::<Vec<String>>().join(", "))
+		}).collect();
+
+		// `y := x` only runs once `x := 1` has
+		assert_eq!(races, ["x := x + 1; | x := 1;: x", "flag == 0 | flag := 1;: flag"]);
+	}
+
+	#[test]
+	fn independent_components_run_one_after_the_other() {
+		let ast = parse(lex_str("int x;\npar {\n\tint a;\n\ta := 1;\n\ta := 2;\n} and {\n\tint b;\n\tb := 1;\n\tx := b;\n}").unwrap()).unwrap();
+		let (program, origins) = build(&ast);
+		let threads = threads(&ast, &origins);
+
+		assert_eq!(explore(&program, &threads, false).len(), 17);
+		assert_eq!(interleavings(&program, &threads), "8 of 17 nodes explored with partial order reduction, the final node is reachable");
+	}
+}
@@ -1,26 +1,451 @@
-use crate::{microc::{decl::Declaration, stmt::Statement}, parser::Ast};
-use petgraph::graph::{DiGraph, NodeIndex};
+//! Program graphs of MicroC programs, whose edges are labelled with the actions of the statements and guards.
 
+use crate::{lexer::Span, microc::{decl::Declaration, expr::{ArithmeticExpr, BooleanExpr, LvalueExpr}, node::{NodeId, size}, stmt::{Scope, Statement, arguments}, visit::Visitor}, parser::Ast};
+use petgraph::{algo::dominators::simple_fast, graph::{DiGraph, EdgeIndex, NodeIndex}, visit::{EdgeRef, Reversed}};
+use std::{collections::{HashMap, HashSet}, fmt::{self, Display, Formatter}};
+
+/// Label of an edge of the program graph.
 #[derive(Debug, Clone)]
 pub enum Action {
+	/// Declaration of variables, arrays, pointers or records.
 	Declaration(Declaration),
+	/// Elementary statement: assignment, read, write, assertion, assumption, break or continue.
 	Statement(Statement),
+	/// Guard that must hold for the edge to be taken.
+	Condition(BooleanExpr),
+	/// Edge from a call site to the initial node of the procedure, binding its parameters.
+	Call(Call),
+	/// Edge from the final node of the procedure back to a call site, assigning its results.
+	Return(Call),
+}
+
+impl Display for Action {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Action::*;
+
+		match self {
+			Declaration(decl) => write!(f, "{decl}"),
+			Statement(stmt) => write!(f, "{stmt}"),
+			Condition(boolex) => write!(f, "{boolex}"),
+			Call(call) => write!(f, "call {call}"),
+			Return(call) => write!(f, "return {call}"),
+		}
+	}
+}
+
+/// Call site of a procedure, labelling both its call and return edges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+	/// Index of the call site in the program graph, the sites being numbered in the order their edges are added.
+	pub site: usize,
+	/// Name of the procedure called.
+	pub procedure: String,
+	/// Arguments of the value parameters.
+	pub arguments: Vec<ArithmeticExpr>,
+	/// Lvalues receiving the result parameters.
+	pub results: Vec<LvalueExpr>,
+	/// Value parameters, assigned the arguments by the call edge.
+	pub values: Vec<String>,
+	/// Result parameters, assigned to the lvalues of the results by the return edge.
+	pub outputs: Vec<String>,
+	/// Parameters and variables of the procedure, whose values at the call site are restored by the return edge.
+	pub locals: Vec<String>,
+}
+
+impl Display for Call {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}({})", self.procedure, arguments(&self.arguments, &self.results))
+	}
+}
+
+/// Program graph, with its initial and final nodes.
+pub type FlowGraph = (DiGraph<(), Action>, NodeIndex<u32>, NodeIndex<u32>);
+
+/// Position of a declaration or statement in the AST, as the indexes leading to it from the outermost scope.
+///
+/// The items of a scope are its declarations followed by its statements, and the scopes of a statement are numbered in order.
+pub type Position = Vec<usize>;
+
+/// Origin of an edge of the program graph: the position of the item it comes from, and the id of its node.
+///
+/// Guard edges, including the negated ones, come from the guard of their statement.
+pub type Origin = (Position, NodeId);
+
+/// Elementary item of a flattened scope, with its id.
+enum Item<'a> {
+	Declaration(&'a Declaration, Position, NodeId),
+	Statement(&'a Statement, Position, NodeId),
+}
+
+/// Flattens nested scopes, which do not exist in the program graph, the first node of the scope having id `id`.
+fn items<'a>(scope: &'a Scope, position: &[usize], mut id: usize) -> Vec<Item<'a>> {
+	let at = |i: usize| [position, &[i]].concat();
+	let mut items = Vec::<Item>::new();
+
+	for (i, decl) in scope.0.iter().enumerate() {
+		// procedures have their own program graph
+		if !matches!(decl, Declaration::Procedure(..)) {
+			items.push(Item::Declaration(decl, at(i), NodeId(id)));
+		}
+
+		id += size(|n| n.visit_decl(decl));
+	}
+
+	for (i, stmt) in scope.1.iter().enumerate() {
+		let position = at(scope.0.len() + i);
+
+		match stmt {
+			Statement::Scope(nested) => items.append(&mut self::items(nested, &[position, vec![0]].concat(), id + 1)),
+			// like empty scopes, components without items leave no edges
+			Statement::Par(first, second) if self::items(first, &[], 0).is_empty() && self::items(second, &[], 0).is_empty() => (),
+			// annotations are only read by the verifier
+			Statement::Annotation(..) => (),
+			_ => items.push(Item::Statement(stmt, position, NodeId(id))),
+		}
+
+		id += size(|n| n.visit_stmt(stmt));
+	}
+
+	items
+}
+
+/// Names of the parameters of a procedure, and of its parameters and variables.
+type Signature = (Vec<String>, Vec<String>, Vec<String>);
+
+/// Call statement between two nodes, waiting for the nodes of its procedure.
+type Pending = (NodeIndex, NodeIndex, Call, Position, NodeId);
+
+struct Builder {
+	graph: DiGraph<(), Action>,
+	origins: HashMap<EdgeIndex, Origin>,
+	signatures: HashMap<String, Signature>,
+	calls: Vec<Pending>,
+}
+
+impl Builder {
+	fn add(&mut self, qs: NodeIndex, qe: NodeIndex, action: Action, position: &[usize], id: NodeId) {
+		let edge = self.graph.add_edge(qs, qe, action);
+
+		self.origins.insert(edge, (position.to_vec(), id));
+	}
+
+	/// Adds the edges of the items between `qs` and `qe`, `exits` holding the head and exit of the innermost loop.
+	fn edges(&mut self, qs: NodeIndex, qe: NodeIndex, items: &[Item], exits: Option<(NodeIndex, NodeIndex)>) {
+		let mut source = qs;
+
+		for (i, item) in items.iter().enumerate() {
+			let target = if i + 1 == items.len() { qe } else { self.graph.add_node(()) };
+
+			match item {
+				Item::Declaration(decl, position, id) => self.add(source, target, Action::Declaration((*decl).clone()), position, *id),
+				Item::Statement(stmt, position, id) => self.edge(source, target, stmt, position, *id, exits),
+			}
+
+			source = target;
+		}
+	}
+
+	/// Adds the edges of a single statement with id `id` between `qs` and `qe`.
+	#[allow(clippy::too_many_arguments)]
+	fn edge(&mut self, qs: NodeIndex, qe: NodeIndex, stmt: &Statement, position: &[usize], id: NodeId, exits: Option<(NodeIndex, NodeIndex)>) {
+		use Statement::*;
+
+		let not = |boolex: &BooleanExpr| Action::Condition(BooleanExpr::NotOperation(Box::new(boolex.clone())));
+		// the guard follows the statement in pre-order, and precedes its scopes
+		let guard = NodeId(id.0 + 1);
+		let scope = |boolex: &BooleanExpr| guard.0 + size(|n| n.visit_boolex(boolex));
+
+		match stmt {
+			If(boolex, body) => {
+				self.branch(qs, qe, boolex.clone(), items(body, &[position, &[0]].concat(), scope(boolex)), position, guard, exits);
+				self.add(qs, qe, not(boolex), position, guard);
+			},
+			IfElse(boolex, body1, body2) => {
+				let first = scope(boolex);
+				let second = first + size(|n| n.visit_scope(body1));
+
+				self.branch(qs, qe, boolex.clone(), items(body1, &[position, &[0]].concat(), first), position, guard, exits);
+				self.branch(qs, qe, BooleanExpr::NotOperation(Box::new(boolex.clone())), items(body2, &[position, &[1]].concat(), second), position, guard, exits);
+			},
+			While(boolex, body) => {
+				self.branch(qs, qs, boolex.clone(), items(body, &[position, &[0]].concat(), scope(boolex)), position, guard, Some((qs, qe)));
+				self.add(qs, qe, not(boolex), position, guard);
+			},
+			Break => if let Some((_, exit)) = exits {
+				self.add(qs, exit, Action::Statement(stmt.clone()), position, id);
+			},
+			Continue => if let Some((head, _)) = exits {
+				self.add(qs, head, Action::Statement(stmt.clone()), position, id);
+			},
+			Scope(body) => self.edges(qs, qe, &items(body, &[position, &[0]].concat(), id.0 + 1), exits),
+			Call(procedure, arguments, results) => {
+				let (values, outputs, locals) = self.signatures[procedure].clone();
+				let call = self::Call { site: self.calls.len(), procedure: procedure.clone(), arguments: arguments.clone(), results: results.clone(), values, outputs, locals };
+
+				self.calls.push((qs, qe, call, position.to_vec(), id));
+			},
+			Par(first, second) => {
+				let second_id = id.0 + 1 + size(|n| n.visit_scope(first));
+				let first = self.component(items(first, &[position, &[0]].concat(), id.0 + 1));
+				let second = self.component(items(second, &[position, &[1]].concat(), second_id));
+
+				self.product(qs, qe, first, second);
+			},
+			_ => self.add(qs, qe, Action::Statement(stmt.clone()), position, id),
+		}
+	}
+
+	/// Program graph of a component of `par`, with the origins of its edges.
+	fn component(&self, items: Vec<Item>) -> (FlowGraph, HashMap<EdgeIndex, Origin>) {
+		let mut builder = Builder { graph: DiGraph::<(), Action>::new(), origins: HashMap::new(), signatures: HashMap::new(), calls: vec![] };
+		let start = builder.graph.add_node(());
+		let end = if items.is_empty() { start } else { builder.graph.add_node(()) };
+
+		builder.edges(start, end, &items, None);
+		((builder.graph, start, end), builder.origins)
+	}
+
+	/// Adds the product of the graphs of two components between `qs` and `qe`, every node pairing a node of each and every edge moving one of them.
+	fn product(&mut self, qs: NodeIndex, qe: NodeIndex, first: (FlowGraph, HashMap<EdgeIndex, Origin>), second: (FlowGraph, HashMap<EdgeIndex, Origin>)) {
+		let (((graph1, start1, end1), origins1), ((graph2, start2, end2), origins2)) = (first, second);
+		let mut nodes: HashMap<(NodeIndex, NodeIndex), NodeIndex> = vec![((start1, start2), qs), ((end1, end2), qe)].into_iter().collect();
+
+		for node1 in graph1.node_indices() {
+			for node2 in graph2.node_indices() {
+				let moves = graph1.edges(node1).map(|edge| (edge, (edge.target(), node2), &origins1))
+					.chain(graph2.edges(node2).map(|edge| (edge, (node1, edge.target()), &origins2)));
+
+				for (edge, target, origins) in moves {
+					let source = *nodes.entry((node1, node2)).or_insert_with(|| self.graph.add_node(()));
+					let target = *nodes.entry(target).or_insert_with(|| self.graph.add_node(()));
+					let (position, id) = &origins[&edge.id()];
+
+					self.add(source, target, edge.weight().clone(), position, *id);
+				}
+			}
+		}
+	}
+
+	/// Adds a guard edge from `qs` followed by the edges of the items of a branch to `qe`.
+	#[allow(clippy::too_many_arguments)]
+	fn branch(&mut self, qs: NodeIndex, qe: NodeIndex, guard: BooleanExpr, items: Vec<Item>, position: &[usize], id: NodeId, exits: Option<(NodeIndex, NodeIndex)>) {
+		let q = if items.is_empty() { qe } else { self.graph.add_node(()) };
+
+		self.add(qs, q, Action::Condition(guard), position, id);
+		self.edges(q, qe, &items, exits);
+	}
+}
+
+/// Variables declared by the declarations of a scope and its nested ones.
+#[derive(Default)]
+struct Locals(Vec<String>);
+
+impl Visitor for Locals {
+	fn visit_decl(&mut self, decl: &Declaration) {
+		self.0.extend(crate::analysis::variables(decl));
+	}
+}
+
+/// Names of the parameters and local variables of a procedure.
+fn signature(values: &[Declaration], results: &[Declaration], body: &Scope) -> Signature {
+	let names = |params: &[Declaration]| params.iter().flat_map(crate::analysis::variables).collect::<Vec<String>>();
+	let mut locals = Locals(names(values).into_iter().chain(names(results)).collect());
+
+	locals.visit_scope(body);
+	(names(values), names(results), locals.0)
+}
+
+/// Constructs the program graph of a program, with the origin in the AST of each edge.
+///
+/// Every procedure has its own initial and final nodes, following the nodes of the program, and each call statement gives a call edge to its initial node and a return edge from its final node.
+pub fn build(program: &Ast) -> (FlowGraph, HashMap<EdgeIndex, Origin>) {
+	let signatures = program.0.iter().filter_map(|decl| match decl {
+		Declaration::Procedure(id, values, results, body) => Some((id.clone(), signature(values, results, body))),
+		_ => None,
+	}).collect();
+	let mut builder = Builder { graph: DiGraph::<(), Action>::new(), origins: HashMap::new(), signatures, calls: vec![] };
+	let items = items(program, &[], 0);
+	let start = builder.graph.add_node(());
+	let end = if items.is_empty() { start } else { builder.graph.add_node(()) };
+
+	builder.edges(start, end, &items, None);
+
+	let mut procedures = HashMap::<String, (NodeIndex, NodeIndex)>::new();
+	let mut id = 0;
+
+	for (i, decl) in program.0.iter().enumerate() {
+		if let Declaration::Procedure(name, values, results, body) = decl {
+			// the body follows the procedure and its parameters in pre-order
+			let items = self::items(body, &[i, 0], id + 1 + values.len() + results.len());
+			let entry = builder.graph.add_node(());
+			let exit = if items.is_empty() { entry } else { builder.graph.add_node(()) };
+
+			builder.edges(entry, exit, &items, None);
+			procedures.insert(name.clone(), (entry, exit));
+		}
+
+		id += size(|n| n.visit_decl(decl));
+	}
+
+	for (qs, qe, call, position, id) in std::mem::take(&mut builder.calls) {
+		let (entry, exit) = procedures[&call.procedure];
+
+		builder.add(qs, entry, Action::Call(call.clone()), &position, id);
+		builder.add(exit, qe, Action::Return(call), &position, id);
+	}
+
+	((builder.graph, start, end), builder.origins)
+}
+
+/// Source spans of the edges, from the spans of the nodes of the AST they come from.
+pub fn spans(origins: &HashMap<EdgeIndex, Origin>, spans: &[Span]) -> HashMap<EdgeIndex, Span> {
+	origins.iter().filter_map(|(edge, (_, id))| spans.get(id.0).map(|span| (*edge, *span))).collect()
 }
 
-pub type FlowGraph = (DiGraph<Action, u32>, NodeIndex<u32>);
-/*
-fn edges<T: Declaration + Statement>(T: token) -> Action {
+/// Prefix locating an edge in the source, empty if its span is unknown.
+pub fn locate(spans: &HashMap<EdgeIndex, Span>, edge: EdgeIndex) -> String {
+	spans.get(&edge).map_or(String::new(), |span| format!("{span}: "))
 }
-*/
 
 /// Constructs the program graph for a program in MicroC
 pub fn flow(program: Ast) -> FlowGraph {
-	/*
-	match program {
-		(decls, stmts) if !decls.empty() && stms
-		Some(expr) => expr,
-		None => expr,
-	}
-	*/
-	(DiGraph::<Action, u32>::new(), NodeIndex::<u32>::new(0))
+	build(&program).0
+}
+
+/// Branch nodes each node is control dependent on, computed from the post-dominator tree.
+///
+/// Nodes that cannot reach the final node only depend on the branches leading directly to them.
+pub fn control_dependences(program: &FlowGraph) -> HashMap<NodeIndex, HashSet<NodeIndex>> {
+	let (graph, _, end) = program;
+	let post_dominators = simple_fast(Reversed(graph), *end);
+	let mut dependences: HashMap<NodeIndex, HashSet<NodeIndex>> = graph.node_indices().map(|node| (node, HashSet::new())).collect();
+
+	for branch in graph.node_indices().filter(|node| 1 < graph.neighbors(*node).count()) {
+		let stop = post_dominators.immediate_dominator(branch);
+
+		for successor in graph.neighbors(branch) {
+			let mut node = Some(successor);
+
+			while let Some(n) = node.filter(|n| Some(*n) != stop) {
+				dependences.get_mut(&n).unwrap().insert(branch);
+				node = post_dominators.immediate_dominator(n);
+			}
+		}
+	}
+
+	dependences
+}
+
+/// Targets of the back edges of the program graph, that is the heads of its loops.
+pub fn loop_heads(program: &FlowGraph) -> HashSet<NodeIndex> {
+	let (graph, start, _) = program;
+	let dominators = simple_fast(graph, *start);
+
+	graph.edge_indices().filter_map(|edge| {
+		let (source, target) = graph.edge_endpoints(edge).unwrap();
+
+		dominators.dominators(source).and_then(|mut iter| iter.find(|node| *node == target))
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Action, build};
+	use crate::{lexer::lex_str, microc::{decl::Declaration, expr::{ArithmeticExpr, BooleanExpr, LvalueExpr}, stmt::Statement, visit::{self, Visitor}}, parser::parse};
+
+	/// Source of every node, by id.
+	#[derive(Default)]
+	struct Nodes(Vec<String>);
+
+	impl Visitor for Nodes {
+		fn visit_decl(&mut self, decl: &Declaration) {
+			self.0.push(decl.to_string());
+			visit::walk_decl(self, decl);
+		}
+
+		fn visit_stmt(&mut self, stmt: &Statement) {
+			self.0.push(stmt.to_string());
+			visit::walk_stmt(self, stmt);
+		}
+
+		fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+			self.0.push(arex.to_string());
+			visit::walk_arex(self, arex);
+		}
+
+		fn visit_boolex(&mut self, boolex: &BooleanExpr) {
+			self.0.push(boolex.to_string());
+			visit::walk_boolex(self, boolex);
+		}
+
+		fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+			self.0.push(lvalue.to_string());
+			visit::walk_lvalue(self, lvalue);
+		}
+	}
+
+	#[test]
+	fn edges_come_from_their_nodes() {
+		let ast = parse(lex_str("{int a; int b;} r;\nint x;\nread x;\nwhile x > 0 {\n\t{\n\t\tint y;\n\t\ty := x;\n\t}\n\tif x == 2 { break; } else { r.a := x; }\n\tx -= 1;\n}").unwrap()).unwrap();
+		let (program, origins) = build(&ast);
+		let mut nodes = Nodes::default();
+
+		nodes.visit_scope(&ast);
+		assert_eq!(origins.len(), program.0.edge_count());
+
+		for edge in program.0.edge_indices() {
+			let (_, id) = &origins[&edge];
+			let node = &nodes.0[id.0];
+
+			match &program.0[edge] {
+				Action::Condition(BooleanExpr::NotOperation(guard)) if *node == guard.to_string() => (),
+				action => assert_eq!(*node, action.to_string()),
+			}
+		}
+	}
+
+	#[test]
+	fn procedures_have_their_own_nodes() {
+		let ast = parse(lex_str("int x;\nproc p(int a; int b) {\n\tb := a;\n}\ncall p(1; x);\ncall p(x; x);").unwrap()).unwrap();
+		let ((graph, start, end), origins) = build(&ast);
+		let edges: Vec<String> = graph.edge_indices().map(|edge| {
+			let (source, target) = graph.edge_endpoints(edge).unwrap();
+
+			format!("q{} -> q{} {} #{}", source.index(), target.index(), graph[edge], origins[&edge].1.0)
+		}).collect();
+
+		assert_eq!((start.index(), end.index()), (0, 1));
+		assert_eq!(edges, [
+			"q0 -> q2 int x; #0",
+			"q4 -> q5 b := a; #4",
+			"q2 -> q4 call p(1; x) #8",
+			"q5 -> q3 return p(1; x) #8",
+			"q3 -> q4 call p(x; x) #11",
+			"q5 -> q1 return p(x; x) #11",
+		]);
+	}
+
+	#[test]
+	fn par_interleaves_its_components() {
+		let ast = parse(lex_str("int x;\npar {\n\tx := 1;\n\tx := 2;\n} and {\n\tx := 3;\n}").unwrap()).unwrap();
+		let ((graph, _, end), origins) = build(&ast);
+		let edges: Vec<String> = graph.edge_indices().map(|edge| {
+			let (source, target) = graph.edge_endpoints(edge).unwrap();
+
+			format!("q{} -> q{} {} {:?}", source.index(), target.index(), graph[edge], origins[&edge].0)
+		}).collect();
+
+		assert_eq!(end.index(), 1);
+		assert_eq!(graph.node_count(), 7);
+		assert_eq!(edges, [
+			"q0 -> q2 int x; [0]",
+			"q2 -> q3 x := 1; [1, 0, 0]",
+			"q2 -> q4 x := 3; [1, 1, 0]",
+			"q4 -> q5 x := 1; [1, 0, 0]",
+			"q6 -> q1 x := 3; [1, 1, 0]",
+			"q3 -> q6 x := 2; [1, 0, 1]",
+			"q3 -> q5 x := 3; [1, 1, 0]",
+			"q5 -> q1 x := 2; [1, 0, 1]",
+		]);
+	}
 }
@@ -0,0 +1,542 @@
+//! Guarded Commands programs, lexed, parsed and turned into program graphs.
+
+use crate::flow_graph::{Action, FlowGraph};
+use crate::lexer::literal::IntegerLiteral;
+use crate::microc::{expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, ops::{ArithmeticOp, BinaryOp, RelationalOp}, stmt::Statement, visit::{Visitor, walk_lvalue}};
+use ast::{Command, GuardedCommand};
+use petgraph::graph::{DiGraph, NodeIndex};
+use std::collections::BTreeMap;
+
+/// Commands of the Guarded Commands language, whose expressions are the ones of MicroC.
+pub mod ast {
+	use crate::microc::expr::{ArithmeticExpr, BooleanExpr, LvalueExpr};
+
+	#[derive(Debug, Clone, PartialEq)]
+	/// Command of a Guarded Commands program.
+	pub enum Command {
+		/// Assignment to a variable or an array element.
+		Assign(LvalueExpr, ArithmeticExpr),
+		/// `skip`, doing nothing.
+		Skip,
+		/// Command that cannot proceed.
+		Abort,
+		/// Command followed by another one.
+		Sequence(Box<Command>, Box<Command>),
+		/// `if ... fi`, running a command whose guard holds, stuck if none does.
+		If(Vec<GuardedCommand>),
+		/// `do ... od`, running commands whose guards hold until none does.
+		Do(Vec<GuardedCommand>),
+	}
+
+	/// Command with the guard enabling it.
+	pub type GuardedCommand = (BooleanExpr, Command);
+}
+
+/// Tokens of the Guarded Commands language.
+pub mod lexer {
+	/// Symbols, longest first so that they are munched greedily.
+	const SYMBOLS: [&str; 24] = [":=", "->", "[]", "&&", "||", "!=", ">=", "<=", "+", "-", "*", "/", "^", "(", ")", "[", "]", ";", "=", "<", ">", "!", "&", "|"];
+
+	#[derive(Debug, Clone, PartialEq)]
+	/// Token of a Guarded Commands program.
+	pub enum Token {
+		/// Integer literal.
+		Number(isize),
+		/// Identifier or keyword.
+		Word(String),
+		/// Operator or punctuation.
+		Symbol(&'static str),
+	}
+
+	/// Token with its line and column.
+	pub type Lexeme = (Token, (usize, usize));
+
+	/// Lexes a program, `//` comments running to the end of the line.
+	pub fn lex(source: &str) -> Result<Vec<Lexeme>, String> {
+		let mut tokens = vec![];
+
+		for (line, text) in source.lines().enumerate() {
+			let chars: Vec<char> = text.split("//").next().unwrap().chars().collect();
+			let mut i = 0;
+
+			while i < chars.len() {
+				let at = (line + 1, i + 1);
+				let rest = &chars[i..];
+				let run = |accept: fn(&char) -> bool| rest.iter().take_while(|c| accept(c)).count();
+
+				if rest[0].is_whitespace() {
+					i += 1;
+				} else if rest[0].is_ascii_digit() {
+					let len = run(char::is_ascii_digit);
+					let digits: String = rest[..len].iter().collect();
+
+					tokens.push((Token::Number(digits.parse().map_err(|_| format!("{}:{}: Number '{digits}' is too large.", at.0, at.1))?), at));
+					i += len;
+				} else if rest[0].is_alphabetic() || rest[0] == '_' {
+					let len = run(|c| c.is_alphanumeric() || *c == '_');
+
+					tokens.push((Token::Word(rest[..len].iter().collect()), at));
+					i += len;
+				} else if let Some(symbol) = SYMBOLS.iter().find(|symbol| symbol.chars().enumerate().all(|(k, c)| rest.get(k) == Some(&c))) {
+					tokens.push((Token::Symbol(symbol), at));
+					i += symbol.len();
+				} else {
+					return Err(format!("{}:{}: Unexpected character '{}'.", at.0, at.1, rest[0]));
+				}
+			}
+		}
+
+		Ok(tokens)
+	}
+}
+
+use lexer::{Lexeme, Token};
+
+const KEYWORDS: [&str; 8] = ["if", "fi", "do", "od", "skip", "abort", "true", "false"];
+
+/// Recursive descent parser over the tokens, positions being kept for the error messages.
+struct Parser {
+	tokens: Vec<Lexeme>,
+	i: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.i).map(|(token, _)| token)
+	}
+
+	fn error(&self, expected: &str) -> String {
+		match self.tokens.get(self.i) {
+			Some((token, (line, column))) => format!("{line}:{column}: Expected {expected}, got '{}'.", match token {
+				Token::Number(n) => n.to_string(),
+				Token::Word(word) => word.clone(),
+				Token::Symbol(symbol) => symbol.to_string(),
+			}),
+			None => format!("Expected {expected}, got the end of input."),
+		}
+	}
+
+	/// Consumes the symbol or keyword if it comes next.
+	fn eat(&mut self, expected: &str) -> bool {
+		let found = match self.peek() {
+			Some(Token::Symbol(symbol)) => *symbol == expected,
+			Some(Token::Word(word)) => word == expected,
+			_ => false,
+		};
+
+		if found {
+			self.i += 1;
+		}
+
+		found
+	}
+
+	fn expect(&mut self, expected: &str) -> Result<(), String> {
+		if self.eat(expected) { Ok(()) } else { Err(self.error(&format!("'{expected}'"))) }
+	}
+
+	fn identifier(&mut self) -> Result<String, String> {
+		match self.peek() {
+			Some(Token::Word(word)) if !KEYWORDS.contains(&word.as_str()) => {
+				let word = word.clone();
+
+				self.i += 1;
+				Ok(word)
+			},
+			_ => Err(self.error("an identifier")),
+		}
+	}
+
+	fn lvalue(&mut self) -> Result<LvalueExpr, String> {
+		let id = self.identifier()?;
+
+		if self.eat("[") {
+			let index = self.arex()?;
+
+			self.expect("]")?;
+			Ok(LvalueExpr::ArrayIndex(id, vec![index]))
+		} else {
+			Ok(LvalueExpr::Variable(id))
+		}
+	}
+
+	/// Sequence of commands, `;` being right associative.
+	fn command(&mut self) -> Result<Command, String> {
+		let first = match self.peek() {
+			Some(Token::Word(word)) if word == "skip" => {
+				self.i += 1;
+				Command::Skip
+			},
+			Some(Token::Word(word)) if word == "abort" => {
+				self.i += 1;
+				Command::Abort
+			},
+			Some(Token::Word(word)) if word == "if" => {
+				self.i += 1;
+				let gcs = self.guarded()?;
+
+				self.expect("fi")?;
+				Command::If(gcs)
+			},
+			Some(Token::Word(word)) if word == "do" => {
+				self.i += 1;
+				let gcs = self.guarded()?;
+
+				self.expect("od")?;
+				Command::Do(gcs)
+			},
+			Some(Token::Word(_)) => {
+				let lvalue = self.lvalue()?;
+
+				self.expect(":=")?;
+				Command::Assign(lvalue, self.arex()?)
+			},
+			_ => return Err(self.error("a command")),
+		};
+
+		if self.eat(";") {
+			Ok(Command::Sequence(Box::new(first), Box::new(self.command()?)))
+		} else {
+			Ok(first)
+		}
+	}
+
+	fn guarded(&mut self) -> Result<Vec<GuardedCommand>, String> {
+		let mut gcs = vec![];
+
+		loop {
+			let guard = self.boolex()?;
+
+			self.expect("->")?;
+			gcs.push((guard, self.command()?));
+
+			if !self.eat("[]") {
+				return Ok(gcs);
+			}
+		}
+	}
+
+	/// Boolean expression, `|` binding looser than `&`, the short-circuit operators being read as the strict ones.
+	fn boolex(&mut self) -> Result<BooleanExpr, String> {
+		let mut boolex = self.conjunction()?;
+
+		while self.eat("|") || self.eat("||") {
+			boolex = BooleanExpr::BinaryOperation(Box::new(boolex), BinaryOp::BitOr, Box::new(self.conjunction()?));
+		}
+
+		Ok(boolex)
+	}
+
+	fn conjunction(&mut self) -> Result<BooleanExpr, String> {
+		let mut boolex = self.negation()?;
+
+		while self.eat("&") || self.eat("&&") {
+			boolex = BooleanExpr::BinaryOperation(Box::new(boolex), BinaryOp::BitAnd, Box::new(self.negation()?));
+		}
+
+		Ok(boolex)
+	}
+
+	fn negation(&mut self) -> Result<BooleanExpr, String> {
+		if self.eat("!") {
+			return Ok(BooleanExpr::NotOperation(Box::new(self.negation()?)));
+		}
+
+		if self.eat("true") {
+			return Ok(BooleanExpr::BooleanLiteral(true));
+		}
+
+		if self.eat("false") {
+			return Ok(BooleanExpr::BooleanLiteral(false));
+		}
+
+		// a parenthesis opens either a comparison or a boolean expression
+		let start = self.i;
+
+		match self.comparison() {
+			Ok(boolex) => Ok(boolex),
+			Err(e) if self.tokens.get(start).map(|(token, _)| token) == Some(&Token::Symbol("(")) => {
+				self.i = start + 1;
+
+				let boolex = self.boolex().map_err(|_| e)?;
+
+				self.expect(")")?;
+				Ok(boolex)
+			},
+			Err(e) => Err(e),
+		}
+	}
+
+	fn comparison(&mut self) -> Result<BooleanExpr, String> {
+		let arex1 = self.arex()?;
+		let op = match self.peek() {
+			Some(Token::Symbol("=")) => RelationalOp::Eq,
+			Some(Token::Symbol("!=")) => RelationalOp::Neq,
+			Some(Token::Symbol("<")) => RelationalOp::Lt,
+			Some(Token::Symbol("<=")) => RelationalOp::Leq,
+			Some(Token::Symbol(">")) => RelationalOp::Gt,
+			Some(Token::Symbol(">=")) => RelationalOp::Geq,
+			_ => return Err(self.error("a relational operator")),
+		};
+
+		self.i += 1;
+		Ok(BooleanExpr::RelationalOperation(arex1, op, self.arex()?))
+	}
+
+	fn arex(&mut self) -> Result<ArithmeticExpr, String> {
+		let mut arex = self.term()?;
+
+		loop {
+			let op = if self.eat("+") { ArithmeticOp::Add } else if self.eat("-") { ArithmeticOp::Sub } else { return Ok(arex) };
+
+			arex = ArithmeticExpr::ArithmeticOperation(Box::new((arex, op, self.term()?)));
+		}
+	}
+
+	fn term(&mut self) -> Result<ArithmeticExpr, String> {
+		let mut arex = self.unary()?;
+
+		loop {
+			let op = if self.eat("*") { ArithmeticOp::Mul } else if self.eat("/") { ArithmeticOp::Div } else { return Ok(arex) };
+
+			arex = ArithmeticExpr::ArithmeticOperation(Box::new((arex, op, self.unary()?)));
+		}
+	}
+
+	/// Negation, as the subtraction from zero the MicroC parser also produces.
+	fn unary(&mut self) -> Result<ArithmeticExpr, String> {
+		if self.eat("-") {
+			let zero = ArithmeticExpr::Literal(ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(0)));
+
+			Ok(ArithmeticExpr::ArithmeticOperation(Box::new((zero, ArithmeticOp::Sub, self.unary()?))))
+		} else {
+			self.power()
+		}
+	}
+
+	/// Exponentiation by a constant, expanded into products since MicroC has no power operator.
+	fn power(&mut self) -> Result<ArithmeticExpr, String> {
+		let base = self.atom()?;
+
+		if !self.eat("^") {
+			return Ok(base);
+		}
+
+		match self.unary()? {
+			ArithmeticExpr::Literal(ArithmeticLiteral::Int(n)) => {
+				let one = ArithmeticExpr::Literal(ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(1)));
+
+				Ok((1..isize::from(n)).fold(if isize::from(n) == 0 { one } else { base.clone() }, |product, _| {
+					ArithmeticExpr::ArithmeticOperation(Box::new((product, ArithmeticOp::Mul, base.clone())))
+				}))
+			},
+			exponent => Err(format!("Exponent `{exponent}` of `{base} ^ {exponent}` is not a natural number.")),
+		}
+	}
+
+	fn atom(&mut self) -> Result<ArithmeticExpr, String> {
+		match self.peek() {
+			Some(Token::Number(n)) => {
+				let n = *n;
+
+				self.i += 1;
+				Ok(ArithmeticExpr::Literal(ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(n))))
+			},
+			Some(Token::Symbol("(")) => {
+				self.i += 1;
+				let arex = self.arex()?;
+
+				self.expect(")")?;
+				Ok(arex)
+			},
+			Some(Token::Word(_)) => Ok(ArithmeticExpr::LvalueExpr(self.lvalue()?)),
+			_ => Err(self.error("an arithmetic expression")),
+		}
+	}
+}
+
+/// Parses a Guarded Commands program.
+pub fn parse(source: &str) -> Result<Command, String> {
+	let mut parser = Parser { tokens: lexer::lex(source)?, i: 0 };
+	let command = parser.command()?;
+
+	if parser.i < parser.tokens.len() {
+		return Err(parser.error("the end of the program"));
+	}
+
+	Ok(command)
+}
+
+/// Variables and arrays of a program, by name.
+#[derive(Default)]
+struct Inputs(BTreeMap<String, LvalueExpr>);
+
+impl Visitor for Inputs {
+	fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+		let input = match lvalue {
+			LvalueExpr::ArrayIndex(id, _) => (id.clone(), LvalueExpr::ArrayIndex(id.clone(), vec![ArithmeticExpr::Literal(ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(0)))])),
+			_ => (lvalue.to_string(), lvalue.clone()),
+		};
+
+		self.0.entry(input.0).or_insert(input.1);
+		walk_lvalue(self, lvalue);
+	}
+}
+
+impl Inputs {
+	fn command(&mut self, command: &Command) {
+		match command {
+			Command::Assign(lvalue, arex) => {
+				self.visit_lvalue(lvalue);
+				self.visit_arex(arex);
+			},
+			Command::Skip | Command::Abort => (),
+			Command::Sequence(c1, c2) => {
+				self.command(c1);
+				self.command(c2);
+			},
+			Command::If(gcs) | Command::Do(gcs) => gcs.iter().for_each(|(guard, command)| {
+				self.visit_boolex(guard);
+				self.command(command);
+			}),
+		}
+	}
+}
+
+struct Builder {
+	graph: DiGraph<(), Action>,
+	deterministic: bool,
+}
+
+impl Builder {
+	fn fresh(&mut self) -> NodeIndex {
+		self.graph.add_node(())
+	}
+
+	/// Adds the edges of a command from `qs` to `qe`, `skip` being the always enabled guard and `abort` having none.
+	fn edges(&mut self, qs: NodeIndex, qe: NodeIndex, command: &Command) {
+		match command {
+			Command::Assign(lvalue, arex) => {
+				self.graph.add_edge(qs, qe, Action::Statement(Statement::LvalueAssign(lvalue.clone(), arex.clone())));
+			},
+			Command::Skip => {
+				self.graph.add_edge(qs, qe, Action::Condition(BooleanExpr::BooleanLiteral(true)));
+			},
+			Command::Abort => (),
+			Command::Sequence(c1, c2) => {
+				let q = self.fresh();
+
+				self.edges(qs, q, c1);
+				self.edges(q, qe, c2);
+			},
+			Command::If(gcs) => {
+				self.guarded(qs, qe, gcs);
+			},
+			Command::Do(gcs) => {
+				let done = self.guarded(qs, qs, gcs);
+
+				self.graph.add_edge(qs, qe, Action::Condition(BooleanExpr::NotOperation(Box::new(done))));
+			},
+		}
+	}
+
+	/// Adds the edges of guarded commands, giving the disjunction of their guards.
+	///
+	/// In a deterministic graph, each guard also requires the previous ones not to hold.
+	fn guarded(&mut self, qs: NodeIndex, qe: NodeIndex, gcs: &[GuardedCommand]) -> BooleanExpr {
+		let mut done: Option<BooleanExpr> = None;
+
+		for (guard, command) in gcs {
+			let q = self.fresh();
+			let enabled = match &done {
+				Some(done) if self.deterministic => BooleanExpr::BinaryOperation(Box::new(guard.clone()), BinaryOp::BitAnd, Box::new(BooleanExpr::NotOperation(Box::new(done.clone())))),
+				_ => guard.clone(),
+			};
+
+			self.graph.add_edge(qs, q, Action::Condition(enabled));
+			self.edges(q, qe, command);
+
+			done = Some(match done {
+				Some(done) => BooleanExpr::BinaryOperation(Box::new(guard.clone()), BinaryOp::BitOr, Box::new(done)),
+				None => guard.clone(),
+			});
+		}
+
+		done.unwrap_or(BooleanExpr::BooleanLiteral(false))
+	}
+}
+
+/// Constructs the program graph of a Guarded Commands program, deterministic if asked, with its initial node `q0` and final node `q1`.
+///
+/// Variables start with arbitrary values, which the graph first reads into each of them, arrays being read weakly through their first element.
+pub fn flow(command: &Command, deterministic: bool) -> FlowGraph {
+	let mut builder = Builder { graph: DiGraph::new(), deterministic };
+	let start = builder.fresh();
+	let end = builder.fresh();
+	let mut inputs = Inputs::default();
+	let mut q = start;
+
+	inputs.command(command);
+
+	for lvalue in inputs.0.into_values() {
+		let next = builder.fresh();
+
+		builder.graph.add_edge(q, next, Action::Statement(Statement::Read(lvalue)));
+		q = next;
+	}
+
+	builder.edges(q, end, command);
+
+	(builder.graph, start, end)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{flow, parse};
+	use crate::analysis::analyze;
+	use petgraph::visit::EdgeRef;
+	use std::collections::HashMap;
+
+	fn edges(source: &str, deterministic: bool) -> Vec<String> {
+		let (graph, _, _) = flow(&parse(source).unwrap(), deterministic);
+
+		graph.edge_references().map(|edge| format!("q{} -> q{} {}", edge.source().index(), edge.target().index(), edge.weight())).collect()
+	}
+
+	#[test]
+	fn program_graphs() {
+		let source = "y := 1; // factorial\ndo x > 0 -> y := x * y; x := x - 1 od";
+
+		assert_eq!(edges(source, false), [
+			"q0 -> q2 read x;",
+			"q2 -> q3 read y;",
+			"q3 -> q4 y := 1;",
+			"q4 -> q5 x > 0",
+			"q5 -> q6 y := x * y;",
+			"q6 -> q4 x := x - 1;",
+			"q4 -> q1 !(x > 0)",
+		]);
+
+		let source = "if x >= y -> z := x [] y > x -> z := y fi; A[z] := -z ^ 2";
+		let reads = ["q0 -> q2 read A[0];", "q2 -> q3 read x;", "q3 -> q4 read y;", "q4 -> q5 read z;"];
+
+		assert_eq!(edges(source, false)[4..], ["q5 -> q7 x >= y", "q7 -> q6 z := x;", "q5 -> q8 y > x", "q8 -> q6 z := y;", "q6 -> q1 A[z] := 0 - z * z;"]);
+		assert_eq!(edges(source, true)[..4], reads);
+		assert_eq!(edges(source, true)[4..], ["q5 -> q7 x >= y", "q7 -> q6 z := x;", "q5 -> q8 y > x & !(x >= y)", "q8 -> q6 z := y;", "q6 -> q1 A[z] := 0 - z * z;"]);
+	}
+
+	#[test]
+	fn analyses_run_unchanged() {
+		let program = flow(&parse("x := 5; y := 1;\ndo x > 1 -> y := y * x; x := x - 1 [] x < 0 -> abort od").unwrap(), true);
+
+		assert!(analyze(program.clone(), "sa".to_string(), &HashMap::new(), &HashMap::new()).unwrap().contains("q1: {x: {0, +}, y: {+}}"));
+		assert!(analyze(program, "ia".to_string(), &HashMap::new(), &HashMap::new()).unwrap().contains("q1: {x: [0, 1], y: [-inf, +inf]}"));
+	}
+
+	#[test]
+	fn errors() {
+		assert_eq!(parse("if x > 0 -> skip od"), Err("1:18: Expected 'fi', got 'od'.".to_string()));
+		assert_eq!(parse("x := 1 $ 2"), Err("1:8: Unexpected character '$'.".to_string()));
+		assert!(parse("x := 2 ^ y").is_err());
+	}
+}
@@ -0,0 +1,508 @@
+//! Verification of the annotations of a program with weakest preconditions.
+//!
+//! The variables of the outermost scope are the inputs of the program, constrained by its `requires` annotations, and the
+//! parameters are the inputs of a procedure. A loop without invariant has the invariant `true`, and a call is replaced by
+//! the contract of its procedure. The verification conditions are decided in linear integer arithmetic.
+
+use crate::analysis::{location, variables};
+use crate::lexer::{Span, literal::IntegerLiteral};
+use crate::linear::{Encoder, Model, satisfiable};
+use crate::microc::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, fold::{self, Folder}, node::{NodeId, size}, ops::BinaryOp, stmt::{Annotation, Scope, Statement}, visit::{self, Visitor}};
+use crate::parser::Ast;
+use std::{collections::{BTreeSet, HashMap}, fmt::{self, Display, Formatter}};
+
+/// Annotation a verification condition proves, by its id, with where it must hold.
+#[derive(Debug, Clone, PartialEq)]
+struct Goal {
+	annotation: NodeId,
+	claim: String,
+}
+
+/// Conjuncts of a condition, each with the goal it proves.
+type Conjuncts = Vec<(Goal, BooleanExpr)>;
+
+/// Verdict on an annotation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+	/// Precondition without calls to check it at.
+	Assumed,
+	/// Holds for every run.
+	Proven,
+	/// Where the annotation does not hold, with the values of the variables of a counterexample.
+	Refuted(String, Model),
+	/// Why the verification conditions could not be decided.
+	Unknown(String),
+}
+
+impl Display for Verdict {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Verdict::Assumed => write!(f, "assumed"),
+			Verdict::Proven => write!(f, "proven"),
+			Verdict::Refuted(claim, model) if model.is_empty() => write!(f, "refuted {claim} for any values"),
+			Verdict::Refuted(claim, model) => write!(f, "refuted {claim} with {}", model.iter().map(|(var, value)| format!("{var} = {value}")).collect::<Vec<String>>().join(", ")),
+			Verdict::Unknown(reason) => write!(f, "unknown: {reason}"),
+		}
+	}
+}
+
+fn not(boolex: BooleanExpr) -> BooleanExpr {
+	BooleanExpr::NotOperation(Box::new(boolex))
+}
+
+fn and(boolex1: BooleanExpr, boolex2: BooleanExpr) -> BooleanExpr {
+	BooleanExpr::BinaryOperation(Box::new(boolex1), BinaryOp::BitAnd, Box::new(boolex2))
+}
+
+fn implies(boolex1: BooleanExpr, boolex2: BooleanExpr) -> BooleanExpr {
+	BooleanExpr::BinaryOperation(Box::new(not(boolex1)), BinaryOp::BitOr, Box::new(boolex2))
+}
+
+/// Conjunction of the conditions, `true` if there are none.
+fn conjunction(boolexs: impl IntoIterator<Item = BooleanExpr>) -> BooleanExpr {
+	boolexs.into_iter().reduce(and).unwrap_or(BooleanExpr::BooleanLiteral(true))
+}
+
+/// Replaces variables and record members by expressions, simultaneously.
+struct Substitution<'a>(&'a HashMap<String, ArithmeticExpr>);
+
+impl Folder for Substitution<'_> {
+	fn fold_arex(&mut self, arex: ArithmeticExpr) -> ArithmeticExpr {
+		if let ArithmeticExpr::LvalueExpr(lvalue @ (LvalueExpr::Variable(_) | LvalueExpr::RecordMember(_, _))) = &arex {
+			if let Some(replacement) = self.0.get(&location(lvalue)) {
+				return replacement.clone();
+			}
+		}
+
+		fold::walk_arex(self, arex)
+	}
+}
+
+fn substitute(boolex: &BooleanExpr, substitution: &HashMap<String, ArithmeticExpr>) -> BooleanExpr {
+	Substitution(substitution).fold_boolex(boolex.clone())
+}
+
+/// Variable named after the statement it gets a value at, which no variable of the program can be named.
+fn fresh(var: &str, id: usize) -> ArithmeticExpr {
+	ArithmeticExpr::LvalueExpr(LvalueExpr::Variable(format!("{var}@{id}")))
+}
+
+/// Ids of the declarations and of the statements of a scope starting at `id`.
+fn ids(scope: &Scope, mut id: usize) -> (Vec<usize>, Vec<usize>) {
+	let mut next = |n: usize| {
+		id += n;
+		id - n
+	};
+
+	(scope.0.iter().map(|decl| next(size(|n| n.visit_decl(decl)))).collect(), scope.1.iter().map(|stmt| next(size(|n| n.visit_stmt(stmt)))).collect())
+}
+
+/// Annotations of a kind leading or ending the statements of a scope starting at `id`, with their ids.
+fn annotations(scope: &Scope, id: usize, kind: Annotation) -> Vec<(NodeId, BooleanExpr)> {
+	let found = scope.1.iter().zip(ids(scope, id).1).filter_map(|(stmt, id)| match stmt {
+		Statement::Annotation(annotation, boolex) if *annotation == kind => Some((NodeId(id), boolex.clone())),
+		_ => None,
+	});
+
+	found.collect()
+}
+
+/// Outermost variables, records and arrays a scope assigns, `*` standing for assignments through pointers.
+#[derive(Default)]
+struct Assigned(BTreeSet<String>);
+
+impl Assigned {
+	fn lvalue(&mut self, lvalue: &LvalueExpr) {
+		self.0.insert(match lvalue {
+			LvalueExpr::Variable(id) | LvalueExpr::ArrayIndex(id, _) | LvalueExpr::RecordMember(id, _) => id.clone(),
+			LvalueExpr::Deref(_) => "*".to_string(),
+		});
+	}
+}
+
+impl Visitor for Assigned {
+	fn visit_stmt(&mut self, stmt: &Statement) {
+		match stmt {
+			Statement::LvalueAssign(lvalue, _) | Statement::Read(lvalue) => self.lvalue(lvalue),
+			Statement::RecordAssign(id, _) => {
+				self.0.insert(id.clone());
+			},
+			Statement::Call(_, _, lvalues) => lvalues.iter().for_each(|lvalue| self.lvalue(lvalue)),
+			_ => (),
+		}
+
+		visit::walk_stmt(self, stmt);
+	}
+}
+
+/// Names declared in a scope and its nested ones.
+#[derive(Default)]
+struct Declared(BTreeSet<String>, HashMap<String, Vec<String>>);
+
+impl Visitor for Declared {
+	fn visit_decl(&mut self, decl: &Declaration) {
+		match decl {
+			Declaration::Var(_, id) | Declaration::Array(_, _, id) | Declaration::Pointer(_, id) => {
+				self.0.insert(id.clone());
+			},
+			Declaration::Record(_, id) => {
+				self.0.insert(id.clone());
+				self.1.insert(id.clone(), variables(decl));
+			},
+			Declaration::Procedure(..) => visit::walk_decl(self, decl),
+		}
+	}
+}
+
+/// Parameters and annotations of a procedure, replacing its calls.
+struct Contract {
+	values: Vec<String>,
+	results: Vec<String>,
+	requires: Vec<(NodeId, BooleanExpr)>,
+	ensures: Vec<BooleanExpr>,
+	/// Value parameters the body assigns, whose final values the postconditions may refer to.
+	assigned: Vec<String>,
+	/// Whether the body assigns variables of the outermost scope, which the contract does not describe.
+	global: bool,
+}
+
+impl Contract {
+	fn new(id: usize, values: &[Declaration], results: &[Declaration], body: &Scope) -> Self {
+		let names = |params: &[Declaration]| params.iter().flat_map(variables).collect::<Vec<String>>();
+		let (mut assigned, mut declared) = (Assigned::default(), Declared::default());
+		let start = id + 1 + values.len() + results.len();
+
+		assigned.visit_scope(body);
+		declared.visit_scope(body);
+
+		let values = names(values);
+		let results = names(results);
+		let global = assigned.0.iter().any(|var| !values.contains(var) && !results.contains(var) && !declared.0.contains(var));
+
+		Contract {
+			assigned: values.iter().filter(|var| assigned.0.contains(*var)).cloned().collect(),
+			values,
+			results,
+			requires: annotations(body, start, Annotation::Requires),
+			ensures: annotations(body, start, Annotation::Ensures).into_iter().map(|(_, boolex)| boolex).collect(),
+			global,
+		}
+	}
+}
+
+/// Generator of the verification conditions of the program.
+struct Verifier {
+	contracts: HashMap<String, Contract>,
+	/// Members of every record, as variables.
+	records: HashMap<String, Vec<String>>,
+	/// Verification conditions of the loops and of the program and procedures.
+	conditions: Vec<(Goal, BooleanExpr)>,
+}
+
+impl Verifier {
+	/// Weakest precondition of a scope starting at `id`, whose declarations are not initialized if they are `inputs`.
+	fn scope(&mut self, scope: &Scope, id: usize, mut post: Conjuncts, inputs: bool) -> Result<Conjuncts, String> {
+		for (stmt, id) in scope.1.iter().zip(ids(scope, id).1).rev() {
+			post = self.stmt(stmt, id, post)?;
+		}
+
+		if !inputs {
+			for decl in scope.0.iter().rev() {
+				let zeros: HashMap<String, ArithmeticExpr> = match decl {
+					Declaration::Var(_, _) | Declaration::Record(_, _) => variables(decl).into_iter().map(|var| (var, ArithmeticExpr::Literal(ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(0))))).collect(),
+					_ => HashMap::new(),
+				};
+
+				post = post.into_iter().map(|(goal, q)| (goal, substitute(&q, &zeros))).collect();
+			}
+		}
+
+		Ok(post)
+	}
+
+	/// Location an lvalue assigns, only variables and record members being supported.
+	fn assignable(lvalue: &LvalueExpr) -> Result<String, String> {
+		match lvalue {
+			LvalueExpr::Variable(_) | LvalueExpr::RecordMember(_, _) => Ok(location(lvalue)),
+			_ => Err(format!("Assignments to `{lvalue}` are not supported.")),
+		}
+	}
+
+	/// Weakest precondition of a statement with id `id`.
+	fn stmt(&mut self, stmt: &Statement, id: usize, post: Conjuncts) -> Result<Conjuncts, String> {
+		use Statement::*;
+
+		let apply = |post: Conjuncts, substitution: HashMap<String, ArithmeticExpr>| post.into_iter().map(|(goal, q)| (goal, substitute(&q, &substitution))).collect();
+		let guarded = |boolex: &BooleanExpr, post: Conjuncts| post.into_iter().map(|(goal, q)| (goal, implies(boolex.clone(), q))).collect::<Conjuncts>();
+		// the guard follows the statement in pre-order, and precedes its scopes
+		let scope = |boolex: &BooleanExpr| id + 1 + size(|n| n.visit_boolex(boolex));
+
+		match stmt {
+			LvalueAssign(lvalue, arex) => Ok(apply(post, std::iter::once((Self::assignable(lvalue)?, arex.clone())).collect())),
+			RecordAssign(record, arexs) => Ok(apply(post, self.records.get(record).cloned().unwrap_or_default().into_iter().zip(arexs.iter().cloned()).collect())),
+			Read(lvalue) => {
+				let var = Self::assignable(lvalue)?;
+
+				Ok(apply(post, std::iter::once((var.clone(), fresh(&var, id))).collect()))
+			},
+			Write(_) | Annotation(_, _) => Ok(post),
+			// a failing assertion stops the program, so that only the runs in which it holds go on
+			Assert(boolex) | Assume(boolex) => Ok(guarded(boolex, post)),
+			If(boolex, body) => {
+				let then = self.scope(body, scope(boolex), post.clone(), false)?;
+
+				Ok([guarded(boolex, then), guarded(&not(boolex.clone()), post)].concat())
+			},
+			IfElse(boolex, body1, body2) => {
+				let second = scope(boolex) + size(|n| n.visit_scope(body1));
+				let then = self.scope(body1, scope(boolex), post.clone(), false)?;
+				let otherwise = self.scope(body2, second, post, false)?;
+
+				Ok([guarded(boolex, then), guarded(&not(boolex.clone()), otherwise)].concat())
+			},
+			While(boolex, body) => self.body(boolex, body, scope(boolex), post),
+			Scope(body) => self.scope(body, id + 1, post, false),
+			Call(procedure, arexs, lvalues) => self.call(stmt, procedure, arexs, lvalues, id, post),
+			Break | Continue => Err("'Break' and 'Continue' are not supported by the verifier.".to_string()),
+			Par(_, _) => Err("'Par' is not supported by the verifier.".to_string()),
+		}
+	}
+
+	/// Weakest precondition of a loop, the invariants leading its body starting at `id`, adding the conditions that they are preserved and imply `post` on exit.
+	fn body(&mut self, boolex: &BooleanExpr, body: &Scope, id: usize, post: Conjuncts) -> Result<Conjuncts, String> {
+		let invariants = annotations(body, id, Annotation::Invariant);
+		let invariant = conjunction(invariants.iter().map(|(_, boolex)| boolex.clone()));
+		let goals = |claim: &str| invariants.iter().map(|(id, boolex)| (Goal { annotation: *id, claim: claim.to_string() }, boolex.clone())).collect::<Conjuncts>();
+
+		for (goal, q) in self.scope(body, id, goals("after an iteration"), false)? {
+			self.conditions.push((goal, implies(and(invariant.clone(), boolex.clone()), q)));
+		}
+
+		for (goal, q) in post {
+			self.conditions.push((goal, implies(and(invariant.clone(), not(boolex.clone())), q)));
+		}
+
+		Ok(goals("on entry"))
+	}
+
+	/// Weakest precondition of a call, from the contract of its procedure.
+	fn call(&mut self, stmt: &Statement, procedure: &str, arexs: &[ArithmeticExpr], lvalues: &[LvalueExpr], id: usize, post: Conjuncts) -> Result<Conjuncts, String> {
+		let contract = &self.contracts[procedure];
+
+		if contract.global {
+			return Err(format!("Procedure {:?} assigns variables of the outermost scope, which its contract does not describe.", procedure));
+		}
+
+		let arguments: HashMap<String, ArithmeticExpr> = contract.values.iter().cloned().zip(arexs.iter().cloned()).collect();
+		let requires = contract.requires.iter().map(|(annotation, boolex)| (Goal { annotation: *annotation, claim: format!("at `{stmt}`") }, substitute(boolex, &arguments)));
+
+		// the postconditions refer to the final values of the parameters
+		let finals: HashMap<String, ArithmeticExpr> = contract.values.iter().map(|value| match contract.assigned.contains(value) {
+			true => (value.clone(), fresh(value, id)),
+			false => (value.clone(), arguments[value].clone()),
+		}).chain(contract.results.iter().map(|result| (result.clone(), fresh(result, id)))).collect();
+		let ensures = substitute(&conjunction(contract.ensures.iter().cloned()), &finals);
+		let results = lvalues.iter().zip(&contract.results).map(|(lvalue, result)| Ok((Self::assignable(lvalue)?, fresh(result, id)))).collect::<Result<HashMap<String, ArithmeticExpr>, String>>()?;
+
+		Ok(requires.chain(post.into_iter().map(|(goal, q)| (goal, implies(ensures.clone(), substitute(&q, &results))))).collect())
+	}
+
+	/// Adds the conditions that the body starting at `id` establishes its postconditions from its preconditions, its declarations being initialized unless they are `inputs`.
+	fn contract(&mut self, body: &Scope, id: usize, inputs: bool) -> Result<(), String> {
+		let requires = conjunction(annotations(body, id, Annotation::Requires).into_iter().map(|(_, boolex)| boolex));
+		let ensures = annotations(body, id, Annotation::Ensures).into_iter().map(|(id, boolex)| (Goal { annotation: id, claim: "at the end".to_string() }, boolex)).collect();
+
+		for (goal, q) in self.scope(body, id, ensures, inputs)? {
+			self.conditions.push((goal, implies(requires.clone(), q)));
+		}
+
+		Ok(())
+	}
+}
+
+/// Annotations of the program with their ids and the procedure they are in, if any.
+#[derive(Default)]
+struct Annotations {
+	count: usize,
+	procedure: Option<String>,
+	found: Vec<(NodeId, Statement, Option<String>)>,
+	/// Reads and calls by id, the statements fresh variables get their values at.
+	inputs: HashMap<usize, Statement>,
+}
+
+impl Visitor for Annotations {
+	fn visit_decl(&mut self, decl: &Declaration) {
+		self.count += 1;
+
+		if let Declaration::Procedure(id, _, _, _) = decl {
+			self.procedure = Some(id.clone());
+			visit::walk_decl(self, decl);
+			self.procedure = None;
+		} else {
+			visit::walk_decl(self, decl);
+		}
+	}
+
+	fn visit_stmt(&mut self, stmt: &Statement) {
+		match stmt {
+			Statement::Annotation(_, _) => self.found.push((NodeId(self.count), stmt.clone(), self.procedure.clone())),
+			Statement::Read(_) | Statement::Call(..) => {
+				self.inputs.insert(self.count, stmt.clone());
+			},
+			_ => (),
+		}
+
+		self.count += 1;
+		visit::walk_stmt(self, stmt);
+	}
+
+	fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+		self.count += 1;
+		visit::walk_arex(self, arex);
+	}
+
+	fn visit_boolex(&mut self, boolex: &BooleanExpr) {
+		self.count += 1;
+		visit::walk_boolex(self, boolex);
+	}
+
+	fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+		self.count += 1;
+		visit::walk_lvalue(self, lvalue);
+	}
+}
+
+/// Decides a verification condition: proven, or refuted with the claim it makes.
+fn decide(claim: &str, condition: &BooleanExpr) -> Verdict {
+	let mut encoder = Encoder::default();
+
+	match encoder.formula(condition, false).and_then(|negation| satisfiable(&encoder.defined(negation))) {
+		Ok(None) => Verdict::Proven,
+		Ok(Some(_)) if !encoder.opaque.is_empty() => Verdict::Unknown(format!(
+			"the counterexample {claim} may be spurious, {} being abstracted",
+			encoder.opaque.iter().map(|term| format!("`{term}`")).collect::<Vec<String>>().join(", "),
+		)),
+		Ok(Some(model)) => Verdict::Refuted(claim.to_string(), model.into_iter().filter(|(var, _)| !encoder.quotients.contains_key(var)).collect()),
+		Err(e) => Verdict::Unknown(e),
+	}
+}
+
+/// Verdict on every annotation of the program, with its id.
+pub fn verify(program: &Ast) -> Vec<(NodeId, Statement, Verdict)> {
+	let mut declared = Declared::default();
+	let mut verifier = Verifier { contracts: HashMap::new(), records: HashMap::new(), conditions: vec![] };
+	let mut failures = HashMap::<Option<String>, String>::new();
+	let (decls, _) = ids(program, 0);
+
+	declared.visit_scope(program);
+	verifier.records = declared.1;
+
+	for (decl, id) in program.0.iter().zip(&decls) {
+		if let Declaration::Procedure(name, values, results, body) = decl {
+			verifier.contracts.insert(name.clone(), Contract::new(*id, values, results, body));
+		}
+	}
+
+	for (decl, id) in program.0.iter().zip(&decls) {
+		if let Declaration::Procedure(name, values, results, body) = decl {
+			if let Err(e) = verifier.contract(body, id + 1 + values.len() + results.len(), false) {
+				failures.insert(Some(name.clone()), e);
+			}
+		}
+	}
+
+	// the procedures only are declarations of the outermost scope, whose variables are inputs
+	if let Err(e) = verifier.contract(program, 0, true) {
+		failures.insert(None, e);
+	}
+
+	let mut annotations = Annotations::default();
+
+	annotations.visit_scope(program);
+
+	// fresh variables are shown as the variable they stand for, after the statement giving it a value
+	let inputs = annotations.inputs;
+	let named = |model: Model| model.into_iter().map(|(var, value)| match var.split_once('@').and_then(|(name, id)| Some((name, inputs.get(&id.parse().ok()?)?))) {
+		Some((name, stmt)) => (format!("{name} after `{stmt}`"), value),
+		None => (var, value),
+	}).collect();
+
+	annotations.found.into_iter().map(|(id, stmt, procedure)| {
+		let verdicts = verifier.conditions.iter().filter(|(goal, _)| goal.annotation == id).map(|(goal, condition)| decide(&goal.claim, condition));
+		let verdict = match failures.get(&procedure) {
+			Some(e) => Verdict::Unknown(e.clone()),
+			None => verdicts.fold(None, |verdict, next| match (verdict, next) {
+				(Some(refuted @ Verdict::Refuted(..)), _) | (_, refuted @ Verdict::Refuted(..)) => Some(refuted),
+				(Some(unknown @ Verdict::Unknown(_)), _) | (_, unknown @ Verdict::Unknown(_)) => Some(unknown),
+				(_, next) => Some(next),
+			}).unwrap_or(match stmt {
+				Statement::Annotation(Annotation::Requires, _) => Verdict::Assumed,
+				_ => Verdict::Proven,
+			}),
+		};
+
+		let verdict = match verdict {
+			Verdict::Refuted(claim, model) => Verdict::Refuted(claim, named(model)),
+			verdict => verdict,
+		};
+
+		(id, stmt, verdict)
+	}).collect()
+}
+
+/// Formats the verdicts with the annotations they are on, located with the spans of the nodes.
+pub fn report(verdicts: &[(NodeId, Statement, Verdict)], spans: &[Span]) -> String {
+	verdicts.iter().map(|(id, stmt, verdict)| {
+		format!("{}`{stmt}` {verdict}", spans.get(id.0).map_or(String::new(), |span| format!("{span}: ")))
+	}).collect::<Vec<String>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Verdict, verify};
+	use crate::{lexer::lex_str, parser::parse};
+
+	fn verdicts(source: &str) -> Vec<(String, Verdict)> {
+		verify(&parse(lex_str(source).unwrap()).unwrap()).into_iter().map(|(_, stmt, verdict)| (stmt.to_string(), verdict)).collect()
+	}
+
+	#[test]
+	fn invariants_prove_the_loop() {
+		let source = "int n;\nint i;\nint s;\nrequires n >= 0;\ni := 0;\ns := 0;\nwhile i < n {\n\tinvariant 0 <= i && i <= n;\n\tinvariant s >= 0;\n\ts := s + i;\n\ti := i + 1;\n}\nensures i == n && s >= 0;";
+
+		assert!(verdicts(source).iter().all(|(stmt, verdict)| *verdict == if stmt.starts_with("requires") { Verdict::Assumed } else { Verdict::Proven }));
+	}
+
+	#[test]
+	fn refutations_have_counterexamples() {
+		let verdicts = verdicts("int x;\nint y;\nif x < 0 {\n\ty := 0 - x;\n} else {\n\ty := x;\n}\nensures y > 0;");
+
+		assert_eq!(verdicts[0].1, Verdict::Refuted("at the end".to_string(), vec![("x".to_string(), 0)].into_iter().collect()));
+	}
+
+	#[test]
+	fn calls_check_preconditions() {
+		let source = "proc half(int a; int b) {\n\trequires a % 2 == 0;\n\tb := a / 2;\n\tensures b + b == a;\n}\nint x;\nint y;\ncall half(4; x);\ncall half(x + 1; y);\nensures x == 2;";
+		let verdicts: Vec<String> = verdicts(source).into_iter().map(|(stmt, verdict)| format!("{stmt} {verdict}")).collect();
+
+		assert_eq!(verdicts, [
+			"requires a % 2 == 0; refuted at `call half(x + 1; y);` with b after `call half(4; x);` = 2",
+			"ensures b + b == a; proven",
+			"ensures x == 2; proven",
+		]);
+	}
+
+	#[test]
+	fn counterexamples_name_the_reads() {
+		let verdicts = verdicts("int x;\nint y;\nread x;\ny := x + 1;\nensures y > 0;");
+
+		assert_eq!(verdicts[0].1.to_string(), "refuted at the end with x after `read x;` = -1");
+	}
+
+	#[test]
+	fn unsupported_statements_leave_annotations_unknown() {
+		let verdicts = verdicts("int[2] a;\na[0] := 1;\nensures a[0] == 1;");
+
+		assert!(matches!(&verdicts[0].1, Verdict::Unknown(e) if e == "Assignments to `a[0]` are not supported."));
+	}
+}
@@ -0,0 +1,290 @@
+//! HTML report of a program with the states of its analyses, its graph and the findings of the checks.
+
+use crate::analysis::{MemoryDisplay, interval, reaching, sign};
+use crate::flow_graph::{self, FlowGraph, Origin, build};
+use crate::lexer::{Span, lex_trivia};
+use crate::parser::{error_message, parse_spans};
+use crate::{assertions, safety, security, taint};
+use petgraph::{Direction, graph::{EdgeIndex, NodeIndex}, visit::EdgeRef};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Horizontal and vertical distances between the nodes of the graph.
+const SPACING: (f64, f64) = (180.0, 90.0);
+const RADIUS: f64 = 18.0;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+h2 { font-size: 1.1em; margin-top: 2em; }
+.source { font-family: monospace; border: 1px solid #ccc; display: inline-block; min-width: 40em; }
+.line { position: relative; white-space: pre; padding: 0 .5em; }
+.line:hover { background: #eef; }
+.line.finding { background: #fee; }
+.number { display: inline-block; width: 3em; color: #999; user-select: none; }
+.state { display: none; position: absolute; left: 100%; top: 0; z-index: 1; margin-left: 1em; padding: .3em .6em; background: #ffd; border: 1px solid #cc9; white-space: pre; }
+.line:hover .state { display: block; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: .3em .6em; text-align: left; vertical-align: top; }
+td code { white-space: pre; }
+svg text { font-family: monospace; font-size: 11px; }
+svg .node circle { fill: #fff; stroke: #333; }
+svg .edge path { fill: none; stroke: #555; }
+svg .edge:hover path { stroke: #c00; stroke-width: 2; }
+";
+
+/// Escapes the text for HTML content and attributes.
+fn escape(text: &str) -> String {
+	text.chars().map(|c| match c {
+		'&' => "&amp;".to_string(),
+		'<' => "&lt;".to_string(),
+		'>' => "&gt;".to_string(),
+		'"' => "&quot;".to_string(),
+		'\'' => "&#39;".to_string(),
+		c => c.to_string(),
+	}).collect()
+}
+
+fn edge_name(program: &FlowGraph, edge: EdgeIndex) -> String {
+	let (source, target) = program.0.edge_endpoints(edge).unwrap();
+
+	format!("q{} -> q{}", source.index(), target.index())
+}
+
+/// Pattern of an analysis, with the formatted state before every node.
+type States = (String, HashMap<NodeIndex, String>);
+
+/// Location, edge, analysis and text of a finding.
+type Finding = (Option<Span>, EdgeIndex, &'static str, String);
+
+/// Abstract state before every node, as formatted by the analyses of their patterns.
+fn states(program: &FlowGraph, analyses: &[String]) -> Result<Vec<States>, String> {
+	analyses.iter().map(|analysis| {
+		let states = match analysis.as_str() {
+			"rd" => reaching::analyze(program).into_iter().map(|(node, definitions)| (node, reaching::display(program, &definitions))).collect(),
+			"sa" => sign::analyze(program).into_iter().map(|(node, memory)| (node, MemoryDisplay(&memory).to_string())).collect(),
+			"ia" => interval::analyze(program).into_iter().map(|(node, memory)| (node, MemoryDisplay(&memory).to_string())).collect(),
+			_ => return Err(format!("Unknown analysis '{analysis}', expected rd, sa or ia.")),
+		};
+
+		Ok((analysis.clone(), states))
+	}).collect()
+}
+
+/// Findings of the safety checks, the assertions not proven, the taint analysis and the security analysis with the classification of the source, in source order.
+fn findings(program: &FlowGraph, origins: &HashMap<EdgeIndex, Origin>, source: &str, spans: &HashMap<EdgeIndex, Span>) -> Result<Vec<Finding>, String> {
+	let safety = safety::check(program, origins).into_iter()
+		.filter(|diagnostic| diagnostic.safety != safety::Safety::Safe)
+		.map(|diagnostic| (diagnostic.edge, "safety", diagnostic.to_string()));
+	let asserts = assertions::check(program).into_iter()
+		.filter(|check| check.verdict != assertions::Verdict::Proven)
+		.map(|check| (check.edge, "asserts", check.to_string()));
+	let taint = taint::check(program, &[]).into_iter().map(|finding| (finding.edge, "taint", finding.to_string()));
+	let security = security::check(program, &security::embedded(source)?).into_iter().map(|leak| (leak.edge, "security", leak.to_string()));
+
+	let mut findings: Vec<Finding> = safety.chain(asserts).chain(taint).chain(security)
+		.map(|(edge, analysis, text)| (spans.get(&edge).copied(), edge, analysis, text))
+		.collect();
+
+	findings.sort_by_key(|(span, edge, _, _)| (span.map(|span| span.start), edge.index()));
+	Ok(findings)
+}
+
+/// Source lines, each showing on hover the states before the first statement or guard starting on it.
+fn listing(source: &str, program: &FlowGraph, spans: &HashMap<EdgeIndex, Span>, states: &[States], flagged: &HashSet<usize>) -> String {
+	let mut first = BTreeMap::<usize, (Span, NodeIndex)>::new();
+
+	for (edge, span) in spans {
+		let (node, _) = program.0.edge_endpoints(*edge).unwrap();
+		let entry = first.entry(span.start.0).or_insert((*span, node));
+
+		if span.start < entry.0.start {
+			*entry = (*span, node);
+		}
+	}
+
+	source.lines().enumerate().map(|(i, line)| {
+		let number = i + 1;
+		let state = first.get(&number).map(|(_, node)| {
+			let lines: Vec<String> = states.iter().map(|(analysis, states)| format!("{analysis}: {}", states[node])).collect();
+
+			format!("<span class=\"state\">q{}\n{}</span>", node.index(), escape(&lines.join("\n")))
+		}).unwrap_or_default();
+		let class = if flagged.contains(&number) { "line finding" } else { "line" };
+
+		format!("<div class=\"{class}\"><span class=\"number\">{number}</span>{}{state}</div>", escape(line))
+	}).collect::<Vec<String>>().join("\n")
+}
+
+/// Layer of every node, the length of the longest path from the initial node once the back edges are removed.
+fn layers(program: &FlowGraph) -> (HashMap<NodeIndex, usize>, HashSet<EdgeIndex>) {
+	let (graph, start, _) = program;
+	let mut back = HashSet::<EdgeIndex>::new();
+	let mut postorder = Vec::<NodeIndex>::new();
+	let mut visited = HashSet::<NodeIndex>::new();
+	// iterative depth-first search, a node being on the stack while its edges are explored
+	let mut stack = vec![(*start, graph.edges(*start).map(|edge| (edge.id(), edge.target())).collect::<Vec<_>>())];
+	let mut on_stack = HashSet::from([*start]);
+
+	visited.insert(*start);
+
+	while let Some((node, edges)) = stack.last_mut() {
+		match edges.pop() {
+			Some((edge, target)) if on_stack.contains(&target) => {
+				back.insert(edge);
+			},
+			Some((_, target)) if visited.insert(target) => {
+				on_stack.insert(target);
+				stack.push((target, graph.edges(target).map(|edge| (edge.id(), edge.target())).collect()));
+			},
+			Some(_) => (),
+			None => {
+				let node = *node;
+
+				on_stack.remove(&node);
+				postorder.push(node);
+				stack.pop();
+			},
+		}
+	}
+
+	let mut layers = HashMap::<NodeIndex, usize>::new();
+
+	for node in postorder.iter().rev() {
+		let layer = graph.edges_directed(*node, Direction::Incoming)
+			.filter(|edge| !back.contains(&edge.id()))
+			.filter_map(|edge| layers.get(&edge.source()).map(|layer| layer + 1))
+			.max()
+			.unwrap_or(0);
+
+		layers.insert(*node, layer);
+	}
+
+	// nodes that cannot be reached are laid out below the others
+	let below = layers.values().max().map_or(0, |layer| layer + 1);
+
+	for node in graph.node_indices() {
+		layers.entry(node).or_insert(below);
+	}
+
+	(layers, back)
+}
+
+/// Program graph as an SVG picture, the nodes laid out in layers from the initial one and the back edges curving on the right.
+fn svg(program: &FlowGraph) -> String {
+	let graph = &program.0;
+	let (layers, back) = layers(program);
+	let mut rows = BTreeMap::<usize, Vec<NodeIndex>>::new();
+
+	for node in graph.node_indices() {
+		rows.entry(layers[&node]).or_default().push(node);
+	}
+
+	let columns = rows.values().map(Vec::len).max().unwrap_or(1);
+	let width = columns as f64 * SPACING.0 + 2.0 * SPACING.0;
+	let height = rows.len() as f64 * SPACING.1 + SPACING.1;
+	let mut at = HashMap::<NodeIndex, (f64, f64)>::new();
+
+	for (layer, nodes) in &rows {
+		let offset = (width - nodes.len() as f64 * SPACING.0) / 2.0;
+
+		for (i, node) in nodes.iter().enumerate() {
+			at.insert(*node, (offset + (i as f64 + 0.5) * SPACING.0, (*layer as f64 + 0.5) * SPACING.1 + RADIUS));
+		}
+	}
+
+	let edges: Vec<String> = graph.edge_references().map(|edge| {
+		let ((x1, y1), (x2, y2)) = (at[&edge.source()], at[&edge.target()]);
+		let (path, label) = if back.contains(&edge.id()) {
+			let bend = RADIUS * 3.0 + (y1 - y2).abs() / 4.0;
+
+			(
+				format!("M {:.1} {:.1} C {:.1} {:.1}, {:.1} {:.1}, {:.1} {:.1}", x1 + RADIUS, y1, x1 + bend, y1, x2 + bend, y2, x2 + RADIUS, y2),
+				((x1 + x2) / 2.0 + bend * 0.75, (y1 + y2) / 2.0),
+			)
+		} else {
+			let (dx, dy) = (x2 - x1, y2 - y1);
+			let length = (dx * dx + dy * dy).sqrt().max(1.0);
+			let (ux, uy) = (dx / length * RADIUS, dy / length * RADIUS);
+
+			(format!("M {:.1} {:.1} L {:.1} {:.1}", x1 + ux, y1 + uy, x2 - ux, y2 - uy), ((x1 + x2) / 2.0 + 4.0, (y1 + y2) / 2.0))
+		};
+		let action = escape(&edge.weight().to_string());
+
+		format!(
+			"<g class=\"edge\"><title>q{} -> q{}: {action}</title><path d=\"{path}\" marker-end=\"url(#arrow)\"/><text x=\"{:.1}\" y=\"{:.1}\">{action}</text></g>",
+			edge.source().index(), edge.target().index(), label.0, label.1,
+		)
+	}).collect();
+
+	let nodes: Vec<String> = graph.node_indices().map(|node| {
+		let (x, y) = at[&node];
+
+		format!("<g class=\"node\"><circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"{RADIUS}\"/><text x=\"{x:.1}\" y=\"{:.1}\" text-anchor=\"middle\">q{}</text></g>", y + 4.0, node.index())
+	}).collect();
+
+	format!(
+		"<svg width=\"{width:.0}\" height=\"{height:.0}\" viewBox=\"0 0 {width:.0} {height:.0}\">\n\
+		<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" orient=\"auto\"><path d=\"M 0 0 L 10 5 L 0 10 z\"/></marker></defs>\n\
+		{}\n{}\n</svg>",
+		edges.join("\n"), nodes.join("\n"),
+	)
+}
+
+/// Self-contained HTML page of the program: its source with the states of the analyses of the patterns on hover, its program graph and the findings of the checks.
+pub fn report(title: &str, source: &str, analyses: &[String]) -> Result<String, String> {
+	let stream = lex_trivia(source)?;
+	let spans = stream.spans();
+	let (ast, spans) = parse_spans(&stream.tokens(), &spans).map_err(|e| error_message(&spans, e))?;
+	let (program, origins) = build(&ast);
+	let spans = flow_graph::spans(&origins, &spans);
+
+	let states = states(&program, analyses)?;
+	let findings = findings(&program, &origins, source, &spans)?;
+	let flagged: HashSet<usize> = findings.iter().filter_map(|(span, _, _, _)| span.map(|span| span.start.0)).collect();
+
+	let rows: Vec<String> = findings.iter().map(|(span, edge, analysis, text)| format!(
+		"<tr><td>{}</td><td>{}</td><td><code>{}</code></td><td>{analysis}</td><td>{}</td></tr>",
+		span.map(|span| span.to_string()).unwrap_or_default(),
+		edge_name(&program, *edge),
+		escape(&program.0[*edge].to_string()),
+		escape(text),
+	)).collect();
+	let table = if rows.is_empty() {
+		"<p>No findings.</p>".to_string()
+	} else {
+		format!("<table>\n<tr><th>Location</th><th>Edge</th><th>Action</th><th>Analysis</th><th>Finding</th></tr>\n{}\n</table>", rows.join("\n"))
+	};
+
+	Ok(format!(
+		"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+		<h1>{title}</h1>\n\
+		<h2>Source</h2>\n<p>Hovering a line shows the states before it: {}.</p>\n<div class=\"source\">\n{}\n</div>\n\
+		<h2>Program graph</h2>\n{}\n\
+		<h2>Findings</h2>\n{table}\n\
+		</body>\n</html>\n",
+		if analyses.is_empty() { "none chosen".to_string() } else { analyses.join(", ") },
+		listing(source, &program, &spans, &states, &flagged),
+		svg(&program),
+		title = escape(title),
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::report;
+
+	#[test]
+	fn self_contained_page() {
+		let source = "int x;\nint[3] a;\nread x;\nwhile x < 3 {\n\ta[x] := 6 / x;\n\tx += 1;\n}\n";
+		let page = report("<loop>", source, &["sa".to_string(), "ia".to_string()]).unwrap();
+
+		assert!(page.starts_with("<!DOCTYPE html>") && !page.contains("http") && !page.contains("<script"));
+		assert!(page.contains("<title>&lt;loop&gt;</title>"));
+		// the guard of the loop, escaped, with the states before it
+		assert!(page.contains("<span class=\"number\">4</span>while x &lt; 3 {<span class=\"state\">q4\nsa: {a: {-, 0, +}, x: {-, 0, +}}\nia: {a: [-6, 6], x: [-inf, +inf]}</span>"));
+		assert!(page.contains("<div class=\"line finding\"><span class=\"number\">5</span>"));
+		assert!(page.contains("<td>5:2</td><td>q5 -> q6</td><td><code>a[x] := 6 / x;</code></td><td>safety</td><td>possibly unsafe: division by `x` in `6 / x`"));
+		assert_eq!(page.matches("<g class=\"node\">").count(), 7);
+		assert!(report("", source, &["xx".to_string()]).is_err());
+	}
+}
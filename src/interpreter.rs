@@ -0,0 +1,490 @@
+//! Concrete interpreter of the program graph.
+
+use crate::analysis::{location, records};
+use crate::flow_graph::{Action, FlowGraph};
+use crate::lexer::keyword::Type;
+use crate::microc::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, ops::{ArithmeticOp, BinaryOp, RelationalOp}, stmt::Statement};
+use petgraph::{graph::{EdgeIndex, NodeIndex}, visit::EdgeRef};
+use std::{collections::{BTreeMap, HashMap}, convert::TryFrom, fmt::{self, Display, Formatter}};
+
+/// Number of edges taken before a run is considered not to terminate.
+pub const MAX_STEPS: usize = 1_000_000;
+
+/// Concrete value of a variable or an array element, pointers holding the variable they point to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	/// Integer.
+	Int(isize),
+	/// Float.
+	Float(f64),
+	/// Address of a variable.
+	Pointer(String),
+}
+
+impl Display for Value {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Value::Int(n) => write!(f, "{n}"),
+			Value::Float(x) => write!(f, "{:?}", x),
+			Value::Pointer(var) => write!(f, "&{var}"),
+		}
+	}
+}
+
+impl TryFrom<String> for Value {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		let text = value.trim();
+
+		if let Ok(n) = text.parse::<isize>() {
+			Ok(Value::Int(n))
+		} else if let Ok(x) = text.parse::<f64>() {
+			Ok(Value::Float(x))
+		} else {
+			Err(format!("Expected a number, got '{text}'."))
+		}
+	}
+}
+
+impl Value {
+	fn zero(t: Type) -> Self {
+		match t {
+			Type::Float => Value::Float(0.0),
+			Type::Int | Type::Bool => Value::Int(0),
+		}
+	}
+
+	/// Number of the value, `None` for pointers.
+	fn float(&self) -> Option<f64> {
+		match self {
+			Value::Int(n) => Some(*n as f64),
+			Value::Float(x) => Some(*x),
+			Value::Pointer(_) => None,
+		}
+	}
+}
+
+/// Concrete memory: variables and record members, and arrays as their dimensions and row-major elements.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct State {
+	/// Values of the variables and record members.
+	pub variables: BTreeMap<String, Value>,
+	/// Dimensions and elements of the arrays.
+	pub arrays: BTreeMap<String, (Vec<usize>, Vec<Value>)>,
+}
+
+impl Display for State {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let variables = self.variables.iter().map(|(var, value)| format!("{var}: {value}"));
+		let arrays = self.arrays.iter().map(|(id, (_, values))| format!("{id}: [{}]", values.iter().map(Value::to_string).collect::<Vec<String>>().join(", ")));
+
+		write!(f, "{{{}}}", variables.chain(arrays).collect::<Vec<String>>().join(", "))
+	}
+}
+
+/// Interpreter of the program graph, reading and writing values through its callbacks.
+struct Interpreter<'a, I, O> {
+	records: HashMap<String, Vec<String>>,
+	state: State,
+	/// Call site and state of the caller of every procedure running.
+	calls: Vec<(usize, State)>,
+	input: &'a mut I,
+	output: &'a mut O,
+}
+
+impl<I: FnMut(&str) -> Result<Value, String>, O: FnMut(Value)> Interpreter<'_, I, O> {
+	/// Declares the variables of a declaration, initialized to zero, record members being prefixed by `prefix`.
+	fn declare(&mut self, decl: &Declaration, prefix: &str) {
+		match decl {
+			Declaration::Var(t, id) => {
+				self.state.variables.insert(format!("{prefix}{id}"), Value::zero(*t));
+			},
+			// pointers start out pointing nowhere
+			Declaration::Pointer(_, id) => {
+				self.state.variables.insert(format!("{prefix}{id}"), Value::Int(0));
+			},
+			Declaration::Array(t, sizes, id) => {
+				let dims: Vec<usize> = sizes.iter().map(|size| usize::try_from(isize::from(*size)).unwrap_or(0)).collect();
+
+				self.state.arrays.insert(format!("{prefix}{id}"), (dims.clone(), vec![Value::zero(*t); dims.iter().product()]));
+			},
+			Declaration::Record(decls, id) => decls.iter().for_each(|member| self.declare(member, &format!("{prefix}{id}."))),
+			Declaration::Procedure(..) => (),
+		}
+	}
+
+	/// Row-major offset of an array element, checking its indexes against the dimensions.
+	fn offset(&mut self, lvalue: &LvalueExpr, id: &str, indexes: &[ArithmeticExpr]) -> Result<usize, String> {
+		let dims = self.state.arrays.get(id).ok_or(format!("Undeclared array '{id}'."))?.0.clone();
+
+		if dims.len() != indexes.len() {
+			return Err(format!("Array '{id}' has {} dimensions, `{lvalue}` indexes {}.", dims.len(), indexes.len()));
+		}
+
+		let mut offset = 0;
+
+		for (dim, (arex, size)) in indexes.iter().zip(dims).enumerate() {
+			let index = match self.arex(arex)? {
+				Value::Int(n) if 0 <= n && (n as usize) < size => n as usize,
+				value => return Err(format!("Index {value} out of bounds [0, {}] in dimension {dim} of `{lvalue}`.", size as isize - 1)),
+			};
+
+			offset = offset * size + index;
+		}
+
+		Ok(offset)
+	}
+
+	/// Variable a dereferenced pointer points to.
+	fn pointee(&self, lvalue: &LvalueExpr, pointer: &str) -> Result<String, String> {
+		match self.state.variables.get(pointer) {
+			Some(Value::Pointer(var)) => Ok(var.clone()),
+			Some(_) => Err(format!("`{lvalue}` does not point to a variable.")),
+			None => Err(format!("Undeclared variable '{pointer}'.")),
+		}
+	}
+
+	fn load(&mut self, lvalue: &LvalueExpr) -> Result<Value, String> {
+		match lvalue {
+			LvalueExpr::ArrayIndex(id, indexes) => {
+				let offset = self.offset(lvalue, id, indexes)?;
+
+				Ok(self.state.arrays[id].1[offset].clone())
+			},
+			LvalueExpr::Deref(pointer) => {
+				let var = self.pointee(lvalue, pointer)?;
+
+				self.state.variables.get(&var).cloned().ok_or(format!("Undeclared variable '{var}'."))
+			},
+			_ => self.state.variables.get(&location(lvalue)).cloned().ok_or(format!("Undeclared variable '{lvalue}'.")),
+		}
+	}
+
+	fn store(&mut self, lvalue: &LvalueExpr, value: Value) -> Result<(), String> {
+		match lvalue {
+			LvalueExpr::ArrayIndex(id, indexes) => {
+				let offset = self.offset(lvalue, id, indexes)?;
+
+				self.state.arrays.get_mut(id).unwrap().1[offset] = value;
+			},
+			LvalueExpr::Deref(pointer) => {
+				let var = self.pointee(lvalue, pointer)?;
+
+				match self.state.variables.get_mut(&var) {
+					Some(variable) => *variable = value,
+					None => return Err(format!("Undeclared variable '{var}'.")),
+				}
+			},
+			_ => match self.state.variables.get_mut(&location(lvalue)) {
+				Some(variable) => *variable = value,
+				None => return Err(format!("Undeclared variable '{lvalue}'.")),
+			},
+		}
+
+		Ok(())
+	}
+
+	fn arex(&mut self, arex: &ArithmeticExpr) -> Result<Value, String> {
+		match arex {
+			ArithmeticExpr::Literal(ArithmeticLiteral::Int(n)) => Ok(Value::Int(isize::from(*n))),
+			ArithmeticExpr::Literal(ArithmeticLiteral::Float(x)) => Ok(Value::Float(*x)),
+			ArithmeticExpr::LvalueExpr(lvalue) => self.load(lvalue),
+			ArithmeticExpr::Reference(lvalue) => Ok(Value::Pointer(location(lvalue))),
+			ArithmeticExpr::ArithmeticOperation(operation) => {
+				let (arex1, op, arex2) = &**operation;
+				let (a, b) = (self.arex(arex1)?, self.arex(arex2)?);
+
+				arithmetic(op, &a, &b).ok_or(format!("Invalid operation `{arex}` on {a} and {b}."))
+			},
+		}
+	}
+
+	fn boolex(&mut self, boolex: &BooleanExpr) -> Result<bool, String> {
+		use BooleanExpr::*;
+
+		match boolex {
+			BooleanLiteral(b) => Ok(*b),
+			NotOperation(boolex) => Ok(!self.boolex(boolex)?),
+			RelationalOperation(arex1, op, arex2) => {
+				let (a, b) = (self.arex(arex1)?, self.arex(arex2)?);
+
+				match (&a, &b) {
+					(Value::Int(a), Value::Int(b)) => Ok(compare(op, a, b)),
+					(Value::Pointer(_), Value::Pointer(_)) if matches!(op, RelationalOp::Eq | RelationalOp::Neq) => Ok(compare(op, &a.to_string(), &b.to_string())),
+					_ => match (a.float(), b.float()) {
+						(Some(x), Some(y)) => Ok(compare(op, x, y)),
+						_ => Err(format!("Invalid comparison `{boolex}` of {a} and {b}.")),
+					},
+				}
+			},
+			BinaryOperation(boolex1, op, boolex2) => {
+				let (a, b) = (self.boolex(boolex1)?, self.boolex(boolex2)?);
+
+				match op {
+					BinaryOp::BitAnd => Ok(a & b),
+					BinaryOp::BitOr => Ok(a | b),
+					BinaryOp::BitXor => Ok(a ^ b),
+					_ => Err(format!("Invalid boolean operator '{op}'.")),
+				}
+			},
+		}
+	}
+
+	/// Executes an action, telling whether its edge can be taken.
+	fn action(&mut self, action: &Action) -> Result<bool, String> {
+		match action {
+			Action::Declaration(decl) => self.declare(decl, ""),
+			Action::Condition(boolex) => return self.boolex(boolex),
+			Action::Statement(stmt) => match stmt {
+				Statement::LvalueAssign(lvalue, arex) => {
+					let value = self.arex(arex)?;
+
+					self.store(lvalue, value)?;
+				},
+				Statement::RecordAssign(id, arexs) => {
+					let members = self.records.get(id).cloned().ok_or(format!("Undeclared record '{id}'."))?;
+
+					if members.len() != arexs.len() {
+						return Err(format!("Record '{id}' has {} members, {} values given.", members.len(), arexs.len()));
+					}
+
+					for (member, arex) in members.iter().zip(arexs) {
+						let value = self.arex(arex)?;
+
+						self.state.variables.insert(member.clone(), value);
+					}
+				},
+				Statement::Read(lvalue) => {
+					let value = (self.input)(&lvalue.to_string())?;
+
+					self.store(lvalue, value)?;
+				},
+				Statement::Write(arex) => {
+					let value = self.arex(arex)?;
+
+					(self.output)(value);
+				},
+				Statement::Assert(boolex) if !self.boolex(boolex)? => return Err(format!("Assertion `{boolex}` failed.")),
+				// the run is not one the program is meant for
+				Statement::Assume(boolex) if !self.boolex(boolex)? => return Err(format!("Assumption `{boolex}` does not hold.")),
+				_ => (),
+			},
+			Action::Call(call) => {
+				let values = call.arguments.iter().map(|arex| self.arex(arex)).collect::<Result<Vec<Value>, String>>()?;
+
+				self.calls.push((call.site, self.state.clone()));
+				self.state.variables.extend(call.values.iter().cloned().zip(values));
+				self.state.variables.extend(call.outputs.iter().map(|output| (output.clone(), Value::Int(0))));
+			},
+			// only the return edge to the site of the innermost call is taken
+			Action::Return(call) => {
+				let caller = match self.calls.last() {
+					Some((site, _)) if *site == call.site => self.calls.pop().unwrap().1,
+					_ => return Ok(false),
+				};
+				let values: Vec<Value> = call.outputs.iter().map(|output| self.state.variables[output].clone()).collect();
+
+				for local in &call.locals {
+					match caller.variables.get(local) {
+						Some(value) => self.state.variables.insert(local.clone(), value.clone()),
+						None => self.state.variables.remove(local),
+					};
+
+					match caller.arrays.get(local) {
+						Some(array) => self.state.arrays.insert(local.clone(), array.clone()),
+						None => self.state.arrays.remove(local),
+					};
+				}
+
+				for (lvalue, value) in call.results.iter().zip(values) {
+					self.store(lvalue, value)?;
+				}
+			},
+		}
+
+		Ok(true)
+	}
+}
+
+/// Applies an arithmetic operator, integers staying integers, `None` on overflow, division by zero or pointers.
+fn arithmetic(op: &ArithmeticOp, a: &Value, b: &Value) -> Option<Value> {
+	use ArithmeticOp::*;
+
+	match (a, b) {
+		(&Value::Int(a), &Value::Int(b)) => match op {
+			Add => a.checked_add(b),
+			Sub | Neg => a.checked_sub(b),
+			Mul => a.checked_mul(b),
+			Div => a.checked_div(b),
+			Rem => a.checked_rem(b),
+		}.map(Value::Int),
+		_ => {
+			let (a, b) = (a.float()?, b.float()?);
+
+			Some(Value::Float(match op {
+				Add => a + b,
+				Sub | Neg => a - b,
+				Mul => a * b,
+				Div => a / b,
+				Rem => a % b,
+			}))
+		},
+	}
+}
+
+fn compare<T: PartialOrd>(op: &RelationalOp, a: T, b: T) -> bool {
+	use RelationalOp::*;
+
+	match op {
+		Lt => a < b,
+		Leq => a <= b,
+		Gt => a > b,
+		Geq => a >= b,
+		Eq => a == b,
+		Neq => a != b,
+	}
+}
+
+/// Runs the program from its initial node, `input` giving the value read into the lvalue it is passed and `output` receiving the written values.
+///
+/// Every node of a program graph has at most one enabled edge, so that a run stops at the final node, but for the nodes of `par` statements
+/// where the first enabled edge is taken: the first component runs until it is done or blocked by a guard.
+pub fn run(program: &FlowGraph, input: impl FnMut(&str) -> Result<Value, String>, output: impl FnMut(Value)) -> Result<State, String> {
+	trace(program, MAX_STEPS, input, output, |_| ())
+}
+
+/// Runs the program like [`run`] for at most `steps` edges, passing every edge taken to `taken`.
+pub fn trace(program: &FlowGraph, steps: usize, mut input: impl FnMut(&str) -> Result<Value, String>, mut output: impl FnMut(Value), mut taken: impl FnMut(EdgeIndex)) -> Result<State, String> {
+	let (graph, start, _) = program;
+	let mut interpreter = Interpreter { records: records(program), state: State::default(), calls: vec![], input: &mut input, output: &mut output };
+	let mut node = *start;
+
+	for _ in 0..steps {
+		let mut next = None;
+
+		for edge in graph.edges(node) {
+			if interpreter.action(edge.weight())? {
+				taken(edge.id());
+				next = Some(edge.target());
+				break;
+			}
+		}
+
+		match next {
+			Some(target) => node = target,
+			None => return Ok(interpreter.state),
+		}
+	}
+
+	Err(format!("No final state after {steps} steps, the program may not terminate."))
+}
+
+/// Configuration of a run between two edges: its node, its memory and the call sites and memories of the callers running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Configuration {
+	/// Node reached.
+	pub node: NodeIndex,
+	/// Memory of the run.
+	pub state: State,
+	/// Call site and memory of the caller of every procedure running, the innermost one last.
+	pub calls: Vec<(usize, State)>,
+}
+
+impl Configuration {
+	/// Configuration of the runs at the initial node, before any declaration.
+	pub fn initial(program: &FlowGraph) -> Self {
+		Configuration { node: program.1, state: State::default(), calls: vec![] }
+	}
+}
+
+/// Configurations reached from a configuration through the edges leaving its node that can be taken, with the errors of the failing ones.
+///
+/// Unlike a run, which takes the first enabled edge, every enabled edge is taken, and a `read` edge reads each of the `inputs` in turn.
+pub fn successors(program: &FlowGraph, configuration: &Configuration, inputs: &[isize]) -> Vec<(EdgeIndex, Result<Configuration, String>)> {
+	let records = records(program);
+	let mut successors = vec![];
+
+	for edge in program.0.edges(configuration.node) {
+		let values: Vec<Option<isize>> = match edge.weight() {
+			Action::Statement(Statement::Read(_)) => inputs.iter().copied().map(Some).collect(),
+			_ => vec![None],
+		};
+
+		for value in values {
+			let mut input = |lvalue: &str| value.map(Value::Int).ok_or(format!("No input for `{lvalue}`."));
+			let mut output = |_: Value| ();
+			let mut interpreter = Interpreter { records: records.clone(), state: configuration.state.clone(), calls: configuration.calls.clone(), input: &mut input, output: &mut output };
+
+			match interpreter.action(edge.weight()) {
+				Ok(true) => successors.push((edge.id(), Ok(Configuration { node: edge.target(), state: interpreter.state, calls: interpreter.calls }))),
+				Ok(false) => (),
+				Err(e) => successors.push((edge.id(), Err(e))),
+			}
+		}
+	}
+
+	successors
+}
+
+/// Evaluates a condition in a state.
+pub fn evaluate(state: &State, boolex: &BooleanExpr) -> Result<bool, String> {
+	let (mut input, mut output) = (|lvalue: &str| Err(format!("No input for `{lvalue}`.")), |_: Value| ());
+	let mut interpreter = Interpreter { records: HashMap::new(), state: state.clone(), calls: vec![], input: &mut input, output: &mut output };
+
+	interpreter.boolex(boolex)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Value, run};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+
+	fn execute(source: &str, inputs: &[isize]) -> Result<(Vec<Value>, String), String> {
+		let program = flow(parse(lex_str(source)?)?);
+		let mut inputs = inputs.iter();
+		let mut outputs = vec![];
+		let state = run(&program, |_| inputs.next().map(|n| Value::Int(*n)).ok_or("No more input.".to_string()), |value| outputs.push(value))?;
+
+		Ok((outputs, state.to_string()))
+	}
+
+	#[test]
+	fn factorial() {
+		let source = "int n;\nint a;\nread n;\na := 1;\nwhile n > 1 {\n\ta *= n;\n\tn -= 1;\n\tif n == 3 { continue; }\n}\nwrite a;";
+
+		assert_eq!(execute(source, &[5]), Ok((vec![Value::Int(120)], "{a: 120, n: 1}".to_string())));
+	}
+
+	#[test]
+	fn arrays_and_records() {
+		let source = "int[2, 3] m;\n{int x; float y;} r;\nint i;\nr := (4, 0.5);\nwhile true {\n\tif i == 6 { break; }\n\tm[i / 3, i % 3] := i * r.x;\n\ti += 1;\n}\nwrite m[1, 2] + r.y;";
+
+		assert_eq!(execute(source, &[]), Ok((vec![Value::Float(20.5)], "{i: 6, r.x: 4, r.y: 0.5, m: [0, 4, 8, 12, 16, 20]}".to_string())));
+	}
+
+	#[test]
+	fn runtime_errors() {
+		assert_eq!(execute("int[3] a;\nint i;\nread i;\na[i] := 1;", &[3]), Err("Index 3 out of bounds [0, 2] in dimension 0 of `a[i]`.".to_string()));
+		assert_eq!(execute("int x;\nwrite 1 / x;", &[]), Err("Invalid operation `1 / x` on 1 and 0.".to_string()));
+		assert_eq!(execute("int x;\nread x;", &[]), Err("No more input.".to_string()));
+		assert!(execute("while true { }", &[]).unwrap_err().starts_with("No final state"));
+		assert_eq!(execute("int x;\nread x;\nassert x > 0;", &[0]), Err("Assertion `x > 0` failed.".to_string()));
+		assert_eq!(execute("int x;\nread x;\nassume x > 0;", &[0]), Err("Assumption `x > 0` does not hold.".to_string()));
+	}
+
+	#[test]
+	fn procedures() {
+		let source = "int n;\nint calls;\nproc fib(int k; int f) {\n\tint a;\n\tcalls += 1;\n\tif k < 2 {\n\t\tf := k;\n\t} else {\n\t\tcall fib(k - 1; a);\n\t\tcall fib(k - 2; f);\n\t\tf += a;\n\t}\n}\nread n;\ncall fib(n; n);\nwrite n;";
+
+		assert_eq!(execute(source, &[10]), Ok((vec![Value::Int(55)], "{calls: 177, n: 55}".to_string())));
+	}
+
+	#[test]
+	fn pointers() {
+		let source = "int x;\nint y;\nint* p;\nread x;\nif x > 0 { p := &x; } else { p := &y; }\n*p := *p + 1;\nwrite x;";
+
+		assert_eq!(execute(source, &[2]), Ok((vec![Value::Int(3)], "{p: &x, x: 3, y: 0}".to_string())));
+		assert_eq!(execute("int* p;\n*p := 1;", &[]), Err("`*p` does not point to a variable.".to_string()));
+	}
+}
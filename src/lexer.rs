@@ -1,15 +1,25 @@
-use std::{convert::TryFrom, fs::File, io::{BufReader, BufRead}, path::Path, vec::Vec};
+//! Lexer of MicroC source into tokens, optionally keeping the whitespace and comments.
 
+use std::{convert::TryFrom, fmt::{self, Display, Formatter}, fs, io::BufRead, mem::take, path::Path, vec::Vec};
+
+/// Brackets, parentheses and curly brackets.
 pub mod delimiter {
 	use std::{convert::TryFrom, fmt::{self, Display, Formatter}};
 
 	#[derive(Debug, PartialEq)]
+	/// Delimiter of a block, an index or a parenthesized expression.
 	pub enum Delimiter {
+		/// `{`
 		OpenCurly,
+		/// `[`
 		OpenSquare,
+		/// `(`
 		OpenPar,
+		/// `}`
 		CloseCurly,
+		/// `]`
 		CloseSquare,
+		/// `)`
 		ClosePar,
 	}
 
@@ -47,13 +57,18 @@ pub mod delimiter {
 	}
 }
 
+/// Reserved words, including the type names.
 pub mod keyword {
 	use std::{convert::TryFrom, fmt::{self, Display, Formatter}};
 
 	#[derive(Debug, PartialEq, Clone, Copy)]
+	/// Type of the variables, array elements and pointed values.
 	pub enum Type {
+		/// `int`
 		Int,
+		/// `float`
 		Float,
+		/// `bool`
 		Bool,
 	}
 
@@ -85,16 +100,45 @@ pub mod keyword {
 	}
 
 	#[derive(Debug, PartialEq)]
+	/// Reserved word.
 	pub enum Keyword {
+		/// `and`
+		And,
+		/// `assert`
+		Assert,
+		/// `assume`
+		Assume,
+		/// `break`
 		Break,
+		/// `call`
+		Call,
+		/// `continue`
 		Continue,
+		/// `else`
 		Else,
+		/// `ensures`
+		Ensures,
+		/// `false`
 		False,
+		/// `if`
 		If,
+		/// `invariant`
+		Invariant,
+		/// `par`
+		Par,
+		/// `proc`
+		Proc,
+		/// `read`
 		Read,
+		/// `requires`
+		Requires,
+		/// `true`
 		True,
+		/// `while`
 		While,
+		/// `write`
 		Write,
+		/// Type name.
 		Type(Type),
 	}
 
@@ -102,13 +146,22 @@ pub mod keyword {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 			use Keyword::*;
 
-			match &*self {
+			match self {
+				And => write!(f, "and"),
+				Assert => write!(f, "assert"),
+				Assume => write!(f, "assume"),
 				Break => write!(f, "break"),
+				Call => write!(f, "call"),
 				Continue => write!(f, "continue"),
 				Else => write!(f, "else"),
+				Ensures => write!(f, "ensures"),
 				False => write!(f, "false"),
 				If => write!(f, "if"),
+				Invariant => write!(f, "invariant"),
+				Par => write!(f, "par"),
+				Proc => write!(f, "proc"),
 				Read => write!(f, "read"),
+				Requires => write!(f, "requires"),
 				True => write!(f, "true"),
 				While => write!(f, "while"),
 				Write => write!(f, "write"),
@@ -125,12 +178,21 @@ pub mod keyword {
 			use crate::lexer::keyword;
 
 			match value.as_str() {
+				"and" => Ok(And),
+				"assert" => Ok(Assert),
+				"assume" => Ok(Assume),
 				"break" => Ok(Break),
+				"call" => Ok(Call),
 				"continue" => Ok(Continue),
 				"else" => Ok(Else),
+				"ensures" => Ok(Ensures),
 				"false" => Ok(False),
 				"if" => Ok(If),
+				"invariant" => Ok(Invariant),
+				"par" => Ok(Par),
+				"proc" => Ok(Proc),
 				"read" => Ok(Read),
+				"requires" => Ok(Requires),
 				"true" => Ok(True),
 				"while" => Ok(While),
 				"write" => Ok(Write),
@@ -144,26 +206,38 @@ pub mod keyword {
 	}
 }
 
+/// Integer, float and boolean literals.
 pub mod literal {
 	use std::{convert::TryFrom, fmt::{self, Display, Formatter}};
 
-	#[derive(Debug, Clone, Copy)]
+	/// Integer literal with the base it is written in.
+	#[derive(Debug, Clone, Copy, PartialEq)]
 	pub enum IntegerLiteral {
+		/// Decimal literal, like `42`.
 		DecimalLiteral(isize),
+		/// Binary literal, like `0b101010`.
 		BinaryLiteral(isize),
+		/// Octal literal, like `0o52`.
 		OctalLiteral(isize),
+		/// Hexadecimal literal, like `0x2a`.
 		HexadecimalLiteral(isize),
+		/// Hexadecimal literal with uppercase digits, like `0x2A`.
+		UpperHexadecimalLiteral(isize),
 	}
 
 	impl Display for IntegerLiteral {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 			use IntegerLiteral::*;
 
+			// the sign is written apart, as radix formatting shows the two's complement
+			let sign = if isize::from(*self) < 0 { "-" } else { "" };
+
 			match *self {
 				DecimalLiteral(n) => write!(f, "{n}"),
-				BinaryLiteral(n) => write!(f, "{:#b}", n),
-				OctalLiteral(n) => write!(f, "{:#o}", n),
-				HexadecimalLiteral(n) => write!(f, "{:#x}", n),
+				BinaryLiteral(n) => write!(f, "{sign}{:#b}", n.unsigned_abs()),
+				OctalLiteral(n) => write!(f, "{sign}{:#o}", n.unsigned_abs()),
+				HexadecimalLiteral(n) => write!(f, "{sign}{:#x}", n.unsigned_abs()),
+				UpperHexadecimalLiteral(n) => write!(f, "{sign}{:#X}", n.unsigned_abs()),
 			}
 		}
 	}
@@ -176,12 +250,15 @@ pub mod literal {
 
 			match value.parse::<isize>() {
 				Ok(n)  => Ok(DecimalLiteral(n)),
-				Err(_) => if value.starts_with("0b") {
-					Ok(BinaryLiteral(isize::from_str_radix(&value[2..], 2).unwrap()))
-				} else if value.starts_with("0o") {
-					Ok(OctalLiteral(isize::from_str_radix(&value[2..], 8).unwrap()))
-				} else if value.starts_with("0x") {
-					Ok(HexadecimalLiteral(isize::from_str_radix(&value[2..], 16).unwrap()))
+				Err(_) => if let Some(digits) = value.strip_prefix("0b") {
+					isize::from_str_radix(digits, 2).map(BinaryLiteral).map_err(|e| format!("Invalid binary literal '{value}': {e}."))
+				} else if let Some(digits) = value.strip_prefix("0o") {
+					isize::from_str_radix(digits, 8).map(OctalLiteral).map_err(|e| format!("Invalid octal literal '{value}': {e}."))
+				} else if let Some(digits) = value.strip_prefix("0x") {
+					// mixed-case digits are written in lowercase
+					let literal = if digits.chars().any(|c| c.is_ascii_uppercase()) && !digits.chars().any(|c| c.is_ascii_lowercase()) { UpperHexadecimalLiteral } else { HexadecimalLiteral };
+
+					isize::from_str_radix(digits, 16).map(literal).map_err(|e| format!("Invalid hexadecimal literal '{value}': {e}."))
 				} else {
 					Err(format!("Unknown integer literal '{value}'."))
 				},
@@ -189,10 +266,24 @@ pub mod literal {
 		}
 	}
 
-	#[derive(Debug)]
+	impl From<IntegerLiteral> for isize {
+		fn from(value: IntegerLiteral) -> Self {
+			use IntegerLiteral::*;
+
+			match value {
+				DecimalLiteral(n) | BinaryLiteral(n) | OctalLiteral(n) | HexadecimalLiteral(n) | UpperHexadecimalLiteral(n) => n,
+			}
+		}
+	}
+
+	#[derive(Debug, PartialEq)]
+	/// Literal value.
 	pub enum Literal {
+		/// Integer literal.
 		IntegerLiteral(IntegerLiteral),
+		/// Float literal, like `1.5`.
 		FloatLiteral(f64),
+		/// `true` or `false`.
 		BooleanLiteral(bool),
 	}
 
@@ -200,7 +291,7 @@ pub mod literal {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 			use Literal::*;
 
-			match &*self {
+			match self {
 				IntegerLiteral(i) => write!(f, "{i}"),
 				FloatLiteral(_f) => write!(f, "{_f}"),
 				BooleanLiteral(b) => write!(f, "{b}"),
@@ -226,89 +317,127 @@ pub mod literal {
 	}
 }
 
+/// Operators and punctuation.
 pub mod symbol {
 	use std::{convert::TryFrom, fmt::{self, Display, Formatter}};
 
 	#[derive(Debug, PartialEq, Clone)]
+	/// Operator or punctuation.
 	pub enum Symbol {
-		Plus,			// Addition
-		Minus,			// Subtraction, Negation
-		Star,			// Multiplication
-		Slash,			// Division
-		Percent,		// Remainder
-		Caret,			// Bitwise and Logical XOR
-		Not,			// Bitwise and Logical NOT
-		And,			// Bitwise and Logical AND
-		Or,				// Bitwise and Logical OR
-		AndAnd,			// Lazy AND
-		OrOr,			// Lazy OR
-		Shl,			// Shift Left
-		Shr,			// Shift Right
-		PlusEq,			// Addition assignment
-		MinusEq,		// Subtraction assignment
-		StarEq,			// Multiplication assignment
-		SlashEq,		// Division assignment
-		PercentEq,		// Remainder assignment
-		CaretEq,		// Bitwise XOR assignment
-		AndEq,			// Bitwise And assignment
-		OrEq,			// Bitwise Or assignment
-		ShlEq,			// Shift Left assignment
-		ShrEq,			// Shift Right assignment
-		Eq,				// Assignment
-		EqEq,			// Equal
-		Ne,				// Not Equal
-		Gt,				// Greater than
-		Lt,				// Less than
-		Ge,				// Greater than or equal to
-		Le,				// Less than or equal to
-		Dot,			// Field access, Tuple index
-		Comma,			// Various separators
-		Semi,			// Terminator for various items and statements, Array types
-		Colon,			// Various separators
-		ColonEq,		// Variable assignment
+		/// `+`, addition.
+		Plus,
+		/// `-`, subtraction, negation.
+		Minus,
+		/// `*`, multiplication.
+		Star,
+		/// `/`, division.
+		Slash,
+		/// `%`, remainder.
+		Percent,
+		/// `^`, bitwise and logical XOR.
+		Caret,
+		/// `!`, bitwise and logical NOT.
+		Not,
+		/// `&`, bitwise and logical AND.
+		And,
+		/// `|`, bitwise and logical OR.
+		Or,
+		/// `&&`, lazy AND.
+		AndAnd,
+		/// `||`, lazy OR.
+		OrOr,
+		/// `<<`, shift left.
+		Shl,
+		/// `>>`, shift right.
+		Shr,
+		/// `+=`, addition assignment.
+		PlusEq,
+		/// `-=`, subtraction assignment.
+		MinusEq,
+		/// `*=`, multiplication assignment.
+		StarEq,
+		/// `/=`, division assignment.
+		SlashEq,
+		/// `%=`, remainder assignment.
+		PercentEq,
+		/// `^=`, bitwise XOR assignment.
+		CaretEq,
+		/// `&=`, bitwise AND assignment.
+		AndEq,
+		/// `|=`, bitwise OR assignment.
+		OrEq,
+		/// `<<=`, shift left assignment.
+		ShlEq,
+		/// `>>=`, shift right assignment.
+		ShrEq,
+		/// `=`, assignment.
+		Eq,
+		/// `==`, equal.
+		EqEq,
+		/// `!=`, not equal.
+		Ne,
+		/// `>`, greater than.
+		Gt,
+		/// `<`, less than.
+		Lt,
+		/// `>=`, greater than or equal to.
+		Ge,
+		/// `<=`, less than or equal to.
+		Le,
+		/// `.`, field access, tuple index.
+		Dot,
+		/// `,`, various separators.
+		Comma,
+		/// `;`, terminator for various items and statements, array types.
+		Semi,
+		/// `:`, various separators.
+		Colon,
+		/// `:=`, variable assignment.
+		ColonEq,
 	}
 
+	/// Spelling of every symbol, longest first so that the first match of a prefix is the maximal munch.
+	pub const TABLE: [(&str, Symbol); 35] = [
+		("<<=", Symbol::ShlEq),
+		(">>=", Symbol::ShrEq),
+		("&&", Symbol::AndAnd),
+		("||", Symbol::OrOr),
+		("<<", Symbol::Shl),
+		(">>", Symbol::Shr),
+		("+=", Symbol::PlusEq),
+		("-=", Symbol::MinusEq),
+		("*=", Symbol::StarEq),
+		("/=", Symbol::SlashEq),
+		("%=", Symbol::PercentEq),
+		("^=", Symbol::CaretEq),
+		("&=", Symbol::AndEq),
+		("|=", Symbol::OrEq),
+		("==", Symbol::EqEq),
+		("!=", Symbol::Ne),
+		(">=", Symbol::Ge),
+		("<=", Symbol::Le),
+		(":=", Symbol::ColonEq),
+		("+", Symbol::Plus),
+		("-", Symbol::Minus),
+		("*", Symbol::Star),
+		("/", Symbol::Slash),
+		("%", Symbol::Percent),
+		("^", Symbol::Caret),
+		("!", Symbol::Not),
+		("&", Symbol::And),
+		("|", Symbol::Or),
+		("=", Symbol::Eq),
+		(">", Symbol::Gt),
+		("<", Symbol::Lt),
+		(".", Symbol::Dot),
+		(",", Symbol::Comma),
+		(";", Symbol::Semi),
+		(":", Symbol::Colon),
+	];
+
 	impl Display for Symbol {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-			use Symbol::*;
-
-			match *self {
-				Plus => write!(f, "+"),
-				Minus => write!(f, "-"),
-				Star => write!(f, "*"),
-				Slash => write!(f, "/"),
-				Percent => write!(f, "%"),
-				Caret => write!(f, "^"),
-				Not => write!(f, "!"),
-				And => write!(f, "&"),
-				Or => write!(f, "|"),
-				AndAnd => write!(f, "&&"),
-				OrOr => write!(f, "||"),
-				Shl => write!(f, "<"),
-				Shr => write!(f, ">"),
-				PlusEq => write!(f, "+="),
-				MinusEq => write!(f, "-="),
-				StarEq => write!(f, "*="),
-				SlashEq => write!(f, "/="),
-				PercentEq => write!(f, "%="),
-				CaretEq => write!(f, "^="),
-				AndEq => write!(f, "&="),
-				OrEq => write!(f, "|="),
-				ShlEq => write!(f, "<="),
-				ShrEq => write!(f, ">="),
-				Eq => write!(f, "="),
-				EqEq => write!(f, "=="),
-				Ne => write!(f, "!="),
-				Gt => write!(f, ">"),
-				Lt => write!(f, "<"),
-				Ge => write!(f, ">="),
-				Le => write!(f, "<="),
-				Dot => write!(f, "."),
-				Comma => write!(f, ","),
-				Semi => write!(f, ";"),
-				Colon => write!(f, ":"),
-				ColonEq => write!(f, ":="),
-			}
+			write!(f, "{}", TABLE.iter().find(|(_, symbol)| symbol == self).unwrap().0)
 		}
 	}
 
@@ -316,249 +445,578 @@ pub mod symbol {
 		type Error = String;
 
 		fn try_from(value: String) -> Result<Self, Self::Error> {
-			use Symbol::*;
-
-			match value.as_str() {
-				"+" => Ok(Plus),
-				"-" => Ok(Minus),
-				"*" => Ok(Star),
-				"/" => Ok(Slash),
-				"%" => Ok(Percent),
-				"^" => Ok(Caret),
-				"!" => Ok(Not),
-				"&" => Ok(And),
-				"|" => Ok(Or),
-				"&&" => Ok(AndAnd),
-				"||" => Ok(OrOr),
-				"<<" => Ok(Shl),
-				">>" => Ok(Shr),
-				"+=" => Ok(PlusEq),
-				"-=" => Ok(MinusEq),
-				"*=" => Ok(StarEq),
-				"/=" => Ok(SlashEq),
-				"%=" => Ok(PercentEq),
-				"^=" => Ok(CaretEq),
-				"&=" => Ok(AndEq),
-				"|=" => Ok(OrEq),
-				"<<=" => Ok(ShlEq),
-				">>=" => Ok(ShrEq),
-				"=" => Ok(Eq),
-				"==" => Ok(EqEq),
-				"!=" => Ok(Ne),
-				">" => Ok(Gt),
-				"<" => Ok(Lt),
-				">=" => Ok(Ge),
-				"<=" => Ok(Le),
-				"." => Ok(Dot),
-				"," => Ok(Comma),
-				";" => Ok(Semi),
-				":" => Ok(Colon),
-				":=" => Ok(ColonEq),
-				_ => Err(format!("Unknown symbol '{value}'.")),
-			}
-		}
-	}
-
-	impl TryFrom<char> for Symbol {
-		type Error = String;
-
-		fn try_from(value: char) -> Result<Self, Self::Error> {
-			use Symbol::*;
-
-			match value {
-				'+' => Ok(Plus),
-				'-' => Ok(Minus),
-				'*' => Ok(Star),
-				'/' => Ok(Slash),
-				'%' => Ok(Percent),
-				'^' => Ok(Caret),
-				'!' => Ok(Not),
-				'&' => Ok(And),
-				'|' => Ok(Or),
-				'=' => Ok(Eq),
-				'>' => Ok(Gt),
-				'<' => Ok(Lt),
-				'.' => Ok(Dot),
-				',' => Ok(Comma),
-				';' => Ok(Semi),
-				':' => Ok(Colon),
-				_ => Err(format!("Unknown single-char symbol '{value}'.")),
+			match TABLE.iter().find(|(spelling, _)| *spelling == value) {
+				Some((_, symbol)) => Ok(symbol.clone()),
+				None => Err(format!("Unknown symbol '{value}'.")),
 			}
 		}
 	}
 
-	impl TryFrom<Vec<Symbol>> for Symbol {
-		type Error = String;
-
-		fn try_from(value: Vec<Symbol>) -> Result<Self, Self::Error> {
-			use Symbol::*;
-
-			if value.len() == 2 {
-				if value[0] == Gt && value[1] == Gt {
-					Ok(Shr)
-				} else if value[0] == Lt && value[1] == Lt {
-					Ok(Shl)
-				} else if value[1] == Eq {
-					match value[0] {
-						Plus => Ok(PlusEq),
-						Minus => Ok(MinusEq),
-						Star => Ok(StarEq),
-						Slash => Ok(SlashEq),
-						Percent => Ok(PercentEq),
-						Caret => Ok(CaretEq),
-						Not => Ok(Ne),
-						And => Ok(AndEq),
-						Or => Ok(OrEq),
-						Eq => Ok(EqEq),
-						Gt => Ok(Ge),
-						Lt => Ok(Le),
-						Colon => Ok(ColonEq),
-						_ => Err(format!("Unknown digraph start symbol '{:?}'.", value)),
-					}
-				} else {
-					Err(format!("Unknown digraph operator '{:?}'.", value))
-				}
-			} else if 3 == value.len() {
-				if value[0] == Gt && value[1] == Gt && value[2] == Eq {
-					Ok(ShlEq)
-				} else if value[0] == Lt && value[1] == Lt && value[2] == Eq {
-					Ok(ShrEq)
-				} else {
-					Err(format!("Unknown trigraph symbol '{:?}'.", value))
-				}
-			} else {
-				Err(format!("Unknown multigraph symbol '{:?}'.", value))
-			}
-		}
+	/// Longest symbol starting the characters, with its length.
+	pub fn munch(chars: &[char]) -> Option<(Symbol, usize)> {
+		TABLE.iter()
+			.find(|(spelling, _)| spelling.chars().count() <= chars.len() && spelling.chars().zip(chars).all(|(c1, c2)| c1 == *c2))
+			.map(|(spelling, symbol)| (symbol.clone(), spelling.len()))
 	}
 }
 
-#[derive(Debug)]
+/// Lexical unit of MicroC source.
+#[derive(Debug, PartialEq)]
 pub enum Token {
+	/// Bracket, parenthesis or curly bracket.
 	Delimiter(delimiter::Delimiter),
+	/// Name of a variable, array, record, member or procedure.
 	Identifier(String),
+	/// Reserved word.
 	Keyword(keyword::Keyword),
+	/// Literal value.
 	Literal(literal::Literal),
+	/// Operator or punctuation.
 	Symbol(symbol::Symbol),
 }
 
+/// Source of a MicroC file.
+fn read(path: &Path) -> Result<String, String> {
+	let bytes = fs::read(path).map_err(|e| format!("Cannot open '{}': {e}.", path.display()))?;
+
+	decode(bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Source held in the bytes, telling the line of the first invalid one.
+fn decode(bytes: Vec<u8>) -> Result<String, String> {
+	String::from_utf8(bytes).map_err(|e| {
+		let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+
+		format!("Line {} is not valid UTF-8.", valid.iter().filter(|b| **b == b'\n').count() + 1)
+	})
+}
+
+/// Lexes a MicroC file.
 pub fn lex(path: &Path) -> Result<Vec<Token>, String> {
+	lex_str(&read(path)?).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Lexes a MicroC file, with the span of every token.
+pub fn lex_spans(path: &Path) -> Result<(Vec<Token>, Vec<Span>), String> {
+	let stream = lex_trivia(&read(path)?).map_err(|e| format!("{}: {e}", path.display()))?;
+	let spans = stream.spans();
+
+	Ok((stream.tokens(), spans))
+}
+
+/// Lexes MicroC source held in memory.
+pub fn lex_str(source: &str) -> Result<Vec<Token>, String> {
+	lex_trivia(source).map(TokenStream::tokens)
+}
+
+/// Lexes MicroC source from any reader, line by line.
+pub fn lex_reader(mut reader: impl BufRead) -> Result<Vec<Token>, String> {
+	let mut lexer = Lexer::default();
 	let mut tokens = Vec::<Token>::new();
-	let reader = BufReader::new(File::open(path).unwrap());
-	let mut line_pos = 0;
-	let mut multiline_comment = false;
+	let mut line = Vec::<u8>::new();
+
+	while reader.read_until(b'\n', &mut line).map_err(|e| format!("Cannot read the source: {e}."))? != 0 {
+		let text = std::str::from_utf8(&line).map_err(|_| format!("Line {} is not valid UTF-8.", lexer.line + 1))?;
+
+		lexer.feed(text)?;
+		// only the trivia pending for the next token is kept
+		tokens.extend(take(&mut lexer.stream.lexemes).into_iter().map(|lexeme| lexeme.token));
+		line.clear();
+	}
+
+	tokens.extend(lexer.finish()?.tokens());
+	Ok(tokens)
+}
+
+/// Number of leading characters satisfying the predicate.
+fn span(chars: &[char], predicate: impl Fn(char) -> bool) -> usize {
+	chars.iter().take_while(|c| predicate(**c)).count()
+}
+
+/// Token starting the characters, with its length.
+fn token(rest: &[char]) -> Result<(Token, usize), String> {
+	// boolean literal, keyword, identifier
+	if rest[0].is_alphabetic() {
+		let len = span(rest, |c| c.is_alphanumeric() || c == '_');
+		let word: String = rest[..len].iter().collect();
+
+		let token = match keyword::Keyword::try_from(word.clone()) {
+			Ok(keyword::Keyword::True) => Token::Literal(literal::Literal::BooleanLiteral(true)),
+			Ok(keyword::Keyword::False) => Token::Literal(literal::Literal::BooleanLiteral(false)),
+			Ok(kw) => Token::Keyword(kw),
+			Err(_) => Token::Identifier(word),
+		};
+
+		Ok((token, len))
+	// int literal & float literal
+	} else if rest[0].is_ascii_digit() {
+		let mut len = span(rest, |c| c.is_alphanumeric());
+
+		// fractional part, a dot not followed by a digit being a symbol
+		if rest.get(len) == Some(&'.') && rest.get(len + 1).is_some_and(char::is_ascii_digit) {
+			len += 1 + span(&rest[len + 1..], |c| c.is_ascii_digit());
+		}
+
+		Ok((Token::Literal(literal::Literal::try_from(rest[..len].iter().collect::<String>())?), len))
+	} else if let Ok(d) = delimiter::Delimiter::try_from(rest[0]) {
+		Ok((Token::Delimiter(d), 1))
+	} else if let Some((s, len)) = symbol::munch(rest) {
+		Ok((Token::Symbol(s), len))
+	} else {
+		Err(format!("Unexpected character '{}'.", rest[0]))
+	}
+}
+
+/// Region of the source, from the line and column of its first character to the ones following its last, counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+	/// Line and column of the first character.
+	pub start: (usize, usize),
+	/// Line and column following the last character.
+	pub end: (usize, usize),
+}
+
+impl Display for Span {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}", self.start.0, self.start.1)
+	}
+}
+
+/// Source text that is not part of a token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trivia {
+	/// Spaces, tabs and line breaks.
+	Whitespace(String),
+	/// Comment from `//` to the end of the line, excluded.
+	LineComment(String),
+	/// Comment between `/*` and `*/`, included.
+	BlockComment(String),
+}
+
+impl Display for Trivia {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Trivia::*;
+
+		match self {
+			Whitespace(text) | LineComment(text) | BlockComment(text) => write!(f, "{text}"),
+		}
+	}
+}
+
+/// Token with its source text and the trivia preceding it.
+#[derive(Debug, PartialEq)]
+pub struct Lexeme {
+	/// Whitespace and comments preceding the token.
+	pub leading: Vec<Trivia>,
+	/// Token lexed.
+	pub token: Token,
+	/// Source text of the token.
+	pub text: String,
+}
+
+/// Lossless token stream, displaying as the source it was lexed from.
+#[derive(Debug, Default, PartialEq)]
+pub struct TokenStream {
+	/// Tokens, in source order.
+	pub lexemes: Vec<Lexeme>,
+	/// Trivia after the last token.
+	pub trailing: Vec<Trivia>,
+}
+
+impl TokenStream {
+	/// Tokens without their trivia, as given by `lex_str`.
+	pub fn tokens(self) -> Vec<Token> {
+		self.lexemes.into_iter().map(|lexeme| lexeme.token).collect()
+	}
+
+	/// Span of every token, in order.
+	pub fn spans(&self) -> Vec<Span> {
+		fn advance(at: &mut (usize, usize), text: &str) {
+			for c in text.chars() {
+				*at = if c == '\n' { (at.0 + 1, 1) } else { (at.0, at.1 + 1) };
+			}
+		}
+
+		let mut at = (1, 1);
+
+		self.lexemes.iter().map(|lexeme| {
+			lexeme.leading.iter().for_each(|trivia| advance(&mut at, &trivia.to_string()));
+
+			let start = at;
+			advance(&mut at, &lexeme.text);
 
-	for line in reader.lines().map(|l| l.unwrap()) {
-		let _line: Vec<char> = line.trim().chars().collect();
-		line_pos += 1;
+			Span { start, end: at }
+		}).collect()
+	}
+}
+
+impl Display for TokenStream {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for lexeme in &self.lexemes {
+			for trivia in &lexeme.leading {
+				write!(f, "{trivia}")?;
+			}
+
+			write!(f, "{}", lexeme.text)?;
+		}
+
+		self.trailing.iter().try_for_each(|trivia| write!(f, "{trivia}"))
+	}
+}
+
+/// Lexer fed the source a line at a time, no token spanning several lines.
+#[derive(Default)]
+struct Lexer {
+	/// Lexemes so far, the trivia after the last one being pending in `trailing` until a token claims it.
+	stream: TokenStream,
+	/// Number of lines fed.
+	line: usize,
+	/// Text and position of a block comment left open by the previous lines.
+	comment: Option<(String, (usize, usize))>,
+}
+
+impl Lexer {
+	/// Trivia pending for the next token, whitespace running over several lines being a single one.
+	fn trivia(&mut self, trivia: Trivia) {
+		match (self.stream.trailing.last_mut(), trivia) {
+			(Some(Trivia::Whitespace(previous)), Trivia::Whitespace(text)) => previous.push_str(&text),
+			(_, trivia) => self.stream.trailing.push(trivia),
+		}
+	}
+
+	/// Lexes the next line, with its line break if any.
+	fn feed(&mut self, line: &str) -> Result<(), String> {
+		let chars: Vec<char> = line.chars().collect();
 		let mut i = 0;
 
-		// multiline comment
-		if multiline_comment {
-			while i < _line.len() {
-				if i < _line.len() - 1 && _line[i] == '*' && _line[i + 1] == '/' {
-					multiline_comment = false;
-					i += 2;
-					break;
-				}
-				i += 1;
+		self.line += 1;
+
+		if let Some((mut comment, at)) = self.comment.take() {
+			match chars.windows(2).position(|w| w == ['*', '/']) {
+				Some(end) => {
+					comment.extend(&chars[..end + 2]);
+					self.trivia(Trivia::BlockComment(comment));
+					i = end + 2;
+				},
+				None => {
+					comment.push_str(line);
+					self.comment = Some((comment, at));
+					return Ok(());
+				},
 			}
 		}
 
-		if !multiline_comment {
-			while i < _line.len() {
-				// boolean literal, keyword, identifier
-				if _line[i].is_alphabetic() {
-					for ii in i.._line.len() {
-						if !(_line[ii].is_alphanumeric() || _line[ii] == '_') {
-							let buff: String = _line[i..ii].iter().collect();
-
-							if let Ok(kw) = keyword::Keyword::try_from(buff.clone()) {
-								if kw == keyword::Keyword::True || kw == keyword::Keyword::False {
-									tokens.push(Token::Literal(literal::Literal::try_from(kw.to_string())?));
-								} else {
-									tokens.push(Token::Keyword(kw));
-								}
-							} else {
-								tokens.push(Token::Identifier(buff.clone()));
-							}
-
-							i = ii - 1;
-							break;
-						}
-					}
-				// int literal & float literal
-				} else if _line[i].is_numeric() {
-					for ii in i.._line.len() {
-						if !_line[ii].is_alphanumeric() {
-							tokens.push(Token::Literal(literal::Literal::try_from(_line[i..ii].iter().collect::<String>())?));
-							i = ii - 1;
-							break;
-						}
-					}
-				// symbols
-				} else if let Ok(s) = symbol::Symbol::try_from(_line[i]) {
-					// comments
-					if s == symbol::Symbol::Slash {
-						if _line[i + 1] == '/' {
-							break;
-						} else if _line[i + 1] == '*' {
-							multiline_comment = true;
-							i += 1;
-						}
-					// punctuation
-					} else if s == symbol::Symbol::Dot || s == symbol::Symbol::Comma || s == symbol::Symbol::Semi {
-						tokens.push(Token::Symbol(s));
-					// multigraphs and simple ops
-					} else {
-						let mut buff = Vec::<symbol::Symbol>::new();
-						buff.push(s);
-
-						for ii in (i + 1).._line.len() {
-							if let Ok(o) = symbol::Symbol::try_from(_line[ii]) {
-								buff.push(o);
-							} else {
-								i = ii - 1;
-								break;
-							}
-						}
-
-						while !buff.is_empty() {
-							match buff.len() {
-								1 => tokens.push(Token::Symbol(buff.remove(0))),
-								2 => if let Ok(d) = symbol::Symbol::try_from(buff[0..=1].to_vec()) {
-									buff.drain(0..=1);
-									tokens.push(Token::Symbol(d))
-								} else {
-									tokens.push(Token::Symbol(buff.remove(0)))
-								},
-								_ => if let Ok(t) = symbol::Symbol::try_from(buff[0..=2].to_vec()) {
-									buff.drain(0..=2);
-									tokens.push(Token::Symbol(t))
-								} else if let Ok(d) = symbol::Symbol::try_from(buff[0..=1].to_vec()) {
-									buff.drain(0..=1);
-									tokens.push(Token::Symbol(d))
-								} else {
-									tokens.push(Token::Symbol(buff.remove(0)))
-								},
-							}
-						}
-					}
-				// delimiter
-				} else if let Ok(d) = delimiter::Delimiter::try_from(_line[i]) {
-					tokens.push(Token::Delimiter(d))
+		while i < chars.len() {
+			let rest = &chars[i..];
+			let text = |len: usize| rest[..len].iter().collect::<String>();
+			let at = (self.line, i + 1);
+
+			let len = if rest[0].is_whitespace() {
+				let len = span(rest, char::is_whitespace);
+
+				self.trivia(Trivia::Whitespace(text(len)));
+				len
+			} else if rest.starts_with(&['/', '/']) {
+				let len = span(rest, |c| c != '\n' && c != '\r');
+
+				self.trivia(Trivia::LineComment(text(len)));
+				len
+			} else if rest.starts_with(&['/', '*']) {
+				match rest[2..].windows(2).position(|w| w == ['*', '/']) {
+					Some(end) => {
+						self.trivia(Trivia::BlockComment(text(end + 4)));
+						end + 4
+					},
+					None => {
+						self.comment = Some((text(rest.len()), at));
+						rest.len()
+					},
 				}
+			} else {
+				let (token, len) = token(rest).map_err(|e| format!("{}:{}: {e}", at.0, at.1))?;
 
-				i += 1;
-			}
+				self.stream.lexemes.push(Lexeme { leading: take(&mut self.stream.trailing), token, text: text(len) });
+				len
+			};
+
+			i += len;
 		}
+
+		Ok(())
 	}
 
-	/*
-	for token in &tokens {
-		println!("{:?}", token);
+	/// Stream of the source fed, unless a block comment is left open.
+	fn finish(self) -> Result<TokenStream, String> {
+		match self.comment {
+			Some((_, (line, column))) => Err(format!("{line}:{column}: Unterminated block comment.")),
+			None => Ok(self.stream),
+		}
 	}
-	*/
+}
 
-	Ok(tokens)
-}
\ No newline at end of file
+/// Lexes MicroC source keeping whitespace and comments, such that the stream displays as the source.
+pub fn lex_trivia(source: &str) -> Result<TokenStream, String> {
+	let mut lexer = Lexer::default();
+
+	source.split_inclusive('\n').try_for_each(|line| lexer.feed(line))?;
+	lexer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Span, Token, Trivia, delimiter::Delimiter, keyword::{Keyword, Type}, lex, lex_reader, lex_spans, lex_str, lex_trivia, literal::{IntegerLiteral, Literal}, symbol::{Symbol, TABLE}};
+
+	fn symbols(source: &str) -> Vec<Symbol> {
+		lex_str(source).unwrap().into_iter().map(|token| match token {
+			Token::Symbol(s) => s,
+			t => panic!("expected a symbol, got {:?}", t),
+		}).collect()
+	}
+
+	#[test]
+	fn every_symbol() {
+		for (spelling, symbol) in TABLE.iter() {
+			assert_eq!(symbols(spelling), vec![symbol.clone()], "lexing '{spelling}'");
+			assert_eq!(symbol.to_string(), *spelling);
+		}
+	}
+
+	#[test]
+	fn shifts() {
+		assert_eq!(symbols("<< >> <<= >>="), vec![Symbol::Shl, Symbol::Shr, Symbol::ShlEq, Symbol::ShrEq]);
+		assert_eq!(Symbol::Shl.to_string(), "<<");
+		assert_eq!(Symbol::Shr.to_string(), ">>");
+	}
+
+	#[test]
+	fn maximal_munch() {
+		assert_eq!(symbols("<<=="), vec![Symbol::ShlEq, Symbol::Eq]);
+		assert_eq!(symbols("&&&"), vec![Symbol::AndAnd, Symbol::And]);
+		assert_eq!(symbols(":=-"), vec![Symbol::ColonEq, Symbol::Minus]);
+		assert_eq!(symbols("!=="), vec![Symbol::Ne, Symbol::Eq]);
+		assert_eq!(symbols("<-"), vec![Symbol::Lt, Symbol::Minus]);
+	}
+
+	#[test]
+	fn every_delimiter() {
+		assert_eq!(lex_str("{[()]}").unwrap(), vec![
+			Token::Delimiter(Delimiter::OpenCurly),
+			Token::Delimiter(Delimiter::OpenSquare),
+			Token::Delimiter(Delimiter::OpenPar),
+			Token::Delimiter(Delimiter::ClosePar),
+			Token::Delimiter(Delimiter::CloseSquare),
+			Token::Delimiter(Delimiter::CloseCurly),
+		]);
+	}
+
+	#[test]
+	fn every_keyword() {
+		let source = "break continue else if read while write int float bool proc call par and requires ensures invariant assert assume";
+
+		assert_eq!(lex_str(source).unwrap(), vec![
+			Token::Keyword(Keyword::Break),
+			Token::Keyword(Keyword::Continue),
+			Token::Keyword(Keyword::Else),
+			Token::Keyword(Keyword::If),
+			Token::Keyword(Keyword::Read),
+			Token::Keyword(Keyword::While),
+			Token::Keyword(Keyword::Write),
+			Token::Keyword(Keyword::Type(Type::Int)),
+			Token::Keyword(Keyword::Type(Type::Float)),
+			Token::Keyword(Keyword::Type(Type::Bool)),
+			Token::Keyword(Keyword::Proc),
+			Token::Keyword(Keyword::Call),
+			Token::Keyword(Keyword::Par),
+			Token::Keyword(Keyword::And),
+			Token::Keyword(Keyword::Requires),
+			Token::Keyword(Keyword::Ensures),
+			Token::Keyword(Keyword::Invariant),
+			Token::Keyword(Keyword::Assert),
+			Token::Keyword(Keyword::Assume),
+		]);
+	}
+
+	#[test]
+	fn boolean_keywords_are_literals() {
+		assert_eq!(lex_str("true false").unwrap(), vec![Token::Literal(Literal::BooleanLiteral(true)), Token::Literal(Literal::BooleanLiteral(false))]);
+	}
+
+	#[test]
+	fn identifiers() {
+		assert_eq!(lex_str("x test_0 iff ifx").unwrap(), vec![
+			Token::Identifier("x".to_string()),
+			Token::Identifier("test_0".to_string()),
+			Token::Identifier("iff".to_string()),
+			Token::Identifier("ifx".to_string()),
+		]);
+	}
+
+	#[test]
+	fn every_literal() {
+		use IntegerLiteral::*;
+
+		assert_eq!(lex_str("42 0b101 0o17 0xfF 1.5 0.25 true").unwrap(), vec![
+			Token::Literal(Literal::IntegerLiteral(DecimalLiteral(42))),
+			Token::Literal(Literal::IntegerLiteral(BinaryLiteral(5))),
+			Token::Literal(Literal::IntegerLiteral(OctalLiteral(15))),
+			Token::Literal(Literal::IntegerLiteral(HexadecimalLiteral(255))),
+			Token::Literal(Literal::FloatLiteral(1.5)),
+			Token::Literal(Literal::FloatLiteral(0.25)),
+			Token::Literal(Literal::BooleanLiteral(true)),
+		]);
+	}
+
+	#[test]
+	fn integer_literals_display_their_base() {
+		use IntegerLiteral::*;
+
+		assert_eq!(DecimalLiteral(-3).to_string(), "-3");
+		assert_eq!(BinaryLiteral(5).to_string(), "0b101");
+		assert_eq!(OctalLiteral(-15).to_string(), "-0o17");
+		assert_eq!(HexadecimalLiteral(255).to_string(), "0xff");
+		assert_eq!(lex_str("0xAF").unwrap(), [Token::Literal(Literal::IntegerLiteral(UpperHexadecimalLiteral(175)))]);
+		assert_eq!(UpperHexadecimalLiteral(-175).to_string(), "-0xAF");
+	}
+
+	#[test]
+	fn dot_after_integer() {
+		assert_eq!(lex_str("1.x").unwrap(), vec![
+			Token::Literal(Literal::IntegerLiteral(IntegerLiteral::DecimalLiteral(1))),
+			Token::Symbol(Symbol::Dot),
+			Token::Identifier("x".to_string()),
+		]);
+	}
+
+	#[test]
+	fn tokens_at_end_of_line() {
+		assert_eq!(lex_str("} else\n{ x\n1").unwrap(), vec![
+			Token::Delimiter(Delimiter::CloseCurly),
+			Token::Keyword(Keyword::Else),
+			Token::Delimiter(Delimiter::OpenCurly),
+			Token::Identifier("x".to_string()),
+			Token::Literal(Literal::IntegerLiteral(IntegerLiteral::DecimalLiteral(1))),
+		]);
+	}
+
+	#[test]
+	fn division_and_comments() {
+		assert_eq!(lex_str("x / y // comment /* not a block\n/").unwrap(), vec![
+			Token::Identifier("x".to_string()),
+			Token::Symbol(Symbol::Slash),
+			Token::Identifier("y".to_string()),
+			Token::Symbol(Symbol::Slash),
+		]);
+	}
+
+	#[test]
+	fn block_comments() {
+		assert_eq!(lex_str("a /* one line */ b").unwrap(), vec![Token::Identifier("a".to_string()), Token::Identifier("b".to_string())]);
+		assert_eq!(lex_str("a /* several\nlines // still comment\n*/ b /**/ c").unwrap(), vec![
+			Token::Identifier("a".to_string()),
+			Token::Identifier("b".to_string()),
+			Token::Identifier("c".to_string()),
+		]);
+		assert!(lex_str("a /* open").is_err());
+	}
+
+	#[test]
+	fn statement() {
+		assert_eq!(lex_str("A[i] := -0x1+x;").unwrap(), vec![
+			Token::Identifier("A".to_string()),
+			Token::Delimiter(Delimiter::OpenSquare),
+			Token::Identifier("i".to_string()),
+			Token::Delimiter(Delimiter::CloseSquare),
+			Token::Symbol(Symbol::ColonEq),
+			Token::Symbol(Symbol::Minus),
+			Token::Literal(Literal::IntegerLiteral(IntegerLiteral::HexadecimalLiteral(1))),
+			Token::Symbol(Symbol::Plus),
+			Token::Identifier("x".to_string()),
+			Token::Symbol(Symbol::Semi),
+		]);
+	}
+
+	#[test]
+	fn errors() {
+		assert_eq!(lex_str("x := 1;\ny := #;"), Err("2:6: Unexpected character '#'.".to_string()));
+		assert!(lex_str("0xZZ").is_err());
+		assert!(lex_str("12abc").is_err());
+		assert_eq!(lex_reader(&[b'x', b'\n', b'y', 0xff][..]), Err("Line 2 is not valid UTF-8.".to_string()));
+		assert_eq!(lex_str("int x; /* open"), Err("1:8: Unterminated block comment.".to_string()));
+	}
+
+	#[test]
+	fn files_must_exist_and_be_utf8() {
+		let missing = std::env::temp_dir().join("analyzer-missing.mc");
+		let invalid = std::env::temp_dir().join(format!("analyzer-invalid-{}.mc", std::process::id()));
+		std::fs::write(&invalid, [b'x', b';', b'\n', 0xff]).unwrap();
+
+		assert!(lex(&missing).unwrap_err().starts_with(&format!("Cannot open '{}': ", missing.display())));
+		assert!(lex_spans(&missing).unwrap_err().starts_with(&format!("Cannot open '{}': ", missing.display())));
+		assert_eq!(lex(&invalid), Err(format!("{}: Line 2 is not valid UTF-8.", invalid.display())));
+		assert_eq!(lex_spans(&invalid), Err(format!("{}: Line 2 is not valid UTF-8.", invalid.display())));
+
+		std::fs::remove_file(invalid).unwrap();
+	}
+
+	#[test]
+	fn reader_and_string_agree() {
+		let source = "int x;\nread x;\nwrite x * 2;";
+
+		assert_eq!(lex_reader(source.as_bytes()).unwrap(), lex_str(source).unwrap());
+
+		// comments run over the lines read
+		let source = "int x; /* first\r\n\n   last */ read x; // end\r\nwrite /**/ x;";
+
+		assert_eq!(lex_reader(source.as_bytes()).unwrap(), lex_str(source).unwrap());
+		assert_eq!(lex_reader("x\n  /* open\n\n".as_bytes()), Err("2:3: Unterminated block comment.".to_string()));
+	}
+
+	#[test]
+	fn trivia_is_lossless() {
+		let source = "int x; // counter\r\n/* several\n\tlines */\n\nread x;\t\n  write x /**/ * 0x2;  // end";
+		let stream = lex_trivia(source).unwrap();
+
+		assert_eq!(stream.to_string(), source);
+		assert_eq!(stream.tokens(), lex_str(source).unwrap());
+	}
+
+	#[test]
+	fn trivia_is_attached_to_the_next_token() {
+		let stream = lex_trivia("  a // c\n/* b */b\n").unwrap();
+
+		assert_eq!(stream.lexemes[0].leading, vec![Trivia::Whitespace("  ".to_string())]);
+		assert_eq!(stream.lexemes[1].leading, vec![
+			Trivia::Whitespace(" ".to_string()),
+			Trivia::LineComment("// c".to_string()),
+			Trivia::Whitespace("\n".to_string()),
+			Trivia::BlockComment("/* b */".to_string()),
+		]);
+		assert_eq!(stream.lexemes[1].text, "b");
+		assert_eq!(stream.trailing, vec![Trivia::Whitespace("\n".to_string())]);
+
+		// trivia is not split at line breaks
+		let stream = lex_trivia("a\n\n  /* b\n */\nb").unwrap();
+
+		assert_eq!(stream.lexemes[1].leading, vec![
+			Trivia::Whitespace("\n\n  ".to_string()),
+			Trivia::BlockComment("/* b\n */".to_string()),
+			Trivia::Whitespace("\n".to_string()),
+		]);
+	}
+
+	#[test]
+	fn trivia_errors() {
+		assert_eq!(lex_trivia("x\n  /* open"), Err("2:3: Unterminated block comment.".to_string()));
+		assert_eq!(lex_trivia("x := $;"), Err("1:6: Unexpected character '$'.".to_string()));
+	}
+
+	#[test]
+	fn spans() {
+		let spans = lex_trivia("int x;\n/* a\nb */ x := 10;").unwrap().spans();
+
+		assert_eq!(spans[1], Span { start: (1, 5), end: (1, 6) });
+		assert_eq!(spans[3], Span { start: (3, 6), end: (3, 7) });
+		assert_eq!(spans[5], Span { start: (3, 11), end: (3, 13) });
+		assert_eq!(spans[5].to_string(), "3:11");
+	}
+}
@@ -0,0 +1,32 @@
+//! Static analyses of MicroC programs.
+//!
+//! Source is lexed by [`lexer`] and parsed by [`parser`] into the AST of [`microc`], from which [`flow_graph`] builds the program graph.
+//! Analyses are instances of [`analysis::Analyzer`], solved by [`analysis::worklist`] with a [`worklist::Worklist`].
+
+#![warn(missing_docs)]
+
+pub mod microc;
+pub mod flow_graph;
+pub mod parser;
+pub mod analysis;
+pub mod points_to;
+pub mod concurrency;
+pub mod linear;
+pub mod hoare;
+pub mod symbolic;
+pub mod testgen;
+pub mod sat;
+pub mod bmc;
+pub mod temporal;
+pub mod lexer;
+pub mod worklist;
+pub mod safety;
+pub mod assertions;
+pub mod security;
+pub mod taint;
+pub mod slicing;
+pub mod interpreter;
+pub mod repl;
+pub mod lsp;
+pub mod html;
+pub mod gcl;
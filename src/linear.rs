@@ -0,0 +1,380 @@
+//! Decision procedure of linear integer arithmetic, over which MicroC conditions are encoded.
+
+use crate::analysis::location;
+use crate::microc::{expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, ops::{ArithmeticOp, BinaryOp, RelationalOp}};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Number of conjunctions the disjunctive normal form of a formula may have before the decision procedure gives up.
+const MAX_DISJUNCTS: usize = 4096;
+
+/// Number of constraints an elimination step may produce before the decision procedure gives up.
+const MAX_CONSTRAINTS: usize = 10_000;
+
+/// Assignment of integers to variables.
+pub type Model = BTreeMap<String, i128>;
+
+/// Linear term over integer variables: a constant plus the variables with their nonzero coefficients.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Linear {
+	/// Nonzero coefficients of the variables.
+	pub coefficients: BTreeMap<String, i128>,
+	/// Constant part.
+	pub constant: i128,
+}
+
+impl Linear {
+	/// Constant term.
+	pub fn constant(constant: i128) -> Self {
+		Linear { coefficients: BTreeMap::new(), constant }
+	}
+
+	/// Term of a variable with coefficient 1.
+	pub fn variable(var: &str) -> Self {
+		Linear { coefficients: std::iter::once((var.to_string(), 1)).collect(), constant: 0 }
+	}
+
+	/// Sum of the term and `k` times the other one, `None` on overflow.
+	pub fn add_scaled(&self, other: &Linear, k: i128) -> Option<Linear> {
+		let mut res = self.clone();
+
+		for (var, coefficient) in &other.coefficients {
+			let sum = res.coefficients.get(var).copied().unwrap_or(0).checked_add(coefficient.checked_mul(k)?)?;
+
+			if sum == 0 {
+				res.coefficients.remove(var);
+			} else {
+				res.coefficients.insert(var.clone(), sum);
+			}
+		}
+
+		res.constant = res.constant.checked_add(other.constant.checked_mul(k)?)?;
+		Some(res)
+	}
+
+	/// Value of the term in the model, the variables it lacks being zero, `None` on overflow.
+	pub fn evaluate(&self, model: &Model) -> Option<i128> {
+		self.coefficients.iter().try_fold(self.constant, |sum, (var, coefficient)| {
+			sum.checked_add(coefficient.checked_mul(model.get(var).copied().unwrap_or(0))?)
+		})
+	}
+
+	/// Constraint `self <= 0` divided by the gcd of its coefficients, the constant being rounded up as the variables are integers.
+	fn tighten(mut self) -> Self {
+		let gcd = self.coefficients.values().fold(0, |gcd, coefficient| self::gcd(gcd, coefficient.abs()));
+
+		if 1 < gcd {
+			self.coefficients.values_mut().for_each(|coefficient| *coefficient /= gcd);
+			self.constant = ceil_div(self.constant, gcd);
+		}
+
+		self
+	}
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+	if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Quotient rounded towards positive infinity, for a positive divisor.
+fn ceil_div(a: i128, b: i128) -> i128 {
+	-(-a).div_euclid(b)
+}
+
+/// Formula in negation normal form over constraints `term <= 0`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+	/// Constraint `term <= 0`.
+	Atom(Linear),
+	/// Conjunction, true when empty.
+	And(Vec<Formula>),
+	/// Disjunction, false when empty.
+	Or(Vec<Formula>),
+}
+
+/// Translation of MicroC expressions to formulas, terms outside linear integer arithmetic being abstracted by variables named after them.
+#[derive(Debug, Default)]
+pub struct Encoder {
+	/// Abstracted terms, such as products of variables and array elements.
+	pub opaque: BTreeSet<String>,
+	/// Quotients by constants, as variables named after them, with the constraints defining them.
+	pub quotients: BTreeMap<String, Formula>,
+}
+
+impl Encoder {
+	fn opaque(&mut self, arex: &ArithmeticExpr) -> Linear {
+		self.opaque.insert(arex.to_string());
+		Linear::variable(&arex.to_string())
+	}
+
+	/// Variable standing for the quotient of `dividend` by the constant `k`, rounded towards zero.
+	fn quotient(&mut self, dividend: &ArithmeticExpr, k: i128) -> Result<Linear, String> {
+		use Formula::*;
+
+		let t = self.term(dividend)?;
+		let name = format!("({dividend}) / {k}");
+		let q = Linear::variable(&name);
+		let overflow = || format!("Overflow in `{name}`.");
+		// remainder `t - k * q`, and its opposite
+		let r = t.add_scaled(&q, -k).ok_or_else(overflow)?;
+		let minus_r = Linear::default().add_scaled(&r, -1).ok_or_else(overflow)?;
+		let bound = |r: &Linear| r.add_scaled(&Linear::constant(1 - k.abs()), 1).ok_or_else(overflow);
+		let minus_t = Linear::default().add_scaled(&t, -1).ok_or_else(overflow)?;
+		let t_negative = t.add_scaled(&Linear::constant(1), 1).ok_or_else(overflow)?;
+
+		let definition = Or(vec![
+			And(vec![Atom(minus_t), Atom(minus_r.clone()), Atom(bound(&r)?)]),
+			And(vec![Atom(t_negative), Atom(r), Atom(bound(&minus_r)?)]),
+		]);
+
+		self.quotients.insert(name, definition);
+		Ok(q)
+	}
+
+	/// Linear term of an arithmetic expression.
+	pub fn term(&mut self, arex: &ArithmeticExpr) -> Result<Linear, String> {
+		let overflow = || format!("Overflow in `{arex}`.");
+
+		match arex {
+			ArithmeticExpr::Literal(ArithmeticLiteral::Int(n)) => Ok(Linear::constant(isize::from(*n) as i128)),
+			ArithmeticExpr::Literal(ArithmeticLiteral::Float(_)) => Err(format!("Float `{arex}` is not an integer.")),
+			ArithmeticExpr::LvalueExpr(lvalue @ (LvalueExpr::Variable(_) | LvalueExpr::RecordMember(_, _))) => Ok(Linear::variable(&location(lvalue))),
+			ArithmeticExpr::LvalueExpr(LvalueExpr::ArrayIndex(_, _)) => Ok(self.opaque(arex)),
+			ArithmeticExpr::LvalueExpr(LvalueExpr::Deref(_)) | ArithmeticExpr::Reference(_) => Err(format!("Pointers are not supported, got `{arex}`.")),
+			ArithmeticExpr::ArithmeticOperation(operation) => {
+				let (t1, t2) = (self.term(&operation.0)?, self.term(&operation.2)?);
+
+				match operation.1 {
+					ArithmeticOp::Add => t1.add_scaled(&t2, 1).ok_or_else(overflow),
+					ArithmeticOp::Sub | ArithmeticOp::Neg => t1.add_scaled(&t2, -1).ok_or_else(overflow),
+					ArithmeticOp::Mul if t1.coefficients.is_empty() => Linear::default().add_scaled(&t2, t1.constant).ok_or_else(overflow),
+					ArithmeticOp::Mul if t2.coefficients.is_empty() => Linear::default().add_scaled(&t1, t2.constant).ok_or_else(overflow),
+					ArithmeticOp::Div if t1.coefficients.is_empty() && t2.coefficients.is_empty() && t2.constant != 0 => Ok(Linear::constant(t1.constant.checked_div(t2.constant).ok_or_else(overflow)?)),
+					ArithmeticOp::Rem if t1.coefficients.is_empty() && t2.coefficients.is_empty() && t2.constant != 0 => Ok(Linear::constant(t1.constant.checked_rem(t2.constant).ok_or_else(overflow)?)),
+					ArithmeticOp::Div if t2.coefficients.is_empty() && t2.constant != 0 => self.quotient(&operation.0, t2.constant),
+					// `a % k` is `a - k * (a / k)`
+					ArithmeticOp::Rem if t2.coefficients.is_empty() && t2.constant != 0 => t1.add_scaled(&self.quotient(&operation.0, t2.constant)?, -t2.constant).ok_or_else(overflow),
+					_ => Ok(self.opaque(arex)),
+				}
+			},
+		}
+	}
+
+	/// Formula with the definitions of the quotients encoded so far.
+	pub fn defined(&self, formula: Formula) -> Formula {
+		Formula::And(std::iter::once(formula).chain(self.quotients.values().cloned()).collect())
+	}
+
+	/// Formula of the states in which `boolex` evaluates to `holds`.
+	pub fn formula(&mut self, boolex: &BooleanExpr, holds: bool) -> Result<Formula, String> {
+		use Formula::*;
+
+		match boolex {
+			BooleanExpr::BooleanLiteral(b) => Ok(if *b == holds { And(vec![]) } else { Or(vec![]) }),
+			BooleanExpr::NotOperation(boolex) => self.formula(boolex, !holds),
+			BooleanExpr::RelationalOperation(arex1, op, arex2) => {
+				let op = if holds { op.clone() } else { !op.clone() };
+				let difference = self.term(arex1)?.add_scaled(&self.term(arex2)?, -1).ok_or_else(|| format!("Overflow in `{boolex}`."))?;
+				let less = |d: &Linear| d.add_scaled(&Linear::constant(1), 1).map(Atom);
+				let opposite = Linear::default().add_scaled(&difference, -1).ok_or_else(|| format!("Overflow in `{boolex}`."))?;
+
+				match op {
+					RelationalOp::Lt => less(&difference),
+					RelationalOp::Leq => Some(Atom(difference)),
+					RelationalOp::Gt => less(&opposite),
+					RelationalOp::Geq => Some(Atom(opposite)),
+					RelationalOp::Eq => Some(And(vec![Atom(difference), Atom(opposite)])),
+					RelationalOp::Neq => less(&difference).and_then(|lt| less(&opposite).map(|gt| Or(vec![lt, gt]))),
+				}.ok_or_else(|| format!("Overflow in `{boolex}`."))
+			},
+			BooleanExpr::BinaryOperation(boolex1, op, boolex2) => match (op, holds) {
+				(BinaryOp::BitAnd, true) | (BinaryOp::BitOr, false) => Ok(And(vec![self.formula(boolex1, holds)?, self.formula(boolex2, holds)?])),
+				(BinaryOp::BitAnd, false) | (BinaryOp::BitOr, true) => Ok(Or(vec![self.formula(boolex1, holds)?, self.formula(boolex2, holds)?])),
+				(BinaryOp::BitXor, _) => Ok(Or(vec![
+					And(vec![self.formula(boolex1, true)?, self.formula(boolex2, !holds)?]),
+					And(vec![self.formula(boolex1, false)?, self.formula(boolex2, holds)?]),
+				])),
+				_ => Err(format!("Operator '{op}' is not supported in `{boolex}`.")),
+			},
+		}
+	}
+}
+
+/// Conjunctions of constraints whose disjunction is equivalent to the formula.
+fn dnf(formula: &Formula) -> Result<Vec<Vec<Linear>>, String> {
+	let too_large = || format!("The formula has more than {MAX_DISJUNCTS} disjuncts.");
+
+	match formula {
+		Formula::Atom(constraint) => Ok(vec![vec![constraint.clone()]]),
+		Formula::Or(formulas) => {
+			let mut res = vec![];
+
+			for formula in formulas {
+				res.append(&mut dnf(formula)?);
+
+				if MAX_DISJUNCTS < res.len() {
+					return Err(too_large());
+				}
+			}
+
+			Ok(res)
+		},
+		Formula::And(formulas) => formulas.iter().try_fold(vec![vec![]], |res, formula| {
+			let disjuncts = dnf(formula)?;
+
+			if MAX_DISJUNCTS < res.len() * disjuncts.len() {
+				return Err(too_large());
+			}
+
+			Ok(res.iter().flat_map(|conjunction: &Vec<Linear>| disjuncts.iter().map(move |disjunct| [&conjunction[..], disjunct].concat())).collect())
+		}),
+	}
+}
+
+/// Integer model of a conjunction of constraints by Fourier–Motzkin elimination, `None` if there is none.
+///
+/// The rational relaxation being tightened at every step, unsatisfiability is exact, but the model built back from the eliminated variables may fail to be integral.
+fn eliminate(constraints: BTreeSet<Linear>) -> Result<Option<Model>, String> {
+	let mut rest = BTreeSet::new();
+
+	for constraint in constraints {
+		let constraint = constraint.tighten();
+
+		if !constraint.coefficients.is_empty() {
+			rest.insert(constraint);
+		} else if 0 < constraint.constant {
+			return Ok(None);
+		}
+	}
+
+	if MAX_CONSTRAINTS < rest.len() {
+		return Err(format!("Elimination produced more than {MAX_CONSTRAINTS} constraints."));
+	}
+
+	// the variable with the fewest pairs of bounds to combine
+	let bounds = |var: &String| {
+		let lower = rest.iter().filter(|constraint| constraint.coefficients.get(var).is_some_and(|c| *c < 0)).count();
+
+		lower * (rest.iter().filter(|constraint| constraint.coefficients.contains_key(var)).count() - lower)
+	};
+	let var = match rest.iter().flat_map(|constraint| constraint.coefficients.keys()).min_by_key(|var| bounds(var)) {
+		Some(var) => var.clone(),
+		None => return Ok(Some(Model::new())),
+	};
+
+	let (with, without): (Vec<Linear>, Vec<Linear>) = rest.into_iter().partition(|constraint| constraint.coefficients.contains_key(&var));
+	let (lower, upper): (Vec<Linear>, Vec<Linear>) = with.into_iter().partition(|constraint| constraint.coefficients[&var] < 0);
+	let mut combined: BTreeSet<Linear> = without.into_iter().collect();
+
+	for l in &lower {
+		for u in &upper {
+			let (a, b) = (l.coefficients[&var], u.coefficients[&var]);
+
+			combined.insert(Linear::default().add_scaled(l, b).and_then(|sum| sum.add_scaled(u, -a)).ok_or("Overflow while eliminating variables.")?);
+		}
+	}
+
+	let mut model = match eliminate(combined)? {
+		Some(model) => model,
+		None => return Ok(None),
+	};
+
+	// every bound `a var + rest <= 0` gives `var >= rest / -a` or `var <= -rest / a`
+	let rest = |constraint: &Linear| {
+		let mut rest = constraint.clone();
+
+		rest.coefficients.remove(&var);
+		rest.evaluate(&model).ok_or("Overflow while building a model.")
+	};
+	let mut low = None;
+	let mut high = None;
+
+	for l in &lower {
+		let bound = ceil_div(rest(l)?, -l.coefficients[&var]);
+
+		low = Some(low.map_or(bound, |low: i128| low.max(bound)));
+	}
+
+	for u in &upper {
+		let bound = (-rest(u)?).div_euclid(u.coefficients[&var]);
+
+		high = Some(high.map_or(bound, |high: i128| high.min(bound)));
+	}
+
+	let value = match (low, high) {
+		(Some(low), Some(high)) if high < low => return Err("No integer model was found for the rational one.".to_string()),
+		(Some(low), _) if 0 < low => low,
+		(_, Some(high)) if high < 0 => high,
+		_ => 0,
+	};
+
+	for var in lower.iter().chain(&upper).flat_map(|constraint| constraint.coefficients.keys()) {
+		model.entry(var.clone()).or_insert(0);
+	}
+
+	model.insert(var, value);
+	Ok(Some(model))
+}
+
+/// Integer model of the formula, `None` if it is unsatisfiable, or why the decision procedure could not tell.
+pub fn satisfiable(formula: &Formula) -> Result<Option<Model>, String> {
+	let mut unknown = None;
+
+	for conjunction in dnf(formula)? {
+		match eliminate(conjunction.into_iter().collect()) {
+			Ok(Some(model)) => return Ok(Some(model)),
+			Ok(None) => (),
+			Err(e) => unknown = Some(e),
+		}
+	}
+
+	match unknown {
+		Some(e) => Err(e),
+		None => Ok(None),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Encoder, satisfiable};
+	use crate::{lexer::lex_str, microc::stmt::Statement, parser::parse};
+
+	/// Model of the condition of an `if` over `x` and `y`, or `None` if it is unsatisfiable.
+	fn solve(condition: &str) -> Option<Vec<(String, i128)>> {
+		let ast = parse(lex_str(&format!("int x;\nint y;\nif {condition} {{}}")).unwrap()).unwrap();
+
+		match &ast.1[0] {
+			Statement::If(boolex, _) => {
+				let mut encoder = Encoder::default();
+				let formula = encoder.formula(boolex, true).unwrap();
+
+				satisfiable(&encoder.defined(formula)).unwrap().map(|model| model.into_iter().filter(|(var, _)| !encoder.quotients.contains_key(var)).collect())
+			},
+			_ => unreachable!(),
+		}
+	}
+
+	#[test]
+	fn integer_bounds_are_tightened() {
+		// 1 < 2x < 2 has rational solutions only
+		assert_eq!(solve("2 * x > 1 && 2 * x < 2"), None);
+		assert_eq!(solve("x < y && y < x + 1"), None);
+		assert_eq!(solve("!(x <= y || y <= x)"), None);
+	}
+
+	#[test]
+	fn models_satisfy_the_formula() {
+		let model = solve("x + y == 10 && x - y >= 4 && (y > 2 || x > 100)").unwrap();
+		let (x, y) = (model[0].1, model[1].1);
+
+		assert!(x + y == 10 && x - y >= 4 && (y > 2 || x > 100));
+		assert_eq!(solve("x != x"), None);
+	}
+
+	#[test]
+	fn quotients_round_towards_zero() {
+		assert_eq!(solve("x % 2 == 0 && x % 2 != 0"), None);
+		assert_eq!(solve("x < 0 && x % 3 > 0"), None);
+		assert_eq!(solve("x == -7 && x / 2 != -3"), None);
+		assert!(solve("x / 2 == 3 && x != 6").unwrap()[0].1 == 7);
+	}
+}
@@ -0,0 +1,767 @@
+//! Language server of MicroC, with diagnostics, hovers, definitions and symbols.
+
+use crate::analysis::{Memory, interval::{self, Interval}, location, reaching::{self, Definitions}, sign::{self, Signs}};
+use crate::flow_graph::{self, FlowGraph, Origin, build};
+use crate::lexer::{Span, lex_trivia};
+use crate::microc::{decl::Declaration, expr::{ArithmeticExpr, BooleanExpr, LvalueExpr}, node::size, stmt::{Program, Scope, Statement}, visit::{self, Visitor}};
+use crate::parser::{Ast, error_span, parse_spans};
+use crate::{assertions::{self, Verdict}, safety::{self, Safety}};
+use json::{Json, object};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use std::{collections::HashMap, io::{self, BufRead, Write}};
+
+/// JSON values, as exchanged by the protocol.
+pub mod json {
+	use std::{collections::BTreeMap, fmt::{self, Display, Formatter}};
+
+	#[derive(Debug, Clone, PartialEq)]
+	/// JSON value.
+	pub enum Json {
+		/// `null`
+		Null,
+		/// `true` or `false`.
+		Bool(bool),
+		/// Number.
+		Number(f64),
+		/// String.
+		String(String),
+		/// Array.
+		Array(Vec<Json>),
+		/// Object, with its members by key.
+		Object(BTreeMap<String, Json>),
+	}
+
+	impl Json {
+		/// Member of an object, `Null` if there is none.
+		pub fn get(&self, key: &str) -> &Json {
+			match self {
+				Json::Object(members) => members.get(key).unwrap_or(&Json::Null),
+				_ => &Json::Null,
+			}
+		}
+
+		/// Element of an array, `Null` if there is none.
+		pub fn nth(&self, i: usize) -> &Json {
+			match self {
+				Json::Array(values) => values.get(i).unwrap_or(&Json::Null),
+				_ => &Json::Null,
+			}
+		}
+
+		/// Last element of an array, `Null` if there is none.
+		pub fn last(&self) -> &Json {
+			match self {
+				Json::Array(values) => values.last().unwrap_or(&Json::Null),
+				_ => &Json::Null,
+			}
+		}
+
+		/// String of a string value.
+		pub fn as_str(&self) -> Option<&str> {
+			match self {
+				Json::String(s) => Some(s),
+				_ => None,
+			}
+		}
+
+		/// Nonnegative integer of a number value.
+		pub fn as_usize(&self) -> Option<usize> {
+			match *self {
+				Json::Number(n) if 0.0 <= n && n.fract() == 0.0 => Some(n as usize),
+				_ => None,
+			}
+		}
+	}
+
+	impl From<&str> for Json {
+		fn from(s: &str) -> Self {
+			Json::String(s.to_string())
+		}
+	}
+
+	impl From<String> for Json {
+		fn from(s: String) -> Self {
+			Json::String(s)
+		}
+	}
+
+	impl From<usize> for Json {
+		fn from(n: usize) -> Self {
+			Json::Number(n as f64)
+		}
+	}
+
+	impl From<bool> for Json {
+		fn from(b: bool) -> Self {
+			Json::Bool(b)
+		}
+	}
+
+	impl From<Vec<Json>> for Json {
+		fn from(values: Vec<Json>) -> Self {
+			Json::Array(values)
+		}
+	}
+
+	/// Object made of the given members.
+	pub fn object(members: Vec<(&str, Json)>) -> Json {
+		Json::Object(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+	}
+
+	fn escape(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
+		write!(f, "\"")?;
+
+		for c in s.chars() {
+			match c {
+				'"' => write!(f, "\\\"")?,
+				'\\' => write!(f, "\\\\")?,
+				'\n' => write!(f, "\\n")?,
+				'\r' => write!(f, "\\r")?,
+				'\t' => write!(f, "\\t")?,
+				c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+				c => write!(f, "{c}")?,
+			}
+		}
+
+		write!(f, "\"")
+	}
+
+	impl Display for Json {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			match self {
+				Json::Null => write!(f, "null"),
+				Json::Bool(b) => write!(f, "{b}"),
+				Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+				Json::Number(n) => write!(f, "{n}"),
+				Json::String(s) => escape(f, s),
+				Json::Array(values) => {
+					write!(f, "[")?;
+
+					for (i, value) in values.iter().enumerate() {
+						write!(f, "{}{value}", if i == 0 { "" } else { "," })?;
+					}
+
+					write!(f, "]")
+				},
+				Json::Object(members) => {
+					write!(f, "{{")?;
+
+					for (i, (key, value)) in members.iter().enumerate() {
+						write!(f, "{}", if i == 0 { "" } else { "," })?;
+						escape(f, key)?;
+						write!(f, ":{value}")?;
+					}
+
+					write!(f, "}}")
+				},
+			}
+		}
+	}
+
+	struct Parser<'a> {
+		chars: &'a [char],
+		i: usize,
+	}
+
+	impl Parser<'_> {
+		fn skip(&mut self) {
+			while self.chars.get(self.i).is_some_and(|c| c.is_whitespace()) {
+				self.i += 1;
+			}
+		}
+
+		fn expect(&mut self, word: &str) -> Result<(), String> {
+			for c in word.chars() {
+				if self.chars.get(self.i) != Some(&c) {
+					return Err(format!("Expected '{word}' at {}.", self.i));
+				}
+
+				self.i += 1;
+			}
+
+			Ok(())
+		}
+
+		fn string(&mut self) -> Result<String, String> {
+			self.expect("\"")?;
+			let mut s = String::new();
+
+			loop {
+				let c = *self.chars.get(self.i).ok_or("Unterminated string.")?;
+				self.i += 1;
+
+				match c {
+					'"' => return Ok(s),
+					'\\' => {
+						let c = *self.chars.get(self.i).ok_or("Unterminated string.")?;
+						self.i += 1;
+
+						s.push(match c {
+							'n' => '\n',
+							'r' => '\r',
+							't' => '\t',
+							'b' => '\u{8}',
+							'f' => '\u{c}',
+							'u' => {
+								let code = self.hex()?;
+
+								// a surrogate pair stands for a character outside the basic plane
+								let code = if (0xd800..0xdc00).contains(&code) && self.chars.get(self.i..self.i + 2) == Some(&['\\', 'u']) {
+									self.i += 2;
+									self.hex()?.checked_sub(0xdc00).filter(|low| *low < 0x400).map_or(0xfffd, |low| 0x10000 + ((code - 0xd800) << 10) + low)
+								} else {
+									code
+								};
+
+								char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+							},
+							c => c,
+						});
+					},
+					c => s.push(c),
+				}
+			}
+		}
+
+		fn hex(&mut self) -> Result<u32, String> {
+			let digits: String = self.chars.get(self.i..self.i + 4).ok_or("Unterminated escape.")?.iter().collect();
+			self.i += 4;
+
+			u32::from_str_radix(&digits, 16).map_err(|_| format!("Invalid escape '\\u{digits}'."))
+		}
+
+		fn value(&mut self) -> Result<Json, String> {
+			self.skip();
+
+			let value = match self.chars.get(self.i) {
+				Some('n') => self.expect("null").map(|_| Json::Null)?,
+				Some('t') => self.expect("true").map(|_| Json::Bool(true))?,
+				Some('f') => self.expect("false").map(|_| Json::Bool(false))?,
+				Some('"') => Json::String(self.string()?),
+				Some('[') => {
+					self.i += 1;
+					self.skip();
+					let mut values = vec![];
+
+					if self.chars.get(self.i) == Some(&']') {
+						self.i += 1;
+					} else {
+						loop {
+							values.push(self.value()?);
+
+							match self.chars.get(self.i) {
+								Some(',') => self.i += 1,
+								Some(']') => { self.i += 1; break; },
+								_ => return Err(format!("Expected ',' or ']' at {}.", self.i)),
+							}
+						}
+					}
+
+					Json::Array(values)
+				},
+				Some('{') => {
+					self.i += 1;
+					self.skip();
+					let mut members = BTreeMap::new();
+
+					if self.chars.get(self.i) == Some(&'}') {
+						self.i += 1;
+					} else {
+						loop {
+							self.skip();
+							let key = self.string()?;
+							self.skip();
+							self.expect(":")?;
+							members.insert(key, self.value()?);
+
+							match self.chars.get(self.i) {
+								Some(',') => self.i += 1,
+								Some('}') => { self.i += 1; break; },
+								_ => return Err(format!("Expected ',' or '}}' at {}.", self.i)),
+							}
+						}
+					}
+
+					Json::Object(members)
+				},
+				Some(c) if *c == '-' || c.is_ascii_digit() => {
+					let start = self.i;
+
+					while self.chars.get(self.i).is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+						self.i += 1;
+					}
+
+					let number: String = self.chars[start..self.i].iter().collect();
+
+					Json::Number(number.parse().map_err(|_| format!("Invalid number '{number}'."))?)
+				},
+				_ => return Err(format!("Unexpected input at {}.", self.i)),
+			};
+
+			self.skip();
+			Ok(value)
+		}
+	}
+
+	/// Parses a JSON text.
+	pub fn parse(text: &str) -> Result<Json, String> {
+		let chars: Vec<char> = text.chars().collect();
+		let mut parser = Parser { chars: &chars, i: 0 };
+		let value = parser.value()?;
+
+		if parser.i == chars.len() { Ok(value) } else { Err(format!("Unexpected input at {}.", parser.i)) }
+	}
+}
+
+/// Declaration of a variable, array, record or record member, by the name its uses refer to it with.
+#[derive(Debug, Clone)]
+struct Binding {
+	name: String,
+	decl: usize,
+}
+
+/// Visitor numbering the nodes as [`Numbering`](crate::microc::node::Numbering) does, resolving every lvalue to the declaration in scope.
+#[derive(Default)]
+struct Resolver {
+	count: usize,
+	scopes: Vec<Vec<Binding>>,
+	/// Record whose members are being declared.
+	record: Option<String>,
+	/// Declarations outside records, with their id.
+	decls: Vec<(usize, Declaration)>,
+	/// Lvalues with the id of their declaration, if any.
+	uses: Vec<(usize, LvalueExpr, Option<usize>)>,
+}
+
+impl Resolver {
+	fn enter(&mut self) -> usize {
+		self.count += 1;
+		self.count - 1
+	}
+
+	fn resolve(&self, name: &str) -> Option<usize> {
+		self.scopes.iter().rev().find_map(|scope| scope.iter().rev().find(|binding| binding.name == name)).map(|binding| binding.decl)
+	}
+}
+
+impl Visitor for Resolver {
+	fn visit_scope(&mut self, scope: &Scope) {
+		self.scopes.push(vec![]);
+		visit::walk_scope(self, scope);
+		self.scopes.pop();
+	}
+
+	fn visit_decl(&mut self, decl: &Declaration) {
+		let id = self.enter();
+		let (name, record) = match decl {
+			Declaration::Var(_, name) | Declaration::Array(_, _, name) | Declaration::Pointer(_, name) => (name.clone(), None),
+			Declaration::Record(_, name) => (name.clone(), Some(name.clone())),
+			Declaration::Procedure(name, _, _, _) => (name.clone(), None),
+		};
+		let name = match &self.record {
+			Some(record) => format!("{record}.{name}"),
+			None => name,
+		};
+
+		self.scopes.last_mut().unwrap().push(Binding { name, decl: id });
+		if self.record.is_none() {
+			self.decls.push((id, decl.clone()));
+		}
+
+		let outer = std::mem::replace(&mut self.record, record);
+
+		if let Declaration::Procedure(_, values, results, body) = decl {
+			// the parameters are only in scope in the body
+			self.scopes.push(vec![]);
+			values.iter().chain(results).for_each(|param| self.visit_decl(param));
+			self.visit_scope(body);
+			self.scopes.pop();
+		} else {
+			visit::walk_decl(self, decl);
+		}
+
+		self.record = outer;
+	}
+
+	fn visit_stmt(&mut self, stmt: &Statement) {
+		self.enter();
+		visit::walk_stmt(self, stmt);
+	}
+
+	fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+		self.enter();
+		visit::walk_arex(self, arex);
+	}
+
+	fn visit_boolex(&mut self, boolex: &BooleanExpr) {
+		self.enter();
+		visit::walk_boolex(self, boolex);
+	}
+
+	fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+		let id = self.enter();
+		let name = match lvalue {
+			LvalueExpr::Variable(id) | LvalueExpr::ArrayIndex(id, _) | LvalueExpr::Deref(id) => id.clone(),
+			LvalueExpr::RecordMember(record, member) => format!("{record}.{member}"),
+		};
+
+		self.uses.push((id, lvalue.clone(), self.resolve(&name)));
+		visit::walk_lvalue(self, lvalue);
+	}
+}
+
+/// Position of the protocol, its line and character counted from 0.
+fn position((line, column): (usize, usize)) -> Json {
+	object(vec![("line", (line - 1).into()), ("character", (column - 1).into())])
+}
+
+fn range(span: &Span) -> Json {
+	object(vec![("start", position(span.start)), ("end", position(span.end))])
+}
+
+fn contains(span: &Span, at: (usize, usize)) -> bool {
+	span.start <= at && at < span.end
+}
+
+/// Size of a span, to pick the innermost of the spans containing a position.
+fn extent(span: &Span) -> (usize, usize) {
+	(span.end.0 - span.start.0, if span.end.0 == span.start.0 { span.end.1 - span.start.1 } else { span.end.1 })
+}
+
+fn diagnostic(span: &Span, severity: usize, message: String) -> Json {
+	object(vec![("range", range(span)), ("severity", severity.into()), ("source", "analyzer".into()), ("message", message.into())])
+}
+
+/// Document analyzed as a whole program, kept until its text changes.
+struct Document {
+	text: String,
+	ast: Ast,
+	/// Span of every node, by id.
+	spans: Vec<Span>,
+	resolver: Resolver,
+	program: FlowGraph,
+	/// Origin of every edge, telling the scope it is in.
+	origins: HashMap<EdgeIndex, Origin>,
+	/// Span of the statement or guard of every edge.
+	edges: HashMap<EdgeIndex, Span>,
+	/// Analyses shown on hover, solved once per version of the text.
+	signs: HashMap<NodeIndex, Memory<Signs>>,
+	intervals: HashMap<NodeIndex, Memory<Interval>>,
+	definitions: HashMap<NodeIndex, Definitions>,
+}
+
+impl Document {
+	fn new(text: &str) -> Result<Self, Json> {
+		let at_start = Span { start: (1, 1), end: (1, 1) };
+		let stream = lex_trivia(text).map_err(|e| {
+			// lexing errors start with their `line:column: ` position
+			let mut parts = e.splitn(3, ':');
+			let at = (parts.next().and_then(|n| n.parse().ok()), parts.next().and_then(|n| n.parse().ok()), parts.next());
+
+			match at {
+				(Some(line), Some(column), Some(message)) => diagnostic(&Span { start: (line, column), end: (line, column + 1) }, 1, message.trim().to_string()),
+				_ => diagnostic(&at_start, 1, e),
+			}
+		})?;
+		let spans = stream.spans();
+		let (ast, spans) = parse_spans(&stream.tokens(), &spans).map_err(|(index, e)| diagnostic(&error_span(&spans, index).unwrap_or(at_start), 1, e))?;
+		let mut resolver = Resolver::default();
+		let (program, origins) = build(&ast);
+		let edges = flow_graph::spans(&origins, &spans);
+
+		let signs = sign::analyze(&program);
+		let intervals = interval::analyze(&program);
+		let definitions = reaching::analyze(&program);
+
+		resolver.visit_scope(&ast);
+		Ok(Document { text: text.to_string(), ast, spans, resolver, program, origins, edges, signs, intervals, definitions })
+	}
+
+	/// Undeclared variables, the safety diagnostics and the assertions not proven.
+	fn diagnostics(&self) -> Vec<Json> {
+		let undeclared = self.resolver.uses.iter()
+			.filter(|(_, _, decl)| decl.is_none())
+			.map(|(id, lvalue, _)| diagnostic(&self.spans[*id], 1, format!("Undeclared variable `{lvalue}`.")));
+		let unsafe_sites = safety::check(&self.program, &self.origins).into_iter().filter_map(|diagnostic| match diagnostic.safety {
+			Safety::Safe => None,
+			Safety::PossiblyUnsafe => Some((2, diagnostic)),
+			Safety::DefinitelyUnsafe => Some((1, diagnostic)),
+		}).filter_map(|(severity, site)| self.edges.get(&site.edge).map(|span| diagnostic(span, severity, site.to_string())));
+		let failing_asserts = assertions::check(&self.program).into_iter().filter_map(|check| match check.verdict {
+			Verdict::Proven => None,
+			Verdict::Unknown => Some((2, check)),
+			Verdict::Violated => Some((1, check)),
+		}).filter_map(|(severity, check)| self.edges.get(&check.edge).map(|span| diagnostic(span, severity, check.to_string())));
+
+		undeclared.chain(unsafe_sites).chain(failing_asserts).collect()
+	}
+
+	/// Innermost lvalue at the position, with the id of its declaration.
+	fn lvalue(&self, at: (usize, usize)) -> Option<&(usize, LvalueExpr, Option<usize>)> {
+		self.resolver.uses.iter().filter(|(id, _, _)| contains(&self.spans[*id], at)).min_by_key(|(id, _, _)| extent(&self.spans[*id]))
+	}
+
+	/// Abstract values of the lvalue at the position, before the statement or guard it is part of.
+	fn hover(&self, at: (usize, usize)) -> Option<Json> {
+		let (id, lvalue, _) = self.lvalue(at)?;
+		let edge = self.edges.iter().filter(|(_, span)| contains(span, at)).min_by_key(|(_, span)| extent(span)).map(|(edge, _)| *edge)?;
+		let (source, _) = self.program.0.edge_endpoints(edge)?;
+		let var = location(lvalue);
+
+		let value = |memory: Option<String>| memory.unwrap_or_else(|| "unreachable".to_string());
+		let signs = value(self.signs[&source].as_ref().map(|memory| memory.get(&var).map_or("?".to_string(), |v| v.to_string())));
+		let intervals = value(self.intervals[&source].as_ref().map(|memory| memory.get(&var).map_or("?".to_string(), |v| v.to_string())));
+		let definitions: Vec<String> = self.definitions[&source].iter().filter(|(defined, _)| *defined == var).map(|(_, edge)| {
+			let (source, target) = self.program.0.edge_endpoints(*edge).unwrap();
+
+			format!("q{} -> q{}", source.index(), target.index())
+		}).collect();
+
+		let contents = format!("`{var}` at q{}\n\nsigns: {signs}\n\nintervals: {intervals}\n\nreaching definitions: {{{}}}", source.index(), definitions.join(", "));
+
+		Some(object(vec![("contents", object(vec![("kind", "markdown".into()), ("value", contents.into())])), ("range", range(&self.spans[*id]))]))
+	}
+
+	fn definition(&self, uri: &str, at: (usize, usize)) -> Option<Json> {
+		let decl = self.lvalue(at)?.2?;
+
+		Some(object(vec![("uri", uri.into()), ("range", range(&self.spans[decl]))]))
+	}
+
+	/// Symbol of a declaration, with the ones of its members.
+	fn symbol(&self, id: usize, decl: &Declaration) -> Json {
+		let (name, kind, members) = match decl {
+			Declaration::Var(_, name) | Declaration::Pointer(_, name) => (name, 13, &[][..]),
+			Declaration::Array(_, _, name) => (name, 18, &[][..]),
+			Declaration::Record(members, name) => (name, 23, &members[..]),
+			// its parameters and variables are symbols of their own
+			Declaration::Procedure(name, _, _, _) => (name, 12, &[][..]),
+		};
+		let detail = match decl {
+			Declaration::Procedure(name, values, results, _) => format!("proc {name}({})", Declaration::parameters(values, results)),
+			_ => decl.to_string(),
+		};
+		let mut member = id + 1;
+		let children: Vec<Json> = members.iter().map(|decl| {
+			let symbol = self.symbol(member, decl);
+
+			member += size(|n| n.visit_decl(decl));
+			symbol
+		}).collect();
+
+		object(vec![
+			("name", name.as_str().into()),
+			("detail", detail.into()),
+			("kind", kind.into()),
+			("range", range(&self.spans[id])),
+			("selectionRange", range(&self.spans[id])),
+			("children", children.into()),
+		])
+	}
+
+	/// Symbols of the declarations, record members being nested in their record.
+	fn symbols(&self) -> Json {
+		self.resolver.decls.iter().map(|(id, decl)| self.symbol(*id, decl)).collect::<Vec<Json>>().into()
+	}
+
+	/// Edit replacing the whole text by the pretty-printed program.
+	fn formatting(&self) -> Json {
+		let lines = self.text.split('\n').count();
+		let end = object(vec![("line", lines.into()), ("character", 0.into())]);
+		let whole = object(vec![("start", object(vec![("line", 0.into()), ("character", 0.into())])), ("end", end)]);
+
+		vec![object(vec![("range", whole), ("newText", format!("{}\n", Program(&self.ast)).into())])].into()
+	}
+}
+
+/// Reads a message, `None` at the end of the input.
+fn receive(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+	let mut length = None;
+
+	loop {
+		let mut header = String::new();
+
+		if input.read_line(&mut header)? == 0 {
+			return Ok(None);
+		}
+
+		match header.trim().split_once(':') {
+			Some((name, value)) if name.eq_ignore_ascii_case("Content-Length") => length = value.trim().parse::<usize>().ok(),
+			Some(_) => (),
+			None if header.trim().is_empty() => break,
+			None => (),
+		}
+	}
+
+	let mut body = vec![0; length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header."))?];
+
+	input.read_exact(&mut body)?;
+	json::parse(&String::from_utf8_lossy(&body)).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn send(output: &mut impl Write, message: Json) -> io::Result<()> {
+	let body = message.to_string();
+
+	write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+	output.flush()
+}
+
+/// Parameters of a request on a position of a document: its URI and the 1-based line and column.
+fn located(params: &Json) -> Option<(String, (usize, usize))> {
+	let uri = params.get("textDocument").get("uri").as_str()?.to_string();
+	let at = params.get("position");
+
+	Some((uri, (at.get("line").as_usize()? + 1, at.get("character").as_usize()? + 1)))
+}
+
+/// Serves the Language Server Protocol on the input and output until the `exit` notification, texts being synchronized whole.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+	// documents that do not parse are left out, and answer no request
+	let mut documents = HashMap::<String, Document>::new();
+
+	while let Some(message) = receive(&mut input)? {
+		let params = message.get("params");
+		let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default().to_string();
+
+		let result = match message.get("method").as_str().unwrap_or_default() {
+			"initialize" => Some(object(vec![
+				("capabilities", object(vec![
+					("textDocumentSync", 1.into()),
+					("hoverProvider", true.into()),
+					("definitionProvider", true.into()),
+					("documentSymbolProvider", true.into()),
+					("documentFormattingProvider", true.into()),
+				])),
+				("serverInfo", object(vec![("name", "analyzer".into())])),
+			])),
+			"shutdown" => Some(Json::Null),
+			"exit" => return Ok(()),
+			method @ ("textDocument/didOpen" | "textDocument/didChange") => {
+				let text = if method == "textDocument/didOpen" { params.get("textDocument").get("text") } else { params.get("contentChanges").last().get("text") };
+
+				let diagnostics = match Document::new(text.as_str().unwrap_or_default()) {
+					Ok(document) => {
+						let diagnostics = document.diagnostics();
+
+						documents.insert(uri.clone(), document);
+						diagnostics
+					},
+					Err(diagnostic) => {
+						documents.remove(&uri);
+						vec![diagnostic]
+					},
+				};
+
+				send(&mut output, object(vec![
+					("jsonrpc", "2.0".into()),
+					("method", "textDocument/publishDiagnostics".into()),
+					("params", object(vec![("uri", uri.as_str().into()), ("diagnostics", diagnostics.into())])),
+				]))?;
+				None
+			},
+			"textDocument/didClose" => {
+				documents.remove(&uri);
+				None
+			},
+			"textDocument/hover" => Some(located(params).and_then(|(uri, at)| documents.get(&uri)?.hover(at)).unwrap_or(Json::Null)),
+			"textDocument/definition" => Some(located(params).and_then(|(uri, at)| documents.get(&uri)?.definition(&uri, at)).unwrap_or(Json::Null)),
+			"textDocument/documentSymbol" => Some(documents.get(&uri).map_or(Json::Null, Document::symbols)),
+			"textDocument/formatting" => Some(documents.get(&uri).map_or(Json::Null, Document::formatting)),
+			_ => None,
+		};
+
+		// notifications have no id, and no response
+		match (message.get("id"), result) {
+			(Json::Null, _) => (),
+			(id, Some(result)) => send(&mut output, object(vec![("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]))?,
+			(id, None) => send(&mut output, object(vec![
+				("jsonrpc", "2.0".into()),
+				("id", id.clone()),
+				("error", object(vec![("code", Json::Number(-32601.0)), ("message", "Method not found.".into())])),
+			]))?,
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{json::{self, Json, object}, serve};
+
+	/// Frames the messages of a client, and gives back the messages of the server.
+	fn session(messages: Vec<Json>) -> Vec<Json> {
+		let input: String = messages.iter().map(|message| {
+			let body = message.to_string();
+
+			format!("Content-Length: {}\r\n\r\n{body}", body.len())
+		}).collect();
+		let mut output = vec![];
+
+		serve(input.as_bytes(), &mut output).unwrap();
+
+		String::from_utf8(output).unwrap().split("Content-Length: ").skip(1).map(|message| json::parse(message.split_once("\r\n\r\n").unwrap().1).unwrap()).collect()
+	}
+
+	fn request(id: usize, method: &str, params: Json) -> Json {
+		object(vec![("jsonrpc", "2.0".into()), ("id", id.into()), ("method", method.into()), ("params", params)])
+	}
+
+	fn at(line: usize, character: usize) -> Json {
+		object(vec![("textDocument", object(vec![("uri", "file:///a.mc".into())])), ("position", object(vec![("line", line.into()), ("character", character.into())]))])
+	}
+
+	#[test]
+	fn json() {
+		let text = r#"{"a": [1, -2.5, true, null], "b\u00e9\n": "\ud83d\ude00 \"q\""}"#;
+		let value = json::parse(text).unwrap();
+
+		assert_eq!(value.get("a"), &Json::Array(vec![1.into(), Json::Number(-2.5), true.into(), Json::Null]));
+		assert_eq!(value.get("b\u{e9}\n").as_str(), Some("\u{1f600} \"q\""));
+		assert_eq!(json::parse(&value.to_string()), Ok(value));
+		assert!(json::parse("[1,]").is_err());
+	}
+
+	#[test]
+	fn scripted_client() {
+		let text = "int x;\n{int a; int b;} r;\nread x;\nif x > 0 {\n  r.a := 10 / x;\n}\nx := y;\n";
+		let document = object(vec![("uri", "file:///a.mc".into()), ("text", text.into())]);
+		let responses = session(vec![
+			request(1, "initialize", object(vec![])),
+			object(vec![("jsonrpc", "2.0".into()), ("method", "textDocument/didOpen".into()), ("params", object(vec![("textDocument", document)]))]),
+			request(2, "textDocument/hover", at(4, 14)),
+			request(3, "textDocument/definition", at(4, 3)),
+			request(4, "textDocument/documentSymbol", object(vec![("textDocument", object(vec![("uri", "file:///a.mc".into())]))])),
+			request(5, "textDocument/formatting", object(vec![("textDocument", object(vec![("uri", "file:///a.mc".into())]))])),
+			request(6, "shutdown", Json::Null),
+			object(vec![("jsonrpc", "2.0".into()), ("method", "exit".into())]),
+		]);
+
+		assert_eq!(responses.len(), 7);
+		assert_eq!(responses[0].get("result").get("capabilities").get("hoverProvider"), &Json::Bool(true));
+
+		let diagnostics = responses[1].get("params").get("diagnostics");
+		assert_eq!(diagnostics.nth(0).get("message").as_str(), Some("Undeclared variable `y`."));
+		assert_eq!(diagnostics.nth(0).get("range").get("start"), &object(vec![("line", 6.into()), ("character", 5.into())]));
+
+		assert_eq!(responses[2].get("result").get("contents").get("value").as_str(), Some("`x` at q6\n\nsigns: {+}\n\nintervals: [1, +inf]\n\nreaching definitions: {q3 -> q4}"));
+		assert_eq!(responses[3].get("result").get("range").get("start"), &object(vec![("line", 1.into()), ("character", 1.into())]));
+
+		let symbols = responses[4].get("result");
+		assert_eq!(symbols.nth(1).get("name").as_str(), Some("r"));
+		assert_eq!(symbols.nth(1).get("children").nth(1).get("name").as_str(), Some("b"));
+
+		assert_eq!(responses[5].get("result").nth(0).get("newText").as_str(), Some("int x;\n{int a; int b;} r;\nread x;\nif x > 0 {\n\tr.a := 10 / x;\n}\nx := y;\n"));
+		assert_eq!(responses[5].get("id"), &5.into());
+	}
+
+	#[test]
+	fn parse_errors_are_at_their_token() {
+		let document = object(vec![("uri", "file:///a.mc".into()), ("text", "int x;\nx := 1;\nx := ;\n".into())]);
+		let responses = session(vec![object(vec![("jsonrpc", "2.0".into()), ("method", "textDocument/didOpen".into()), ("params", object(vec![("textDocument", document)]))])]);
+		let diagnostics = responses[0].get("params").get("diagnostics");
+
+		assert_eq!(diagnostics.nth(0).get("message").as_str(), Some("Cannot parse arithmetic expression starting with 'Symbol(Semi)'."));
+		assert_eq!(diagnostics.nth(0).get("range").get("start"), &object(vec![("line", 2.into()), ("character", 5.into())]));
+	}
+}
@@ -1,22 +1,35 @@
-#![feature(arbitrary_enum_discriminant)]
-#![feature(format_args_capture)]
-
-use crate::{analysis::analyze, lexer::lex, parser::parse, flow_graph::flow};
+use analyzer::{analysis::analyze, bmc, concurrency, flow_graph::{self, FlowGraph, Origin, build}, gcl, hoare, html, lexer::{self, Span, lex, lex_spans}, microc::stmt::Program, parser::{self, parse_spans}, lsp, repl::{self, Session}, security, slicing, symbolic, taint, temporal, testgen};
+use analyzer::interpreter::{self, Value};
+use petgraph::graph::EdgeIndex;
 use structopt::StructOpt;
-use std::path::PathBuf;
-
-mod microc;
-mod flow_graph;
-mod parser;
-mod analysis;
-mod lexer;
-mod worklist;
+use std::{collections::HashMap, convert::TryFrom, fs::{read_to_string, write}, io::{BufRead, stdin, stdout}, path::{Path, PathBuf}};
 
 /// patterns:
 /// - reaching definitions (rd)
 /// - sign analysis (sa)
-/// - ...
-/// - * (all)
+/// - interval analysis (ia)
+/// - flow-sensitive points-to analysis (pt)
+/// - flow-insensitive points-to analysis of Andersen (andersen)
+/// - division-by-zero and array-bounds checks (safety)
+/// - `assert` statements proven or violated with the interval and sign analyses (asserts)
+/// - symbolic execution, with inputs covering the branches and failing the assertions, loops unrolled `--unroll` times (symbolic)
+/// - bounded model checking of the assertions, with runs of at most `--bound` edges and `--width`-bit integers (bmc)
+/// - CTL or LTL property of `--property`, over the configurations with `--width`-bit integers (temporal)
+/// - information flow security (security)
+/// - taint analysis from reads to writes, array indexes and loop guards (taint)
+/// - backward slice, or forward slice with `--forward` (slice)
+/// - races between the components of `par` statements (races)
+/// - nodes explored with and without partial order reduction (interleavings)
+/// - proof of the `requires`, `ensures` and `invariant` annotations (verify)
+/// - inputs covering the edges of the program graph, written to the `--suite` file (gen-tests)
+/// - runs of the program on the inputs of the `--suite` file, or on the standard input (run)
+/// - formatting of the program (fmt)
+/// - tokens of the program, with their whitespace and comments with `--trivia` (lex)
+/// - HTML page of the program with the states of `--analyses`, its graph and the findings of the checks (html)
+/// - interactive session, starting from the program if given (repl)
+/// - language server over the standard input and output (lsp)
+///
+/// Files ending in `.gcl` are Guarded Commands programs, on which the patterns needing a MicroC AST (fmt, verify, slice, races, interleavings, html, repl) are not available.
 ///
 #[derive(StructOpt)]
 struct Cli {
@@ -24,20 +37,236 @@ struct Cli {
 	analysis: String,
 	/// The path to the file to read
 	#[structopt(parse(from_os_str))]
-	path: PathBuf,
+	path: Option<PathBuf>,
+	/// Security levels of the variables, instead of the `//@ level: variable, ...` comments of the file
+	#[structopt(long, parse(from_os_str))]
+	annotations: Option<PathBuf>,
+	/// Sanitizer patterns of the taint analysis, one `variable op bound` guard per line
+	#[structopt(long, parse(from_os_str))]
+	sanitizers: Option<PathBuf>,
+	/// Slicing criterion `node[:variable, ...]`, the variables defaulting to the ones used at the node
+	#[structopt(long)]
+	criterion: Option<String>,
+	/// Computes the forward slice from the criterion node instead of the backward slice
+	#[structopt(long)]
+	forward: bool,
+	/// Constructs the deterministic program graph of a Guarded Commands program
+	#[structopt(long)]
+	deterministic: bool,
+	/// Dumps the whitespace and comments preceding each token
+	#[structopt(long)]
+	trivia: bool,
+	/// File of test inputs, one run per line, written by `gen-tests` and read by `run`
+	#[structopt(long, parse(from_os_str))]
+	suite: Option<PathBuf>,
+	/// Seed of the random inputs of `gen-tests`
+	#[structopt(long, default_value = "1")]
+	seed: u64,
+	/// Number of runs `gen-tests` may try
+	#[structopt(long, default_value = "10000")]
+	budget: usize,
+	/// Number of times a node may occur on a path of the symbolic execution
+	#[structopt(long, default_value = "3")]
+	unroll: usize,
+	/// Number of edges of the runs the bounded model checker explores
+	#[structopt(long, default_value = "20")]
+	bound: usize,
+	/// Number of bits of the integers of the bounded model checker and of the temporal properties, whose arithmetic wraps around
+	#[structopt(long, default_value = "8")]
+	width: usize,
+	/// Temporal property, like `AG (x >= 0)` in CTL or `G (read -> F write)` in LTL
+	#[structopt(long)]
+	property: Option<String>,
+	/// Analyses whose states the HTML page shows on the source lines, among rd, sa and ia
+	#[structopt(long, default_value = "rd,sa,ia", use_delimiter = true)]
+	analyses: Vec<String>,
 }
 
-fn main() {
-	let args = Cli::from_args();
-
-	println!("Lexing...");
-	if let Ok(tokens) = lex(&args.path.as_path()) {
-		println!("Parsing...");
-		if let Ok(ast) = parse(tokens) {
-			println!("Flow graph generation...");
-			let fg = flow(ast);
-			println!("Analyzing...");
-			analyze(fg, args.analysis);
+/// Path of the program, which every pattern but `repl` needs.
+fn path(args: &Cli) -> Result<&Path, String> {
+	args.path.as_deref().ok_or_else(|| "Missing path of the program.".to_string())
+}
+
+/// Runs an interactive session on the standard input, starting from the program of the file if any.
+fn session(args: &Cli) -> Result<(), String> {
+	let mut session = Session::default();
+
+	if let Some(path) = &args.path {
+		session.add(&read_to_string(path).map_err(|e| format!("Cannot open '{}': {e}.", path.display()))?)?;
+	}
+
+	repl::repl(stdin().lock(), stdout(), session).map_err(|e| e.to_string())
+}
+
+/// Runs the information flow security analysis, with the classification from the annotation file or the source.
+fn security(fg: &FlowGraph, spans: &HashMap<EdgeIndex, Span>, args: &Cli) -> Result<String, String> {
+	let classification = match &args.annotations {
+		Some(path) => security::classification(&read_to_string(path).map_err(|e| e.to_string())?)?,
+		None => security::embedded(&read_to_string(path(args)?).map_err(|e| e.to_string())?)?,
+	};
+
+	Ok(security::report(fg, &security::check(fg, &classification), spans))
+}
+
+/// Runs the taint analysis, with the sanitizers of the pattern file if any.
+fn taint(fg: &FlowGraph, spans: &HashMap<EdgeIndex, Span>, args: &Cli) -> Result<String, String> {
+	let sanitizers = match &args.sanitizers {
+		Some(path) => taint::sanitizers(&read_to_string(path).map_err(|e| e.to_string())?)?,
+		None => vec![],
+	};
+
+	Ok(taint::report(fg, &taint::check(fg, &sanitizers), spans))
+}
+
+/// Slices the program on the criterion, printing the sliced program.
+fn slice(ast: &parser::Ast, fg: &FlowGraph, origins: &HashMap<EdgeIndex, Origin>, args: &Cli) -> Result<String, String> {
+	let criterion = slicing::Criterion::parse(args.criterion.as_ref().ok_or("Missing slicing criterion.")?)?;
+	let slice = if args.forward { slicing::forward(fg, criterion.node)? } else { slicing::backward(fg, &criterion)? };
+
+	Ok(Program(&slicing::program(ast, fg, origins, &slice)).to_string())
+}
+
+/// Runs the race detector or the exploration of the interleavings of the `par` statements.
+fn concurrency(ast: &parser::Ast, fg: &FlowGraph, origins: &HashMap<EdgeIndex, Origin>, spans: &HashMap<EdgeIndex, Span>, args: &Cli) -> Result<String, String> {
+	let threads = concurrency::threads(ast, origins);
+
+	match args.analysis.as_str() {
+		"races" => Ok(concurrency::report(fg, &concurrency::races(fg, origins, &threads), spans)),
+		_ => Ok(concurrency::interleavings(fg, &threads)),
+	}
+}
+
+/// Checks the temporal property on the configurations of the program.
+fn temporal(fg: &FlowGraph, spans: &HashMap<EdgeIndex, Span>, args: &Cli) -> Result<String, String> {
+	let property = args.property.clone().ok_or("Pattern 'temporal' needs a --property.")?;
+	let formula = temporal::Formula::try_from(property.clone())?;
+
+	temporal::check(fg, &formula, args.width).map(|outcome| temporal::report(fg, &property, &outcome, spans))
+}
+
+/// Generates a test suite, or runs the program on the tests of a suite or on the standard input.
+fn testing(fg: &FlowGraph, spans: &HashMap<EdgeIndex, Span>, args: &Cli) -> Result<String, String> {
+	if args.analysis == "gen-tests" {
+		let suite = testgen::generate(fg, args.seed, args.budget);
+
+		if let Some(path) = &args.suite {
+			write(path, testgen::file(&suite)).map_err(|e| format!("Cannot write '{}': {e}.", path.display()))?;
 		}
+
+		return Ok(testgen::report(fg, &suite, spans));
+	}
+
+	let tests = match &args.suite {
+		Some(path) => testgen::parse(&read_to_string(path).map_err(|e| format!("Cannot open '{}': {e}.", path.display()))?)?,
+		None => vec![stdin().lock().lines().map(|line| Value::try_from(line.map_err(|e| e.to_string())?)).collect::<Result<Vec<Value>, String>>()?],
+	};
+
+	Ok(tests.into_iter().enumerate().map(|(i, inputs)| {
+		let mut inputs = inputs.into_iter();
+		let mut outputs = vec![];
+		let result = interpreter::run(fg, |lvalue| inputs.next().ok_or(format!("No more input for `{lvalue}`.")), |value| outputs.push(value.to_string()));
+
+		format!("run {}: output [{}], {}", i + 1, outputs.join(", "), result.map_or_else(|e| e, |state| state.to_string()))
+	}).collect::<Vec<String>>().join("\n"))
+}
+
+/// Runs the analysis of the pattern on the program graph of a Guarded Commands program.
+fn guarded_commands(path: &Path, args: &Cli) -> Result<String, String> {
+	let source = read_to_string(path).map_err(|e| format!("Cannot open '{}': {e}.", path.display()))?;
+	let fg = gcl::flow(&gcl::parse(&source).map_err(|e| format!("{}: {e}", path.display()))?, args.deterministic);
+	let spans = HashMap::new();
+
+	match args.analysis.as_str() {
+		"security" => security(&fg, &spans, args),
+		"taint" => taint(&fg, &spans, args),
+		"symbolic" => Ok(symbolic::report(&fg, &symbolic::execute(&fg, args.unroll), &spans)),
+		"bmc" => bmc::check(&fg, args.bound, args.width).map(|counterexample| bmc::report(&fg, &counterexample, args.bound, args.width, &spans)),
+		"temporal" => temporal(&fg, &spans, args),
+		"gen-tests" | "run" => testing(&fg, &spans, args),
+		pattern @ ("fmt" | "verify" | "slice" | "races" | "interleavings" | "html") => Err(format!("Pattern '{pattern}' is only available for MicroC programs.")),
+		_ => analyze(fg, args.analysis.clone(), &HashMap::new(), &spans),
+	}
+}
+
+/// Generates the HTML page of the program.
+fn page(path: &Path, args: &Cli) -> Result<String, String> {
+	let source = read_to_string(path).map_err(|e| format!("Cannot open '{}': {e}.", path.display()))?;
+
+	html::report(&path.display().to_string(), &source, &args.analyses)
+}
+
+/// Dumps the tokens of the file one per line, preceded by their trivia if asked.
+fn tokens(args: &Cli) -> Result<String, String> {
+	if args.trivia {
+		let stream = lexer::lex_trivia(&read_to_string(path(args)?).map_err(|e| e.to_string())?)?;
+
+		Ok(stream.lexemes.iter()
+			.map(|lexeme| format!("{:?} {:?}", lexeme.leading, lexeme.token))
+			.chain(std::iter::once(format!("{:?}", stream.trailing)))
+			.collect::<Vec<String>>()
+			.join("\n"))
+	} else {
+		Ok(lex(path(args)?)?.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>().join("\n"))
+	}
+}
+
+/// Runs the pattern of the arguments, printing its report.
+fn execute(args: Cli) -> Result<(), String> {
+	match args.analysis.as_str() {
+		"lex" => return tokens(&args).map(|dump| println!("{dump}")),
+		"repl" => return session(&args),
+		"lsp" => return lsp::serve(stdin().lock(), stdout()).map_err(|e| e.to_string()),
+		_ => (),
+	}
+
+	let path = path(&args)?;
+
+	if path.extension().is_some_and(|extension| extension == "gcl") {
+		return guarded_commands(path, &args).map(|report| println!("{report}"));
+	}
+
+	if args.analysis == "html" {
+		return page(path, &args).map(|page| print!("{page}"));
+	}
+
+	// progress goes to stderr, leaving stdout to the report
+	eprintln!("Lexing...");
+	let (tokens, spans) = lex_spans(path)?;
+
+	eprintln!("Parsing...");
+	let (ast, spans) = parse_spans(&tokens, &spans).map_err(|e| format!("{}: {}", path.display(), parser::error_message(&spans, e)))?;
+
+	match args.analysis.as_str() {
+		"fmt" => println!("{}", Program(&ast)),
+		"verify" => println!("{}", hoare::report(&hoare::verify(&ast), &spans)),
+		_ => {
+			eprintln!("Flow graph generation...");
+			let (fg, origins) = build(&ast);
+			let spans = flow_graph::spans(&origins, &spans);
+			eprintln!("Analyzing...");
+			let report = match args.analysis.as_str() {
+				"security" => security(&fg, &spans, &args),
+				"taint" => taint(&fg, &spans, &args),
+				"slice" => slice(&ast, &fg, &origins, &args),
+				"symbolic" => Ok(symbolic::report(&fg, &symbolic::execute(&fg, args.unroll), &spans)),
+				"bmc" => bmc::check(&fg, args.bound, args.width).map(|counterexample| bmc::report(&fg, &counterexample, args.bound, args.width, &spans)),
+				"temporal" => temporal(&fg, &spans, &args),
+				"gen-tests" | "run" => testing(&fg, &spans, &args),
+				"races" | "interleavings" => concurrency(&ast, &fg, &origins, &spans, &args),
+				_ => analyze(fg, args.analysis.clone(), &origins, &spans),
+			}?;
+
+			println!("{report}");
+		},
+	}
+
+	Ok(())
+}
+
+/// Exits with a failure status on errors, for scripts to tell them from reports.
+fn main() {
+	if let Err(e) = execute(Cli::from_args()) {
+		eprintln!("{e}");
+		std::process::exit(1);
 	}
 }
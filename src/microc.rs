@@ -1,13 +1,23 @@
+//! AST of MicroC programs, with its printer, visitors and folders.
+
+/// Operators of the expressions.
 pub mod ops {
-	use std::{convert::TryFrom, fmt::{self, Display, Formatter}};
+	use std::{convert::TryFrom, fmt::{self, Display, Formatter}, ops::Not};
 
-	#[derive(Debug, Clone)]
+	#[derive(Debug, Clone, PartialEq)]
+	/// Operator combining boolean expressions.
 	pub enum BinaryOp {
+		/// `&`, conjunction.
 		BitAnd,
+		/// `|`, disjunction.
 		BitOr,
+		/// `^`, exclusive disjunction.
 		BitXor,
+		/// `!`, negation.
 		Not,
+		/// `<<`, left shift.
 		Shl,
+		/// `>>`, right shift.
 		Shr,
 	}
 
@@ -27,6 +37,19 @@ pub mod ops {
 		}
 	}
 
+	impl BinaryOp {
+		/// Binding strength of the operator, operators of equal precedence associating to the left.
+		pub fn precedence(&self) -> u8 {
+			use BinaryOp::*;
+
+			match *self {
+				BitOr => 1,
+				BitXor => 2,
+				BitAnd | Not | Shl | Shr => 3,
+			}
+		}
+	}
+
 	impl TryFrom<String> for BinaryOp {
 		type Error = String;
 
@@ -45,13 +68,20 @@ pub mod ops {
 		}
 	}
 
-	#[derive(Debug, Clone)]
+	#[derive(Debug, Clone, PartialEq)]
+	/// Operator combining arithmetic expressions.
 	pub enum ArithmeticOp {
+		/// `+`, addition.
 		Add,
+		/// `-`, subtraction.
 		Sub,
+		/// `*`, multiplication.
 		Mul,
+		/// `-`, negation, subtracting its operand from a zero that is not written.
 		Neg,
+		/// `/`, division.
 		Div,
+		/// `%`, remainder.
 		Rem,
 	}
 
@@ -70,6 +100,19 @@ pub mod ops {
 		}
 	}
 
+	impl ArithmeticOp {
+		/// Binding strength of the operator, operators of equal precedence associating to the left.
+		pub fn precedence(&self) -> u8 {
+			use ArithmeticOp::*;
+
+			match *self {
+				Add | Sub => 1,
+				Mul | Div | Rem => 2,
+				Neg => 3,
+			}
+		}
+	}
+
 	impl TryFrom<String> for ArithmeticOp {
 		type Error = String;
 
@@ -78,7 +121,7 @@ pub mod ops {
 
 			match value.as_str() {
 				"+" => Ok(Add),
-				"-" => Err(format!("Context needed to parse either Subtraction or Negation operation.")),
+				"-" => Err("Context needed to parse either Subtraction or Negation operation.".to_string()),
 				"*" => Ok(Mul),
 				"/" => Ok(Div),
 				"%" => Ok(Rem),
@@ -87,13 +130,20 @@ pub mod ops {
 		}
 	}
 
-	#[derive(Debug, Clone)]
+	#[derive(Debug, Clone, PartialEq)]
+	/// Comparison of arithmetic expressions.
 	pub enum RelationalOp {
+		/// `<`
 		Lt,
+		/// `<=`
 		Leq,
+		/// `>`
 		Gt,
+		/// `>=`
 		Geq,
+		/// `==`
 		Eq,
+		/// `!=`
 		Neq,
 	}
 
@@ -112,119 +162,115 @@ pub mod ops {
 		}
 	}
 
-	impl TryFrom<String> for RelationalOp {
-		type Error = String;
+	/// Complement of the comparison, such that `!(a op b)` is `a !op b`.
+	impl Not for RelationalOp {
+		type Output = Self;
 
-		fn try_from(value: String) -> Result<Self, Self::Error> {
+		fn not(self) -> Self::Output {
 			use RelationalOp::*;
 
-			match value.as_str() {
-				"<" => Ok(Lt),
-				"<=" => Ok(Leq),
-				">" => Ok(Gt),
-				">=" => Ok(Geq),
-				"==" => Ok(Eq),
-				"!=" => Ok(Neq),
-				_ => Err(format!("Unknown relational operator '{value}'."))
+			match self {
+				Lt => Geq,
+				Leq => Gt,
+				Gt => Leq,
+				Geq => Lt,
+				Eq => Neq,
+				Neq => Eq,
 			}
 		}
 	}
 
-	#[derive(Debug, Clone)]
-	enum AssignmentOp {
-		Assign,
-	}
-
-	impl Display for AssignmentOp {
-		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-			write!(f, ":=")
-		}
-	}
-
-	impl TryFrom<String> for AssignmentOp {
-		type Error = String;
-
-		fn try_from(value: String) -> Result<Self, Self::Error> {
-			if value == ":=" {
-				Ok(AssignmentOp::Assign)
-			} else {
-				Err(format!("Unknown assignment operator '{value}'."))
-			}
-		}
-	}
-
-	#[derive(Debug)]
-	enum Operator {
-		ArithmeticOp(ArithmeticOp),
-		AssignmentOp(AssignmentOp),
-		BinaryOp(BinaryOp),
-		RelationalOp(RelationalOp),
-	}
-
-	impl Display for Operator {
-		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-			use Operator::*;
+	impl RelationalOp {
+		/// Comparison with swapped operands, such that `a op b` is `b op.flip() a`.
+		pub fn flip(&self) -> Self {
+			use RelationalOp::*;
 
-			match &*self {
-				ArithmeticOp(op) => Ok(op.fmt(f).unwrap()),
-				AssignmentOp(op) => Ok(op.fmt(f).unwrap()),
-				BinaryOp(op) => Ok(op.fmt(f).unwrap()),
-				RelationalOp(op) => Ok(op.fmt(f).unwrap()),
+			match *self {
+				Lt => Gt,
+				Leq => Geq,
+				Gt => Lt,
+				Geq => Leq,
+				Eq => Eq,
+				Neq => Neq,
 			}
 		}
 	}
 
-	impl TryFrom<String> for Operator {
+	impl TryFrom<String> for RelationalOp {
 		type Error = String;
 
 		fn try_from(value: String) -> Result<Self, Self::Error> {
-			if value ==":=" {
-				Ok(Operator::AssignmentOp(AssignmentOp::Assign))
-			} else if let Ok(op) = ArithmeticOp::try_from(value.clone()) {
-				Ok(Operator::ArithmeticOp(op))
-			} else if let Ok(op) = BinaryOp::try_from(value.clone()) {
-				Ok(Operator::BinaryOp(op))
-			} else if let Ok(op) = RelationalOp::try_from(value.clone()) {
-				Ok(Operator::RelationalOp(op))
-			} else {
-				Err(format!("Unknown operator '{value}'."))
+			use RelationalOp::*;
+
+			match value.as_str() {
+				"<" => Ok(Lt),
+				"<=" => Ok(Leq),
+				">" => Ok(Gt),
+				">=" => Ok(Geq),
+				"==" => Ok(Eq),
+				"!=" => Ok(Neq),
+				_ => Err(format!("Unknown relational operator '{value}'."))
 			}
 		}
 	}
 }
 
+/// Declarations of variables, arrays, pointers, records and procedures.
 pub mod decl {
 	use crate::lexer::{keyword::Type, literal::IntegerLiteral};
+	use super::stmt::{Program, Scope};
 	use std::fmt::{self, Display, Formatter};
 
-	/// Size 2..n
-	#[derive(Debug, Clone)]
+	/// Declaration of a variable, array, pointer, record or procedure.
+	#[derive(Debug, Clone, PartialEq)]
 	pub enum Declaration {
+		/// Variable of the type.
 		Var(Type, String),
+		/// Array of the type, with the size of each dimension.
 		Array(Type, Vec<IntegerLiteral>, String),
+		/// Pointer to a variable of the type.
+		Pointer(Type, String),
+		/// Record with the declarations of its members.
 		Record(Vec<Declaration>, String),
+		/// Procedure with its value parameters, its result parameters and its body, only declared in the outermost scope.
+		Procedure(String, Vec<Declaration>, Vec<Declaration>, Box<Scope>),
+	}
+
+	impl Declaration {
+		/// Parameters of a procedure as written in its header, `int x, int y; int r`.
+		pub fn parameters(values: &[Declaration], results: &[Declaration]) -> String {
+			let list = |params: &[Declaration]| params.iter().map(|param| param.to_string().trim_end_matches(';').to_string()).collect::<Vec<String>>().join(", ");
+
+			if results.is_empty() { list(values) } else { format!("{}; {}", list(values), list(results)).trim_start().to_string() }
+		}
 	}
 
 	impl Display for Declaration {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 			use Declaration::*;
 
-			match &*self {
+			match self {
 				Var(_type, id) => write!(f, "{} {};", _type, id),
-				Array(_type, sizes, id) => write!(f, "[{}; {:?}] {};", _type, sizes, id),
-				Record(decls, id) => write!(f, "{{{:?}}} {};", decls, id),
+				Array(_type, sizes, id) => write!(f, "{}[{}] {};", _type, sizes.iter().map(|size| size.to_string()).collect::<Vec<String>>().join(", "), id),
+				Pointer(_type, id) => write!(f, "{}* {};", _type, id),
+				Record(decls, id) => write!(f, "{{{}}} {};", decls.iter().map(|decl| decl.to_string()).collect::<Vec<String>>().join(" "), id),
+				Procedure(..) => write!(f, "{}", Program(&(vec![self.clone()], vec![]))),
 			}
 		}
 	}
 }
 
+/// Arithmetic, boolean and lvalue expressions.
 pub mod expr {
+	use crate::lexer::literal::IntegerLiteral;
 	use std::{convert::TryFrom, fmt::{self, Display, Formatter}, string::String};
 
-	/// Size 1
-	#[derive(Debug, Clone)]
+	/// Number written in an arithmetic expression.
+	#[derive(Debug, Clone, PartialEq)]
 	pub enum ArithmeticLiteral {
-		Int(isize),
+		/// Integer literal.
+		Int(IntegerLiteral),
+		/// Float literal.
 		Float(f64),
 	}
 
@@ -234,7 +280,8 @@ pub mod expr {
 
 			match *self {
 				Int(int) => write!(f, "{int}"),
-				Float(float) => write!(f, "{float}"),
+				// the debug format keeps the fractional part of integral values
+				Float(float) => write!(f, "{:?}", float),
 			}
 		}
 	}
@@ -245,7 +292,7 @@ pub mod expr {
 		fn try_from(value: String) -> Result<Self, Self::Error> {
 			use ArithmeticLiteral::*;
 
-			if let Ok(int) = value.parse::<isize>() {
+			if let Ok(int) = IntegerLiteral::try_from(value.clone()) {
 				Ok(Int(int))
 			} else if let Ok(float) = value.parse::<f64>() {
 				Ok(Float(float))
@@ -255,22 +302,28 @@ pub mod expr {
 		}
 	}
 
-	/// Size 1..n
-	#[derive(Debug, Clone)]
+	/// Location that can be assigned and read.
+	#[derive(Debug, Clone, PartialEq)]
 	pub enum LvalueExpr {
+		/// Variable.
 		Variable(String),
-		ArrayIndex(String, Box<ArithmeticOperation>),
+		/// Element of an array, with an index for each dimension.
+		ArrayIndex(String, Vec<ArithmeticExpr>),
+		/// Member of a record.
 		RecordMember(String, String),
+		/// Variable the pointer points to.
+		Deref(String),
 	}
 
 	impl Display for LvalueExpr {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 			use LvalueExpr::*;
 
-			match &*self {
+			match self {
 				Variable(id) => write!(f, "{id}"),
-				ArrayIndex(id, op) => write!(f, "{id}, {:?}", op),
-				RecordMember(id, mem_id) => write!(f, "{id}, {mem_id}"),
+				ArrayIndex(id, indexes) => write!(f, "{id}[{}]", indexes.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ")),
+				RecordMember(id, mem_id) => write!(f, "{id}.{mem_id}"),
+				Deref(id) => write!(f, "*{id}"),
 			}
 		}
 	}
@@ -290,37 +343,74 @@ pub mod expr {
 		}
 	}
 
-	/// Size 3..n
+	/// Combination of two arithmetic expressions.
 	pub type ArithmeticOperation = (ArithmeticExpr, super::ops::ArithmeticOp, ArithmeticExpr);
+	/// Comparison of two arithmetic expressions.
 	pub type RelationalOperation = (ArithmeticExpr, super::ops::RelationalOp, ArithmeticExpr);
+	/// Combination of two boolean expressions.
 	pub type BinaryOperation = (BooleanExpr, super::ops::BinaryOp, BooleanExpr);
 
-	/// Size 1..n
-	#[derive(Debug, Clone)]
+	/// Expression evaluating to a number or an address.
+	#[derive(Debug, Clone, PartialEq)]
 	pub enum ArithmeticExpr {
+		/// Literal number.
 		Literal(ArithmeticLiteral),
+		/// Value of an lvalue.
 		LvalueExpr(LvalueExpr),
+		/// Operation on two arithmetic expressions.
 		ArithmeticOperation(Box<ArithmeticOperation>),
+		/// Address of a variable or record member.
+		Reference(LvalueExpr),
 	}
 
 	impl Display for ArithmeticExpr {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 			use ArithmeticExpr::*;
 
-			match &*self {
+			match self {
 				Literal(literal) => write!(f, "{literal}"),
 				LvalueExpr(lvalue) => write!(f, "{lvalue}"),
-				ArithmeticOperation(op) => write!(f, "{:?}", op),
+				Reference(lvalue) => write!(f, "&{lvalue}"),
+				ArithmeticOperation(op) if op.1 == super::ops::ArithmeticOp::Neg => {
+					// a negative operand is parenthesized, so that the signs do not read as a single one
+					write!(f, "-")?;
+					parenthesize(f, &op.2, op.2.precedence() < op.1.precedence() || op.2.to_string().starts_with('-'))
+				},
+				ArithmeticOperation(op) => {
+					let (arex1, arithop, arex2) = &**op;
+
+					parenthesize(f, arex1, arex1.precedence() < arithop.precedence())?;
+					write!(f, " {arithop} ")?;
+					parenthesize(f, arex2, arex2.precedence() <= arithop.precedence())
+				},
+			}
+		}
+	}
+
+	impl ArithmeticExpr {
+		fn precedence(&self) -> u8 {
+			match self {
+				ArithmeticExpr::ArithmeticOperation(op) => op.1.precedence(),
+				_ => u8::MAX,
 			}
 		}
 	}
 
-	/// Size 1..n
-	#[derive(Debug, Clone)]
+	/// Writes an operand, between parentheses if it binds less tightly than its operator.
+	fn parenthesize(f: &mut Formatter<'_>, operand: &impl Display, parentheses: bool) -> fmt::Result {
+		if parentheses { write!(f, "({operand})") } else { write!(f, "{operand}") }
+	}
+
+	/// Expression evaluating to a truth value.
+	#[derive(Debug, Clone, PartialEq)]
 	pub enum BooleanExpr {
+		/// `true` or `false`.
 		BooleanLiteral(bool),
+		/// Negation of a boolean expression.
 		NotOperation(Box<BooleanExpr>),
+		/// Comparison of two arithmetic expressions.
 		RelationalOperation(ArithmeticExpr, super::ops::RelationalOp, ArithmeticExpr),
+		/// Combination of two boolean expressions.
 		BinaryOperation(Box<BooleanExpr>, super::ops::BinaryOp, Box<BooleanExpr>),
 	}
 
@@ -328,20 +418,39 @@ pub mod expr {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 			use BooleanExpr::*;
 
-			match &*self {
+			match self {
 				BooleanLiteral(boolean) => write!(f, "{boolean}"),
-				NotOperation(boolex) => write!(f, "-{boolex}"),
+				NotOperation(boolex) => {
+					write!(f, "!")?;
+					parenthesize(f, boolex, !matches!(**boolex, BooleanLiteral(_) | NotOperation(_)))
+				},
 				RelationalOperation(arex1, relop, arex2) => write!(f, "{arex1} {relop} {arex2}"),
-				BinaryOperation(boolex1, binop, boolex2) => write!(f, "{boolex1} {binop} {boolex2}"),
+				BinaryOperation(boolex1, binop, boolex2) => {
+					parenthesize(f, boolex1, boolex1.precedence() < binop.precedence())?;
+					write!(f, " {binop} ")?;
+					parenthesize(f, boolex2, boolex2.precedence() <= binop.precedence())
+				},
 			}
 		}
 	}
 
-	/// 3..n
+	impl BooleanExpr {
+		fn precedence(&self) -> u8 {
+			match self {
+				BooleanExpr::BinaryOperation(_, binop, _) => binop.precedence(),
+				_ => u8::MAX,
+			}
+		}
+	}
+
+	/// Arithmetic, boolean or lvalue expression.
 	#[derive(Debug)]
 	pub enum Expression {
+		/// Arithmetic expression.
 		ArithmeticExpr(ArithmeticExpr),
+		/// Boolean expression.
 		BooleanExpr(BooleanExpr),
+		/// Lvalue.
 		LvalueExpr(LvalueExpr),
 	}
 
@@ -349,7 +458,7 @@ pub mod expr {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 			use Expression::*;
 
-			match &*self {
+			match self {
 				ArithmeticExpr(expr) => write!(f, "{expr}"),
 				BooleanExpr(expr) => write!(f, "{expr}"),
 				LvalueExpr(expr) => write!(f, "{expr}"),
@@ -358,49 +467,670 @@ pub mod expr {
 	}
 }
 
+/// Statements and scopes, with the pretty-printer of programs.
 pub mod stmt {
 	use std::fmt::{self, Display, Formatter};
-	use super::{expr::{ArithmeticExpr, BooleanExpr, LvalueExpr}, decl::Declaration};
+	use super::{expr::{ArithmeticExpr, BooleanExpr, LvalueExpr}, decl::Declaration, visit::Visitor};
 
+	/// Declarations of a block, followed by its statements.
 	pub type Scope = (Vec<Declaration>, Vec<Statement>);
 
-	/// Size 0..n
-	#[derive(Debug, Clone)]
+	/// Statement of a scope.
+	#[derive(Debug, Clone, PartialEq)]
 	pub enum Statement {
+		/// Assignment of an arithmetic expression to an lvalue.
 		LvalueAssign(LvalueExpr, ArithmeticExpr),
+		/// Assignment of an expression to each member of a record, in declaration order.
 		RecordAssign(String, Vec<ArithmeticExpr>),
+		/// Conditional without an `else` branch.
 		If(BooleanExpr, Box<Scope>),
+		/// Conditional with an `else` branch.
 		IfElse(BooleanExpr, Box<Scope>, Box<Scope>),
+		/// Loop running its body while the condition holds.
 		While(BooleanExpr, Box<Scope>),
+		/// Input of a value into an lvalue.
 		Read(LvalueExpr),
+		/// Output of the value of an arithmetic expression.
 		Write(ArithmeticExpr),
+		/// Exit of the innermost loop.
 		Break,
+		/// Jump to the condition of the innermost loop.
 		Continue,
+		/// Nested block.
 		Scope(Scope),
+		/// Call of a procedure with the arguments of its value parameters, and the lvalues receiving its result parameters.
+		Call(String, Vec<ArithmeticExpr>, Vec<LvalueExpr>),
+		/// Parallel composition of two scopes, whose actions interleave.
+		Par(Box<Scope>, Box<Scope>),
+		/// Condition stated for the verifier, which does not run.
+		Annotation(Annotation, BooleanExpr),
+		/// Condition checked at runtime, the program stopping with an error if it does not hold.
+		Assert(BooleanExpr),
+		/// Condition restricting the runs to the ones in which it holds.
+		Assume(BooleanExpr),
 	}
 
-	impl Display for Statement {
+	/// Kind of an annotation: precondition and postcondition of the program or of a procedure, or invariant of a loop.
+	#[derive(Debug, Clone, Copy, PartialEq)]
+	pub enum Annotation {
+		/// `requires`, precondition.
+		Requires,
+		/// `ensures`, postcondition.
+		Ensures,
+		/// `invariant`, loop invariant.
+		Invariant,
+	}
+
+	impl Display for Annotation {
 		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			match self {
+				Annotation::Requires => write!(f, "requires"),
+				Annotation::Ensures => write!(f, "ensures"),
+				Annotation::Invariant => write!(f, "invariant"),
+			}
+		}
+	}
+
+	/// Arguments of a call as written in the source, `x + 1, 2; r`.
+	pub fn arguments(arexs: &[ArithmeticExpr], lvalues: &[LvalueExpr]) -> String {
+		let arexs = arexs.iter().map(|arex| arex.to_string()).collect::<Vec<String>>().join(", ");
+
+		if lvalues.is_empty() { arexs } else { format!("{arexs}; {}", lvalues.iter().map(|lvalue| lvalue.to_string()).collect::<Vec<String>>().join(", ")).trim_start().to_string() }
+	}
+
+	/// Pretty-printer of MicroC source, the items of nested scopes being indented by one more tab.
+	#[derive(Default)]
+	struct Printer {
+		source: String,
+		depth: usize,
+	}
+
+	impl Printer {
+		/// Writes a scope between braces, its items being indented one level deeper.
+		fn block(&mut self, scope: &Scope) {
+			if scope.0.is_empty() && scope.1.is_empty() {
+				self.source.push_str("{}");
+			} else {
+				self.depth += 1;
+				self.source.push_str("{\n");
+				self.visit_scope(scope);
+				self.depth -= 1;
+				self.source += &format!("\n{}}}", "\t".repeat(self.depth));
+			}
+		}
+	}
+
+	impl Visitor for Printer {
+		/// Writes the items of the scope one per line.
+		fn visit_scope(&mut self, (decls, stmts): &Scope) {
+			let indent = "\t".repeat(self.depth);
+
+			for (i, decl) in decls.iter().enumerate() {
+				self.source += &format!("{}{indent}", if i == 0 { "" } else { "\n" });
+				self.visit_decl(decl);
+			}
+
+			for (i, stmt) in stmts.iter().enumerate() {
+				self.source += &format!("{}{indent}", if i == 0 && decls.is_empty() { "" } else { "\n" });
+				self.visit_stmt(stmt);
+			}
+		}
+
+		fn visit_decl(&mut self, decl: &Declaration) {
+			match decl {
+				Declaration::Procedure(id, values, results, body) => {
+					self.source += &format!("proc {id}({}) ", Declaration::parameters(values, results));
+					self.block(body);
+				},
+				_ => self.source += &decl.to_string(),
+			}
+		}
+
+		/// Writes a statement starting at the current column.
+		fn visit_stmt(&mut self, stmt: &Statement) {
 			use Statement::*;
 
-			match &*self {
-				LvalueAssign(lvalue, arex) => write!(f, "{lvalue} := {arex};"),
-				RecordAssign(recid, arex) => write!(f, "{recid} := {:?};", arex),
-				If(boolex, scope) => write!(f, "if {boolex} {:?}", scope),
-				IfElse(boolex, scope1, scope2) => write!(f, "if {boolex} {:?} else {:?}", scope1, scope2),
-				While(boolex, scope) => write!(f, "while {boolex} {:?}", scope),
-				Read(lvalue) => write!(f, "read {lvalue};"),
-				Write(arex) => write!(f, "write {arex};"),
-				Break => write!(f, "break;"),
-				Continue => write!(f, "continue;"),
-				Scope((decls, stmts)) => if decls.is_empty() && stmts.is_empty() {
-					write!(f, ";")
-				} else if decls.len() + stmts.len() == 1 {
-					write!(f, "{:?} {:?}", decls, stmts)
-				} else {
-					write!(f, "{{\n{:?}\n{:?}\n}}", decls, stmts)
-				}
+			match stmt {
+				LvalueAssign(lvalue, arex) => self.source += &format!("{lvalue} := {arex};"),
+				RecordAssign(recid, arexs) => self.source += &format!("{recid} := ({});", arexs.iter().map(|arex| arex.to_string()).collect::<Vec<String>>().join(", ")),
+				If(boolex, scope) => {
+					self.source += &format!("if {boolex} ");
+					self.block(scope);
+				},
+				IfElse(boolex, scope1, scope2) => {
+					self.source += &format!("if {boolex} ");
+					self.block(scope1);
+					self.source.push_str(" else ");
+					self.block(scope2);
+				},
+				While(boolex, scope) => {
+					self.source += &format!("while {boolex} ");
+					self.block(scope);
+				},
+				Read(lvalue) => self.source += &format!("read {lvalue};"),
+				Write(arex) => self.source += &format!("write {arex};"),
+				Break => self.source.push_str("break;"),
+				Continue => self.source.push_str("continue;"),
+				Scope(scope) => self.block(scope),
+				Call(id, arexs, lvalues) => self.source += &format!("call {id}({});", arguments(arexs, lvalues)),
+				Par(scope1, scope2) => {
+					self.source.push_str("par ");
+					self.block(scope1);
+					self.source.push_str(" and ");
+					self.block(scope2);
+				},
+				Annotation(annotation, boolex) => self.source += &format!("{annotation} {boolex};"),
+				Assert(boolex) => self.source += &format!("assert {boolex};"),
+				Assume(boolex) => self.source += &format!("assume {boolex};"),
 			}
 		}
 	}
+
+	impl Display for Statement {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			let mut printer = Printer::default();
+
+			printer.visit_stmt(self);
+			write!(f, "{}", printer.source)
+		}
+	}
+
+	/// MicroC source of a whole program, such that parsing it gives back the same AST.
+	pub struct Program<'a>(pub &'a Scope);
+
+	impl Display for Program<'_> {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			let mut printer = Printer::default();
+
+			printer.visit_scope(self.0);
+			write!(f, "{}", printer.source)
+		}
+	}
+}
+
+/// Read-only traversal of the AST.
+///
+/// Every method defaults to the `walk_` function visiting the children of its node, so that a visitor only overrides the nodes it cares about.
+pub mod visit {
+	use super::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, Expression, LvalueExpr}, stmt::{Scope, Statement}};
+
+	/// Visitor of the nodes of the AST.
+	pub trait Visitor {
+		/// Visits a scope.
+		fn visit_scope(&mut self, scope: &Scope) {
+			walk_scope(self, scope)
+		}
+
+		/// Visits a declaration.
+		fn visit_decl(&mut self, decl: &Declaration) {
+			walk_decl(self, decl)
+		}
+
+		/// Visits a statement.
+		fn visit_stmt(&mut self, stmt: &Statement) {
+			walk_stmt(self, stmt)
+		}
+
+		/// Visits an expression.
+		fn visit_expr(&mut self, expr: &Expression) {
+			walk_expr(self, expr)
+		}
+
+		/// Visits an arithmetic expression.
+		fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+			walk_arex(self, arex)
+		}
+
+		/// Visits a boolean expression.
+		fn visit_boolex(&mut self, boolex: &BooleanExpr) {
+			walk_boolex(self, boolex)
+		}
+
+		/// Visits an lvalue.
+		fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+			walk_lvalue(self, lvalue)
+		}
+
+		/// Visits a literal.
+		fn visit_literal(&mut self, _literal: &ArithmeticLiteral) {}
+	}
+
+	/// Visits the declarations of the scope, then its statements.
+	pub fn walk_scope<V: Visitor + ?Sized>(visitor: &mut V, (decls, stmts): &Scope) {
+		decls.iter().for_each(|decl| visitor.visit_decl(decl));
+		stmts.iter().for_each(|stmt| visitor.visit_stmt(stmt));
+	}
+
+	/// Visits the members of a record, or the parameters of a procedure followed by its body.
+	pub fn walk_decl<V: Visitor + ?Sized>(visitor: &mut V, decl: &Declaration) {
+		match decl {
+			Declaration::Record(decls, _) => decls.iter().for_each(|decl| visitor.visit_decl(decl)),
+			Declaration::Procedure(_, values, results, body) => {
+				values.iter().chain(results).for_each(|decl| visitor.visit_decl(decl));
+				visitor.visit_scope(body);
+			},
+			_ => (),
+		}
+	}
+
+	/// Visits the expressions of the statement, then its scopes.
+	pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Statement) {
+		use Statement::*;
+
+		match stmt {
+			LvalueAssign(lvalue, arex) => {
+				visitor.visit_lvalue(lvalue);
+				visitor.visit_arex(arex);
+			},
+			RecordAssign(_, arexs) => arexs.iter().for_each(|arex| visitor.visit_arex(arex)),
+			If(boolex, scope) | While(boolex, scope) => {
+				visitor.visit_boolex(boolex);
+				visitor.visit_scope(scope);
+			},
+			IfElse(boolex, scope1, scope2) => {
+				visitor.visit_boolex(boolex);
+				visitor.visit_scope(scope1);
+				visitor.visit_scope(scope2);
+			},
+			Par(scope1, scope2) => {
+				visitor.visit_scope(scope1);
+				visitor.visit_scope(scope2);
+			},
+			Annotation(_, boolex) | Assert(boolex) | Assume(boolex) => visitor.visit_boolex(boolex),
+			Read(lvalue) => visitor.visit_lvalue(lvalue),
+			Write(arex) => visitor.visit_arex(arex),
+			Break | Continue => (),
+			Scope(scope) => visitor.visit_scope(scope),
+			Call(_, arexs, lvalues) => {
+				arexs.iter().for_each(|arex| visitor.visit_arex(arex));
+				lvalues.iter().for_each(|lvalue| visitor.visit_lvalue(lvalue));
+			},
+		}
+	}
+
+	/// Visits the arithmetic or boolean expression or lvalue it holds.
+	pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+		match expr {
+			Expression::ArithmeticExpr(arex) => visitor.visit_arex(arex),
+			Expression::BooleanExpr(boolex) => visitor.visit_boolex(boolex),
+			Expression::LvalueExpr(lvalue) => visitor.visit_lvalue(lvalue),
+		}
+	}
+
+	/// Visits the lvalue, the literal or the operands of the arithmetic expression.
+	pub fn walk_arex<V: Visitor + ?Sized>(visitor: &mut V, arex: &ArithmeticExpr) {
+		match arex {
+			ArithmeticExpr::Literal(literal) => visitor.visit_literal(literal),
+			ArithmeticExpr::LvalueExpr(lvalue) | ArithmeticExpr::Reference(lvalue) => visitor.visit_lvalue(lvalue),
+			ArithmeticExpr::ArithmeticOperation(op) => {
+				visitor.visit_arex(&op.0);
+				visitor.visit_arex(&op.2);
+			},
+		}
+	}
+
+	/// Visits the operands of the boolean expression.
+	pub fn walk_boolex<V: Visitor + ?Sized>(visitor: &mut V, boolex: &BooleanExpr) {
+		use BooleanExpr::*;
+
+		match boolex {
+			BooleanLiteral(_) => (),
+			NotOperation(boolex) => visitor.visit_boolex(boolex),
+			RelationalOperation(arex1, _, arex2) => {
+				visitor.visit_arex(arex1);
+				visitor.visit_arex(arex2);
+			},
+			BinaryOperation(boolex1, _, boolex2) => {
+				visitor.visit_boolex(boolex1);
+				visitor.visit_boolex(boolex2);
+			},
+		}
+	}
+
+	/// Visits the indexes of an array access.
+	pub fn walk_lvalue<V: Visitor + ?Sized>(visitor: &mut V, lvalue: &LvalueExpr) {
+		if let LvalueExpr::ArrayIndex(_, indexes) = lvalue {
+			indexes.iter().for_each(|arex| visitor.visit_arex(arex));
+		}
+	}
+}
+
+/// Rewriting of the AST, taking nodes by value and giving back their replacement.
+///
+/// Every method defaults to the `walk_` function rebuilding its node from its folded children, so that a folder only overrides the nodes it rewrites.
+pub mod fold {
+	use super::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, Expression, LvalueExpr}, stmt::{Scope, Statement}};
+
+	/// Folder of the nodes of the AST into their replacements.
+	pub trait Folder {
+		/// Folds a scope.
+		fn fold_scope(&mut self, scope: Scope) -> Scope {
+			walk_scope(self, scope)
+		}
+
+		/// Folds a declaration.
+		fn fold_decl(&mut self, decl: Declaration) -> Declaration {
+			walk_decl(self, decl)
+		}
+
+		/// Folds a statement.
+		fn fold_stmt(&mut self, stmt: Statement) -> Statement {
+			walk_stmt(self, stmt)
+		}
+
+		/// Folds an expression.
+		fn fold_expr(&mut self, expr: Expression) -> Expression {
+			walk_expr(self, expr)
+		}
+
+		/// Folds an arithmetic expression.
+		fn fold_arex(&mut self, arex: ArithmeticExpr) -> ArithmeticExpr {
+			walk_arex(self, arex)
+		}
+
+		/// Folds a boolean expression.
+		fn fold_boolex(&mut self, boolex: BooleanExpr) -> BooleanExpr {
+			walk_boolex(self, boolex)
+		}
+
+		/// Folds an lvalue.
+		fn fold_lvalue(&mut self, lvalue: LvalueExpr) -> LvalueExpr {
+			walk_lvalue(self, lvalue)
+		}
+
+		/// Folds a literal.
+		fn fold_literal(&mut self, literal: ArithmeticLiteral) -> ArithmeticLiteral {
+			literal
+		}
+	}
+
+	/// Folds the declarations of the scope, then its statements.
+	pub fn walk_scope<F: Folder + ?Sized>(folder: &mut F, (decls, stmts): Scope) -> Scope {
+		let decls = decls.into_iter().map(|decl| folder.fold_decl(decl)).collect();
+		let stmts = stmts.into_iter().map(|stmt| folder.fold_stmt(stmt)).collect();
+
+		(decls, stmts)
+	}
+
+	/// Folds the members of a record, or the parameters of a procedure followed by its body.
+	pub fn walk_decl<F: Folder + ?Sized>(folder: &mut F, decl: Declaration) -> Declaration {
+		match decl {
+			Declaration::Record(decls, id) => Declaration::Record(decls.into_iter().map(|decl| folder.fold_decl(decl)).collect(), id),
+			Declaration::Procedure(id, values, results, body) => {
+				let values = values.into_iter().map(|decl| folder.fold_decl(decl)).collect();
+				let results = results.into_iter().map(|decl| folder.fold_decl(decl)).collect();
+
+				Declaration::Procedure(id, values, results, Box::new(folder.fold_scope(*body)))
+			},
+			_ => decl,
+		}
+	}
+
+	/// Folds the expressions of the statement, then its scopes.
+	pub fn walk_stmt<F: Folder + ?Sized>(folder: &mut F, stmt: Statement) -> Statement {
+		use Statement::*;
+
+		match stmt {
+			LvalueAssign(lvalue, arex) => LvalueAssign(folder.fold_lvalue(lvalue), folder.fold_arex(arex)),
+			RecordAssign(id, arexs) => RecordAssign(id, arexs.into_iter().map(|arex| folder.fold_arex(arex)).collect()),
+			If(boolex, scope) => If(folder.fold_boolex(boolex), Box::new(folder.fold_scope(*scope))),
+			IfElse(boolex, scope1, scope2) => IfElse(folder.fold_boolex(boolex), Box::new(folder.fold_scope(*scope1)), Box::new(folder.fold_scope(*scope2))),
+			While(boolex, scope) => While(folder.fold_boolex(boolex), Box::new(folder.fold_scope(*scope))),
+			Read(lvalue) => Read(folder.fold_lvalue(lvalue)),
+			Write(arex) => Write(folder.fold_arex(arex)),
+			Break | Continue => stmt,
+			Scope(scope) => Scope(folder.fold_scope(scope)),
+			Call(id, arexs, lvalues) => {
+				let arexs = arexs.into_iter().map(|arex| folder.fold_arex(arex)).collect();
+
+				Call(id, arexs, lvalues.into_iter().map(|lvalue| folder.fold_lvalue(lvalue)).collect())
+			},
+			Par(scope1, scope2) => Par(Box::new(folder.fold_scope(*scope1)), Box::new(folder.fold_scope(*scope2))),
+			Annotation(annotation, boolex) => Annotation(annotation, folder.fold_boolex(boolex)),
+			Assert(boolex) => Assert(folder.fold_boolex(boolex)),
+			Assume(boolex) => Assume(folder.fold_boolex(boolex)),
+		}
+	}
+
+	/// Folds the arithmetic or boolean expression or lvalue it holds.
+	pub fn walk_expr<F: Folder + ?Sized>(folder: &mut F, expr: Expression) -> Expression {
+		match expr {
+			Expression::ArithmeticExpr(arex) => Expression::ArithmeticExpr(folder.fold_arex(arex)),
+			Expression::BooleanExpr(boolex) => Expression::BooleanExpr(folder.fold_boolex(boolex)),
+			Expression::LvalueExpr(lvalue) => Expression::LvalueExpr(folder.fold_lvalue(lvalue)),
+		}
+	}
+
+	/// Folds the lvalue, the literal or the operands of the arithmetic expression.
+	pub fn walk_arex<F: Folder + ?Sized>(folder: &mut F, arex: ArithmeticExpr) -> ArithmeticExpr {
+		match arex {
+			ArithmeticExpr::Literal(literal) => ArithmeticExpr::Literal(folder.fold_literal(literal)),
+			ArithmeticExpr::LvalueExpr(lvalue) => ArithmeticExpr::LvalueExpr(folder.fold_lvalue(lvalue)),
+			ArithmeticExpr::Reference(lvalue) => ArithmeticExpr::Reference(folder.fold_lvalue(lvalue)),
+			ArithmeticExpr::ArithmeticOperation(op) => {
+				let (arex1, arithop, arex2) = *op;
+
+				ArithmeticExpr::ArithmeticOperation(Box::new((folder.fold_arex(arex1), arithop, folder.fold_arex(arex2))))
+			},
+		}
+	}
+
+	/// Folds the operands of the boolean expression.
+	pub fn walk_boolex<F: Folder + ?Sized>(folder: &mut F, boolex: BooleanExpr) -> BooleanExpr {
+		use BooleanExpr::*;
+
+		match boolex {
+			BooleanLiteral(_) => boolex,
+			NotOperation(boolex) => NotOperation(Box::new(folder.fold_boolex(*boolex))),
+			RelationalOperation(arex1, relop, arex2) => RelationalOperation(folder.fold_arex(arex1), relop, folder.fold_arex(arex2)),
+			BinaryOperation(boolex1, binop, boolex2) => BinaryOperation(Box::new(folder.fold_boolex(*boolex1)), binop, Box::new(folder.fold_boolex(*boolex2))),
+		}
+	}
+
+	/// Folds the indexes of an array access.
+	pub fn walk_lvalue<F: Folder + ?Sized>(folder: &mut F, lvalue: LvalueExpr) -> LvalueExpr {
+		match lvalue {
+			LvalueExpr::ArrayIndex(id, indexes) => LvalueExpr::ArrayIndex(id, indexes.into_iter().map(|arex| folder.fold_arex(arex)).collect()),
+			_ => lvalue,
+		}
+	}
+}
+
+/// Identity of the nodes of the AST.
+pub mod node {
+	use super::{decl::Declaration, expr::{ArithmeticExpr, BooleanExpr, LvalueExpr}, stmt::{Scope, Statement}, visit::{self, Visitor}};
+	use std::fmt::{self, Display, Formatter};
+
+	/// Index of a declaration, statement or expression in the pre-order traversal of the program by a [`Visitor`].
+	///
+	/// The nodes of a subtree are numbered consecutively from its root, so that ids only depend on the shape of the AST.
+	#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+	pub struct NodeId(pub usize);
+
+	impl Display for NodeId {
+		fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+			write!(f, "#{}", self.0)
+		}
+	}
+
+	/// Visitor numbering the nodes in the order they are entered, and in the order they are left.
+	#[derive(Default)]
+	pub struct Numbering {
+		/// Number of nodes entered.
+		pub count: usize,
+		/// Index in post-order of every node, by id.
+		pub postorder: Vec<usize>,
+		left: usize,
+	}
+
+	impl Numbering {
+		fn enter(&mut self) -> usize {
+			self.postorder.push(0);
+			self.count += 1;
+			self.count - 1
+		}
+
+		fn leave(&mut self, id: usize) {
+			self.postorder[id] = self.left;
+			self.left += 1;
+		}
+	}
+
+	impl Visitor for Numbering {
+		fn visit_decl(&mut self, decl: &Declaration) {
+			let id = self.enter();
+			visit::walk_decl(self, decl);
+			self.leave(id);
+		}
+
+		fn visit_stmt(&mut self, stmt: &Statement) {
+			let id = self.enter();
+			visit::walk_stmt(self, stmt);
+			self.leave(id);
+		}
+
+		fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+			let id = self.enter();
+			visit::walk_arex(self, arex);
+			self.leave(id);
+		}
+
+		fn visit_boolex(&mut self, boolex: &BooleanExpr) {
+			let id = self.enter();
+			visit::walk_boolex(self, boolex);
+			self.leave(id);
+		}
+
+		fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+			let id = self.enter();
+			visit::walk_lvalue(self, lvalue);
+			self.leave(id);
+		}
+	}
+
+	/// Number of nodes of the subtrees visited by `visit`.
+	pub fn size(visit: impl FnOnce(&mut Numbering)) -> usize {
+		let mut numbering = Numbering::default();
+
+		visit(&mut numbering);
+		numbering.count
+	}
+
+	/// Index in post-order of every node of the program, by id.
+	pub fn postorder(program: &Scope) -> Vec<usize> {
+		let mut numbering = Numbering::default();
+
+		numbering.visit_scope(program);
+		numbering.postorder
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{expr::LvalueExpr, fold::{Folder, walk_lvalue}, stmt::{Program, Scope}, visit::{Visitor, walk_lvalue as visit_lvalue}};
+	use crate::{lexer::lex_str, parser::parse};
+
+	const SOURCE: &str = "int x;\nint[4] a;\nread x;\nwhile x > 0 {\n\ta[x % 4] := a[x - 1] + x;\n\tif !(x == 2) {\n\t\tx := x - 1;\n\t}\n}\nwrite x;";
+
+	fn ast(source: &str) -> Scope {
+		parse(lex_str(source).unwrap()).unwrap()
+	}
+
+	#[derive(Default)]
+	struct Lvalues(Vec<String>);
+
+	impl Visitor for Lvalues {
+		fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+			self.0.push(lvalue.to_string());
+			visit_lvalue(self, lvalue);
+		}
+	}
+
+	struct Rename;
+
+	impl Folder for Rename {
+		fn fold_lvalue(&mut self, lvalue: LvalueExpr) -> LvalueExpr {
+			match walk_lvalue(self, lvalue) {
+				LvalueExpr::Variable(id) if id == "x" => LvalueExpr::Variable("y".to_string()),
+				lvalue => lvalue,
+			}
+		}
+	}
+
+	#[test]
+	fn visits_nested_lvalues() {
+		let mut lvalues = Lvalues::default();
+
+		lvalues.visit_scope(&ast(SOURCE));
+		assert_eq!(lvalues.0, ["x", "x", "a[x % 4]", "x", "a[x - 1]", "x", "x", "x", "x", "x", "x"]);
+	}
+
+	#[test]
+	fn folds_every_lvalue() {
+		let renamed = Rename.fold_scope(ast(SOURCE));
+
+		// declarations are left to `fold_decl`
+		assert_eq!(Program(&renamed).to_string(), "int x;\nint[4] a;\nread y;\nwhile y > 0 {\n\ta[y % 4] := a[y - 1] + y;\n\tif !(y == 2) {\n\t\ty := y - 1;\n\t}\n}\nwrite y;");
+	}
+
+	#[test]
+	fn parses_what_it_prints() {
+		let expressions = "int x;\nfloat f;\nint[2, 3] m;\n{int a; float g;} r;\n\
+			x := (x - (1 - 2)) * -3 % (x / 0x1F) + 0o17 - 0b101;\nf := f * (1.5 - r.g) / 0.25;\nm[x + 1, m[0, 0]] := -x;\nr := (x, f);\nx += 1;\n\
+			while !(x < 1 | x <= 2) & (x > 3 ^ x >= 4) || x == 5 && !!(x != 6) {\n\tif true | false & (true | false) {} else {\n\t\twrite x;\n\t}\n}";
+
+		for source in std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/data")).unwrap().map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap()).chain(vec![expressions.to_string()]) {
+			let printed = Program(&ast(&source)).to_string();
+
+			assert_eq!(ast(&printed), ast(&source), "{printed}");
+			assert_eq!(Program(&ast(&printed)).to_string(), printed);
+		}
+	}
+
+	#[test]
+	fn prints_what_it_parses() {
+		assert_eq!(Program(&ast(SOURCE)).to_string(), SOURCE);
+	}
+
+	#[test]
+	fn prints_procedures() {
+		let procedures = "int x;\nproc p(int a, int b; int c) {\n\tint t;\n\tc := a * b;\n}\nproc q(; int r) {}\ncall p(x, 2; x);\ncall q(; x);";
+
+		assert_eq!(Program(&ast(procedures)).to_string(), procedures);
+	}
+
+	#[test]
+	fn prints_pointers() {
+		let pointers = "int x;\n{int a;} r;\nint* p;\np := &r.a;\n*p := *p * 2;\np := &x;\nread *p;";
+
+		assert_eq!(Program(&ast(pointers)).to_string(), pointers);
+	}
+
+	#[test]
+	fn prints_parallel_statements() {
+		let par = "int x;\npar {\n\tx := 1;\n} and {\n\tint y;\n\tpar {} and {\n\t\ty := x;\n\t}\n}";
+
+		assert_eq!(Program(&ast(par)).to_string(), par);
+	}
+
+	#[test]
+	fn prints_annotations() {
+		let annotations = "int n;\nrequires n >= 0;\nwhile n > 0 {\n\tinvariant n >= 0;\n\tn := n - 1;\n}\nensures n == 0;";
+
+		assert_eq!(Program(&ast(annotations)).to_string(), annotations);
+	}
+
+	#[test]
+	fn prints_assertions() {
+		let assertions = "int x;\nread x;\nassume x != 0;\nassert !(x == 0);";
+
+		assert_eq!(Program(&ast(assertions)).to_string(), assertions);
+	}
+
+	#[test]
+	fn prints_literals_and_negations_as_written() {
+		let negations = "int x;\nint y;\nx := 0xA + 0x1f - -0b1;\ny := -x * 2 - -(x + 1);\ny := x - -(-1);";
+
+		assert_eq!(Program(&ast(negations)).to_string(), negations);
+	}
 }
@@ -1,16 +1,33 @@
-use crate::parser::Declaration::{Array, Record, Var};
-use crate::lexer::{Token, delimiter::Delimiter, keyword::Keyword::*, literal::{IntegerLiteral, Literal}, symbol::Symbol};
-use crate::microc::{decl::Declaration, expr::{Expression, BooleanExpr}, stmt::{Scope, Statement}};
-use std::collections::linked_list::LinkedList;
+//! Recursive descent parser of MicroC tokens into the AST.
 
+use crate::parser::Declaration::{Array, Pointer, Procedure, Record, Var};
+use crate::lexer::{Span, Token, delimiter::Delimiter, keyword::Keyword::*, literal::{IntegerLiteral, Literal}, symbol::Symbol};
+use crate::microc::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, ops::{ArithmeticOp, BinaryOp, RelationalOp}, node::{NodeId, postorder, size}, stmt::{Annotation, Scope, Statement}, visit::{self, Visitor}};
+use std::{collections::{HashMap, linked_list::LinkedList}, ops::Range};
+
+/// Program, as its outermost scope.
 pub type Ast = Scope;
 
-fn contains(scope: &Vec<Declaration>, name: &String) -> Option<Declaration> {
+/// Message of a syntax error, with the index of the token it occurred at.
+pub type Error = (usize, String);
+
+/// Token ranges of the nodes parsed so far, in the order they are completed, that is in post-order.
+type Ranges = Vec<Range<usize>>;
+
+/// Records the range of a node spanning the tokens from `start` to the ones parsed.
+fn record<X>(ranges: &mut Ranges, start: usize, parsed: (X, usize)) -> (X, usize) {
+	ranges.push(start..parsed.1);
+	parsed
+}
+
+fn contains(scope: &[Declaration], name: &str) -> Option<Declaration> {
 	for decl in scope.iter() {
 		match decl {
 			Var(t, id) if name == id => return Some(Var(*t, id.to_string())),
 			Array(t, s, id) if name == id => return Some(Array(*t, s.to_vec(), id.to_string())),
+			Pointer(t, id) if name == id => return Some(Pointer(*t, id.to_string())),
 			Record(s, id) if name == id => return Some(Record(s.to_vec(), id.to_string())),
+			Procedure(id, _, _, _) if name == id => return Some(decl.clone()),
 			_ => (),
 		}
 	}
@@ -18,188 +35,515 @@ fn contains(scope: &Vec<Declaration>, name: &String) -> Option<Declaration> {
 	None
 }
 
-fn parse_lvalueexpr(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>) -> Result<(Expression, usize), String> {
-	if i < tokens.len() {
-		match tokens[i] {
+/// Whether the innermost declaration of `name` in the enclosing scopes is a record.
+fn is_record(nested_scope: &LinkedList<Vec<Declaration>>, name: &str) -> bool {
+	matches!(nested_scope.iter().rev().find_map(|scope| contains(scope, name)), Some(Record(_, _)))
+}
 
-		}
-		Ok()
-	} else {
-		Err(format!("At least one token is necessary to parse a lvalue expr, 0 found."))
+fn token(tokens: &[Token], i: usize) -> Result<&Token, Error> {
+	tokens.get(i).ok_or_else(|| (i, "Unexpected end of input.".to_string()))
+}
+
+fn expect(tokens: &[Token], i: usize, expected: Token) -> Result<usize, Error> {
+	match token(tokens, i)? {
+		t if *t == expected => Ok(i + 1),
+		t => Err((i, format!("Expected '{:?}', got '{:?}'.", expected, t))),
 	}
 }
 
-fn parse_boolexpr(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>) -> Result<(BooleanExpr, usize), String> {
-	Err("".to_string())
+fn parse_identifier(tokens: &[Token], i: usize) -> Result<(String, usize), Error> {
+	match token(tokens, i)? {
+		Token::Identifier(id) => Ok((id.clone(), i + 1)),
+		t => Err((i, format!("Expected 'Identifier', got '{:?}'.", t))),
+	}
 }
 
-fn parse_arex(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>) -> Result<(Expression, usize), String> {
-	Err("".to_string())
+fn parse_lvalueexpr(tokens: &[Token], start: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(LvalueExpr, usize), Error> {
+	if let Some(Token::Symbol(Symbol::Star)) = tokens.get(start) {
+		let (id, i) = parse_identifier(tokens, start + 1)?;
+
+		return Ok(record(ranges, start, (LvalueExpr::Deref(id), i)));
+	}
+
+	let (id, i) = parse_identifier(tokens, start)?;
+
+	let parsed = match tokens.get(i) {
+		Some(Token::Delimiter(Delimiter::OpenSquare)) => {
+			let (index, mut i) = parse_arex(tokens, i + 1, nested_scope, ranges)?;
+			let mut indexes = vec![index];
+
+			while let Some(Token::Symbol(Symbol::Comma)) = tokens.get(i) {
+				let (index, _i) = parse_arex(tokens, i + 1, nested_scope, ranges)?;
+				i = _i;
+				indexes.push(index);
+			}
+
+			(LvalueExpr::ArrayIndex(id, indexes), expect(tokens, i, Token::Delimiter(Delimiter::CloseSquare))?)
+		},
+		Some(Token::Symbol(Symbol::Dot)) => {
+			let (member, i) = parse_identifier(tokens, i + 1)?;
+
+			(LvalueExpr::RecordMember(id, member), i)
+		},
+		_ => (LvalueExpr::Variable(id), i),
+	};
+
+	Ok(record(ranges, start, parsed))
 }
 
-fn parse_assign(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>) -> Result<(Statement, usize), String> {
-	/*
-	LvalueAssign(LvalueExpr, ArithmeticExpr),
-	RecordAssign(String, Vec<ArithmeticExpr>),
-	*/
-	Err("".to_string())
+/// Negation of a literal, keeping its base.
+fn negate(literal: &Literal) -> Option<ArithmeticLiteral> {
+	use IntegerLiteral::*;
+
+	match *literal {
+		Literal::IntegerLiteral(DecimalLiteral(n)) => Some(ArithmeticLiteral::Int(DecimalLiteral(-n))),
+		Literal::IntegerLiteral(BinaryLiteral(n)) => Some(ArithmeticLiteral::Int(BinaryLiteral(-n))),
+		Literal::IntegerLiteral(OctalLiteral(n)) => Some(ArithmeticLiteral::Int(OctalLiteral(-n))),
+		Literal::IntegerLiteral(HexadecimalLiteral(n)) => Some(ArithmeticLiteral::Int(HexadecimalLiteral(-n))),
+		Literal::IntegerLiteral(UpperHexadecimalLiteral(n)) => Some(ArithmeticLiteral::Int(UpperHexadecimalLiteral(-n))),
+		Literal::FloatLiteral(x) => Some(ArithmeticLiteral::Float(-x)),
+		Literal::BooleanLiteral(_) => None,
+	}
 }
 
-fn parse_write(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>) -> Result<(Statement, usize), String> {
-	if i + 2 < tokens.len() {
-		if let (Token::Keyword(Write), Ok((Expression::ArithmeticExpr(arex), i))) = (&tokens[i], parse_arex(tokens, i + 1, nested_scope)) {
-			Ok((Statement::Write(arex), i))
-		} else {
-			Err(format!("Expected 'Write', ArithmeticExpr, got '{:?}', '{:?}'.", &tokens[i], tokens[i + 1]))
-		}
-	} else {
-		Err(format!("At least three tokens are necessary to parse a write statement, {:?} found.", tokens.len().saturating_sub(i + 2)))
+/// Literal, lvalue, parenthesized or negated arithmetic expression.
+///
+/// Negated literals are negative literals, other negations being [`ArithmeticOp::Neg`] operations subtracting from zero.
+fn parse_factor(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(ArithmeticExpr, usize), Error> {
+	match token(tokens, i)? {
+		Token::Literal(Literal::IntegerLiteral(il)) => Ok(record(ranges, i, (ArithmeticExpr::Literal(ArithmeticLiteral::Int(*il)), i + 1))),
+		Token::Literal(Literal::FloatLiteral(fl)) => Ok(record(ranges, i, (ArithmeticExpr::Literal(ArithmeticLiteral::Float(*fl)), i + 1))),
+		Token::Identifier(_) | Token::Symbol(Symbol::Star) => {
+			let (lvalue, end) = parse_lvalueexpr(tokens, i, nested_scope, ranges)?;
+
+			Ok(record(ranges, i, (ArithmeticExpr::LvalueExpr(lvalue), end)))
+		},
+		Token::Symbol(Symbol::And) => match parse_lvalueexpr(tokens, i + 1, nested_scope, ranges)? {
+			(lvalue @ (LvalueExpr::Variable(_) | LvalueExpr::RecordMember(_, _)), end) => Ok(record(ranges, i, (ArithmeticExpr::Reference(lvalue), end))),
+			(lvalue, _) => Err((i + 1, format!("Only variables and record members have an address, got '{lvalue}'."))),
+		},
+		Token::Delimiter(Delimiter::OpenPar) => {
+			let (arex, i) = parse_arex(tokens, i + 1, nested_scope, ranges)?;
+
+			Ok((arex, expect(tokens, i, Token::Delimiter(Delimiter::ClosePar))?))
+		},
+		Token::Symbol(Symbol::Minus) => match token(tokens, i + 1)? {
+			Token::Literal(literal) if negate(literal).is_some() => Ok(record(ranges, i, (ArithmeticExpr::Literal(negate(literal).unwrap()), i + 2))),
+			_ => {
+				let operand = ranges.len();
+				let (arex, end) = parse_factor(tokens, i + 1, nested_scope, ranges)?;
+				let zero = ArithmeticExpr::Literal(ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(0)));
+
+				// the zero stands for the minus sign, and precedes the operand
+				ranges.insert(operand, i..i + 1);
+				Ok(record(ranges, i, (ArithmeticExpr::ArithmeticOperation(Box::new((zero, ArithmeticOp::Neg, arex))), end)))
+			},
+		},
+		t => Err((i, format!("Cannot parse arithmetic expression starting with '{:?}'.", t))),
 	}
 }
 
-fn parse_read(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>) -> Result<(Statement, usize), String> {
-	if i + 2 < tokens.len() {
-		if let (Token::Keyword(Read), Ok((Expression::LvalueExpr(lvalueexpr), i))) = (&tokens[i], parse_lvalueexpr(tokens, i + 1, nested_scope)) {
-			Ok((Statement::Read(lvalueexpr), i))
-		} else {
-			Err(format!("Expected 'Read', LvalueExpr, got '{:?}', '{:?}'.", &tokens[i], tokens[i + 1]))
-		}
-	} else {
-		Err(format!("At least three tokens are necessary to parse a read statement, {:?} found.", tokens.len().saturating_sub(i + 2)))
+fn parse_term(tokens: &[Token], start: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(ArithmeticExpr, usize), Error> {
+	let (mut arex, mut i) = parse_factor(tokens, start, nested_scope, ranges)?;
+
+	loop {
+		let op = match tokens.get(i) {
+			Some(Token::Symbol(Symbol::Star)) => ArithmeticOp::Mul,
+			Some(Token::Symbol(Symbol::Slash)) => ArithmeticOp::Div,
+			Some(Token::Symbol(Symbol::Percent)) => ArithmeticOp::Rem,
+			_ => return Ok((arex, i)),
+		};
+
+		let (rhs, _i) = parse_factor(tokens, i + 1, nested_scope, ranges)?;
+		i = _i;
+		arex = ArithmeticExpr::ArithmeticOperation(Box::new((arex, op, rhs)));
+		ranges.push(start..i);
 	}
 }
 
-fn parse_statement_scope(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>, in_loop: bool) -> Result<(Statement, usize), String> {
-	if let Token::Delimiter(Delimiter::OpenCurly) = tokens[i] {
-		let mut decls = Vec::<Declaration>::new();
+fn parse_arex(tokens: &[Token], start: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(ArithmeticExpr, usize), Error> {
+	let (mut arex, mut i) = parse_term(tokens, start, nested_scope, ranges)?;
 
-		while let Some(_i) = parse_declaration(&tokens, i, &mut decls) {
-			i = _i;
-		}
+	loop {
+		let op = match tokens.get(i) {
+			Some(Token::Symbol(Symbol::Plus)) => ArithmeticOp::Add,
+			Some(Token::Symbol(Symbol::Minus)) => ArithmeticOp::Sub,
+			_ => return Ok((arex, i)),
+		};
 
-		let mut stmts = Vec::<Statement>::new();
-		nested_scope.push_back(decls.clone());
+		let (rhs, _i) = parse_term(tokens, i + 1, nested_scope, ranges)?;
+		i = _i;
+		arex = ArithmeticExpr::ArithmeticOperation(Box::new((arex, op, rhs)));
+		ranges.push(start..i);
+	}
+}
 
-		while let Ok((stmt, _i)) = parse_statement(&tokens, i, &nested_scope, in_loop) {
-			i = _i;
-			stmts.push(stmt);
-		}
+fn parse_relational(tokens: &[Token], start: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(BooleanExpr, usize), Error> {
+	let (arex1, i) = parse_arex(tokens, start, nested_scope, ranges)?;
 
-		if let Token::Delimiter(Delimiter::CloseCurly) = tokens[i] {
-			Ok((Statement::Scope((decls, stmts)), i + 1))
-		} else {
-			Err(format!("Expected 'CloseCurly', got '{:?}'.", &tokens[i]))
-		}
+	let op = match token(tokens, i)? {
+		Token::Symbol(Symbol::Lt) => RelationalOp::Lt,
+		Token::Symbol(Symbol::Le) => RelationalOp::Leq,
+		Token::Symbol(Symbol::Gt) => RelationalOp::Gt,
+		Token::Symbol(Symbol::Ge) => RelationalOp::Geq,
+		Token::Symbol(Symbol::EqEq) => RelationalOp::Eq,
+		Token::Symbol(Symbol::Ne) => RelationalOp::Neq,
+		t => return Err((i, format!("Expected relational operator, got '{:?}'.", t))),
+	};
+
+	let (arex2, i) = parse_arex(tokens, i + 1, nested_scope, ranges)?;
+
+	Ok(record(ranges, start, (BooleanExpr::RelationalOperation(arex1, op, arex2), i)))
+}
+
+/// Literal, negated, relational or parenthesized boolean expression.
+fn parse_boolex_factor(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(BooleanExpr, usize), Error> {
+	match token(tokens, i)? {
+		Token::Literal(Literal::BooleanLiteral(b)) => Ok(record(ranges, i, (BooleanExpr::BooleanLiteral(*b), i + 1))),
+		Token::Symbol(Symbol::Not) => {
+			let (boolex, end) = parse_boolex_factor(tokens, i + 1, nested_scope, ranges)?;
+
+			Ok(record(ranges, i, (BooleanExpr::NotOperation(Box::new(boolex)), end)))
+		},
+		// a parenthesis opens either an arithmetic operand of a comparison or a boolean expression
+		Token::Delimiter(Delimiter::OpenPar) => {
+			let attempt = ranges.len();
+
+			parse_relational(tokens, i, nested_scope, ranges).or_else(|_| {
+				ranges.truncate(attempt);
+
+				let (boolex, i) = parse_boolexpr(tokens, i + 1, nested_scope, ranges)?;
+
+				Ok((boolex, expect(tokens, i, Token::Delimiter(Delimiter::ClosePar))?))
+			})
+		},
+		_ => parse_relational(tokens, i, nested_scope, ranges),
+	}
+}
+
+/// Boolean expression whose operators bind at least as tightly as `precedence`.
+fn parse_boolex_level(tokens: &[Token], start: usize, nested_scope: &LinkedList<Vec<Declaration>>, precedence: u8, ranges: &mut Ranges) -> Result<(BooleanExpr, usize), Error> {
+	let operand = |i: usize, ranges: &mut Ranges| if precedence < BinaryOp::BitAnd.precedence() {
+		parse_boolex_level(tokens, i, nested_scope, precedence + 1, ranges)
 	} else {
-		Err(format!("Expected 'OpenCurly', got '{:?}'.", &tokens[i]))
+		parse_boolex_factor(tokens, i, nested_scope, ranges)
+	};
+
+	let (mut boolex, mut i) = operand(start, ranges)?;
+
+	loop {
+		let op = match tokens.get(i) {
+			Some(Token::Symbol(Symbol::Or)) | Some(Token::Symbol(Symbol::OrOr)) => BinaryOp::BitOr,
+			Some(Token::Symbol(Symbol::Caret)) => BinaryOp::BitXor,
+			Some(Token::Symbol(Symbol::And)) | Some(Token::Symbol(Symbol::AndAnd)) => BinaryOp::BitAnd,
+			_ => return Ok((boolex, i)),
+		};
+
+		if op.precedence() != precedence {
+			return Ok((boolex, i));
+		}
+
+		let (rhs, _i) = operand(i + 1, ranges)?;
+		i = _i;
+		boolex = BooleanExpr::BinaryOperation(Box::new(boolex), op, Box::new(rhs));
+		ranges.push(start..i);
 	}
 }
 
-fn parse_if(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>, in_loop: bool) -> Result<(Statement, usize), String> {
-	if let (Token::Keyword(If), Token::Delimiter(Delimiter::OpenPar)) = (tokens[i], tokens[i + 1]) {
-		if let Ok((boolex, i)) = parse_boolexpr(&tokens, i + 2, nested_scope) {
-			if let (Token::Delimiter(Delimiter::ClosePar), Token::Delimiter(Delimiter::OpenCurly)) = (tokens[i], tokens[i + 1]) {
-				if let Ok((Statement::Scope(scope), i)) = parse_statement_scope(tokens, i + 2, nested_scope, in_loop) {
-					if let (Token::Keyword(Else), Token::Delimiter(Delimiter::OpenCurly)) = (tokens[i], tokens[i + 1]) {
-						if let Ok((Statement::Scope(scope2), i)) = parse_statement_scope(tokens, i + 2, nested_scope, in_loop) {
-							Ok((Statement::IfElse(boolex, Box::new(scope), Box::new(scope2)), i + 1))
-						} else {
-							Err(format!("Cannot parse scope starting with '{:?}'.", &tokens[i + 2]))
-						}
-					} else {
-						Ok((Statement::If(boolex, Box::new(scope)), i + 1))
-					}
-				} else {
-					Err(format!("Cannot parse scope starting with '{:?}'.", &tokens[i + 2]))
+fn parse_boolexpr(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(BooleanExpr, usize), Error> {
+	parse_boolex_level(tokens, i, nested_scope, BinaryOp::BitOr.precedence(), ranges)
+}
+
+/// Parses a boolean literal or a comparison starting at token `i` outside of any program, giving the index of the token after it.
+///
+/// Propositions of temporal properties are combined by their own connectives, so that the comparison is not followed by boolean operators.
+pub fn parse_proposition(tokens: &[Token], i: usize) -> Result<(BooleanExpr, usize), String> {
+	match tokens.get(i) {
+		Some(Token::Literal(Literal::BooleanLiteral(b))) => Ok((BooleanExpr::BooleanLiteral(*b), i + 1)),
+		_ => parse_relational(tokens, i, &LinkedList::new(), &mut vec![]).map_err(|(_, e)| e),
+	}
+}
+
+/// Assignment to an lvalue or a whole record, compound assignments `x op= a` standing for `x := x op a`.
+fn parse_assign(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let target = ranges.len();
+	let (lvalue, i) = parse_lvalueexpr(tokens, i, nested_scope, ranges)?;
+	let operand = ranges.len();
+
+	let op = match token(tokens, i)? {
+		Token::Symbol(Symbol::ColonEq) => None,
+		Token::Symbol(Symbol::PlusEq) => Some(ArithmeticOp::Add),
+		Token::Symbol(Symbol::MinusEq) => Some(ArithmeticOp::Sub),
+		Token::Symbol(Symbol::StarEq) => Some(ArithmeticOp::Mul),
+		Token::Symbol(Symbol::SlashEq) => Some(ArithmeticOp::Div),
+		Token::Symbol(Symbol::PercentEq) => Some(ArithmeticOp::Rem),
+		t => return Err((i, format!("Expected assignment operator, got '{:?}'.", t))),
+	};
+
+	match (&lvalue, op) {
+		(LvalueExpr::Variable(id), None) if is_record(nested_scope, id) => {
+			// the record is not an lvalue of the statement
+			ranges.truncate(target);
+
+			let mut i = expect(tokens, i + 1, Token::Delimiter(Delimiter::OpenPar))?;
+			let mut arexs = Vec::<ArithmeticExpr>::new();
+
+			loop {
+				let (arex, _i) = parse_arex(tokens, i, nested_scope, ranges)?;
+				arexs.push(arex);
+
+				match token(tokens, _i)? {
+					Token::Symbol(Symbol::Comma) => i = _i + 1,
+					_ => {
+						i = expect(tokens, _i, Token::Delimiter(Delimiter::ClosePar))?;
+						break;
+					},
 				}
-			} else {
-				Err(format!("Expected 'ClosePar', 'OpenCurly', got '{:?}', '{:?}'.", tokens[i], tokens[i + 1]))
 			}
-		} else {
-			Err(format!("Cannot parse boolean expression starting with '{:?}'.", &tokens[i]))
-		}
-	} else {
-		Err(format!("Expected 'If', 'OpenPar', got '{:?}'", (&tokens[i], &tokens[i + 1])))
+
+			Ok((Statement::RecordAssign(id.clone(), arexs), expect(tokens, i, Token::Symbol(Symbol::Semi))?))
+		},
+		(_, op) => {
+			let (arex, i) = parse_arex(tokens, i + 1, nested_scope, ranges)?;
+			let arex = match op {
+				Some(op) => {
+					// the lvalue read by the operation spans the same tokens as the assigned one
+					let lvalue_ranges = ranges[target..operand].to_vec();
+					let read = ranges[operand - 1].clone();
+
+					ranges.splice(operand..operand, lvalue_ranges.into_iter().chain(std::iter::once(read.clone())));
+					ranges.push(read.start..i);
+					ArithmeticExpr::ArithmeticOperation(Box::new((ArithmeticExpr::LvalueExpr(lvalue.clone()), op, arex)))
+				},
+				None => arex,
+			};
+
+			Ok((Statement::LvalueAssign(lvalue, arex), expect(tokens, i, Token::Symbol(Symbol::Semi))?))
+		},
 	}
 }
 
-fn parse_while(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>) -> Result<(Statement, usize), String> {
-	if let (Token::Keyword(While), Token::Delimiter(Delimiter::OpenPar)) = (tokens[i], tokens[i + 1]) {
-		if let Ok((boolex, i)) = parse_boolexpr(&tokens, i + 2, nested_scope) {
-			if let (Token::Delimiter(Delimiter::ClosePar), Token::Delimiter(Delimiter::OpenCurly)) = (tokens[i], tokens[i + 1]) {
-				if let Ok((Statement::Scope(scope), i)) = parse_statement_scope(tokens, i + 2, nested_scope, true) {
-					Ok((Statement::While(boolex, Box::new(scope)), i + 1))
-				} else {
-					Err(format!("Cannot parse scope starting with '{:?}'.", &tokens[i + 2]))
-				}
-			} else {
-				Err(format!("Expected 'ClosePar', 'OpenCurly', got '{:?}', '{:?}'.", tokens[i], tokens[i + 1]))
+fn parse_write(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let i = expect(tokens, i, Token::Keyword(Write))?;
+	let (arex, i) = parse_arex(tokens, i, nested_scope, ranges)?;
+
+	Ok((Statement::Write(arex), expect(tokens, i, Token::Symbol(Symbol::Semi))?))
+}
+
+fn parse_assertion(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let assert = token(tokens, i)? == &Token::Keyword(Assert);
+	let (boolex, i) = parse_boolexpr(tokens, i + 1, nested_scope, ranges)?;
+	let stmt = if assert { Statement::Assert(boolex) } else { Statement::Assume(boolex) };
+
+	Ok((stmt, expect(tokens, i, Token::Symbol(Symbol::Semi))?))
+}
+
+fn parse_annotation(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let annotation = match token(tokens, i)? {
+		Token::Keyword(Requires) => Annotation::Requires,
+		Token::Keyword(Ensures) => Annotation::Ensures,
+		_ => Annotation::Invariant,
+	};
+	let (boolex, i) = parse_boolexpr(tokens, i + 1, nested_scope, ranges)?;
+
+	Ok((Statement::Annotation(annotation, boolex), expect(tokens, i, Token::Symbol(Symbol::Semi))?))
+}
+
+fn parse_read(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let i = expect(tokens, i, Token::Keyword(Read))?;
+	let (lvalue, i) = parse_lvalueexpr(tokens, i, nested_scope, ranges)?;
+
+	Ok((Statement::Read(lvalue), expect(tokens, i, Token::Symbol(Symbol::Semi))?))
+}
+
+fn parse_call(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let i = expect(tokens, i, Token::Keyword(Call))?;
+	let (id, i) = parse_identifier(tokens, i)?;
+	let mut i = expect(tokens, i, Token::Delimiter(Delimiter::OpenPar))?;
+	let mut arexs = Vec::<ArithmeticExpr>::new();
+	let mut lvalues = Vec::<LvalueExpr>::new();
+
+	if !matches!(token(tokens, i)?, Token::Symbol(Symbol::Semi) | Token::Delimiter(Delimiter::ClosePar)) {
+		loop {
+			let (arex, _i) = parse_arex(tokens, i, nested_scope, ranges)?;
+			arexs.push(arex);
+			i = _i;
+
+			match token(tokens, i)? {
+				Token::Symbol(Symbol::Comma) => i += 1,
+				_ => break,
 			}
-		} else {
-			Err(format!("Cannot parse boolean expression starting with '{:?}'.", &tokens[i]))
 		}
+	}
+
+	if token(tokens, i)? == &Token::Symbol(Symbol::Semi) {
+		i += 1;
+
+		loop {
+			let (lvalue, _i) = parse_lvalueexpr(tokens, i, nested_scope, ranges)?;
+			lvalues.push(lvalue);
+			i = _i;
+
+			match token(tokens, i)? {
+				Token::Symbol(Symbol::Comma) => i += 1,
+				_ => break,
+			}
+		}
+	}
+
+	let i = expect(tokens, i, Token::Delimiter(Delimiter::ClosePar))?;
+
+	Ok((Statement::Call(id, arexs, lvalues), expect(tokens, i, Token::Symbol(Symbol::Semi))?))
+}
+
+fn parse_scope(tokens: &[Token], mut i: usize, nested_scope: &LinkedList<Vec<Declaration>>, in_loop: bool, ranges: &mut Ranges) -> Result<(Scope, usize), Error> {
+	i = expect(tokens, i, Token::Delimiter(Delimiter::OpenCurly))?;
+	let mut decls = Vec::<Declaration>::new();
+
+	while let Some(_i) = parse_declaration(tokens, i, &mut decls, ranges)? {
+		i = _i;
+	}
+
+	let mut nested_scope = nested_scope.clone();
+	let mut stmts = Vec::<Statement>::new();
+	nested_scope.push_back(decls.clone());
+
+	while token(tokens, i)? != &Token::Delimiter(Delimiter::CloseCurly) {
+		let (stmt, _i) = parse_statement(tokens, i, &nested_scope, in_loop, ranges)?;
+		i = _i;
+		stmts.push(stmt);
+	}
+
+	Ok(((decls, stmts), i + 1))
+}
+
+fn parse_statement_scope(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, in_loop: bool, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	parse_scope(tokens, i, nested_scope, in_loop, ranges).map(|(scope, i)| (Statement::Scope(scope), i))
+}
+
+fn parse_if(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, in_loop: bool, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let i = expect(tokens, i, Token::Keyword(If))?;
+	let (boolex, i) = parse_boolexpr(tokens, i, nested_scope, ranges)?;
+	let (scope, i) = parse_scope(tokens, i, nested_scope, in_loop, ranges)?;
+
+	if let Some(Token::Keyword(Else)) = tokens.get(i) {
+		let (scope2, i) = parse_scope(tokens, i + 1, nested_scope, in_loop, ranges)?;
+
+		Ok((Statement::IfElse(boolex, Box::new(scope), Box::new(scope2)), i))
 	} else {
-		Err(format!("Expected 'While', 'OpenPar', got '{:?}'", (&tokens[i], &tokens[i + 1])))
+		Ok((Statement::If(boolex, Box::new(scope)), i))
+	}
+}
+
+fn parse_while(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let i = expect(tokens, i, Token::Keyword(While))?;
+	let (boolex, i) = parse_boolexpr(tokens, i, nested_scope, ranges)?;
+	let (scope, i) = parse_scope(tokens, i, nested_scope, true, ranges)?;
+
+	Ok((Statement::While(boolex, Box::new(scope)), i))
+}
+
+/// Parses the two components of a `par`, which are neither loop bodies nor allowed to call procedures.
+fn parse_par(tokens: &[Token], start: usize, nested_scope: &LinkedList<Vec<Declaration>>, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let i = expect(tokens, start, Token::Keyword(Par))?;
+	let (scope1, i) = parse_scope(tokens, i, nested_scope, false, ranges)?;
+	let i = expect(tokens, i, Token::Keyword(And))?;
+	let (scope2, i) = parse_scope(tokens, i, nested_scope, false, ranges)?;
+	let mut calls = Calls::default();
+
+	calls.visit_scope(&scope1);
+	calls.visit_scope(&scope2);
+
+	match calls.0.first() {
+		Some((id, _, _, _)) => Err((start, format!("Procedure {:?} cannot be called in a component of 'par'.", id))),
+		None => Ok((Statement::Par(Box::new(scope1), Box::new(scope2)), i)),
 	}
 }
 
-fn parse_statement(tokens: &Vec<Token>, i: usize, nested_scope: &LinkedList<Vec<Declaration>>, in_loop: bool) -> Result<(Statement, usize), String> {
-	match &tokens[i] {
-		Token::Keyword(While) => parse_while(tokens, i, nested_scope),
-		Token::Keyword(Write) => parse_write(tokens, i, nested_scope),
-		Token::Keyword(Read) => parse_read(tokens, i, nested_scope),
-		Token::Keyword(If) => parse_if(tokens, i, nested_scope, in_loop),
-		Token::Identifier(id) => parse_assign(tokens, i, nested_scope),
-		Token::Delimiter(Delimiter::OpenCurly) => parse_statement_scope(tokens, i, nested_scope, in_loop),
+fn parse_statement(tokens: &[Token], i: usize, nested_scope: &LinkedList<Vec<Declaration>>, in_loop: bool, ranges: &mut Ranges) -> Result<(Statement, usize), Error> {
+	let parsed = match token(tokens, i)? {
+		Token::Keyword(While) => parse_while(tokens, i, nested_scope, ranges),
+		Token::Keyword(Write) => parse_write(tokens, i, nested_scope, ranges),
+		Token::Keyword(Read) => parse_read(tokens, i, nested_scope, ranges),
+		Token::Keyword(If) => parse_if(tokens, i, nested_scope, in_loop, ranges),
+		Token::Keyword(Call) => parse_call(tokens, i, nested_scope, ranges),
+		Token::Keyword(Par) => parse_par(tokens, i, nested_scope, ranges),
+		Token::Keyword(Requires | Ensures | Invariant) => parse_annotation(tokens, i, nested_scope, ranges),
+		Token::Keyword(Assert | Assume) => parse_assertion(tokens, i, nested_scope, ranges),
+		Token::Identifier(_) | Token::Symbol(Symbol::Star) => parse_assign(tokens, i, nested_scope, ranges),
+		Token::Delimiter(Delimiter::OpenCurly) => parse_statement_scope(tokens, i, nested_scope, in_loop, ranges),
 		Token::Keyword(Break) => if in_loop {
-			Ok((Statement::Break, i + 1))
+			Ok((Statement::Break, expect(tokens, i + 1, Token::Symbol(Symbol::Semi))?))
 		} else {
-			Err("'Break' keyword only allowed in the body of loops.".to_string())
+			Err((i, "'Break' keyword only allowed in the body of loops.".to_string()))
 		},
 		Token::Keyword(Continue) => if in_loop {
-			Ok((Statement::Continue, i + 1))
+			Ok((Statement::Continue, expect(tokens, i + 1, Token::Symbol(Symbol::Semi))?))
 		} else {
-			Err("'Continue' keyword only allowed in the body of loops.".to_string())
+			Err((i, "'Continue' keyword only allowed in the body of loops.".to_string()))
 		},
-		_ => Err(format!("Cannot parse statement starting with '{:?}'.", &tokens[i]))
-	}
-}
+		t => Err((i, format!("Cannot parse statement starting with '{:?}'.", t)))
+	}?;
 
-fn parse_declaration_variable(tokens: &Vec<Token>, i: usize, scope: &mut Vec<Declaration>) -> Result<usize, String> {
-	//let min_token_number = 2;
+	Ok(record(ranges, i, parsed))
+}
 
+fn parse_declaration_variable(tokens: &[Token], i: usize, scope: &mut Vec<Declaration>, ranges: &mut Ranges) -> Result<usize, Error> {
 	if i + 2 < tokens.len() {
 		if let (
 			Token::Keyword(Type(t)), Token::Identifier(id), Token::Symbol(Symbol::Semi)
 		) = (&tokens[i], &tokens[i + 1], &tokens[i + 2]) {
 			if contains(scope, id).is_some() {
-				Err(format!("A variable with the name {:?} is already present in the scope.", id))
+				Err((i + 1, format!("A variable with the name {:?} is already present in the scope.", id)))
 			} else {
 				scope.push(Var(*t, id.to_string()));
+				ranges.push(i..i + 3);
 				Ok(i + 3)
 			}
 		} else {
-			Err(format!("Expected 'Type', 'Identifier', 'Semi', got '{:?}'", (&tokens[i], &tokens[i + 1], &tokens[i + 2])))
+			Err((i, format!("Expected 'Type', 'Identifier', 'Semi', got '{:?}'", (&tokens[i], &tokens[i + 1], &tokens[i + 2]))))
 		}
 	} else {
-		Err(format!("At least three tokens are necessary to parse a variable, {:?} found.", tokens.len().saturating_sub(i + 2)))
+		Err((i, format!("At least three tokens are necessary to parse a variable, {:?} found.", tokens.len().saturating_sub(i))))
 	}
 }
 
-fn parse_dimension (tokens: &Vec<Token>, i: usize) -> Option<(IntegerLiteral, usize)> {
-	if let Token::Literal(Literal::IntegerLiteral(il)) = &tokens[i] {
-		if let Token::Symbol(Symbol::Comma) = &tokens[i + 1] {
-			return Some((*il, i + 2))
-		} else if let Token::Delimiter(Delimiter::CloseSquare) = &tokens[i + 1] {
-			return Some((*il, i + 1))
+fn parse_declaration_pointer(tokens: &[Token], i: usize, scope: &mut Vec<Declaration>, ranges: &mut Ranges) -> Result<usize, Error> {
+	if i + 3 < tokens.len() {
+		if let (
+			Token::Keyword(Type(t)), Token::Symbol(Symbol::Star), Token::Identifier(id), Token::Symbol(Symbol::Semi)
+		) = (&tokens[i], &tokens[i + 1], &tokens[i + 2], &tokens[i + 3]) {
+			if contains(scope, id).is_some() {
+				Err((i + 2, format!("A pointer with the name {:?} is already present in the scope.", id)))
+			} else {
+				scope.push(Pointer(*t, id.to_string()));
+				ranges.push(i..i + 4);
+				Ok(i + 4)
+			}
+		} else {
+			Err((i, format!("Expected 'Type', 'Star', 'Identifier', 'Semi', got '{:?}'", (&tokens[i], &tokens[i + 1], &tokens[i + 2], &tokens[i + 3]))))
+		}
+	} else {
+		Err((i, format!("At least four tokens are necessary to parse a pointer, {:?} found.", tokens.len().saturating_sub(i))))
+	}
+}
+
+fn parse_dimension(tokens: &[Token], i: usize) -> Option<(IntegerLiteral, usize)> {
+	if let Some(Token::Literal(Literal::IntegerLiteral(il))) = tokens.get(i) {
+		match tokens.get(i + 1) {
+			Some(Token::Symbol(Symbol::Comma)) => return Some((*il, i + 2)),
+			Some(Token::Delimiter(Delimiter::CloseSquare)) => return Some((*il, i + 1)),
+			_ => (),
 		}
 	}
 
 	None
 }
 
-fn parse_declaration_array(tokens: &Vec<Token>, mut i: usize, scope: &mut Vec<Declaration>) -> Result<usize, String> {
-	if i + 6 < tokens.len() {
+fn parse_declaration_array(tokens: &[Token], start: usize, scope: &mut Vec<Declaration>, ranges: &mut Ranges) -> Result<usize, Error> {
+	let mut i = start;
+
+	if i + 5 < tokens.len() {
 		if let (Token::Keyword(Type(t)), Token::Delimiter(Delimiter::OpenSquare)) = (&tokens[i], &tokens[i + 1]) {
 			i += 2;
 			let mut dimensions = Vec::<IntegerLiteral>::new();
@@ -209,110 +553,440 @@ fn parse_declaration_array(tokens: &Vec<Token>, mut i: usize, scope: &mut Vec<De
 				dimensions.push(dim);
 			}
 
-			if !dimensions.is_empty() {
+			if dimensions.is_empty() {
+				Err((i, format!("Dimensionless array, got '{:?}'.", tokens[i])))
+			} else if i + 2 < tokens.len() {
 				if let (
 					Token::Delimiter(Delimiter::CloseSquare), Token::Identifier(id), Token::Symbol(Symbol::Semi)
 				) = (&tokens[i], &tokens[i + 1], &tokens[i + 2]) {
 					if contains(scope, id).is_some() {
-						Err(format!("An array with the name {:?} is already present in the scope.", id))
+						Err((i + 1, format!("An array with the name {:?} is already present in the scope.", id)))
 					} else {
 						scope.push(Array(*t, dimensions, id.to_string()));
+						ranges.push(start..i + 3);
 						Ok(i + 3)
 					}
 				} else {
-					Err(format!("Expected 'CloseSquare', 'Identifier', 'Semi', got '{:?}'", (&tokens[i], &tokens[i + 1], &tokens[i + 2])))
+					Err((i, format!("Expected 'CloseSquare', 'Identifier', 'Semi', got '{:?}'", (&tokens[i], &tokens[i + 1], &tokens[i + 2]))))
 				}
 			} else {
-				Err(format!("Dimensionless array, got '{:?}'.", tokens[i]))
+				Err((tokens.len(), "Unexpected end of input.".to_string()))
 			}
 		} else {
-			Err(format!("Expected 'Type', 'OpenSquare', got '{:?}'", (&tokens[i], &tokens[i + 1])))
+			Err((i, format!("Expected 'Type', 'OpenSquare', got '{:?}'", (&tokens[i], &tokens[i + 1]))))
 		}
 	} else {
-		Err(format!("At least six tokens are necessary to parse an array, {:?} found.", tokens.len().saturating_sub(i + 6)))
+		Err((i, format!("At least six tokens are necessary to parse an array, {:?} found.", tokens.len().saturating_sub(i))))
 	}
 }
 
-fn parse_declaration_record(tokens: &Vec<Token>, mut i: usize, scope: &mut Vec<Declaration>) -> Result<usize, String> {
-	if i + 7 < tokens.len() {
+fn parse_declaration_record(tokens: &[Token], start: usize, scope: &mut Vec<Declaration>, ranges: &mut Ranges) -> Result<usize, Error> {
+	let mut i = start;
+
+	if i + 6 < tokens.len() {
 		if let Token::Delimiter(Delimiter::OpenCurly) = &tokens[i] {
 			i += 1;
 			let mut decls = Vec::<Declaration>::new();
 
-			while let Some(_i) = parse_declaration(tokens, i, &mut decls) {
+			while let Some(_i) = parse_declaration(tokens, i, &mut decls, ranges)? {
 				i = _i;
 			}
 
-			if !decls.is_empty() {
+			if decls.is_empty() {
+				Err((i, format!("Record must contain valid declarations, got '{:?}'", tokens[i])))
+			} else if i + 2 < tokens.len() {
 				if let (
 					Token::Delimiter(Delimiter::CloseCurly), Token::Identifier(id), Token::Symbol(Symbol::Semi)
 				) = (&tokens[i], &tokens[i + 1], &tokens[i + 2]) {
 					if contains(scope, id).is_some() {
-						Err(format!("A record with the name {:?} is already present in the scope.", id))
+						Err((i + 1, format!("A record with the name {:?} is already present in the scope.", id)))
 					} else {
 						scope.push(Record(decls, id.to_string()));
+						ranges.push(start..i + 3);
 						Ok(i + 3)
 					}
 				} else {
-					Err(format!("Expected 'CloseCurly', 'Identifier', 'Semi', got '{:?}'", (&tokens[i], &tokens[i + 1], &tokens[i + 2])))
+					Err((i, format!("Expected 'CloseCurly', 'Identifier', 'Semi', got '{:?}'", (&tokens[i], &tokens[i + 1], &tokens[i + 2]))))
 				}
 			} else {
-				Err(format!("Record must contain valid declarations, got '{:?}'", tokens[i]))
+				Err((tokens.len(), "Unexpected end of input.".to_string()))
 			}
 		} else {
-			Err(format!("Expected 'OpenCurly', found '{:?}'.", tokens[i]))
+			Err((i, format!("Expected 'OpenCurly', found '{:?}'.", tokens[i])))
 		}
 	} else {
-		Err(format!("At least seven tokens are necessary to parse an array, {:?} found.", tokens.len().saturating_sub(i + 7)))
+		Err((i, format!("At least seven tokens are necessary to parse a record, {:?} found.", tokens.len().saturating_sub(i))))
 	}
 }
 
-fn parse_declaration(tokens: &Vec<Token>, i: usize, scope: &mut Vec<Declaration>) -> Option<usize> {
-	if i < tokens.len() {
-		if let Token::Keyword(Type(_)) = &tokens[i] {
-			if let Ok(i) = parse_declaration_variable(tokens, i, scope) {
-				return Some(i);
-			} else if let Ok(i) = parse_declaration_array(tokens, i, scope) {
-				return Some(i);
+/// Parses the declaration at `i` if any, a curly bracket not opening a record opening a scope statement instead.
+fn parse_declaration(tokens: &[Token], i: usize, scope: &mut Vec<Declaration>, ranges: &mut Ranges) -> Result<Option<usize>, Error> {
+	match tokens.get(i) {
+		Some(Token::Keyword(Type(_))) => match tokens.get(i + 1) {
+			Some(Token::Delimiter(Delimiter::OpenSquare)) => parse_declaration_array(tokens, i, scope, ranges).map(Some),
+			Some(Token::Symbol(Symbol::Star)) => parse_declaration_pointer(tokens, i, scope, ranges).map(Some),
+			_ => parse_declaration_variable(tokens, i, scope, ranges).map(Some),
+		},
+		Some(Token::Delimiter(Delimiter::OpenCurly)) => {
+			let attempt = ranges.len();
+			let record = parse_declaration_record(tokens, i, scope, ranges).ok();
+
+			if record.is_none() {
+				ranges.truncate(attempt);
 			}
-		} else if let Token::Delimiter(Delimiter::OpenCurly) = &tokens[i] {
-			if let Ok(i) = parse_declaration_record(tokens, i, scope) {
-				return Some(i);
+
+			Ok(record)
+		},
+		_ => Ok(None),
+	}
+}
+
+/// Parses the `,`-separated parameters of a procedure up to the `;` or parenthesis ending them, none of them named as one of `others`.
+fn parse_parameters(tokens: &[Token], mut i: usize, params: &mut Vec<Declaration>, others: &[Declaration], ranges: &mut Ranges) -> Result<usize, Error> {
+	if !matches!(tokens.get(i), Some(Token::Keyword(Type(_)))) {
+		return Ok(i);
+	}
+
+	loop {
+		let t = match token(tokens, i)? {
+			Token::Keyword(Type(t)) => *t,
+			t => return Err((i, format!("Expected 'Type', got '{:?}'.", t))),
+		};
+		let (id, _i) = parse_identifier(tokens, i + 1)?;
+
+		if contains(params, &id).is_some() || contains(others, &id).is_some() {
+			return Err((i + 1, format!("A parameter with the name {:?} is already present in the procedure.", id)));
+		}
+
+		params.push(Var(t, id));
+		ranges.push(i.._i);
+		i = _i;
+
+		match token(tokens, i)? {
+			Token::Symbol(Symbol::Comma) => i += 1,
+			_ => return Ok(i),
+		}
+	}
+}
+
+/// Parses a procedure of the outermost scope, whose body sees the declarations of that scope preceding it and its parameters.
+fn parse_procedure(tokens: &[Token], start: usize, scope: &mut Vec<Declaration>, ranges: &mut Ranges) -> Result<usize, Error> {
+	let i = expect(tokens, start, Token::Keyword(Proc))?;
+	let (id, i) = parse_identifier(tokens, i)?;
+
+	if contains(scope, &id).is_some() {
+		return Err((start + 1, format!("A procedure with the name {:?} is already present in the scope.", id)));
+	}
+
+	let mut values = Vec::<Declaration>::new();
+	let mut results = Vec::<Declaration>::new();
+	let mut i = parse_parameters(tokens, expect(tokens, i, Token::Delimiter(Delimiter::OpenPar))?, &mut values, &[], ranges)?;
+
+	if token(tokens, i)? == &Token::Symbol(Symbol::Semi) {
+		i = parse_parameters(tokens, i + 1, &mut results, &values, ranges)?;
+	}
+
+	let i = expect(tokens, i, Token::Delimiter(Delimiter::ClosePar))?;
+	let nested_scope: LinkedList<Vec<Declaration>> = vec![scope.clone(), values.iter().chain(&results).cloned().collect()].into_iter().collect();
+	let (body, i) = parse_scope(tokens, i, &nested_scope, false, ranges)?;
+
+	scope.push(Procedure(id, values, results, Box::new(body)));
+	ranges.push(start..i);
+	Ok(i)
+}
+
+/// Names declared by the declarations of a scope and its nested ones, without the members of records.
+#[derive(Default)]
+struct Declared(Vec<String>);
+
+impl Visitor for Declared {
+	fn visit_decl(&mut self, decl: &Declaration) {
+		match decl {
+			Var(_, id) | Array(_, _, id) | Pointer(_, id) | Record(_, id) => self.0.push(id.clone()),
+			Procedure(..) => visit::walk_decl(self, decl),
+		}
+	}
+}
+
+/// Procedure and number of arguments of every call, with the id of the call relative to the first node visited.
+#[derive(Default)]
+struct Calls(Vec<(String, usize, usize, NodeId)>, usize);
+
+impl Visitor for Calls {
+	fn visit_decl(&mut self, decl: &Declaration) {
+		self.1 += 1;
+		visit::walk_decl(self, decl);
+	}
+
+	fn visit_stmt(&mut self, stmt: &Statement) {
+		if let Statement::Call(id, arexs, lvalues) = stmt {
+			self.0.push((id.clone(), arexs.len(), lvalues.len(), NodeId(self.1)));
+		}
+
+		self.1 += 1;
+		visit::walk_stmt(self, stmt);
+	}
+
+	fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+		self.1 += 1;
+		visit::walk_arex(self, arex);
+	}
+
+	fn visit_boolex(&mut self, boolex: &BooleanExpr) {
+		self.1 += 1;
+		visit::walk_boolex(self, boolex);
+	}
+
+	fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+		self.1 += 1;
+		visit::walk_lvalue(self, lvalue);
+	}
+}
+
+/// Checks that the calls match the parameters of declared procedures, and that procedures do not shadow the variables of the outermost scope.
+fn check_procedures(program: &Ast) -> Result<(), (NodeId, String)> {
+	let mut globals = Declared::default();
+	let mut procedures = HashMap::<&str, (usize, usize)>::new();
+
+	for decl in program.0.iter() {
+		match decl {
+			Procedure(id, values, results, _) => {
+				procedures.insert(id, (values.len(), results.len()));
+			},
+			_ => globals.visit_decl(decl),
+		}
+	}
+
+	let mut at = 0;
+
+	for decl in program.0.iter() {
+		if let Procedure(id, _, _, _) = decl {
+			let mut locals = Declared::default();
+
+			locals.visit_decl(decl);
+
+			if let Some(var) = locals.0.iter().find(|var| globals.0.contains(var)) {
+				return Err((NodeId(at), format!("Variable {:?} of procedure {:?} shadows a variable of the outermost scope.", var, id)));
 			}
 		}
+
+		at += size(|n| n.visit_decl(decl));
 	}
 
-	None
+	let mut calls = Calls::default();
+
+	calls.visit_scope(program);
+
+	for (id, values, results, at) in calls.0 {
+		match procedures.get(id.as_str()) {
+			None => return Err((at, format!("Call of the undeclared procedure {:?}.", id))),
+			Some(&(v, r)) if (v, r) != (values, results) => return Err((at, format!("Procedure {:?} takes {} value and {} result parameters, {} and {} given.", id, v, r, values, results))),
+			_ => (),
+		}
+	}
+
+	Ok(())
 }
 
-pub fn parse(tokens: Vec<Token>) -> Result<Ast, String> {
+/// Checks that preconditions start and postconditions end the program and the procedure bodies, and that invariants start loop bodies.
+///
+/// The first node of the scope has id `id`, `contract` tells whether the scope is the program or a procedure body, `invariants` whether it is a loop body.
+fn check_annotations(scope: &Scope, mut id: usize, contract: bool, invariants: bool) -> Result<(), (NodeId, String)> {
+	let stmts = &scope.1;
+	let annotation = |stmt: &Statement| match stmt {
+		Statement::Annotation(annotation, _) => Some(*annotation),
+		_ => None,
+	};
+	let leading = stmts.iter().take_while(|stmt| annotation(stmt).is_some()).count();
+	let trailing = stmts.len() - stmts.iter().rposition(|stmt| annotation(stmt) != Some(Annotation::Ensures)).map_or(0, |i| i + 1);
+
+	let mut bodies = vec![];
+
+	for decl in &scope.0 {
+		if let Procedure(_, values, results, body) = decl {
+			bodies.push((body, id + 1 + values.iter().chain(results).map(|param| size(|n| n.visit_decl(param))).sum::<usize>()));
+		}
+
+		id += size(|n| n.visit_decl(decl));
+	}
+
+	for (i, stmt) in stmts.iter().enumerate() {
+		let misplaced = |message: &str| Err((NodeId(id), message.to_string()));
+
+		match annotation(stmt) {
+			Some(Annotation::Requires) if !contract || leading <= i =>
+				return misplaced("'Requires' annotations are only allowed at the start of the program or of a procedure body."),
+			Some(Annotation::Ensures) if !contract || i < stmts.len() - trailing =>
+				return misplaced("'Ensures' annotations are only allowed at the end of the program or of a procedure body."),
+			Some(Annotation::Invariant) if !invariants || leading <= i =>
+				return misplaced("'Invariant' annotations are only allowed at the start of the body of a loop."),
+			_ => (),
+		}
+
+		// the nodes of a scope follow the ones of the guard of its statement
+		match stmt {
+			Statement::If(boolex, scope) => check_annotations(scope, id + 1 + size(|n| n.visit_boolex(boolex)), false, false)?,
+			Statement::Scope(scope) => check_annotations(scope, id + 1, false, false)?,
+			Statement::IfElse(boolex, scope1, scope2) => {
+				let first = id + 1 + size(|n| n.visit_boolex(boolex));
+
+				check_annotations(scope1, first, false, false)?;
+				check_annotations(scope2, first + size(|n| n.visit_scope(scope1)), false, false)?;
+			},
+			Statement::Par(scope1, scope2) => {
+				check_annotations(scope1, id + 1, false, false)?;
+				check_annotations(scope2, id + 1 + size(|n| n.visit_scope(scope1)), false, false)?;
+			},
+			Statement::While(boolex, scope) => check_annotations(scope, id + 1 + size(|n| n.visit_boolex(boolex)), false, true)?,
+			_ => (),
+		}
+
+		id += size(|n| n.visit_stmt(stmt));
+	}
+
+	for (body, id) in bodies {
+		check_annotations(body, id, true, false)?;
+	}
+
+	Ok(())
+}
+
+/// Parses the tokens of a whole program, with the token range of every node by id.
+fn parse_ranges(tokens: &[Token]) -> Result<(Ast, Ranges), Error> {
 	if tokens.is_empty() {
-		Err("No tokens to parse".to_string())
+		Err((0, "No tokens to parse".to_string()))
 	} else {
 		let mut i = 0;
+		let mut ranges = Ranges::new();
 		let mut top_level_scope = Vec::<Declaration>::new();
 
-		while let Some(_i) = parse_declaration(&tokens, i, &mut top_level_scope) {
-			i = _i;
+		loop {
+			if let Some(Token::Keyword(Proc)) = tokens.get(i) {
+				i = parse_procedure(tokens, i, &mut top_level_scope, &mut ranges)?;
+			} else if let Some(_i) = parse_declaration(tokens, i, &mut top_level_scope, &mut ranges)? {
+				i = _i;
+			} else {
+				break;
+			}
 		}
 
 		let mut scope_stack = LinkedList::<Vec<Declaration>>::new();
 		let mut stmts = Vec::<Statement>::new();
 		scope_stack.push_back(top_level_scope.clone());
 
-		while let Ok((stmt, _i)) = parse_statement(&tokens, i, &scope_stack, false) {
+		while i < tokens.len() {
+			let (stmt, _i) = parse_statement(tokens, i, &scope_stack, false, &mut ranges)?;
 			i = _i;
 			stmts.push(stmt);
 		}
 
-		for decl in top_level_scope.iter() {
-			println!("{:?}", decl);
-		}
+		let ast = (top_level_scope, stmts);
+		let order = postorder(&ast);
 
-		for stmt in stmts.iter() {
-			println!("{:?}", stmt);
-		}
+		debug_assert_eq!(order.len(), ranges.len(), "every node has a range");
+
+		let ranges: Ranges = order.into_iter().map(|index| ranges[index].clone()).collect();
+
+		// the checks of the whole program fail at the first token of a node
+		check_procedures(&ast).and_then(|()| check_annotations(&ast, 0, true, false)).map_err(|(id, e)| (ranges[id.0].start, e))?;
+
+		Ok((ast, ranges))
+	}
+}
+
+/// Span of the token an error occurred at, the end of the last token if the input ended before it.
+pub fn error_span(spans: &[Span], index: usize) -> Option<Span> {
+	spans.get(index).copied().or_else(|| spans.last().map(|last| Span { start: last.end, end: last.end }))
+}
+
+/// Message of an error prefixed by the `line:column` of its token, like the ones of the lexer.
+pub fn error_message(spans: &[Span], (index, message): Error) -> String {
+	match error_span(spans, index) {
+		Some(span) => format!("{}:{}: {message}", span.start.0, span.start.1),
+		None => message,
+	}
+}
+
+/// Parses the tokens of a whole program.
+pub fn parse(tokens: Vec<Token>) -> Result<Ast, String> {
+	parse_ranges(&tokens).map(|(ast, _)| ast).map_err(|(_, e)| e)
+}
+
+/// Parses the tokens of a whole program, with the span of every node of the AST indexed by its [`NodeId`].
+pub fn parse_spans(tokens: &[Token], spans: &[Span]) -> Result<(Ast, Vec<Span>), Error> {
+	let (ast, ranges) = parse_ranges(tokens)?;
+
+	Ok((ast, ranges.into_iter().map(|range| Span { start: spans[range.start].start, end: spans[range.end - 1].end }).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{error_message, parse, parse_spans};
+	use crate::{lexer::{Span, lex_str, lex_trivia}, microc::{node::size, visit::Visitor}};
+
+	fn spans(source: &str) -> (usize, Vec<Span>) {
+		let stream = lex_trivia(source).unwrap();
+		let spans = stream.spans();
+		let (ast, spans) = parse_spans(&stream.tokens(), &spans).unwrap();
+
+		(size(|n| n.visit_scope(&ast)), spans)
+	}
+
+	#[test]
+	fn every_node_has_a_span() {
+		let (count, spans) = spans("int x;\n{int a; int b;} r;\nx += -x * 2;\nif (x + 1) > 2 & !(x < 0) {\n\tr := (x, 1);\n}\n");
+
+		assert_eq!(spans.len(), count);
+		// record and its members
+		assert_eq!(spans[1], Span { start: (2, 1), end: (2, 19) });
+		assert_eq!(spans[3], Span { start: (2, 9), end: (2, 15) });
+		// `x := x + (0 - x) * 2`, the lvalue read by the compound assignment and the zero of the negation standing for source tokens
+		assert_eq!(spans[4], Span { start: (3, 1), end: (3, 13) });
+		assert_eq!(spans[6], Span { start: (3, 1), end: (3, 12) });
+		assert_eq!(spans[7], Span { start: (3, 1), end: (3, 2) });
+		assert_eq!(spans[11], Span { start: (3, 6), end: (3, 7) });
+		// guard, after the comparison first tried from its parenthesis is rolled back
+		assert_eq!(spans[16], Span { start: (4, 4), end: (4, 26) });
+		assert_eq!(spans[17], Span { start: (4, 4), end: (4, 15) });
+		assert_eq!(spans[28], Span { start: (5, 2), end: (5, 14) });
+	}
+
+	#[test]
+	fn procedures() {
+		let (count, spans) = spans("int x;\nproc p(int a; int b, int c) {\n\tb := a;\n}\ncall p(x + 1; x, x);\n");
+
+		assert_eq!(spans.len(), count);
+		assert_eq!(spans[1], Span { start: (2, 1), end: (4, 2) });
+		assert_eq!(spans[3], Span { start: (2, 15), end: (2, 20) });
+		assert_eq!(spans[9], Span { start: (5, 1), end: (5, 21) });
+
+		let error = |source: &str| parse(lex_str(source).unwrap()).unwrap_err();
+
+		assert_eq!(error("proc p(int a) {}\ncall q(1);"), "Call of the undeclared procedure \"q\".");
+		assert_eq!(error("int x;\nproc p(int a) {}\ncall p(; x);"), "Procedure \"p\" takes 1 value and 0 result parameters, 0 and 1 given.");
+		assert_eq!(error("proc p(int a) { int x; }\nint x;"), "Variable \"x\" of procedure \"p\" shadows a variable of the outermost scope.");
+		assert_eq!(error("proc p(int a; int a) {}"), "A parameter with the name \"a\" is already present in the procedure.");
+		assert_eq!(error("int x;\nproc p(int a) {}\npar { call p(1); } and { x := 1; }"), "Procedure \"p\" cannot be called in a component of 'par'.");
+		assert_eq!(error("while true { par { break; } and {} }"), "'Break' keyword only allowed in the body of loops.");
+		assert_eq!(error("int x;\nx := 1;\nrequires x > 0;"), "'Requires' annotations are only allowed at the start of the program or of a procedure body.");
+		assert_eq!(error("int x;\nensures x > 0;\nx := 1;"), "'Ensures' annotations are only allowed at the end of the program or of a procedure body.");
+		assert_eq!(error("int x;\nif x > 0 { ensures x > 0; }"), "'Ensures' annotations are only allowed at the end of the program or of a procedure body.");
+		assert_eq!(error("int x;\nwhile x > 0 {\n\tx := x - 1;\n\tinvariant x >= 0;\n}"), "'Invariant' annotations are only allowed at the start of the body of a loop.");
+	}
+
+	#[test]
+	fn errors_are_at_their_token() {
+		let error = |source: &str| {
+			let stream = lex_trivia(source).unwrap();
+			let spans = stream.spans();
 
-		Ok((top_level_scope, stmts))
+			parse_spans(&stream.tokens(), &spans).map(|_| ()).map_err(|e| error_message(&spans, e))
+		};
+
+		assert_eq!(error("int x;\nx := ;"), Err("2:6: Cannot parse arithmetic expression starting with 'Symbol(Semi)'.".to_string()));
+		assert_eq!(error("int x;\nx := 1"), Err("2:7: Unexpected end of input.".to_string()));
+		assert_eq!(error("int x;\nproc p(int a) {\n\ta := 1;\n}\nx := 1;\ncall p(; x);"), Err("6:1: Procedure \"p\" takes 1 value and 0 result parameters, 0 and 1 given.".to_string()));
+		assert_eq!(error("int x;\nwhile true {\n\tx := 1;\n\tinvariant x > 0;\n}"), Err("4:2: 'Invariant' annotations are only allowed at the start of the body of a loop.".to_string()));
+		assert_eq!(error("int x;\nproc p(int a) {\n\ta := 1;\n\trequires a > 0;\n}"), Err("4:2: 'Requires' annotations are only allowed at the start of the program or of a procedure body.".to_string()));
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,196 @@
+//! Points-to analyses, flow-sensitive and flow-insensitive after Andersen.
+
+use crate::analysis::{Analyzer, context, location, records, variables};
+use crate::flow_graph::{Action, FlowGraph};
+use crate::microc::{expr::{ArithmeticExpr, LvalueExpr}, stmt::Statement};
+use petgraph::graph::NodeIndex;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Variables each pointer may point to, pointers that point nowhere being left out.
+pub type PointsTo = BTreeMap<String, BTreeSet<String>>;
+
+/// Variables the pointer may point to.
+pub fn targets<'a>(points_to: &'a PointsTo, pointer: &str) -> impl Iterator<Item = &'a String> {
+	points_to.get(pointer).into_iter().flatten()
+}
+
+/// Variables written through an lvalue, with whether they are weakly updated.
+///
+/// Writing through a pointer only updates its target strongly when it has a single one.
+pub fn written(points_to: &PointsTo, lvalue: &LvalueExpr) -> Vec<(String, bool)> {
+	match lvalue {
+		LvalueExpr::Deref(pointer) => {
+			let targets: Vec<String> = targets(points_to, pointer).cloned().collect();
+			let weak = 1 < targets.len();
+
+			targets.into_iter().map(|var| (var, weak)).collect()
+		},
+		LvalueExpr::ArrayIndex(_, _) => vec![(location(lvalue), true)],
+		_ => vec![(location(lvalue), false)],
+	}
+}
+
+/// Locations an lvalue may store into, with whether they are weakly updated, for analyses keeping `*pointer` as a location of its own.
+///
+/// The targets are updated as by [`written`], while `*pointer` stands for whatever the pointer points to over the whole run and is only weakly updated.
+pub fn stored(points_to: &PointsTo, lvalue: &LvalueExpr) -> Vec<(String, bool)> {
+	match lvalue {
+		LvalueExpr::Deref(_) => std::iter::once((location(lvalue), true)).chain(written(points_to, lvalue)).collect(),
+		_ => written(points_to, lvalue),
+	}
+}
+
+/// Addresses an arithmetic expression may evaluate to, none for numbers.
+fn addresses(points_to: &PointsTo, arex: &ArithmeticExpr) -> BTreeSet<String> {
+	match arex {
+		ArithmeticExpr::Reference(lvalue) => std::iter::once(location(lvalue)).collect(),
+		ArithmeticExpr::LvalueExpr(LvalueExpr::Deref(pointer)) => targets(points_to, pointer).flat_map(|var| targets(points_to, var)).cloned().collect(),
+		ArithmeticExpr::LvalueExpr(lvalue) => targets(points_to, &location(lvalue)).cloned().collect(),
+		_ => BTreeSet::new(),
+	}
+}
+
+/// Lvalues assigned by an action with the expressions assigned to them, `None` for values that are not addresses.
+fn assignments(a: &Action, records: &HashMap<String, Vec<String>>) -> Vec<(LvalueExpr, Option<ArithmeticExpr>)> {
+	let variable = |var: &String| LvalueExpr::Variable(var.clone());
+
+	match a {
+		Action::Declaration(decl) => variables(decl).iter().map(|var| (variable(var), None)).collect(),
+		Action::Statement(Statement::LvalueAssign(lvalue, arex)) => vec![(lvalue.clone(), Some(arex.clone()))],
+		Action::Statement(Statement::RecordAssign(id, arexs)) => records.get(id).into_iter().flatten()
+			.zip(arexs)
+			.map(|(member, arex)| (variable(member), Some(arex.clone())))
+			.collect(),
+		Action::Statement(Statement::Read(lvalue)) => vec![(lvalue.clone(), None)],
+		Action::Call(call) => call.values.iter().zip(&call.arguments)
+			.map(|(param, arex)| (variable(param), Some(arex.clone())))
+			.chain(call.outputs.iter().map(|output| (variable(output), None)))
+			.collect(),
+		Action::Return(call) => call.results.iter().zip(&call.outputs)
+			.map(|(lvalue, output)| (lvalue.clone(), Some(ArithmeticExpr::LvalueExpr(variable(output)))))
+			.collect(),
+		_ => vec![],
+	}
+}
+
+/// Andersen's flow-insensitive points-to analysis, adding the addresses of every assignment to the variables it writes until none is missing.
+pub fn andersen(program: &FlowGraph) -> PointsTo {
+	let records = records(program);
+	let assignments: Vec<(LvalueExpr, Option<ArithmeticExpr>)> = program.0.raw_edges().iter().flat_map(|edge| assignments(&edge.weight, &records)).collect();
+	let mut points_to = PointsTo::new();
+
+	loop {
+		let mut changed = false;
+
+		for (lvalue, arex) in &assignments {
+			let addresses = arex.as_ref().map_or_else(BTreeSet::new, |arex| addresses(&points_to, arex));
+
+			if addresses.is_empty() {
+				continue;
+			}
+
+			for (var, _) in written(&points_to, lvalue) {
+				let targets = points_to.entry(var).or_default();
+				let count = targets.len();
+
+				targets.extend(addresses.iter().cloned());
+				changed |= targets.len() != count;
+			}
+		}
+
+		if !changed {
+			return points_to;
+		}
+	}
+}
+
+/// Flow-sensitive points-to analysis, assignments to variables replacing their targets.
+struct PointsToAnalysis {
+	records: HashMap<String, Vec<String>>,
+}
+
+impl Analyzer<PointsTo> for PointsToAnalysis {
+	fn bottom(&self) -> PointsTo {
+		PointsTo::new()
+	}
+
+	fn initial(&self) -> PointsTo {
+		PointsTo::new()
+	}
+
+	fn map(&self, a: &Action, r: &PointsTo) -> PointsTo {
+		let mut res = r.clone();
+
+		for (lvalue, arex) in assignments(a, &self.records) {
+			let addresses = arex.map_or_else(BTreeSet::new, |arex| addresses(r, &arex));
+
+			for (var, weak) in written(r, &lvalue) {
+				if !weak {
+					res.remove(&var);
+				}
+
+				if !addresses.is_empty() {
+					res.entry(var).or_default().extend(addresses.iter().cloned());
+				}
+			}
+		}
+
+		res
+	}
+
+	fn join(&self, r1: &PointsTo, r2: &PointsTo) -> PointsTo {
+		let mut res = r1.clone();
+
+		for (pointer, targets) in r2 {
+			res.entry(pointer.clone()).or_default().extend(targets.iter().cloned());
+		}
+
+		res
+	}
+}
+
+/// Flow-sensitive points-to analysis of the program.
+///
+/// It stands alone, reported by the `pt` pattern: the other analyses consult the targets of [`andersen`], which hold at every node.
+pub fn analyze(program: &FlowGraph) -> HashMap<NodeIndex, PointsTo> {
+	context::solve(program, &PointsToAnalysis { records: records(program) }, context::DEPTH)
+}
+
+/// Formats the targets of the pointers.
+pub fn display(points_to: &PointsTo) -> String {
+	let pointers: Vec<String> = points_to.iter().map(|(pointer, targets)| {
+		format!("{pointer} -> {{{}}}", targets.iter().cloned().collect::<Vec<String>>().join(", "))
+	}).collect();
+
+	format!("{{{}}}", pointers.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{andersen, analyze, display};
+	use crate::analysis::{MemoryDisplay, sign};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+
+	const SOURCE: &str = "int x;\nint y;\nint* p;\nint* q;\np := &x;\n*p := 1;\nq := p;\np := &y;\n*p := 0 - 1;";
+
+	#[test]
+	fn flow_sensitivity() {
+		let program = flow(parse(lex_str(SOURCE).unwrap()).unwrap());
+
+		assert_eq!(display(&andersen(&program)), "{p -> {x, y}, q -> {x, y}}");
+		assert_eq!(display(&analyze(&program)[&program.2]), "{p -> {y}, q -> {x}}");
+	}
+
+	#[test]
+	fn assignments_through_pointers() {
+		let signs = |source: &str| {
+			let program = flow(parse(lex_str(source).unwrap()).unwrap());
+
+			MemoryDisplay(&sign::analyze(&program)[&program.2]).to_string()
+		};
+
+		// a single target is strongly updated, several ones weakly
+		assert_eq!(signs("int x;\nint* p;\np := &x;\n*p := 1;"), "{p: {-, 0, +}, x: {+}}");
+		assert_eq!(signs(SOURCE), "{p: {-, 0, +}, q: {-, 0, +}, x: {-, 0, +}, y: {-, 0, +}}");
+	}
+}
@@ -0,0 +1,268 @@
+//! Interactive session declaring, running and analyzing MicroC code.
+
+use crate::analysis::analyze;
+use crate::flow_graph::{self, FlowGraph, Origin, build};
+use crate::interpreter::{Value, run};
+use crate::lexer::{Span, Token, delimiter::Delimiter, lex_str, lex_trivia, symbol::Symbol};
+use crate::microc::stmt::Program;
+use crate::parser::{Ast, error_message, parse, parse_spans};
+use crate::{security, slicing, taint};
+use petgraph::{graph::EdgeIndex, visit::EdgeRef};
+use std::{cell::RefCell, collections::HashMap, convert::TryFrom, io::{self, BufRead, Write}};
+
+/// Program graph, with the origins of its edges and their spans in the source.
+type Graph = (FlowGraph, HashMap<EdgeIndex, Origin>, HashMap<EdgeIndex, Span>);
+
+const HELP: &str = "\
+Declarations and statements are added to the program, those of a line ending inside a block being continued on the next lines.
+commands:
+- :list            the program
+- :ast             its AST
+- :tokens          its tokens
+- :graph           its program graph
+- :analyze PATTERN analysis of the program, one of rd, sa, ia, pt, andersen, safety, asserts,
+                   security LEVEL: VARIABLE, ...; ..., taint [VARIABLE OP BOUND; ...], slice [--forward] CRITERION
+- :run             runs the program, reading from the prompt
+- :reset           empties the program
+- :quit            leaves";
+
+/// Program typed so far.
+#[derive(Debug, Default)]
+pub struct Session {
+	/// Declarations and statements typed so far.
+	pub ast: Ast,
+}
+
+/// Tells whether a chunk of input is a whole sequence of declarations and statements, rather than one to continue on the next line.
+fn complete(chunk: &str) -> bool {
+	match lex_str(chunk) {
+		Ok(tokens) => {
+			let depth = tokens.iter().fold(0, |depth, token| match token {
+				Token::Delimiter(Delimiter::OpenCurly) => depth + 1,
+				Token::Delimiter(Delimiter::CloseCurly) => depth - 1,
+				_ => depth,
+			});
+
+			depth <= 0 && matches!(tokens.last(), Some(Token::Symbol(Symbol::Semi) | Token::Delimiter(Delimiter::CloseCurly)))
+		},
+		// a block comment may be closed on the next lines, while the other errors are reported at once
+		Err(e) => !e.ends_with("Unterminated block comment."),
+	}
+}
+
+/// Parses the `;`-separated lines of an inline argument, as its file would be.
+fn lines(argument: &str) -> String {
+	argument.split(';').collect::<Vec<&str>>().join("\n")
+}
+
+impl Session {
+	/// Adds declarations and statements to the program, the declarations of a chunk joining those of the program.
+	pub fn add(&mut self, chunk: &str) -> Result<(), String> {
+		let source = format!("{}{chunk}", Program(&(self.ast.0.clone(), vec![])));
+		let (decls, stmts) = parse(lex_str(&source)?)?;
+
+		self.ast.0 = decls;
+		self.ast.1.extend(stmts);
+		Ok(())
+	}
+
+	/// Source of the program, as printed by the `fmt` pattern.
+	pub fn source(&self) -> String {
+		Program(&self.ast).to_string()
+	}
+
+	/// Program graph of the program, with the origins of its edges and their spans in its source.
+	fn graph(&self) -> Result<Graph, String> {
+		let stream = lex_trivia(&self.source())?;
+		let spans = stream.spans();
+		let (ast, spans) = parse_spans(&stream.tokens(), &spans).map_err(|e| error_message(&spans, e))?;
+		let (program, origins) = build(&ast);
+		let spans = flow_graph::spans(&origins, &spans);
+
+		Ok((program, origins, spans))
+	}
+
+	/// Runs the analysis of a pattern, followed by its argument if any.
+	fn analyze(&self, pattern: &str) -> Result<String, String> {
+		let (pattern, argument) = pattern.split_once(' ').map(|(pattern, argument)| (pattern, argument.trim())).unwrap_or((pattern, ""));
+		let (program, origins, spans) = self.graph()?;
+
+		match pattern {
+			"security" => Ok(security::report(&program, &security::check(&program, &security::classification(&lines(argument))?), &spans)),
+			"taint" => Ok(taint::report(&program, &taint::check(&program, &taint::sanitizers(&lines(argument))?), &spans)),
+			"slice" => {
+				let (forward, argument) = match argument.strip_prefix("--forward") {
+					Some(argument) => (true, argument.trim()),
+					None => (false, argument),
+				};
+				let criterion = slicing::Criterion::parse(argument)?;
+				let slice = if forward { slicing::forward(&program, criterion.node)? } else { slicing::backward(&program, &criterion)? };
+
+				Ok(Program(&slicing::program(&self.ast, &program, &origins, &slice)).to_string())
+			},
+			"" => Err("Missing analysis pattern.".to_string()),
+			_ => analyze(program, pattern.to_string(), &origins, &spans),
+		}
+	}
+
+	/// Runs a command other than `:run` and `:quit`, giving what it prints.
+	pub fn command(&mut self, command: &str) -> Result<String, String> {
+		let (name, argument) = command.split_once(' ').map(|(name, argument)| (name, argument.trim())).unwrap_or((command, ""));
+
+		match name {
+			":help" => Ok(HELP.to_string()),
+			":list" | ":fmt" => Ok(self.source()),
+			":ast" => Ok(format!("{:#?}", self.ast)),
+			":tokens" => Ok(lex_str(&self.source())?.iter().map(|token| format!("{:?}", token)).collect::<Vec<String>>().join("\n")),
+			":graph" => {
+				let (program, _, _) = self.graph()?;
+
+				Ok(program.0.edge_references().map(|edge| format!("q{} -> q{} {}", edge.source().index(), edge.target().index(), edge.weight())).collect::<Vec<String>>().join("\n"))
+			},
+			":analyze" => self.analyze(argument),
+			":reset" => {
+				self.ast = Ast::default();
+				Ok(String::new())
+			},
+			_ => Err(format!("Unknown command '{name}', :help lists them.")),
+		}
+	}
+}
+
+/// Reads a line, `None` at the end of the input.
+fn line(input: &mut impl BufRead) -> io::Result<Option<String>> {
+	let mut line = String::new();
+
+	Ok(if input.read_line(&mut line)? == 0 { None } else { Some(line) })
+}
+
+/// Runs the program, its reads prompting for values on the input and its writes printed to the output.
+fn execute(session: &Session, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+	let program = match session.graph() {
+		Ok((program, _, _)) => program,
+		Err(e) => return writeln!(output, "{e}"),
+	};
+	let output = RefCell::new(output);
+	let read = |lvalue: &str| -> Result<Value, String> {
+		let mut prompt = output.borrow_mut();
+
+		write!(prompt, "{lvalue}? ").and_then(|_| prompt.flush()).map_err(|e| e.to_string())?;
+		drop(prompt);
+
+		match line(input).map_err(|e| e.to_string())? {
+			Some(text) => Value::try_from(text),
+			None => Err("Unexpected end of input.".to_string()),
+		}
+	};
+	let write = |value: Value| {
+		let _ = writeln!(output.borrow_mut(), "{value}");
+	};
+
+	let result = run(&program, read, write);
+	let mut output = output.borrow_mut();
+
+	match result {
+		Ok(state) => writeln!(output, "{state}"),
+		Err(e) => writeln!(output, "{e}"),
+	}
+}
+
+/// Reads declarations, statements and commands from the input until its end or `:quit`, printing prompts and results to the output.
+pub fn repl(mut input: impl BufRead, mut output: impl Write, mut session: Session) -> io::Result<()> {
+	let mut chunk = String::new();
+
+	loop {
+		write!(output, "{}", if chunk.is_empty() { "> " } else { "| " })?;
+		output.flush()?;
+
+		let text = match line(&mut input)? {
+			Some(text) => text,
+			None => return writeln!(output),
+		};
+
+		if chunk.is_empty() && text.trim().starts_with(':') {
+			match text.trim() {
+				":quit" => return Ok(()),
+				":run" => execute(&session, &mut input, &mut output)?,
+				command => match session.command(command) {
+					Ok(result) if result.is_empty() => (),
+					Ok(result) => writeln!(output, "{result}")?,
+					Err(e) => writeln!(output, "{e}")?,
+				},
+			}
+
+			continue;
+		}
+
+		chunk.push_str(&text);
+
+		if complete(&chunk) {
+			if let Err(e) = session.add(&chunk) {
+				writeln!(output, "{e}")?;
+			}
+
+			chunk.clear();
+		} else if lex_str(&chunk).is_ok_and(|tokens| tokens.is_empty()) {
+			chunk.clear();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Session, complete, lex_str, parse, repl};
+
+	fn transcript(script: &str) -> String {
+		let mut output = vec![];
+
+		repl(script.as_bytes(), &mut output, Session::default()).unwrap();
+		String::from_utf8(output).unwrap()
+	}
+
+	#[test]
+	fn braces_in_comments_are_not_counted() {
+		assert!(!complete("if x > 0 { // }\n"));
+		assert!(complete("while x > 0 { /* { */ x := 1; }\n"));
+		assert!(complete("x := 1; // done\n"));
+		assert!(!complete("x := 1; /* open\n"));
+		// errors are reported without waiting for more lines
+		assert!(complete("x := #\n"));
+	}
+
+	#[test]
+	fn blocks_continue_on_the_next_lines() {
+		assert_eq!(transcript("int x;\nif x == 0 {\n\tx := 1;\n} // done\n/* a\nb */\n:list\n"), "> > | | > | > int x;\nif x == 0 {\n\tx := 1;\n}\n> \n");
+	}
+
+	#[test]
+	fn commands_show_the_program() {
+		let mut session = Session::default();
+
+		session.add("int x;\nx := 1;").unwrap();
+
+		assert_eq!(session.command(":tokens"), Ok("Keyword(Type(Int))\nIdentifier(\"x\")\nSymbol(Semi)\nIdentifier(\"x\")\nSymbol(ColonEq)\nLiteral(IntegerLiteral(DecimalLiteral(1)))\nSymbol(Semi)".to_string()));
+		assert_eq!(session.command(":graph"), Ok("q0 -> q2 int x;\nq2 -> q1 x := 1;".to_string()));
+		assert_eq!(session.command(":ast"), Ok(format!("{:#?}", parse(lex_str("int x;\nx := 1;").unwrap()).unwrap())));
+	}
+
+	#[test]
+	fn parse_errors_leave_the_program_unchanged() {
+		assert_eq!(transcript("int x;\nx := ;\nx := 2;\n:list\n"), "> > Cannot parse arithmetic expression starting with 'Symbol(Semi)'.\n> > int x;\nx := 2;\n> \n");
+	}
+
+	#[test]
+	fn session() {
+		let script = "int n;\nint a;\nread n;\na := 1;\nwhile n > 1 {\n\ta *= n;\n\tn -= 1;\n}\nwrite a;\n{int x;} r;\n:run\n4\n:analyze slice q8:a\n:analyze security secret: n; public: a\n:foo\n:reset\n:list\n";
+		let mut output = vec![];
+
+		repl(script.as_bytes(), &mut output, Session::default()).unwrap();
+
+		assert_eq!(String::from_utf8(output).unwrap(), "\
+> > > > > | | | > > > n? 24\n{a: 24, n: 1, r.x: 0}\n\
+> int n;\nint a;\nread n;\na := 1;\nwhile n > 1 {\n\ta := a * n;\n\tn := n - 1;\n}\n\
+> 7:2: q8 -> q9 `a := a * n;`: public variable `a` may depend on secret data, explicitly through `a`, `n` and implicitly through `n`\n\
+10:1: q7 -> q1 `write a;`: output may depend on secret data, explicitly through `a`\n\
+> Unknown command ':foo', :help lists them.\n\
+> > > \n");
+	}
+}
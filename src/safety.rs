@@ -0,0 +1,300 @@
+//! Division-by-zero and array-bounds checks with the interval analysis.
+
+use crate::analysis::{Memory, Value, evaluate, floats, is_float, interval::{self, Interval}, sign::{self, Sign, Signs}};
+use crate::flow_graph::{Action, FlowGraph, Origin, Position, locate};
+use crate::points_to::{PointsTo, andersen};
+use crate::lexer::{Span, literal::IntegerLiteral};
+use crate::microc::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticOperation, LvalueExpr}, ops::ArithmeticOp, visit::{Visitor, walk_arex}};
+use petgraph::graph::EdgeIndex;
+use std::{collections::{BTreeSet, HashMap}, fmt::{self, Display, Formatter}};
+
+/// Verdict on an operation that may fail at runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Safety {
+	/// Never fails.
+	Safe,
+	/// May fail.
+	PossiblyUnsafe,
+	/// Fails whenever it is reached.
+	DefinitelyUnsafe,
+}
+
+impl Display for Safety {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Safety::*;
+
+		match *self {
+			Safe => write!(f, "safe"),
+			PossiblyUnsafe => write!(f, "possibly unsafe"),
+			DefinitelyUnsafe => write!(f, "definitely unsafe"),
+		}
+	}
+}
+
+/// Operation that may fail at runtime.
+#[derive(Debug, Clone)]
+pub enum Site {
+	/// Division or remainder, which fails when the divisor is zero.
+	Division(ArithmeticOperation),
+	/// Index of the given dimension of an array access, which fails when out of the declared bounds.
+	ArrayIndex(LvalueExpr, usize),
+}
+
+impl Display for Site {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Site::*;
+
+		match self {
+			Division(operation) => {
+				let kind = if let ArithmeticOp::Rem = operation.1 { "remainder" } else { "division" };
+
+				write!(f, "{kind} by `{}` in `{}`", operation.2, ArithmeticExpr::ArithmeticOperation(Box::new(operation.clone())))
+			},
+			ArrayIndex(lvalue @ LvalueExpr::ArrayIndex(_, indexes), dim) => write!(f, "index `{}` in `{lvalue}`", indexes[*dim]),
+			ArrayIndex(lvalue, _) => write!(f, "`{lvalue}`"),
+		}
+	}
+}
+
+/// Verdict on a site, with the values it was reached from.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+	/// Edge of the program graph whose action contains the site.
+	pub edge: EdgeIndex,
+	/// Operation checked.
+	pub site: Site,
+	/// Verdict on the operation.
+	pub safety: Safety,
+	/// Values of the divisor or of the index.
+	pub value: Interval,
+	/// Signs of a divisor.
+	pub signs: Option<Signs>,
+	/// Valid values of an index.
+	pub bounds: Option<Interval>,
+}
+
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}, {} ", self.safety, self.site, if let Site::Division(_) = self.site { "divisor" } else { "index" })?;
+
+		match (&self.signs, self.bounds) {
+			(Some(signs), _) => write!(f, "{} with signs {signs}", self.value),
+			(_, Some(bounds)) => write!(f, "{} for bounds {bounds}", self.value),
+			_ => write!(f, "{}", self.value),
+		}
+	}
+}
+
+/// Classifies the values of a divisor, both domains being sound so that the most precise one wins.
+fn divisor(value: &Interval, signs: &Signs) -> Safety {
+	if !value.contains(0) || !signs.0.contains(&Sign::Zero) {
+		Safety::Safe
+	} else if *value == Interval::constant(0) || signs.0.iter().all(|s| *s == Sign::Zero) {
+		Safety::DefinitelyUnsafe
+	} else {
+		Safety::PossiblyUnsafe
+	}
+}
+
+/// Classifies the values of an index against the valid ones.
+fn index(value: &Interval, bounds: &Interval) -> Safety {
+	if value.within(bounds) {
+		Safety::Safe
+	} else if value.meet(bounds) == Interval::Empty {
+		Safety::DefinitelyUnsafe
+	} else {
+		Safety::PossiblyUnsafe
+	}
+}
+
+struct Checker<'a> {
+	dimensions: &'a HashMap<String, Vec<IntegerLiteral>>,
+	intervals: &'a Memory<Interval>,
+	signs: &'a Memory<Signs>,
+	points_to: &'a PointsTo,
+	floats: &'a BTreeSet<String>,
+	edge: EdgeIndex,
+	diagnostics: Vec<Diagnostic>,
+}
+
+/// Values of an expression at the site, bottom if the site is unreachable.
+fn evaluate_at<V: Value>(memory: &Memory<V>, arex: &ArithmeticExpr, points_to: &PointsTo, floats: &BTreeSet<String>) -> V {
+	match memory {
+		Some(memory) => evaluate(memory, arex, points_to, floats),
+		None => V::bottom(),
+	}
+}
+
+impl Visitor for Checker<'_> {
+	fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+		walk_arex(self, arex);
+
+		// float divisions by zero give infinities or NaN instead of failing
+		if let ArithmeticExpr::ArithmeticOperation(operation) = arex {
+			if let (ArithmeticOp::Div | ArithmeticOp::Rem, false) = (&operation.1, is_float(arex, self.floats)) {
+				let value = evaluate_at(self.intervals, &operation.2, self.points_to, self.floats);
+				let signs = evaluate_at(self.signs, &operation.2, self.points_to, self.floats);
+
+				// an empty divisor is never computed, the site being unreachable
+				if value == Interval::Empty || signs.0.is_empty() {
+					return;
+				}
+
+				self.diagnostics.push(Diagnostic {
+					edge: self.edge,
+					site: Site::Division((**operation).clone()),
+					safety: divisor(&value, &signs),
+					value,
+					signs: Some(signs),
+					bounds: None,
+				});
+			}
+		}
+	}
+
+	fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+		if let LvalueExpr::ArrayIndex(id, indexes) = lvalue {
+			for (dim, arex) in indexes.iter().enumerate() {
+				self.visit_arex(arex);
+
+				let bounds = self.dimensions.get(id).and_then(|sizes| sizes.get(dim)).map(|size| Interval::range(0, isize::from(*size) - 1));
+
+				if let Some(bounds) = bounds {
+					let value = evaluate_at(self.intervals, arex, self.points_to, self.floats);
+
+					if value == Interval::Empty {
+						continue;
+					}
+
+					self.diagnostics.push(Diagnostic {
+						edge: self.edge,
+						site: Site::ArrayIndex(lvalue.clone(), dim),
+						safety: index(&value, &bounds),
+						value,
+						signs: None,
+						bounds: Some(bounds),
+					});
+				}
+			}
+		}
+	}
+}
+
+impl Checker<'_> {
+	fn action(&mut self, action: &Action) {
+		match action {
+			Action::Declaration(_) => (),
+			Action::Statement(stmt) => self.visit_stmt(stmt),
+			Action::Condition(boolex) => self.visit_boolex(boolex),
+			Action::Call(call) => call.arguments.iter().for_each(|arex| self.visit_arex(arex)),
+			// the results are assigned once the procedure has returned
+			Action::Return(call) => call.results.iter().for_each(|lvalue| self.visit_lvalue(lvalue)),
+		}
+	}
+}
+
+/// Checks every reachable division, remainder and array access of the program with the interval and sign analyses, the origins of the edges telling which array declaration is in scope.
+pub fn check(program: &FlowGraph, origins: &HashMap<EdgeIndex, Origin>) -> Vec<Diagnostic> {
+	let intervals = interval::analyze(program);
+	let signs = sign::analyze(program);
+	let points_to = andersen(program);
+	let floats = floats(program);
+	let position = |edge: EdgeIndex| origins.get(&edge).map(|(position, _)| position.as_slice()).unwrap_or(&[]);
+	// the scope of a declaration holds the items sharing its position but the last index, innermost scopes last
+	let mut declarations: Vec<(Position, &String, &Vec<IntegerLiteral>)> = program.0.edge_indices().filter_map(|edge| match &program.0[edge] {
+		Action::Declaration(Declaration::Array(_, sizes, id)) => {
+			let position = position(edge);
+
+			Some((position[..position.len().saturating_sub(1)].to_vec(), id, sizes))
+		},
+		_ => None,
+	}).collect();
+	declarations.sort_by_key(|(scope, _, _)| scope.len());
+
+	let mut diagnostics = Vec::<Diagnostic>::new();
+
+	for edge in program.0.edge_indices() {
+		let (source, _) = program.0.edge_endpoints(edge).unwrap();
+		let dimensions: HashMap<String, Vec<IntegerLiteral>> = declarations.iter()
+			.filter(|(scope, _, _)| position(edge).starts_with(scope))
+			.map(|(_, id, sizes)| ((*id).clone(), (*sizes).clone()))
+			.collect();
+		let mut checker = Checker { dimensions: &dimensions, intervals: &intervals[&source], signs: &signs[&source], points_to: &points_to, floats: &floats, edge, diagnostics: vec![] };
+
+		checker.action(&program.0[edge]);
+		diagnostics.append(&mut checker.diagnostics);
+	}
+
+	diagnostics
+}
+
+/// Formats the diagnostics with the program graph edges they occur on, located with the spans of the edges.
+pub fn report(program: &FlowGraph, diagnostics: &[Diagnostic], spans: &HashMap<EdgeIndex, Span>) -> String {
+	diagnostics.iter().map(|diagnostic| {
+		let (source, target) = program.0.edge_endpoints(diagnostic.edge).unwrap();
+
+		format!("{}q{} -> q{} `{}`: {diagnostic}", locate(spans, diagnostic.edge), source.index(), target.index(), program.0[diagnostic.edge])
+	}).collect::<Vec<String>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{check, report};
+	use crate::{flow_graph::build, lexer::lex_str, parser::parse};
+	use std::collections::HashMap;
+
+	fn safety(source: &str) -> Vec<String> {
+		let (program, origins) = build(&parse(lex_str(source).unwrap()).unwrap());
+
+		report(&program, &check(&program, &origins), &HashMap::new()).lines().map(String::from).collect()
+	}
+
+	#[test]
+	fn divisors_may_be_zero() {
+		let source = "int x;\nint y;\nfloat f;\nread x;\nif x > 0 {\n\ty := 10 / x;\n\ty := 10 % (x - 1);\n}\nf := 1 / f;\nif false {\n\ty := 1 / 0;\n}\ny := 1 / 0;";
+
+		assert_eq!(safety(source), [
+			"q7 -> q8 `y := 10 / x;`: safe: division by `x` in `10 / x`, divisor [1, +inf] with signs {+}",
+			"q8 -> q6 `y := 10 % (x - 1);`: possibly unsafe: remainder by `x - 1` in `10 % (x - 1)`, divisor [0, +inf] with signs {-, 0, +}",
+			// float divisions do not fail, and the unreachable divisions are left out
+			"q10 -> q1 `y := 1 / 0;`: definitely unsafe: division by `0` in `1 / 0`, divisor [0, 0] with signs {0}",
+		]);
+	}
+
+	#[test]
+	fn indexes_may_be_out_of_bounds() {
+		let source = "int[0x4] a;\nint[0o10] b;\nint[0b11] c;\nint x;\nread x;\na[3] := 1;\nb[x] := 2;\nc[3] := 0;\nwhile false {\n\ta[4] := 0;\n}";
+
+		assert_eq!(safety(source), [
+			"q6 -> q7 `a[3] := 1;`: safe: index `3` in `a[3]`, index [3, 3] for bounds [0, 3]",
+			"q7 -> q8 `b[x] := 2;`: possibly unsafe: index `x` in `b[x]`, index [-inf, +inf] for bounds [0, 7]",
+			"q8 -> q9 `c[3] := 0;`: definitely unsafe: index `3` in `c[3]`, index [3, 3] for bounds [0, 2]",
+		]);
+	}
+
+	#[test]
+	fn sites_after_failures_are_unreachable() {
+		assert_eq!(safety("int x;\nint y;\nx := 0;\ny := 1 / x;\ny := 2 / x;"), [
+			"q4 -> q5 `y := 1 / x;`: definitely unsafe: division by `x` in `1 / x`, divisor [0, 0] with signs {0}",
+		]);
+	}
+
+	#[test]
+	fn empty_arrays_have_no_valid_index() {
+		assert_eq!(safety("int[0] a;\na[0] := 1;"), [
+			"q2 -> q1 `a[0] := 1;`: definitely unsafe: index `0` in `a[0]`, index [0, 0] for bounds empty",
+		]);
+	}
+
+	#[test]
+	fn shadowed_arrays_keep_their_bounds() {
+		let source = "int[2] a;\n{\n\tint[10] a;\n\ta[5] := 1;\n}\na[1] := 1;\na[5] := 1;";
+
+		assert_eq!(safety(source), [
+			"q3 -> q4 `a[5] := 1;`: safe: index `5` in `a[5]`, index [5, 5] for bounds [0, 9]",
+			// the inner declaration does not outlive its scope
+			"q4 -> q5 `a[1] := 1;`: safe: index `1` in `a[1]`, index [1, 1] for bounds [0, 1]",
+			"q5 -> q1 `a[5] := 1;`: definitely unsafe: index `5` in `a[5]`, index [5, 5] for bounds [0, 1]",
+		]);
+	}
+}
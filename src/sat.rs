@@ -0,0 +1,417 @@
+//! Conflict-driven clause learning SAT solver.
+//!
+//! Clauses are watched by two of their literals, conflicts are analyzed up to their first unique implication point, and the
+//! decisions follow the activity of the variables in the recent conflicts, with restarts after Luby-scheduled numbers of
+//! conflicts.
+
+use std::{cmp::Ordering, collections::BinaryHeap, ops::Not};
+
+/// Number of conflicts of the unit of the restart schedule.
+const RESTART: usize = 100;
+
+/// Factor by which the activities of the variables decay after every conflict.
+const DECAY: f64 = 0.95;
+
+/// Variable or negated variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Lit(usize);
+
+impl Lit {
+	/// Literal of a variable, negated or not.
+	pub fn new(var: usize, negated: bool) -> Self {
+		Lit(2 * var + negated as usize)
+	}
+
+	/// Variable of the literal.
+	pub fn var(self) -> usize {
+		self.0 / 2
+	}
+
+	/// Whether the literal is the negation of its variable.
+	pub fn negated(self) -> bool {
+		self.0 % 2 == 1
+	}
+}
+
+impl Not for Lit {
+	type Output = Lit;
+
+	fn not(self) -> Lit {
+		Lit(self.0 ^ 1)
+	}
+}
+
+/// Variable ordered by its activity.
+#[derive(PartialEq)]
+struct Activity(f64, usize);
+
+impl Eq for Activity {}
+
+impl PartialOrd for Activity {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Activity {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.0.total_cmp(&other.0).then(other.1.cmp(&self.1))
+	}
+}
+
+/// Solver of a set of clauses, added before solving.
+#[derive(Default)]
+pub struct Solver {
+	clauses: Vec<Vec<Lit>>,
+	/// Clauses watched by every literal, as their first or second literal.
+	watches: Vec<Vec<usize>>,
+	values: Vec<Option<bool>>,
+	levels: Vec<usize>,
+	/// Clause that implied the value of every variable, whose first literal it is, `None` for decisions.
+	reasons: Vec<Option<usize>>,
+	trail: Vec<Lit>,
+	/// Length of the trail before every decision.
+	decisions: Vec<usize>,
+	/// Index in the trail of the next literal to propagate.
+	head: usize,
+	activity: Vec<f64>,
+	increment: f64,
+	/// Variables by activity, possibly assigned or with an outdated activity.
+	order: BinaryHeap<Activity>,
+	/// Last value of every variable, which it is decided to again.
+	phases: Vec<bool>,
+	unsatisfiable: bool,
+}
+
+/// Term of the Luby sequence 1, 1, 2, 1, 1, 2, 4, ... at index `i`.
+fn luby(mut i: usize) -> usize {
+	let (mut size, mut power) = (1, 1);
+
+	while size < i + 1 {
+		size = 2 * size + 1;
+		power *= 2;
+	}
+
+	while size - 1 != i {
+		size = (size - 1) / 2;
+		power /= 2;
+		i %= size;
+	}
+
+	power
+}
+
+impl Solver {
+	/// Solver without variables or clauses.
+	pub fn new() -> Self {
+		Solver { increment: 1.0, ..Solver::default() }
+	}
+
+	/// Adds a variable, returning its index.
+	pub fn var(&mut self) -> usize {
+		let var = self.values.len();
+
+		self.values.push(None);
+		self.levels.push(0);
+		self.reasons.push(None);
+		self.activity.push(0.0);
+		self.phases.push(false);
+		self.watches.extend([vec![], vec![]]);
+		self.order.push(Activity(0.0, var));
+		var
+	}
+
+	fn value(&self, lit: Lit) -> Option<bool> {
+		self.values[lit.var()].map(|value| value != lit.negated())
+	}
+
+	/// Value of a variable in the model found by the last successful [`Solver::solve`].
+	pub fn model(&self, var: usize) -> bool {
+		self.values[var].unwrap_or(false)
+	}
+
+	fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+		self.values[lit.var()] = Some(!lit.negated());
+		self.levels[lit.var()] = self.decisions.len();
+		self.reasons[lit.var()] = reason;
+		self.trail.push(lit);
+	}
+
+	fn watch(&mut self, clause: Vec<Lit>) -> usize {
+		let index = self.clauses.len();
+
+		self.watches[clause[0].0].push(index);
+		self.watches[clause[1].0].push(index);
+		self.clauses.push(clause);
+		index
+	}
+
+	/// Adds a clause, before solving.
+	pub fn clause(&mut self, lits: &[Lit]) {
+		let mut clause: Vec<Lit> = lits.to_vec();
+
+		clause.sort();
+		clause.dedup();
+
+		if self.unsatisfiable || clause.windows(2).any(|pair| pair[0] == !pair[1]) || clause.iter().any(|lit| self.value(*lit) == Some(true)) {
+			return;
+		}
+
+		clause.retain(|lit| self.value(*lit).is_none());
+
+		match clause.len() {
+			0 => self.unsatisfiable = true,
+			1 => {
+				self.assign(clause[0], None);
+				self.unsatisfiable = self.propagate().is_some();
+			},
+			_ => {
+				self.watch(clause);
+			},
+		}
+	}
+
+	/// Propagates the assignments of the trail, giving the clause of a conflict if any.
+	fn propagate(&mut self) -> Option<usize> {
+		while self.head < self.trail.len() {
+			let falsified = !self.trail[self.head];
+			let mut watching = std::mem::take(&mut self.watches[falsified.0]);
+			let mut i = 0;
+
+			self.head += 1;
+
+			while i < watching.len() {
+				let index = watching[i];
+				let clause = &mut self.clauses[index];
+
+				if clause[0] == falsified {
+					clause.swap(0, 1);
+				}
+
+				let first = clause[0];
+
+				if self.values[first.var()].map(|value| value != first.negated()) == Some(true) {
+					i += 1;
+					continue;
+				}
+
+				let values = &self.values;
+				let replacement = (2..clause.len()).find(|k| values[clause[*k].var()].map(|value| value != clause[*k].negated()) != Some(false));
+
+				if let Some(k) = replacement {
+					clause.swap(1, k);
+					self.watches[clause[1].0].push(index);
+					watching.swap_remove(i);
+				} else if self.value(first) == Some(false) {
+					self.watches[falsified.0] = watching;
+					return Some(index);
+				} else {
+					self.assign(first, Some(index));
+					i += 1;
+				}
+			}
+
+			self.watches[falsified.0] = watching;
+		}
+
+		None
+	}
+
+	fn bump(&mut self, var: usize) {
+		self.activity[var] += self.increment;
+
+		if 1e100 < self.activity[var] {
+			self.activity.iter_mut().for_each(|activity| *activity *= 1e-100);
+			self.increment *= 1e-100;
+			self.order = self.activity.iter().enumerate().map(|(var, activity)| Activity(*activity, var)).collect();
+		} else {
+			self.order.push(Activity(self.activity[var], var));
+		}
+	}
+
+	/// Learned clause of a conflict, asserting its first literal, with the level to go back to.
+	fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+		let level = self.decisions.len();
+		let mut learned = vec![Lit(0)];
+		let mut seen = vec![false; self.values.len()];
+		let (mut pending, mut index, mut clause, mut implied) = (0, self.trail.len(), conflict, None::<Lit>);
+
+		loop {
+			let skip = if implied.is_some() { 1 } else { 0 };
+
+			for k in skip..self.clauses[clause].len() {
+				let lit = self.clauses[clause][k];
+				let var = lit.var();
+
+				if !seen[var] && 0 < self.levels[var] {
+					seen[var] = true;
+					self.bump(var);
+
+					if self.levels[var] == level {
+						pending += 1;
+					} else {
+						learned.push(lit);
+					}
+				}
+			}
+
+			loop {
+				index -= 1;
+
+				if seen[self.trail[index].var()] {
+					break;
+				}
+			}
+
+			let lit = self.trail[index];
+
+			seen[lit.var()] = false;
+			pending -= 1;
+
+			if pending == 0 {
+				learned[0] = !lit;
+				break;
+			}
+
+			clause = self.reasons[lit.var()].unwrap();
+			implied = Some(lit);
+		}
+
+		// the literal of the highest level after the asserting one is watched, and undone last
+		let back = (1..learned.len()).max_by_key(|k| self.levels[learned[*k].var()]);
+
+		match back {
+			Some(k) => {
+				learned.swap(1, k);
+				let level = self.levels[learned[1].var()];
+
+				(learned, level)
+			},
+			None => (learned, 0),
+		}
+	}
+
+	fn backtrack(&mut self, level: usize) {
+		if level < self.decisions.len() {
+			for lit in self.trail.drain(self.decisions[level]..).rev() {
+				self.phases[lit.var()] = !lit.negated();
+				self.values[lit.var()] = None;
+				self.reasons[lit.var()] = None;
+				self.order.push(Activity(self.activity[lit.var()], lit.var()));
+			}
+
+			self.decisions.truncate(level);
+			self.head = self.trail.len();
+		}
+	}
+
+	/// Unassigned variable of highest activity.
+	fn decide(&mut self) -> Option<usize> {
+		while let Some(Activity(_, var)) = self.order.pop() {
+			if self.values[var].is_none() {
+				return Some(var);
+			}
+		}
+
+		None
+	}
+
+	/// Tells whether the clauses are satisfiable, the model being given by [`Solver::model`].
+	pub fn solve(&mut self) -> bool {
+		if self.unsatisfiable {
+			return false;
+		}
+
+		let (mut conflicts, mut restarts) = (0, 0);
+
+		loop {
+			if let Some(conflict) = self.propagate() {
+				if self.decisions.is_empty() {
+					self.unsatisfiable = true;
+					return false;
+				}
+
+				let (learned, level) = self.analyze(conflict);
+
+				self.backtrack(level);
+				self.increment /= DECAY;
+				conflicts += 1;
+
+				if learned.len() == 1 {
+					self.assign(learned[0], None);
+				} else {
+					let asserting = learned[0];
+					let index = self.watch(learned);
+
+					self.assign(asserting, Some(index));
+				}
+			} else if RESTART * luby(restarts) <= conflicts {
+				conflicts = 0;
+				restarts += 1;
+				self.backtrack(0);
+			} else {
+				match self.decide() {
+					Some(var) => {
+						self.decisions.push(self.trail.len());
+						self.assign(Lit::new(var, !self.phases[var]), None);
+					},
+					None => return true,
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Lit, Solver, luby};
+
+	/// Clauses stating that `pigeons` pigeons sit in `holes` holes, one at most per hole.
+	fn pigeonhole(pigeons: usize, holes: usize) -> Solver {
+		let mut solver = Solver::new();
+		let sits: Vec<Vec<usize>> = (0..pigeons).map(|_| (0..holes).map(|_| solver.var()).collect()).collect();
+
+		for pigeon in &sits {
+			solver.clause(&pigeon.iter().map(|var| Lit::new(*var, false)).collect::<Vec<Lit>>());
+		}
+
+		for hole in 0..holes {
+			for (p, first) in sits.iter().enumerate() {
+				for second in &sits[p + 1..] {
+					solver.clause(&[Lit::new(first[hole], true), Lit::new(second[hole], true)]);
+				}
+			}
+		}
+
+		solver
+	}
+
+	#[test]
+	fn pigeons_do_not_fit_in_fewer_holes() {
+		assert!(pigeonhole(5, 5).solve());
+		assert!(!pigeonhole(6, 5).solve());
+		assert_eq!((0..10).map(luby).collect::<Vec<usize>>(), [1, 1, 2, 1, 1, 2, 4, 1, 1, 2]);
+	}
+
+	#[test]
+	fn models_satisfy_the_clauses() {
+		// pseudo-random 3-SAT instances below the satisfiability threshold
+		let mut seed = 12345u64;
+		let mut next = |n: usize| {
+			seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+			(seed >> 33) as usize % n
+		};
+
+		for _ in 0..20 {
+			let mut solver = Solver::new();
+			let vars: Vec<usize> = (0..40).map(|_| solver.var()).collect();
+			let clauses: Vec<Vec<Lit>> = (0..120).map(|_| (0..3).map(|_| Lit::new(vars[next(40)], next(2) == 1)).collect()).collect();
+
+			clauses.iter().for_each(|clause| solver.clause(clause));
+
+			if solver.solve() {
+				assert!(clauses.iter().all(|clause| clause.iter().any(|lit| solver.model(lit.var()) != lit.negated())));
+			}
+		}
+	}
+}
@@ -0,0 +1,313 @@
+//! Information flow security of variables classified as public or secret.
+
+use crate::analysis::{Analyzer, arex_variables, boolex_variables, lvalue_variables, records, variables, worklist};
+use crate::flow_graph::{Action, FlowGraph, control_dependences, locate};
+use crate::lexer::Span;
+use crate::points_to::{PointsTo, andersen, stored};
+use crate::microc::stmt::Statement;
+use crate::worklist::FifoWorklist;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use std::{collections::{BTreeSet, HashMap}, convert::TryFrom, fmt::{self, Display, Formatter}};
+
+/// Security level of a variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+	/// Observable by an attacker.
+	Public,
+	/// Not to be observed by an attacker.
+	Secret,
+}
+
+impl Display for Level {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Level::*;
+
+		match *self {
+			Public => write!(f, "public"),
+			Secret => write!(f, "secret"),
+		}
+	}
+}
+
+impl TryFrom<String> for Level {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		use Level::*;
+
+		match value.as_str() {
+			"public" => Ok(Public),
+			"secret" => Ok(Secret),
+			_ => Err(format!("Unknown security level '{value}'.")),
+		}
+	}
+}
+
+/// Security levels of the annotated variables, record members being named `record.member`.
+pub type Classification = HashMap<String, Level>;
+
+/// Parses a `level: variable, ...` annotation into the classification.
+fn annotate(annotation: &str, classification: &mut Classification) -> Result<(), String> {
+	if let Some((level, vars)) = annotation.split_once(':') {
+		let level = Level::try_from(level.trim().to_string())?;
+
+		for var in vars.split(',').map(str::trim).filter(|var| !var.is_empty()) {
+			if classification.insert(var.to_string(), level).is_some() {
+				return Err(format!("Variable '{var}' is classified twice."));
+			}
+		}
+
+		Ok(())
+	} else {
+		Err(format!("Expected 'level: variable, ...', got '{annotation}'."))
+	}
+}
+
+/// Parses an annotation file, made of `level: variable, ...` lines and `#` comments.
+pub fn classification(annotations: &str) -> Result<Classification, String> {
+	let mut classification = Classification::new();
+
+	for line in annotations.lines().map(|line| line.split('#').next().unwrap().trim()).filter(|line| !line.is_empty()) {
+		annotate(line, &mut classification)?;
+	}
+
+	Ok(classification)
+}
+
+/// Extracts the classification from the `//@ level: variable, ...` comments of a MicroC source.
+pub fn embedded(source: &str) -> Result<Classification, String> {
+	let mut classification = Classification::new();
+
+	for line in source.lines() {
+		if let Some((_, annotation)) = line.split_once("//@") {
+			annotate(annotation.trim(), &mut classification)?;
+		}
+	}
+
+	Ok(classification)
+}
+
+/// Dangerous variables analysis: variables that may hold a value depending on secret data.
+///
+/// Secret variables are always dangerous, and are thus left out of the sets.
+struct DangerousVariables {
+	secrets: BTreeSet<String>,
+	records: HashMap<String, Vec<String>>,
+	points_to: PointsTo,
+	/// Dangerous variables of the guards controlling each edge.
+	implicit: HashMap<EdgeIndex, BTreeSet<String>>,
+}
+
+impl DangerousVariables {
+	fn dangerous(&self, vars: BTreeSet<String>, r: &BTreeSet<String>) -> BTreeSet<String> {
+		vars.into_iter().filter(|var| self.secrets.contains(var) || r.contains(var)).collect()
+	}
+
+	/// Variables whose values flow into the assigned ones, for each of them.
+	fn flows(&self, a: &Action) -> Vec<(String, bool, BTreeSet<String>)> {
+		match a {
+			Action::Declaration(decl) => variables(decl).into_iter().map(|var| (var, false, BTreeSet::new())).collect(),
+			Action::Statement(Statement::LvalueAssign(lvalue, arex)) => {
+				let vars: BTreeSet<String> = arex_variables(arex).union(&lvalue_variables(lvalue)).cloned().collect();
+
+				stored(&self.points_to, lvalue).into_iter().map(|(var, weak)| (var, weak, vars.clone())).collect()
+			},
+			Action::Statement(Statement::RecordAssign(id, arexs)) => self.records.get(id).into_iter().flatten()
+				.zip(arexs)
+				.map(|(member, arex)| (member.clone(), false, arex_variables(arex)))
+				.collect(),
+			Action::Statement(Statement::Read(lvalue)) => stored(&self.points_to, lvalue).into_iter().map(|(var, weak)| (var, weak, lvalue_variables(lvalue))).collect(),
+			Action::Call(call) => call.values.iter().zip(&call.arguments)
+				.map(|(param, arex)| (param.clone(), false, arex_variables(arex)))
+				.chain(call.outputs.iter().map(|output| (output.clone(), false, BTreeSet::new())))
+				.collect(),
+			Action::Return(call) => call.results.iter().zip(&call.outputs).flat_map(|(lvalue, output)| {
+				let mut vars = lvalue_variables(lvalue);
+				vars.insert(output.clone());
+
+				stored(&self.points_to, lvalue).into_iter().map(move |(var, weak)| (var, weak, vars.clone()))
+			}).collect(),
+			_ => vec![],
+		}
+	}
+
+	fn transfer(&self, a: &Action, r: &BTreeSet<String>, context: bool) -> BTreeSet<String> {
+		let mut res = r.clone();
+
+		for (var, weak, vars) in self.flows(a) {
+			if context || !self.dangerous(vars, r).is_empty() {
+				res.insert(var);
+			} else if !weak {
+				res.remove(&var);
+			}
+		}
+
+		res
+	}
+}
+
+impl Analyzer<BTreeSet<String>> for DangerousVariables {
+	fn bottom(&self) -> BTreeSet<String> {
+		BTreeSet::new()
+	}
+
+	fn initial(&self) -> BTreeSet<String> {
+		BTreeSet::new()
+	}
+
+	fn map(&self, a: &Action, r: &BTreeSet<String>) -> BTreeSet<String> {
+		self.transfer(a, r, false)
+	}
+
+	fn map_edge(&self, edge: EdgeIndex, a: &Action, r: &BTreeSet<String>) -> BTreeSet<String> {
+		self.transfer(a, r, self.implicit.get(&edge).is_some_and(|vars| !vars.is_empty()))
+	}
+
+	fn join(&self, r1: &BTreeSet<String>, r2: &BTreeSet<String>) -> BTreeSet<String> {
+		r1.union(r2).cloned().collect()
+	}
+}
+
+/// Flow of secret data into a public variable or the output.
+#[derive(Debug, Clone)]
+pub struct Leak {
+	/// Edge of the assignment or the write.
+	pub edge: EdgeIndex,
+	/// Public variable assigned, `None` for a write.
+	pub variable: Option<String>,
+	/// Dangerous variables flowing explicitly.
+	pub explicit: BTreeSet<String>,
+	/// Dangerous variables of the guards controlling the edge.
+	pub implicit: BTreeSet<String>,
+}
+
+impl Display for Leak {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let format = |vars: &BTreeSet<String>| vars.iter().map(|var| format!("`{var}`")).collect::<Vec<String>>().join(", ");
+
+		match &self.variable {
+			Some(var) => write!(f, "public variable `{var}` may depend on secret data")?,
+			None => write!(f, "output may depend on secret data")?,
+		}
+
+		match (self.explicit.is_empty(), self.implicit.is_empty()) {
+			(false, false) => write!(f, ", explicitly through {} and implicitly through {}", format(&self.explicit), format(&self.implicit)),
+			(false, true) => write!(f, ", explicitly through {}", format(&self.explicit)),
+			(true, false) => write!(f, ", implicitly through {}", format(&self.implicit)),
+			(true, true) => Ok(()),
+		}
+	}
+}
+
+/// Reports the assignments to public variables and the writes that may leak secret data, through explicit and implicit flows.
+///
+/// Variables that are not annotated may carry secret data, but are only reported when written.
+pub fn check(program: &FlowGraph, classification: &Classification) -> Vec<Leak> {
+	let graph = &program.0;
+	let dependences = control_dependences(program);
+	let secrets = classification.iter().filter(|(_, level)| **level == Level::Secret).map(|(var, _)| var.clone()).collect();
+	let mut specification = DangerousVariables { secrets, records: records(program), points_to: andersen(program), implicit: HashMap::new() };
+
+	// the guards becoming dangerous makes more variables dangerous, until both stabilize
+	let dangerous = loop {
+		let dangerous = worklist::<FifoWorklist<NodeIndex>, _, _>(program, &specification);
+		let guards = |branch: &NodeIndex| -> BTreeSet<String> {
+			let vars = graph.edges(*branch).flat_map(|edge| match edge.weight() {
+				Action::Condition(boolex) => boolex_variables(boolex),
+				_ => BTreeSet::new(),
+			}).collect();
+
+			specification.dangerous(vars, &dangerous[branch])
+		};
+
+		let new: HashMap<EdgeIndex, BTreeSet<String>> = graph.edge_indices().map(|edge| {
+			let (source, _) = graph.edge_endpoints(edge).unwrap();
+
+			(edge, dependences[&source].iter().flat_map(guards).collect())
+		}).collect();
+
+		if new == specification.implicit {
+			break dangerous;
+		}
+
+		specification.implicit = new;
+	};
+
+	graph.edge_indices().flat_map(|edge| {
+		let (source, _) = graph.edge_endpoints(edge).unwrap();
+		let public = |var: &String| classification.get(var) == Some(&Level::Public);
+
+		let sinks: Vec<(Option<String>, BTreeSet<String>)> = match &graph[edge] {
+			Action::Statement(Statement::Write(arex)) => vec![(None, arex_variables(arex))],
+			action => specification.flows(action).into_iter().filter(|(var, _, _)| public(var)).map(|(var, _, vars)| (Some(var), vars)).collect(),
+		};
+
+		sinks.into_iter().filter_map(|(variable, vars)| {
+			let leak = Leak { edge, variable, explicit: specification.dangerous(vars, &dangerous[&source]), implicit: specification.implicit[&edge].clone() };
+
+			if leak.explicit.is_empty() && leak.implicit.is_empty() { None } else { Some(leak) }
+		}).collect::<Vec<Leak>>()
+	}).collect()
+}
+
+/// Formats the leaks with the program graph edges they occur on, located with the spans of the edges.
+pub fn report(program: &FlowGraph, leaks: &[Leak], spans: &HashMap<EdgeIndex, Span>) -> String {
+	leaks.iter().map(|leak| {
+		let (source, target) = program.0.edge_endpoints(leak.edge).unwrap();
+
+		format!("{}q{} -> q{} `{}`: {leak}", locate(spans, leak.edge), source.index(), target.index(), program.0[leak.edge])
+	}).collect::<Vec<String>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Classification, Level, check, classification, embedded, report};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+	use std::collections::HashMap;
+
+	fn leaks(source: &str) -> Vec<String> {
+		let program = flow(parse(lex_str(source).unwrap()).unwrap());
+
+		report(&program, &check(&program, &embedded(source).unwrap()), &HashMap::new()).lines().map(String::from).collect()
+	}
+
+	#[test]
+	fn explicit_flows_leak_through_assignments() {
+		assert_eq!(leaks("//@ secret: s\n//@ public: p\nint s;\nint p;\nint t;\nread s;\nt := s + 1;\np := t;\nwrite p;"), [
+			"q6 -> q7 `p := t;`: public variable `p` may depend on secret data, explicitly through `t`",
+			"q7 -> q1 `write p;`: output may depend on secret data, explicitly through `p`",
+		]);
+		// overwriting the public member with a constant stops the flow
+		assert_eq!(leaks("//@ secret: r.x\n//@ public: r.y, p\n{int x; int y;} r;\nint p;\nr := (1, 2);\nr.y := r.x;\np := r.y;\nr.y := 0;\np := r.y;"), [
+			"q4 -> q5 `r.y := r.x;`: public variable `r.y` may depend on secret data, explicitly through `r.x`",
+			"q5 -> q6 `p := r.y;`: public variable `p` may depend on secret data, explicitly through `r.y`",
+		]);
+	}
+
+	#[test]
+	fn stores_through_pointers_leak_into_their_targets() {
+		assert_eq!(leaks("//@ secret: x\n//@ public: y\nint x;\nint y;\nint* p;\np := &y;\nread x;\n*p := x;\nwrite y;"), [
+			"q6 -> q7 `*p := x;`: public variable `y` may depend on secret data, explicitly through `x`",
+			"q7 -> q1 `write y;`: output may depend on secret data, explicitly through `y`",
+		]);
+	}
+
+	#[test]
+	fn implicit_flows_leak_through_guards() {
+		assert_eq!(leaks("//@ secret: s\n//@ public: p, q\nint s;\nint p;\nint q;\nif s > 0 {\n\tp := 1;\n}\nwhile q < s {\n\tq := q + 1;\n}\nwrite 0;"), [
+			"q6 -> q5 `p := 1;`: public variable `p` may depend on secret data, implicitly through `s`",
+			"q8 -> q5 `q := q + 1;`: public variable `q` may depend on secret data, explicitly through `q` and implicitly through `q`, `s`",
+		]);
+	}
+
+	#[test]
+	fn annotations_classify_variables_once() {
+		let expected = |pairs: &[(&str, Level)]| pairs.iter().map(|(var, level)| (var.to_string(), *level)).collect::<Classification>();
+
+		assert_eq!(classification("# levels\nsecret: s, r.x\n\npublic: p # output\n"), Ok(expected(&[("s", Level::Secret), ("r.x", Level::Secret), ("p", Level::Public)])));
+		assert_eq!(embedded("int s; //@ secret: s\n//@ public: p,\nint p;"), Ok(expected(&[("s", Level::Secret), ("p", Level::Public)])));
+		assert_eq!(classification("secret: s\npublic: p, s"), Err("Variable 's' is classified twice.".to_string()));
+		assert_eq!(embedded("//@ hidden: s"), Err("Unknown security level 'hidden'.".to_string()));
+		assert_eq!(classification("secret s"), Err("Expected 'level: variable, ...', got 'secret s'.".to_string()));
+	}
+}
@@ -0,0 +1,282 @@
+//! Backward and forward slicing of programs on their program graphs.
+
+use crate::analysis::{arex_variables, boolex_variables, location, lvalue_variables, reaching::{self, Definitions, ReachingDefinitions}, variables};
+use crate::flow_graph::{Action, FlowGraph, Origin, Position, control_dependences};
+use crate::microc::{decl::Declaration, expr::LvalueExpr, stmt::{Scope, Statement}};
+use crate::parser::Ast;
+use petgraph::{graph::{EdgeIndex, NodeIndex}, visit::EdgeRef};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Program point and variables a slice is computed for.
+#[derive(Debug, Clone)]
+pub struct Criterion {
+	/// Node of the program graph.
+	pub node: usize,
+	/// Variables of interest, those used by the edges leaving the node if empty.
+	pub variables: BTreeSet<String>,
+}
+
+impl Criterion {
+	/// Parses a `node[:variable, ...]` criterion, the node being optionally prefixed by `q`.
+	pub fn parse(text: &str) -> Result<Self, String> {
+		let (node, vars) = text.split_once(':').unwrap_or((text, ""));
+		let node = node.trim();
+
+		Ok(Criterion {
+			node: node.trim_start_matches('q').parse().map_err(|_| format!("Invalid program point '{node}'."))?,
+			variables: vars.split(',').map(str::trim).filter(|var| !var.is_empty()).map(str::to_string).collect(),
+		})
+	}
+}
+
+/// Variables read by an action, a weak definition of an array also reading its previous content.
+pub fn used(a: &Action) -> BTreeSet<String> {
+	let weak = |lvalue: &LvalueExpr| -> BTreeSet<String> {
+		let mut vars = lvalue_variables(lvalue);
+
+		if let LvalueExpr::ArrayIndex(_, _) = lvalue {
+			vars.insert(location(lvalue));
+		}
+
+		vars
+	};
+
+	match a {
+		Action::Declaration(_) => BTreeSet::new(),
+		Action::Statement(Statement::LvalueAssign(lvalue, arex)) => weak(lvalue).union(&arex_variables(arex)).cloned().collect(),
+		Action::Statement(Statement::RecordAssign(_, arexs)) => arexs.iter().flat_map(arex_variables).collect(),
+		Action::Statement(Statement::Read(lvalue)) => weak(lvalue),
+		Action::Statement(Statement::Write(arex)) => arex_variables(arex),
+		Action::Statement(Statement::Assert(boolex) | Statement::Assume(boolex)) => boolex_variables(boolex),
+		Action::Statement(_) => BTreeSet::new(),
+		Action::Condition(boolex) => boolex_variables(boolex),
+		Action::Call(call) => call.arguments.iter().flat_map(arex_variables).collect(),
+		Action::Return(call) => call.results.iter().flat_map(weak).chain(call.outputs.iter().cloned()).collect(),
+	}
+}
+
+/// Dependences of the program graph edges.
+struct Dependences<'a> {
+	program: &'a FlowGraph,
+	specification: ReachingDefinitions,
+	definitions: HashMap<NodeIndex, Definitions>,
+	control: HashMap<NodeIndex, HashSet<NodeIndex>>,
+}
+
+impl<'a> Dependences<'a> {
+	fn new(program: &'a FlowGraph) -> Self {
+		Dependences { program, specification: ReachingDefinitions::new(program), definitions: reaching::analyze(program), control: control_dependences(program) }
+	}
+
+	/// Definitions of the variables reaching a node.
+	fn data(&self, node: NodeIndex, vars: &BTreeSet<String>) -> Vec<EdgeIndex> {
+		self.definitions[&node].iter().filter(|(var, _)| vars.contains(var)).map(|(_, edge)| *edge).collect()
+	}
+
+	/// Guard edges of the branches controlling a node.
+	fn guards(&self, node: NodeIndex) -> Vec<EdgeIndex> {
+		let graph = &self.program.0;
+
+		self.control[&node].iter().flat_map(|branch| graph.edges(*branch).map(|edge| edge.id())).collect()
+	}
+
+	fn source(&self, edge: EdgeIndex) -> NodeIndex {
+		self.program.0.edge_endpoints(edge).unwrap().0
+	}
+}
+
+/// Adds the edges reachable from `slice` through `next` to it.
+fn closure(mut slice: HashSet<EdgeIndex>, next: impl Fn(EdgeIndex) -> Vec<EdgeIndex>) -> HashSet<EdgeIndex> {
+	let mut pending: Vec<EdgeIndex> = slice.iter().copied().collect();
+
+	while let Some(edge) = pending.pop() {
+		for dependency in next(edge) {
+			if slice.insert(dependency) {
+				pending.push(dependency);
+			}
+		}
+	}
+
+	slice
+}
+
+/// Edges of the backward slice: those the values of the variables at the node may depend on, through data and control dependences.
+///
+/// Variables of the criterion default to the ones used by the edges leaving the node.
+pub fn backward(program: &FlowGraph, criterion: &Criterion) -> Result<HashSet<EdgeIndex>, String> {
+	let graph = &program.0;
+	let node = NodeIndex::new(criterion.node);
+
+	if graph.node_count() <= criterion.node {
+		return Err(format!("Unknown program point 'q{}'.", criterion.node));
+	}
+
+	let dependences = Dependences::new(program);
+	let vars = if criterion.variables.is_empty() {
+		graph.edges(node).flat_map(|edge| used(edge.weight())).collect()
+	} else {
+		criterion.variables.clone()
+	};
+
+	let seeds = dependences.data(node, &vars).into_iter().chain(dependences.guards(node)).collect();
+	let next = |edge: EdgeIndex| -> Vec<EdgeIndex> {
+		let source = dependences.source(edge);
+
+		dependences.data(source, &used(&graph[edge])).into_iter().chain(dependences.guards(source)).collect()
+	};
+
+	let slice = closure(seeds, next);
+
+	// jumps are kept when the branches leading to them are, as they shape the control flow of the slice
+	let jumps: Vec<EdgeIndex> = graph.edge_indices().filter(|edge| {
+		let source = dependences.source(*edge);
+
+		matches!(graph[*edge], Action::Statement(Statement::Break) | Action::Statement(Statement::Continue))
+			&& !dependences.control[&source].is_empty()
+			&& dependences.guards(source).iter().all(|guard| slice.contains(guard))
+	}).collect();
+
+	Ok(closure(slice.into_iter().chain(jumps).collect(), next))
+}
+
+/// Edges of the forward slice: those whose effect may depend on the edges leaving the node, such as a `read`.
+pub fn forward(program: &FlowGraph, node: usize) -> Result<HashSet<EdgeIndex>, String> {
+	let graph = &program.0;
+
+	if graph.node_count() <= node {
+		return Err(format!("Unknown program point 'q{node}'."));
+	}
+
+	let dependences = Dependences::new(program);
+	let seeds = graph.edges(NodeIndex::new(node)).map(|edge| edge.id()).collect();
+
+	// definitions flow to the edges they reach and use them, guards to the edges they control
+	let next = |edge: EdgeIndex| -> Vec<EdgeIndex> {
+		let source = dependences.source(edge);
+		let defined: BTreeSet<String> = dependences.specification.defined(&graph[edge]).into_iter().map(|(var, _)| var).collect();
+		let controlled = match graph[edge] {
+			Action::Condition(_) => dependences.control.iter().filter(|(_, branches)| branches.contains(&source)).map(|(node, _)| *node).collect(),
+			_ => vec![],
+		};
+
+		graph.edge_indices().filter(|other| {
+			let source = dependences.source(*other);
+
+			controlled.contains(&source) || used(&graph[*other]).iter().any(|var| defined.contains(var) && dependences.definitions[&source].contains(&(var.clone(), edge)))
+		}).collect()
+	};
+
+	Ok(closure(seeds, next))
+}
+
+/// Keeps the items of a scope at the given position that are in the slice, with the declarations of the variables they refer to.
+fn project(scope: &Scope, position: &[usize], kept: &HashSet<Position>, referenced: &BTreeSet<String>) -> Scope {
+	let at = |i: usize| [position, &[i]].concat();
+	let nested = |scope: &Scope, i: usize, k: usize| project(scope, &[at(i), vec![k]].concat(), kept, referenced);
+	let empty = |scope: &Scope| scope.0.is_empty() && scope.1.is_empty();
+
+	let decls = scope.0.iter().enumerate().filter_map(|(i, decl)| match decl {
+		// kept with all their parameters for the calls to still match them
+		Declaration::Procedure(id, values, results, body) => Some(Declaration::Procedure(id.clone(), values.clone(), results.clone(), Box::new(nested(body, i, 0)))),
+		_ if kept.contains(&at(i)) || variables(decl).iter().any(|var| referenced.contains(var)) => Some(decl.clone()),
+		_ => None,
+	}).collect();
+
+	let stmts = scope.1.iter().enumerate().filter_map(|(i, stmt)| {
+		let i = scope.0.len() + i;
+
+		match stmt {
+			Statement::If(boolex, scope) => {
+				let scope = nested(scope, i, 0);
+
+				if kept.contains(&at(i)) || !empty(&scope) { Some(Statement::If(boolex.clone(), Box::new(scope))) } else { None }
+			},
+			Statement::IfElse(boolex, scope1, scope2) => {
+				let (scope1, scope2) = (nested(scope1, i, 0), nested(scope2, i, 1));
+
+				if empty(&scope2) {
+					if kept.contains(&at(i)) || !empty(&scope1) { Some(Statement::If(boolex.clone(), Box::new(scope1))) } else { None }
+				} else {
+					Some(Statement::IfElse(boolex.clone(), Box::new(scope1), Box::new(scope2)))
+				}
+			},
+			Statement::While(boolex, scope) => {
+				let scope = nested(scope, i, 0);
+
+				if kept.contains(&at(i)) || !empty(&scope) { Some(Statement::While(boolex.clone(), Box::new(scope))) } else { None }
+			},
+			Statement::Scope(scope) => {
+				let scope = nested(scope, i, 0);
+
+				if empty(&scope) { None } else { Some(Statement::Scope(scope)) }
+			},
+			Statement::Par(scope1, scope2) => {
+				let (scope1, scope2) = (nested(scope1, i, 0), nested(scope2, i, 1));
+
+				if empty(&scope1) && empty(&scope2) { None } else { Some(Statement::Par(Box::new(scope1), Box::new(scope2))) }
+			},
+			_ => if kept.contains(&at(i)) { Some(stmt.clone()) } else { None },
+		}
+	}).collect();
+
+	(decls, stmts)
+}
+
+/// Sliced program, made of the statements of the AST whose edges are in the slice, the program graph and the origins of its edges being built from the AST.
+pub fn program(ast: &Ast, program: &FlowGraph, origins: &HashMap<EdgeIndex, Origin>, slice: &HashSet<EdgeIndex>) -> Ast {
+	let kept: HashSet<Position> = slice.iter().map(|edge| origins[edge].0.clone()).collect();
+	let specification = ReachingDefinitions::new(program);
+	let referenced: BTreeSet<String> = slice.iter().flat_map(|edge| {
+		let action = &program.0[*edge];
+
+		used(action).into_iter().chain(specification.defined(action).into_iter().map(|(var, _)| var))
+	}).collect();
+
+	project(ast, &[], &kept, &referenced)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Criterion, backward, forward, program};
+	use crate::{flow_graph::build, lexer::lex_str, microc::stmt::Program, parser::parse};
+
+	const SOURCE: &str = "int n;\nint a;\nint b;\nread n;\na := 1;\nb := 0;\nwhile n > 1 {\n\ta := a * n;\n\tb := b + 1;\n\tn := n - 1;\n}\nwrite a;\nwrite b;";
+
+	fn slice(criterion: &str, forwards: bool) -> String {
+		let ast = parse(lex_str(SOURCE).unwrap()).unwrap();
+		let (graph, origins) = build(&ast);
+		let criterion = Criterion::parse(criterion).unwrap();
+		let slice = if forwards { forward(&graph, criterion.node).unwrap() } else { backward(&graph, &criterion).unwrap() };
+
+		Program(&program(&ast, &graph, &origins, &slice)).to_string()
+	}
+
+	#[test]
+	fn criteria_name_a_node_and_variables() {
+		assert_eq!(Criterion::parse("q12").map(|c| (c.node, c.variables.len())), Ok((12, 0)));
+		assert_eq!(Criterion::parse("3: a, b,").map(|c| (c.node, c.variables.into_iter().collect::<Vec<String>>())), Ok((3, vec!["a".to_string(), "b".to_string()])));
+		assert_eq!(Criterion::parse("qx:a").map(|c| c.node), Err("Invalid program point 'qx'.".to_string()));
+
+		let graph = build(&parse(lex_str(SOURCE).unwrap()).unwrap()).0;
+
+		assert_eq!(backward(&graph, &Criterion::parse("q13").unwrap()).map(|slice| slice.len()), Err("Unknown program point 'q13'.".to_string()));
+		assert_eq!(forward(&graph, 13).map(|slice| slice.len()), Err("Unknown program point 'q13'.".to_string()));
+	}
+
+	#[test]
+	fn backward_slices_keep_what_the_variables_depend_on() {
+		// the variables default to the ones the node uses, `b` for the last write
+		let b = "int n;\nint b;\nread n;\nb := 0;\nwhile n > 1 {\n\tb := b + 1;\n\tn := n - 1;\n}";
+
+		assert_eq!(slice("q12", false), b);
+		assert_eq!(slice("q12: b", false), b);
+		assert_eq!(slice("12:a", false), "int n;\nint a;\nread n;\na := 1;\nwhile n > 1 {\n\ta := a * n;\n\tn := n - 1;\n}");
+	}
+
+	#[test]
+	fn forward_slices_keep_what_depends_on_the_node() {
+		assert_eq!(slice("q5", true), "int n;\nint a;\na := 1;\nwhile n > 1 {\n\ta := a * n;\n}\nwrite a;");
+		// the guard depends on the read, so does everything in the loop
+		assert_eq!(slice("q4", true), SOURCE.replace("a := 1;\nb := 0;\n", ""));
+	}
+}
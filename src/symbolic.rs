@@ -0,0 +1,509 @@
+//! Symbolic execution of the program graph.
+//!
+//! The values read are symbols, the variables are bound to expressions over them, and the guards taken are the conditions of
+//! the path, whose unsatisfiable ones are pruned. A node may occur a bounded number of times on a path, which unrolls loops.
+
+use crate::flow_graph::{Action, FlowGraph, locate};
+use crate::interpreter::{self, Value};
+use crate::lexer::{Span, keyword::Type, literal::IntegerLiteral};
+use crate::linear::{Encoder, Formula, Model, satisfiable};
+use crate::microc::{decl::Declaration, expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, fold::{self, Folder}, ops::{BinaryOp, RelationalOp}, stmt::Statement};
+use crate::analysis::location;
+use petgraph::{graph::{EdgeIndex, NodeIndex}, visit::EdgeRef};
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, convert::TryFrom};
+
+/// Number of complete or cut paths explored before giving up on the others.
+const MAX_PATHS: usize = 10_000;
+
+/// Number of models of an assertion violation replayed before giving up on finding failing inputs.
+const MAX_CANDIDATES: usize = 20;
+
+/// Values of the inputs of a run, in the order they are read, with the lvalues they are read into.
+pub type Inputs = Vec<(String, i128)>;
+
+/// Inputs driving a run along a path, with the guards it takes.
+#[derive(Debug, Clone)]
+pub struct Test {
+	/// Inputs of the run.
+	pub inputs: Inputs,
+	/// Guards taken by the path.
+	pub branches: BTreeSet<EdgeIndex>,
+	/// Whether the path conditions abstract nonlinear terms, so that the inputs may drive the run along another path.
+	pub approximate: bool,
+}
+
+/// Inputs making an assertion fail, checked by running the program on them.
+#[derive(Debug, Clone)]
+pub struct Failure {
+	/// Edge of the assertion.
+	pub edge: EdgeIndex,
+	/// Inputs of the run.
+	pub inputs: Inputs,
+}
+
+/// Result of the symbolic execution of a program.
+#[derive(Debug, Default)]
+pub struct Exploration {
+	/// Tests of the complete paths, each covering a guard the previous ones do not.
+	pub tests: Vec<Test>,
+	/// First failure found for each assertion.
+	pub failures: Vec<Failure>,
+	/// Guards of the program graph.
+	pub branches: Vec<EdgeIndex>,
+	/// Number of complete paths.
+	pub paths: usize,
+	/// Number of paths cut at the unrolling bound.
+	pub cut: usize,
+	/// Edges at which paths were given up, with the reason.
+	pub abandoned: BTreeMap<EdgeIndex, String>,
+	/// Whether paths were left unexplored after `MAX_PATHS` ones.
+	pub exhausted: bool,
+}
+
+/// Symbolic state of a run along a path.
+#[derive(Debug, Clone, Default)]
+struct Path {
+	node: NodeIndex,
+	/// Values of the variables and of the array elements, named `a[i, j]`.
+	variables: BTreeMap<String, ArithmeticExpr>,
+	/// Dimensions of the arrays.
+	arrays: BTreeMap<String, Vec<usize>>,
+	/// Float variables, whose values are not supported.
+	floats: BTreeSet<String>,
+	/// Lvalue every symbol is read into, the `k`-th symbol being named `#k`.
+	inputs: Vec<String>,
+	conditions: Vec<BooleanExpr>,
+	branches: BTreeSet<EdgeIndex>,
+	/// Call site and variables of the caller of every procedure running.
+	calls: Vec<(usize, BTreeMap<String, ArithmeticExpr>)>,
+	occurrences: HashMap<NodeIndex, usize>,
+}
+
+fn literal(n: isize) -> ArithmeticExpr {
+	ArithmeticExpr::Literal(ArithmeticLiteral::Int(IntegerLiteral::DecimalLiteral(n)))
+}
+
+/// Replaces the symbols by their values in a model, the unconstrained ones being zero.
+struct Ground<'a>(&'a Model);
+
+impl Folder for Ground<'_> {
+	fn fold_arex(&mut self, arex: ArithmeticExpr) -> ArithmeticExpr {
+		match &arex {
+			ArithmeticExpr::LvalueExpr(LvalueExpr::Variable(symbol)) if symbol.starts_with('#') => {
+				literal(self.0.get(symbol).and_then(|value| isize::try_from(*value).ok()).unwrap_or(0))
+			},
+			_ => fold::walk_arex(self, arex),
+		}
+	}
+}
+
+/// Solution of the conditions, `None` if they are unsatisfiable, with whether it may not satisfy them, nonlinear terms being abstracted.
+fn solve(conditions: &[BooleanExpr]) -> Result<Option<(Model, bool)>, String> {
+	let mut encoder = Encoder::default();
+	let formulas = conditions.iter().map(|boolex| encoder.formula(boolex, true)).collect::<Result<Vec<Formula>, String>>()?;
+	let model = match satisfiable(&encoder.defined(Formula::And(formulas)))? {
+		Some(model) => model,
+		None => return Ok(None),
+	};
+
+	if encoder.opaque.is_empty() {
+		return Ok(Some((model, false)));
+	}
+
+	// the conditions without symbols are linear, and evaluated by the decision procedure
+	let mut ground = Encoder::default();
+	let holds = conditions.iter().map(|boolex| ground.formula(&Ground(&model).fold_boolex(boolex.clone()), true)).collect::<Result<Vec<Formula>, String>>()
+		.and_then(|formulas| satisfiable(&ground.defined(Formula::And(formulas))));
+
+	Ok(Some((model, !matches!(holds, Ok(Some(_))))))
+}
+
+impl Path {
+	/// Expression of the value of an arithmetic expression over the symbols, folded to a literal when it is constant.
+	fn value(&self, arex: &ArithmeticExpr) -> Result<ArithmeticExpr, String> {
+		let value = match arex {
+			ArithmeticExpr::Literal(ArithmeticLiteral::Float(_)) => return Err(format!("Float `{arex}` is not supported.")),
+			ArithmeticExpr::Literal(_) => return Ok(arex.clone()),
+			ArithmeticExpr::LvalueExpr(lvalue) => {
+				let var = self.variable(lvalue)?;
+
+				match self.variables.get(&var) {
+					Some(value) => value.clone(),
+					// array elements are initialized to zero
+					None if self.arrays.contains_key(&location(lvalue)) => literal(0),
+					None => return Err(format!("Undeclared variable '{lvalue}'.")),
+				}
+			},
+			ArithmeticExpr::Reference(_) => return Err(format!("Pointers are not supported, got `{arex}`.")),
+			ArithmeticExpr::ArithmeticOperation(operation) => {
+				ArithmeticExpr::ArithmeticOperation(Box::new((self.value(&operation.0)?, operation.1.clone(), self.value(&operation.2)?)))
+			},
+		};
+
+		Ok(match Encoder::default().term(&value) {
+			Ok(term) if term.coefficients.is_empty() => isize::try_from(term.constant).map_or(value, literal),
+			_ => value,
+		})
+	}
+
+	/// Boolean expression over the symbols.
+	fn condition(&self, boolex: &BooleanExpr) -> Result<BooleanExpr, String> {
+		use BooleanExpr::*;
+
+		Ok(match boolex {
+			BooleanLiteral(_) => boolex.clone(),
+			NotOperation(boolex) => NotOperation(Box::new(self.condition(boolex)?)),
+			RelationalOperation(arex1, op, arex2) => RelationalOperation(self.value(arex1)?, op.clone(), self.value(arex2)?),
+			BinaryOperation(boolex1, op, boolex2) => BinaryOperation(Box::new(self.condition(boolex1)?), op.clone(), Box::new(self.condition(boolex2)?)),
+		})
+	}
+
+	/// Variable of an lvalue, array elements needing constant indexes within bounds.
+	fn variable(&self, lvalue: &LvalueExpr) -> Result<String, String> {
+		match lvalue {
+			LvalueExpr::ArrayIndex(id, indexes) => {
+				let dims = self.arrays.get(id).ok_or(format!("Undeclared array '{id}'."))?;
+				let mut constants = vec![];
+
+				for (dim, (arex, size)) in indexes.iter().zip(dims).enumerate() {
+					match self.value(arex)? {
+						ArithmeticExpr::Literal(ArithmeticLiteral::Int(n)) if 0 <= isize::from(n) && (isize::from(n) as usize) < *size => constants.push(isize::from(n).to_string()),
+						ArithmeticExpr::Literal(n) => return Err(format!("Index {n} out of bounds [0, {}] in dimension {dim} of `{lvalue}`.", *size as isize - 1)),
+						value => return Err(format!("Symbolic index `{value}` in `{lvalue}` is not supported.")),
+					}
+				}
+
+				Ok(format!("{id}[{}]", constants.join(", ")))
+			},
+			LvalueExpr::Deref(_) => Err(format!("Pointers are not supported, got `{lvalue}`.")),
+			_ => Ok(location(lvalue)),
+		}
+	}
+
+	fn store(&mut self, lvalue: &LvalueExpr, value: ArithmeticExpr) -> Result<(), String> {
+		let var = self.variable(lvalue)?;
+
+		self.variables.insert(var, value);
+		Ok(())
+	}
+
+	/// Declares the variables of a declaration, initialized to zero, record members being prefixed by `prefix`.
+	fn declare(&mut self, decl: &Declaration, prefix: &str) {
+		match decl {
+			Declaration::Var(t, id) | Declaration::Pointer(t, id) => {
+				let var = format!("{prefix}{id}");
+
+				if let (Type::Float, Declaration::Var(..)) = (t, decl) {
+					self.floats.insert(var.clone());
+				}
+
+				self.variables.insert(var, literal(0));
+			},
+			Declaration::Array(_, sizes, id) => {
+				let id = format!("{prefix}{id}");
+
+				self.variables.retain(|var, _| !var.starts_with(&format!("{id}[")));
+				self.arrays.insert(id, sizes.iter().map(|size| usize::try_from(isize::from(*size)).unwrap_or(0)).collect());
+			},
+			Declaration::Record(decls, id) => decls.iter().for_each(|member| self.declare(member, &format!("{prefix}{id}."))),
+			Declaration::Procedure(..) => (),
+		}
+	}
+
+	/// Adds a condition to the path, `None` if it makes the path infeasible.
+	fn assume(mut self, condition: BooleanExpr) -> Result<Option<Path>, String> {
+		if condition == BooleanExpr::BooleanLiteral(true) {
+			return Ok(Some(self));
+		}
+
+		self.conditions.push(condition);
+		Ok(solve(&self.conditions)?.map(|_| self))
+	}
+
+	/// Values of the inputs in a model of the path conditions, the unconstrained ones being zero.
+	fn inputs(&self, model: &Model) -> Inputs {
+		self.inputs.iter().enumerate().map(|(k, lvalue)| (lvalue.clone(), model.get(&format!("#{k}")).copied().unwrap_or(0))).collect()
+	}
+}
+
+/// Explorer of the paths of a program graph.
+struct Executor<'a> {
+	program: &'a FlowGraph,
+	exploration: Exploration,
+	/// Tests of all the complete paths.
+	tests: Vec<Test>,
+}
+
+impl Executor<'_> {
+	/// Takes an edge from the node of the path, `None` if it cannot be taken.
+	fn step(&mut self, mut path: Path, edge: EdgeIndex) -> Result<Option<Path>, String> {
+		match &self.program.0[edge] {
+			Action::Declaration(decl) => path.declare(decl, ""),
+			Action::Condition(boolex) => {
+				let condition = path.condition(boolex)?;
+
+				path.branches.insert(edge);
+				return path.assume(condition);
+			},
+			Action::Statement(stmt) => match stmt {
+				Statement::LvalueAssign(lvalue, arex) => {
+					let value = path.value(arex)?;
+
+					path.store(lvalue, value)?;
+				},
+				Statement::RecordAssign(id, arexs) => {
+					let members: Vec<String> = path.variables.keys().filter(|var| var.starts_with(&format!("{id}."))).cloned().collect();
+
+					for (member, arex) in members.into_iter().zip(arexs) {
+						let value = path.value(arex)?;
+
+						path.variables.insert(member, value);
+					}
+				},
+				Statement::Read(lvalue) => {
+					if path.floats.contains(&location(lvalue)) {
+						return Err(format!("Float input `{lvalue}` is not supported."));
+					}
+
+					let symbol = ArithmeticExpr::LvalueExpr(LvalueExpr::Variable(format!("#{}", path.inputs.len())));
+
+					path.store(lvalue, symbol)?;
+					path.inputs.push(lvalue.to_string());
+				},
+				Statement::Assert(boolex) => {
+					let condition = path.condition(boolex)?;
+
+					if self.exploration.failures.iter().all(|failure| failure.edge != edge) {
+						let violation = [path.conditions.clone(), vec![BooleanExpr::NotOperation(Box::new(condition.clone()))]].concat();
+
+						if let Some(inputs) = self.failing(&path, edge, violation)? {
+							self.exploration.failures.push(Failure { edge, inputs });
+						}
+					}
+
+					// the runs going past the assertion satisfy it
+					return path.assume(condition);
+				},
+				Statement::Assume(boolex) => {
+					let condition = path.condition(boolex)?;
+
+					return path.assume(condition);
+				},
+				_ => (),
+			},
+			Action::Call(call) => {
+				let values = call.arguments.iter().map(|arex| path.value(arex)).collect::<Result<Vec<ArithmeticExpr>, String>>()?;
+
+				path.calls.push((call.site, path.variables.clone()));
+				path.variables.extend(call.values.iter().cloned().zip(values));
+				path.variables.extend(call.outputs.iter().map(|output| (output.clone(), literal(0))));
+			},
+			// only the return edge to the site of the innermost call is taken
+			Action::Return(call) => {
+				let caller = match path.calls.last() {
+					Some((site, _)) if *site == call.site => path.calls.pop().unwrap().1,
+					_ => return Ok(None),
+				};
+				let values: Vec<ArithmeticExpr> = call.outputs.iter().map(|output| path.variables[output].clone()).collect();
+
+				for local in &call.locals {
+					path.variables.retain(|var, _| var != local && !var.starts_with(&format!("{local}[")) && !var.starts_with(&format!("{local}.")));
+					path.variables.extend(caller.iter().filter(|(var, _)| *var == local || var.starts_with(&format!("{local}[")) || var.starts_with(&format!("{local}."))).map(|(var, value)| (var.clone(), value.clone())));
+				}
+
+				for (lvalue, value) in call.results.iter().zip(values) {
+					path.store(lvalue, value)?;
+				}
+			},
+		}
+
+		Ok(Some(path))
+	}
+
+	/// Inputs of a model of the violation of the assertion of the edge, replayed as the decision procedure abstracts nonlinear terms and division by zero.
+	fn failing(&self, path: &Path, edge: EdgeIndex, mut violation: Vec<BooleanExpr>) -> Result<Option<Inputs>, String> {
+		for _ in 0..MAX_CANDIDATES {
+			let inputs = match solve(&violation)? {
+				Some((model, _)) => path.inputs(&model),
+				None => return Ok(None),
+			};
+
+			if fails(self.program, edge, &inputs) {
+				return Ok(Some(inputs));
+			}
+
+			// the next model differs from the spurious one in an input at least
+			let other = inputs.iter().enumerate().map(|(k, (_, value))| isize::try_from(*value).ok().map(|value| {
+				BooleanExpr::RelationalOperation(ArithmeticExpr::LvalueExpr(LvalueExpr::Variable(format!("#{k}"))), RelationalOp::Neq, literal(value))
+			})).collect::<Option<Vec<BooleanExpr>>>();
+
+			match other.and_then(|other| other.into_iter().reduce(|b1, b2| BooleanExpr::BinaryOperation(Box::new(b1), BinaryOp::BitOr, Box::new(b2)))) {
+				Some(other) => violation.push(other),
+				None => return Ok(None),
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// Records the test of a complete path, whose conditions were solved when they were added.
+	fn complete(&mut self, path: &Path) {
+		if let Ok(Some((model, approximate))) = solve(&path.conditions) {
+			self.exploration.paths += 1;
+			self.tests.push(Test { inputs: path.inputs(&model), branches: path.branches.clone(), approximate });
+		}
+	}
+
+	/// Explores the paths depth first, a node occurring at most `unroll` times on each.
+	fn explore(&mut self, unroll: usize) {
+		let (graph, start, end) = self.program;
+		let mut stack = vec![Path { node: *start, ..Path::default() }];
+
+		while let Some(path) = stack.pop() {
+			if MAX_PATHS <= self.exploration.paths + self.exploration.cut {
+				self.exploration.exhausted = true;
+				break;
+			}
+
+			if path.node == *end && path.calls.is_empty() {
+				self.complete(&path);
+				continue;
+			}
+
+			// later edges are pushed first, so that the first edges of a node are explored first
+			for edge in graph.edges(path.node).map(|edge| edge.id()).collect::<Vec<EdgeIndex>>().into_iter().rev() {
+				let target = graph.edge_endpoints(edge).unwrap().1;
+
+				match self.step(path.clone(), edge) {
+					Ok(Some(mut next)) => {
+						let occurrences = next.occurrences.entry(target).or_insert(0);
+
+						*occurrences += 1;
+
+						if unroll < *occurrences {
+							self.exploration.cut += 1;
+						} else {
+							next.node = target;
+							stack.push(next);
+						}
+					},
+					Ok(None) => (),
+					Err(e) => {
+						self.exploration.abandoned.entry(edge).or_insert(e);
+					},
+				}
+			}
+		}
+	}
+}
+
+/// Whether running the program on the inputs fails the assertion of the edge.
+fn fails(program: &FlowGraph, edge: EdgeIndex, inputs: &Inputs) -> bool {
+	let (graph, start, _) = program;
+	let mut values = inputs.iter().map(|(_, value)| isize::try_from(*value).map(Value::Int).map_err(|e| e.to_string()));
+	let mut node = *start;
+	let result = interpreter::trace(program, interpreter::MAX_STEPS, |_| values.next().unwrap_or_else(|| Err("No more input.".to_string())), |_| (), |taken| node = graph.edge_endpoints(taken).unwrap().1);
+
+	// the run stops at the source of the failing edge
+	matches!(result, Err(e) if e.starts_with("Assertion")) && graph.edge_endpoints(edge).unwrap().0 == node
+}
+
+/// Explores the paths of the program, each node occurring at most `unroll` times on a path, with tests covering the guards taken.
+pub fn execute(program: &FlowGraph, unroll: usize) -> Exploration {
+	let mut executor = Executor { program, exploration: Exploration::default(), tests: vec![] };
+
+	executor.explore(unroll);
+	executor.exploration.branches = program.0.edge_indices().filter(|edge| matches!(program.0[*edge], Action::Condition(_))).collect();
+
+	let mut covered = BTreeSet::new();
+
+	for test in executor.tests {
+		if covered.is_empty() || !test.branches.is_subset(&covered) {
+			covered.extend(test.branches.iter().copied());
+			executor.exploration.tests.push(test);
+		}
+	}
+
+	executor.exploration
+}
+
+fn inputs(inputs: &Inputs) -> String {
+	match inputs.is_empty() {
+		true => "no inputs".to_string(),
+		false => inputs.iter().map(|(lvalue, value)| format!("{lvalue} = {value}")).collect::<Vec<String>>().join(", "),
+	}
+}
+
+/// Formats the tests, the assertion failures and the guards not covered, located with the spans of the edges.
+pub fn report(program: &FlowGraph, exploration: &Exploration, spans: &HashMap<EdgeIndex, Span>) -> String {
+	let approximate = |approximate: bool| if approximate { " (approximate, nonlinear terms being abstracted)" } else { "" };
+	let edge = |edge: EdgeIndex| {
+		let (source, target) = program.0.edge_endpoints(edge).unwrap();
+
+		format!("{}q{} -> q{} `{}`", locate(spans, edge), source.index(), target.index(), program.0[edge])
+	};
+	let covered: BTreeSet<EdgeIndex> = exploration.tests.iter().flat_map(|test| test.branches.iter().copied()).collect();
+	let mut lines: Vec<String> = exploration.tests.iter().enumerate()
+		.map(|(i, test)| format!("test {}: {}{}", i + 1, inputs(&test.inputs), approximate(test.approximate)))
+		.collect();
+
+	lines.extend(exploration.failures.iter().map(|failure| format!("assertion failure: {} with {}", edge(failure.edge), inputs(&failure.inputs))));
+	lines.push(format!(
+		"{} of {} branches covered by {} paths, {} paths cut at the unrolling bound{}",
+		covered.len(),
+		exploration.branches.len(),
+		exploration.paths,
+		exploration.cut,
+		if exploration.exhausted { format!(", paths left unexplored after {MAX_PATHS}") } else { String::new() },
+	));
+	lines.extend(exploration.branches.iter().filter(|branch| !covered.contains(branch)).map(|branch| format!("not covered: {}", edge(*branch))));
+	lines.extend(exploration.abandoned.iter().map(|(abandoned, e)| format!("abandoned at {}: {e}", edge(*abandoned))));
+	lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{execute, report};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+	use std::collections::HashMap;
+
+	fn symbolic(source: &str, unroll: usize) -> String {
+		let program = flow(parse(lex_str(source).unwrap()).unwrap());
+
+		report(&program, &execute(&program, unroll), &HashMap::new())
+	}
+
+	#[test]
+	fn tests_cover_the_feasible_branches() {
+		let source = "int x;\nint y;\nread x;\nread y;\nif x + y > 10 {\n\tif x - y == 3 {\n\t\twrite 1;\n\t}\n}\nif x > 100 && x < 50 {\n\twrite 2;\n}";
+
+		assert_eq!(symbolic(source, 3), [
+			"test 1: x = 0, y = 0",
+			"test 2: x = 0, y = 11",
+			"test 3: x = 7, y = 4",
+			"5 of 6 branches covered by 3 paths, 0 paths cut at the unrolling bound",
+			"not covered: q6 -> q9 `x > 100 & x < 50`",
+		].join("\n"));
+	}
+
+	#[test]
+	fn assertions_fail_with_inputs() {
+		let source = "int n;\nint i;\nint s;\nread n;\nwhile i < n {\n\ts := s + 2;\n\ti := i + 1;\n}\nassert s != 4;";
+		// the runs with `n = 2` stop at the assertion, and the loop is entered at most 3 times
+		assert_eq!(symbolic(source, 4), [
+			"test 1: n = 0",
+			"test 2: n = 1",
+			"assertion failure: q6 -> q1 `assert s != 4;` with n = 2",
+			"2 of 2 branches covered by 3 paths, 1 paths cut at the unrolling bound",
+		].join("\n"));
+	}
+
+	#[test]
+	fn failures_are_replayed() {
+		// the solver abstracts the division, and its first models do not fail or divide by zero
+		let source = "int x;\nint y;\nread x;\nif x > 5 {\n\ty := x / (x - 7);\n}\nassert y != 3;";
+
+		assert_eq!(symbolic(source, 3).lines().nth(2), Some("assertion failure: q5 -> q1 `assert y != 3;` with x = 10"));
+		assert!(!symbolic("int x;\nint y;\nread x;\ny := x * x;\nassert y != 2;", 3).contains("assertion failure"));
+	}
+}
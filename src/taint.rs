@@ -0,0 +1,455 @@
+//! Taint analysis from the reads to the writes, array indexes and loop guards.
+
+use crate::analysis::{Analyzer, arex_variables, boolex_variables, location, lvalue_variables, records, variables, worklist};
+use crate::flow_graph::{Action, FlowGraph, locate, loop_heads};
+use crate::lexer::Span;
+use crate::points_to::{PointsTo, andersen, stored};
+use crate::microc::{expr::{ArithmeticExpr, ArithmeticLiteral, BooleanExpr, LvalueExpr}, ops::{BinaryOp, RelationalOp}, stmt::Statement, visit::{Visitor, walk_lvalue}};
+use crate::worklist::FifoWorklist;
+use petgraph::{graph::{EdgeIndex, NodeIndex}, visit::EdgeRef, Direction};
+use std::{collections::{BTreeSet, HashMap, HashSet, VecDeque}, convert::TryFrom, fmt::{self, Display, Formatter}};
+
+/// Operand of a sanitizer pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+	/// `_`, any variable, or any untainted expression as a bound.
+	Any,
+	/// Variable of that name.
+	Variable(String),
+	/// Integer of that value.
+	Integer(isize),
+}
+
+impl Display for Pattern {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Pattern::*;
+
+		match self {
+			Any => write!(f, "_"),
+			Variable(id) => write!(f, "{id}"),
+			Integer(n) => write!(f, "{n}"),
+		}
+	}
+}
+
+impl TryFrom<String> for Pattern {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		if value == "_" {
+			Ok(Pattern::Any)
+		} else if let Ok(n) = value.parse::<isize>() {
+			Ok(Pattern::Integer(n))
+		} else if value.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+			Ok(Pattern::Variable(value))
+		} else {
+			Err(format!("Unknown pattern operand '{value}'."))
+		}
+	}
+}
+
+/// Guard `variable op bound` sanitizing the variable on the edges where it holds, such as the bounds check `_ < _`.
+#[derive(Debug, Clone)]
+pub struct Sanitizer {
+	/// Variable sanitized.
+	pub variable: Pattern,
+	/// Comparison of the guard.
+	pub op: RelationalOp,
+	/// Bound the variable is compared to.
+	pub bound: Pattern,
+}
+
+impl Display for Sanitizer {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{} {} {}", self.variable, self.op, self.bound)
+	}
+}
+
+impl TryFrom<String> for Sanitizer {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		let operands: Vec<&str> = value.split_whitespace().collect();
+
+		if let [variable, op, bound] = operands[..] {
+			Ok(Sanitizer {
+				variable: Pattern::try_from(variable.to_string())?,
+				op: RelationalOp::try_from(op.to_string())?,
+				bound: Pattern::try_from(bound.to_string())?,
+			})
+		} else {
+			Err(format!("Expected 'variable op bound', got '{value}'."))
+		}
+	}
+}
+
+/// Parses a sanitizer file, made of `variable op bound` lines and `#` comments.
+pub fn sanitizers(patterns: &str) -> Result<Vec<Sanitizer>, String> {
+	patterns.lines()
+		.map(|line| line.split('#').next().unwrap().trim())
+		.filter(|line| !line.is_empty())
+		.map(|line| Sanitizer::try_from(line.to_string()))
+		.collect()
+}
+
+impl Sanitizer {
+	/// Variable sanitized by the comparison, when it matches the pattern.
+	fn sanitized(&self, arex1: &ArithmeticExpr, op: &RelationalOp, arex2: &ArithmeticExpr, tainted: &BTreeSet<String>) -> Option<String> {
+		let var = match arex1 {
+			ArithmeticExpr::LvalueExpr(lvalue @ (LvalueExpr::Variable(_) | LvalueExpr::RecordMember(_, _))) => location(lvalue),
+			_ => return None,
+		};
+
+		let variable = match &self.variable {
+			Pattern::Any => true,
+			Pattern::Variable(id) => *id == var,
+			Pattern::Integer(_) => false,
+		};
+
+		let bound = match (&self.bound, arex2) {
+			(Pattern::Any, arex) => arex_variables(arex).is_disjoint(tainted),
+			(Pattern::Variable(id), ArithmeticExpr::LvalueExpr(lvalue)) => *id == location(lvalue) && !tainted.contains(id),
+			(Pattern::Integer(n), ArithmeticExpr::Literal(ArithmeticLiteral::Int(m))) => *n == isize::from(*m),
+			_ => false,
+		};
+
+		if variable && bound && self.op == *op { Some(var) } else { None }
+	}
+}
+
+/// Taint analysis: variables that may hold a value derived from a `read`.
+struct TaintAnalysis<'a> {
+	sanitizers: &'a [Sanitizer],
+	records: HashMap<String, Vec<String>>,
+	points_to: PointsTo,
+}
+
+impl TaintAnalysis<'_> {
+	/// Variables assigned by an action, whether they are weakly updated, and the variables flowing into them.
+	fn flows(&self, a: &Action) -> Vec<(String, bool, BTreeSet<String>)> {
+		match a {
+			Action::Statement(Statement::LvalueAssign(lvalue, arex)) => {
+				let vars: BTreeSet<String> = arex_variables(arex).union(&lvalue_variables(lvalue)).cloned().collect();
+
+				stored(&self.points_to, lvalue).into_iter().map(|(var, weak)| (var, weak, vars.clone())).collect()
+			},
+			Action::Statement(Statement::RecordAssign(id, arexs)) => self.records.get(id).into_iter().flatten()
+				.zip(arexs)
+				.map(|(member, arex)| (member.clone(), false, arex_variables(arex)))
+				.collect(),
+			Action::Call(call) => call.values.iter().zip(&call.arguments)
+				.map(|(param, arex)| (param.clone(), false, arex_variables(arex)))
+				.chain(call.outputs.iter().map(|output| (output.clone(), false, BTreeSet::new())))
+				.collect(),
+			Action::Return(call) => call.results.iter().zip(&call.outputs).flat_map(|(lvalue, output)| {
+				let mut vars = lvalue_variables(lvalue);
+				vars.insert(output.clone());
+
+				stored(&self.points_to, lvalue).into_iter().map(move |(var, weak)| (var, weak, vars.clone()))
+			}).collect(),
+			_ => vec![],
+		}
+	}
+
+	/// Variables sanitized by a guard holding or not.
+	fn sanitized(&self, boolex: &BooleanExpr, holds: bool, tainted: &BTreeSet<String>) -> BTreeSet<String> {
+		use BooleanExpr::*;
+
+		match boolex {
+			NotOperation(boolex) => self.sanitized(boolex, !holds, tainted),
+			BinaryOperation(b1, BinaryOp::BitAnd, b2) if holds => self.sanitized(b1, holds, tainted).union(&self.sanitized(b2, holds, tainted)).cloned().collect(),
+			BinaryOperation(b1, BinaryOp::BitOr, b2) if !holds => self.sanitized(b1, holds, tainted).union(&self.sanitized(b2, holds, tainted)).cloned().collect(),
+			RelationalOperation(arex1, op, arex2) => {
+				let op = if holds { op.clone() } else { !op.clone() };
+
+				self.sanitizers.iter().flat_map(|sanitizer| vec![
+					sanitizer.sanitized(arex1, &op, arex2, tainted),
+					sanitizer.sanitized(arex2, &op.flip(), arex1, tainted),
+				]).flatten().collect()
+			},
+			_ => BTreeSet::new(),
+		}
+	}
+
+	/// Tainted variables at the target of an edge which the `var` at its source flows into.
+	fn successors(&self, a: &Action, var: &str, tainted: &BTreeSet<String>) -> BTreeSet<String> {
+		let flows = self.flows(a);
+		let mut successors: BTreeSet<String> = flows.iter().filter(|(_, _, vars)| vars.contains(var)).map(|(target, _, _)| target.clone()).collect();
+
+		let killed = match a {
+			Action::Declaration(decl) => variables(decl).contains(&var.to_string()),
+			Action::Statement(Statement::Read(lvalue)) => stored(&self.points_to, lvalue).contains(&(var.to_string(), false)),
+			Action::Condition(boolex) => self.sanitized(boolex, true, tainted).contains(var),
+			_ => flows.iter().any(|(target, weak, _)| target == var && !weak),
+		};
+
+		if !killed {
+			successors.insert(var.to_string());
+		}
+
+		successors
+	}
+}
+
+impl Analyzer<BTreeSet<String>> for TaintAnalysis<'_> {
+	fn bottom(&self) -> BTreeSet<String> {
+		BTreeSet::new()
+	}
+
+	fn initial(&self) -> BTreeSet<String> {
+		BTreeSet::new()
+	}
+
+	fn map(&self, a: &Action, r: &BTreeSet<String>) -> BTreeSet<String> {
+		let mut res: BTreeSet<String> = r.iter().flat_map(|var| self.successors(a, var, r)).collect();
+
+		if let Action::Statement(Statement::Read(lvalue)) = a {
+			res.extend(stored(&self.points_to, lvalue).into_iter().map(|(var, _)| var));
+		}
+
+		res
+	}
+
+	fn join(&self, r1: &BTreeSet<String>, r2: &BTreeSet<String>) -> BTreeSet<String> {
+		r1.union(r2).cloned().collect()
+	}
+}
+
+/// Operation that must not depend on the input.
+#[derive(Debug, Clone)]
+pub enum Sink {
+	/// `write` statement.
+	Write,
+	/// Index of an array element.
+	ArrayIndex(LvalueExpr),
+	/// Guard of a `while` loop.
+	LoopGuard,
+}
+
+impl Display for Sink {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Sink::*;
+
+		match self {
+			Write => write!(f, "output"),
+			ArrayIndex(lvalue) => write!(f, "index of `{lvalue}`"),
+			LoopGuard => write!(f, "loop guard"),
+		}
+	}
+}
+
+/// Sink reached by tainted variables.
+#[derive(Debug, Clone)]
+pub struct Finding {
+	/// Edge of the sink.
+	pub edge: EdgeIndex,
+	/// Operation reached.
+	pub sink: Sink,
+	/// Tainted variables reaching the sink.
+	pub variables: BTreeSet<String>,
+	/// Edges from a `read` to the sink, along which the first of the variables is tainted.
+	pub witness: Vec<EdgeIndex>,
+}
+
+impl Display for Finding {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "tainted {} through {}", self.sink, self.variables.iter().map(|var| format!("`{var}`")).collect::<Vec<String>>().join(", "))
+	}
+}
+
+/// Collector of the sinks of an action.
+#[derive(Default)]
+struct Sinks(Vec<(Sink, BTreeSet<String>)>);
+
+impl Visitor for Sinks {
+	fn visit_lvalue(&mut self, lvalue: &LvalueExpr) {
+		if let LvalueExpr::ArrayIndex(_, _) = lvalue {
+			self.0.push((Sink::ArrayIndex(lvalue.clone()), lvalue_variables(lvalue)));
+		}
+
+		walk_lvalue(self, lvalue);
+	}
+}
+
+/// Sinks of an action, with the variables they read.
+fn sinks(a: &Action, loop_guard: bool) -> Vec<(Sink, BTreeSet<String>)> {
+	let mut sinks = Sinks::default();
+
+	match a {
+		Action::Declaration(_) => (),
+		Action::Statement(stmt) => {
+			if let Statement::Write(arex) = stmt {
+				sinks.0.push((Sink::Write, arex_variables(arex)));
+			}
+
+			sinks.visit_stmt(stmt);
+		},
+		Action::Condition(boolex) => {
+			if loop_guard {
+				sinks.0.push((Sink::LoopGuard, boolex_variables(boolex)));
+			}
+
+			sinks.visit_boolex(boolex);
+		},
+		Action::Call(call) => call.arguments.iter().for_each(|arex| sinks.visit_arex(arex)),
+		Action::Return(call) => call.results.iter().for_each(|lvalue| sinks.visit_lvalue(lvalue)),
+	}
+
+	sinks.0
+}
+
+/// Shortest path from a `read` to the edge, along which `var` is tainted at the source of each edge.
+fn witness(program: &FlowGraph, specification: &TaintAnalysis, tainted: &HashMap<NodeIndex, BTreeSet<String>>, edge: EdgeIndex, var: &str) -> Vec<EdgeIndex> {
+	let graph = &program.0;
+	let (source, _) = graph.edge_endpoints(edge).unwrap();
+	let mut parents = HashMap::<(NodeIndex, String), Option<(EdgeIndex, (NodeIndex, String))>>::new();
+	let mut queue = VecDeque::<(NodeIndex, String)>::new();
+
+	parents.insert((source, var.to_string()), None);
+	queue.push_back((source, var.to_string()));
+
+	while let Some((node, var)) = queue.pop_front() {
+		for e in graph.edges_directed(node, Direction::Incoming) {
+			let action = e.weight();
+
+			if let Action::Statement(Statement::Read(lvalue)) = action {
+				// reading through a pointer taints its targets
+				if stored(&specification.points_to, lvalue).iter().any(|(read, _)| *read == var) {
+					// found the source, the path is read back from the parents
+					let mut path = vec![e.id()];
+					let mut fact = (node, var);
+
+					while let Some(Some((e, parent))) = parents.get(&fact) {
+						path.push(*e);
+						fact = parent.clone();
+					}
+
+					path.push(edge);
+					return path;
+				}
+			}
+
+			for previous in tainted[&e.source()].iter().filter(|previous| specification.successors(action, previous, &tainted[&e.source()]).contains(&var)) {
+				let fact = (e.source(), previous.clone());
+
+				if !parents.contains_key(&fact) {
+					parents.insert(fact.clone(), Some((e.id(), (node, var.clone()))));
+					queue.push_back(fact);
+				}
+			}
+		}
+	}
+
+	vec![edge]
+}
+
+/// Reports the writes, array indexes and loop guards that may depend on the input, with a witness path from a `read`.
+pub fn check(program: &FlowGraph, sanitizers: &[Sanitizer]) -> Vec<Finding> {
+	let graph = &program.0;
+	let specification = TaintAnalysis { sanitizers, records: records(program), points_to: andersen(program) };
+	let tainted = worklist::<FifoWorklist<NodeIndex>, _, _>(program, &specification);
+	let heads = loop_heads(program);
+	let mut guards = HashSet::<NodeIndex>::new();
+	let mut findings = Vec::<Finding>::new();
+
+	for edge in graph.edge_indices() {
+		let (source, _) = graph.edge_endpoints(edge).unwrap();
+		let action = &graph[edge];
+		// both guards of a loop read the same variables
+		let loop_guard = matches!(action, Action::Condition(_)) && heads.contains(&source) && guards.insert(source);
+
+		for (sink, vars) in sinks(action, loop_guard) {
+			let variables: BTreeSet<String> = vars.intersection(&tainted[&source]).cloned().collect();
+
+			if let Some(var) = variables.iter().next() {
+				let witness = witness(program, &specification, &tainted, edge, var);
+
+				findings.push(Finding { edge, sink, variables, witness });
+			}
+		}
+	}
+
+	findings
+}
+
+/// Formats the findings with the program graph edges they occur on and their witnesses.
+pub fn report(program: &FlowGraph, findings: &[Finding], spans: &HashMap<EdgeIndex, Span>) -> String {
+	let format = |edge: &EdgeIndex| {
+		let (source, target) = program.0.edge_endpoints(*edge).unwrap();
+
+		format!("q{} -> q{} `{}`", source.index(), target.index(), program.0[*edge])
+	};
+
+	findings.iter().map(|finding| format!(
+		"{}{}: {finding}\n\twitness: {}",
+		locate(spans, finding.edge),
+		format(&finding.edge),
+		finding.witness.iter().map(format).collect::<Vec<String>>().join(", "),
+	)).collect::<Vec<String>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{check, report, sanitizers};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+	use std::collections::HashMap;
+
+	fn taint(source: &str, patterns: &str) -> Vec<String> {
+		let program = flow(parse(lex_str(source).unwrap()).unwrap());
+
+		report(&program, &check(&program, &sanitizers(patterns).unwrap()), &HashMap::new()).lines().map(String::from).collect()
+	}
+
+	#[test]
+	fn reads_taint_writes_indexes_and_loop_guards() {
+		let source = "int x;\nint y;\nint[4] a;\nread x;\ny := x + 1;\na[y] := 0;\nwhile y > 0 {\n\ty := y - 1;\n}\nwrite a[0];\nwrite y;";
+
+		assert_eq!(taint(source, "")[..6], [
+			"q6 -> q7 `a[y] := 0;`: tainted index of `a[y]` through `y`",
+			"\twitness: q4 -> q5 `read x;`, q5 -> q6 `y := x + 1;`, q6 -> q7 `a[y] := 0;`",
+			"q7 -> q9 `y > 0`: tainted loop guard through `y`",
+			"\twitness: q4 -> q5 `read x;`, q5 -> q6 `y := x + 1;`, q6 -> q7 `a[y] := 0;`, q7 -> q9 `y > 0`",
+			// the element stored depends on the index
+			"q8 -> q10 `write a[0];`: tainted output through `a`",
+			"\twitness: q4 -> q5 `read x;`, q5 -> q6 `y := x + 1;`, q6 -> q7 `a[y] := 0;`, q7 -> q8 `!(y > 0)`, q8 -> q10 `write a[0];`",
+		]);
+		assert_eq!(taint("int x;\nread x;\nx := 1;\nwrite x;", ""), Vec::<String>::new());
+	}
+
+	#[test]
+	fn stores_through_pointers_taint_their_targets() {
+		assert_eq!(taint("int x;\nint y;\nint* p;\np := &y;\nread x;\n*p := x;\nwrite y;", ""), [
+			"q7 -> q1 `write y;`: tainted output through `y`",
+			"\twitness: q5 -> q6 `read x;`, q6 -> q7 `*p := x;`, q7 -> q1 `write y;`",
+		]);
+	}
+
+	#[test]
+	fn stores_through_single_targets_overwrite_them() {
+		assert_eq!(taint("int x;\nint* p;\np := &x;\nread x;\n*p := 0;\nwrite x;", ""), Vec::<String>::new());
+	}
+
+	#[test]
+	fn reads_through_pointers_start_witnesses() {
+		assert_eq!(taint("int x;\nint y;\nint* p;\np := &x;\nread *p;\ny := x + 1;\nwrite y;", ""), [
+			"q7 -> q1 `write y;`: tainted output through `y`",
+			"\twitness: q5 -> q6 `read *p;`, q6 -> q7 `y := x + 1;`, q7 -> q1 `write y;`",
+		]);
+	}
+
+	#[test]
+	fn guards_sanitize_the_variables_they_bound() {
+		// the negated guard holds on the same inputs as the first one, but the disjunction also lets the ones above 10 through
+		let source = "int x;\nint[4] a;\nread x;\nif x < 4 {\n\ta[x] := 1;\n}\nif !(x >= 4) {\n\ta[x] := 2;\n}\nif x < 4 || x > 10 {\n\ta[x] := 3;\n}";
+		let unsafe_store = [
+			"q9 -> q1 `a[x] := 3;`: tainted index of `a[x]` through `x`",
+			"\twitness: q3 -> q4 `read x;`, q4 -> q5 `!(x < 4)`, q5 -> q7 `!!(x >= 4)`, q7 -> q9 `x < 4 | x > 10`, q9 -> q1 `a[x] := 3;`",
+		];
+
+		assert_eq!(taint(source, "_ < _"), unsafe_store);
+		assert_eq!(taint(source, "# bounds check\nx < 4\n"), unsafe_store);
+		assert_eq!(taint(source, "").len(), 6);
+		assert_eq!(taint(source, "y < 4").len(), 6);
+		assert!(sanitizers("x <").is_err());
+	}
+}
@@ -0,0 +1,964 @@
+//! Model checking of temporal properties over the configurations of the program graph.
+//!
+//! The configurations reachable from the initial node form a Kripke structure, whose state space is bounded by reading the
+//! integers of a given width and wrapping the stored ones around at that width. Runs stopping at the final node, at a blocked
+//! node or on an error stutter forever. CTL formulas are checked by labelling the states with their subformulas, and LTL
+//! formulas by looking for an accepting lasso in the product of the structure with a Büchi automaton of their negation.
+
+use crate::analysis::boolex_variables;
+use crate::flow_graph::{Action, FlowGraph, locate};
+use crate::interpreter::{Configuration, Value, evaluate, successors};
+use crate::lexer::{Span, Token, delimiter::Delimiter, keyword::Keyword, lex_str, symbol::Symbol};
+use crate::microc::{expr::BooleanExpr, stmt::Statement};
+use crate::parser::parse_proposition;
+use petgraph::{algo::tarjan_scc, graph::{DiGraph, EdgeIndex, NodeIndex}};
+use std::{collections::{BTreeSet, HashMap, VecDeque}, convert::TryFrom, fmt::{self, Display, Formatter}};
+
+/// Number of states of the Kripke structure explored before giving up.
+const MAX_STATES: usize = 100_000;
+
+/// Atomic proposition: a condition on the variables, or the kind of the edge entering the state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Proposition {
+	/// Condition holding in the state.
+	Condition(BooleanExpr),
+	/// State entered by a `read` edge.
+	Read,
+	/// State entered by a `write` edge.
+	Write,
+}
+
+impl Display for Proposition {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Proposition::Condition(boolex) => write!(f, "{boolex}"),
+			Proposition::Read => write!(f, "read"),
+			Proposition::Write => write!(f, "write"),
+		}
+	}
+}
+
+/// Temporal formula, of CTL when every temporal operator comes right after a path quantifier, and of LTL when there is no quantifier.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+	/// Atomic proposition.
+	Proposition(Proposition),
+	/// Negation.
+	Not(Box<Formula>),
+	/// Conjunction.
+	And(Box<Formula>, Box<Formula>),
+	/// Disjunction.
+	Or(Box<Formula>, Box<Formula>),
+	/// `X`, holding in the next state.
+	Next(Box<Formula>),
+	/// `F`, holding in some later state.
+	Finally(Box<Formula>),
+	/// `G`, holding in every later state.
+	Globally(Box<Formula>),
+	/// `U`, the first formula holding until the second one does.
+	Until(Box<Formula>, Box<Formula>),
+	/// Formula holding on every path from the state.
+	All(Box<Formula>),
+	/// Formula holding on some path from the state.
+	Exists(Box<Formula>),
+}
+
+impl Display for Formula {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Formula::*;
+
+		match self {
+			Proposition(proposition @ self::Proposition::Condition(_)) => write!(f, "({proposition})"),
+			Proposition(proposition) => write!(f, "{proposition}"),
+			Not(formula) => write!(f, "!{formula}"),
+			And(formula1, formula2) => write!(f, "({formula1} & {formula2})"),
+			Or(formula1, formula2) => write!(f, "({formula1} | {formula2})"),
+			Next(formula) => write!(f, "X {formula}"),
+			Finally(formula) => write!(f, "F {formula}"),
+			Globally(formula) => write!(f, "G {formula}"),
+			Until(formula1, formula2) => write!(f, "({formula1} U {formula2})"),
+			All(formula) => write!(f, "A{formula}"),
+			Exists(formula) => write!(f, "E{formula}"),
+		}
+	}
+}
+
+/// Temporal operators, the path quantifier coming first when they are written as one word like `AG`.
+fn operators(id: &str) -> Option<Vec<char>> {
+	let chars: Vec<char> = id.chars().collect();
+
+	match chars[..] {
+		['A' | 'E' | 'X' | 'F' | 'G'] | ['A' | 'E', 'X' | 'F' | 'G'] => Some(chars),
+		_ => None,
+	}
+}
+
+/// Negation, temporal operator, proposition or parenthesized formula.
+fn unary(tokens: &[Token], i: usize) -> Result<(Formula, usize), String> {
+	match tokens.get(i) {
+		Some(Token::Symbol(Symbol::Not)) => unary(tokens, i + 1).map(|(formula, i)| (Formula::Not(Box::new(formula)), i)),
+		Some(Token::Identifier(id)) if operators(id).is_some() => {
+			let (operand, i) = unary(tokens, i + 1)?;
+			let formula = operators(id).unwrap().into_iter().rev().fold(operand, |formula, op| match op {
+				'A' => Formula::All(Box::new(formula)),
+				'E' => Formula::Exists(Box::new(formula)),
+				'X' => Formula::Next(Box::new(formula)),
+				'F' => Formula::Finally(Box::new(formula)),
+				_ => Formula::Globally(Box::new(formula)),
+			});
+
+			Ok((formula, i))
+		},
+		Some(Token::Keyword(Keyword::Read)) => Ok((Formula::Proposition(Proposition::Read), i + 1)),
+		Some(Token::Keyword(Keyword::Write)) => Ok((Formula::Proposition(Proposition::Write), i + 1)),
+		// a parenthesis opens either an arithmetic operand of a comparison or a formula
+		Some(Token::Delimiter(Delimiter::OpenPar)) => parse_proposition(tokens, i)
+			.map(|(boolex, i)| (Formula::Proposition(Proposition::Condition(boolex)), i))
+			.or_else(|_| {
+				let (formula, i) = implication(tokens, i + 1)?;
+
+				match tokens.get(i) {
+					Some(Token::Delimiter(Delimiter::ClosePar)) => Ok((formula, i + 1)),
+					t => Err(format!("Expected ')' in property, got '{:?}'.", t)),
+				}
+			}),
+		_ => parse_proposition(tokens, i).map(|(boolex, i)| (Formula::Proposition(Proposition::Condition(boolex)), i)),
+	}
+}
+
+fn until(tokens: &[Token], i: usize) -> Result<(Formula, usize), String> {
+	let (formula, i) = unary(tokens, i)?;
+
+	match tokens.get(i) {
+		Some(Token::Identifier(id)) if id == "U" => until(tokens, i + 1).map(|(rhs, i)| (Formula::Until(Box::new(formula), Box::new(rhs)), i)),
+		_ => Ok((formula, i)),
+	}
+}
+
+fn conjunction(tokens: &[Token], i: usize) -> Result<(Formula, usize), String> {
+	let (mut formula, mut i) = until(tokens, i)?;
+
+	while let Some(Token::Symbol(Symbol::And | Symbol::AndAnd)) = tokens.get(i) {
+		let (rhs, _i) = until(tokens, i + 1)?;
+		i = _i;
+		formula = Formula::And(Box::new(formula), Box::new(rhs));
+	}
+
+	Ok((formula, i))
+}
+
+fn disjunction(tokens: &[Token], i: usize) -> Result<(Formula, usize), String> {
+	let (mut formula, mut i) = conjunction(tokens, i)?;
+
+	while let Some(Token::Symbol(Symbol::Or | Symbol::OrOr)) = tokens.get(i) {
+		let (rhs, _i) = conjunction(tokens, i + 1)?;
+		i = _i;
+		formula = Formula::Or(Box::new(formula), Box::new(rhs));
+	}
+
+	Ok((formula, i))
+}
+
+/// Implications `a -> b`, associating to the right and standing for `!a | b`.
+fn implication(tokens: &[Token], i: usize) -> Result<(Formula, usize), String> {
+	let (formula, i) = disjunction(tokens, i)?;
+
+	match (tokens.get(i), tokens.get(i + 1)) {
+		(Some(Token::Symbol(Symbol::Minus)), Some(Token::Symbol(Symbol::Gt))) => {
+			implication(tokens, i + 2).map(|(rhs, i)| (Formula::Or(Box::new(Formula::Not(Box::new(formula))), Box::new(rhs)), i))
+		},
+		_ => Ok((formula, i)),
+	}
+}
+
+impl TryFrom<String> for Formula {
+	type Error = String;
+
+	/// Parses a property, whose propositions are MicroC comparisons, and whose temporal operators `A`, `E`, `X`, `F`, `G` and `U` cannot name variables.
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		let tokens = lex_str(&value)?;
+		let (formula, i) = implication(&tokens, 0)?;
+
+		if let Some(token) = tokens.get(i) {
+			return Err(format!("Unexpected '{:?}' in property `{value}`.", token));
+		}
+
+		if formula.quantified() {
+			formula.ctl(false)?;
+		}
+
+		Ok(formula)
+	}
+}
+
+impl Formula {
+	/// Whether the formula has path quantifiers, making it a CTL formula.
+	pub fn quantified(&self) -> bool {
+		use Formula::*;
+
+		match self {
+			Proposition(_) => false,
+			All(_) | Exists(_) => true,
+			Not(formula) | Next(formula) | Finally(formula) | Globally(formula) => formula.quantified(),
+			And(formula1, formula2) | Or(formula1, formula2) | Until(formula1, formula2) => formula1.quantified() || formula2.quantified(),
+		}
+	}
+
+	/// Checks that the temporal operators and the path quantifiers come in pairs, `quantified` telling whether a quantifier precedes the formula.
+	fn ctl(&self, quantified: bool) -> Result<(), String> {
+		use Formula::*;
+
+		match self {
+			Proposition(_) if !quantified => Ok(()),
+			Not(formula) if !quantified => formula.ctl(false),
+			And(formula1, formula2) | Or(formula1, formula2) if !quantified => formula1.ctl(false).and(formula2.ctl(false)),
+			All(formula) | Exists(formula) if !quantified => formula.ctl(true),
+			Next(formula) | Finally(formula) | Globally(formula) if quantified => formula.ctl(false),
+			Until(formula1, formula2) if quantified => formula1.ctl(false).and(formula2.ctl(false)),
+			All(_) | Exists(_) => Err(format!("Path quantifiers follow each other in `{self}`.")),
+			Next(_) | Finally(_) | Globally(_) | Until(..) => Err(format!("Temporal operator without a path quantifier in CTL formula `{self}`.")),
+			_ => Err(format!("Path quantifier not followed by X, F, G or U in `{self}`.")),
+		}
+	}
+}
+
+/// State of the Kripke structure: a configuration with the edge entering it, and whether the run stopped there.
+#[derive(Debug, Clone)]
+pub struct State {
+	/// Configuration of the program.
+	pub configuration: Configuration,
+	/// Edge taken into the state, `None` for the initial state and the stuttering ones.
+	pub entered: Option<EdgeIndex>,
+	/// Whether the run stopped in the state, which then stutters.
+	pub stopped: bool,
+	/// Error of an edge from the configuration, when the run stopped on it.
+	pub error: Option<String>,
+}
+
+/// Kripke structure of the configurations of the program graph, every state having a successor.
+pub struct Kripke {
+	/// States, the initial one first.
+	pub states: Vec<State>,
+	/// Indexes of the successors of every state.
+	pub successors: Vec<Vec<usize>>,
+}
+
+/// Integer wrapped around to `width` bits in two's complement.
+fn wrap(n: isize, width: usize) -> isize {
+	let modulus = 1isize << width;
+	let n = n.rem_euclid(modulus);
+
+	if modulus / 2 <= n { n - modulus } else { n }
+}
+
+fn wrap_state(state: &mut crate::interpreter::State, width: usize) {
+	let values = state.variables.values_mut().chain(state.arrays.values_mut().flat_map(|(_, values)| values.iter_mut()));
+
+	for value in values {
+		if let Value::Int(n) = value {
+			*n = wrap(*n, width);
+		}
+	}
+}
+
+/// Explores the configurations reachable from the initial node, the values read being the integers of `width` bits.
+pub fn kripke(program: &FlowGraph, width: usize) -> Result<Kripke, String> {
+	if !(1..=16).contains(&width) {
+		return Err(format!("Width {width} is not between 1 and 16 bits."));
+	}
+
+	let inputs: Vec<isize> = (-(1 << (width - 1))..(1 << (width - 1))).collect();
+	let initial = State { configuration: Configuration::initial(program), entered: None, stopped: false, error: None };
+	let mut kripke = Kripke { states: vec![], successors: vec![] };
+	let mut indexes = HashMap::<String, usize>::new();
+	let mut index = |kripke: &mut Kripke, state: State| -> Result<usize, String> {
+		let key = format!("{:?}", (&state.configuration, state.entered, state.stopped));
+
+		if let Some(index) = indexes.get(&key) {
+			return Ok(*index);
+		}
+
+		if MAX_STATES <= kripke.states.len() {
+			return Err(format!("More than {MAX_STATES} states, the width of the integers may be reduced."));
+		}
+
+		indexes.insert(key, kripke.states.len());
+		kripke.states.push(state);
+		kripke.successors.push(vec![]);
+		Ok(kripke.states.len() - 1)
+	};
+
+	index(&mut kripke, initial)?;
+
+	let mut next = 0;
+
+	while next < kripke.states.len() {
+		let state = kripke.states[next].clone();
+		let mut targets = BTreeSet::new();
+
+		if state.stopped {
+			targets.insert(next);
+		} else {
+			let mut error = None;
+
+			for (edge, result) in successors(program, &state.configuration, &inputs) {
+				match result {
+					Ok(mut configuration) => {
+						wrap_state(&mut configuration.state, width);
+						configuration.calls.iter_mut().for_each(|(_, state)| wrap_state(state, width));
+						targets.insert(index(&mut kripke, State { configuration, entered: Some(edge), stopped: false, error: None })?);
+					},
+					Err(e) => error = error.or(Some(e)),
+				}
+			}
+
+			if targets.is_empty() {
+				targets.insert(index(&mut kripke, State { configuration: state.configuration, entered: None, stopped: true, error })?);
+			}
+		}
+
+		kripke.successors[next] = targets.into_iter().collect();
+		next += 1;
+	}
+
+	Ok(kripke)
+}
+
+/// Shortest path from a state to one satisfying `target`, through states satisfying `within`, of at least one edge if `nonempty`.
+fn path(successors: &[Vec<usize>], from: usize, within: impl Fn(usize) -> bool, target: impl Fn(usize) -> bool, nonempty: bool) -> Option<Vec<usize>> {
+	if !nonempty && target(from) {
+		return Some(vec![from]);
+	}
+
+	let mut parents = HashMap::new();
+	let mut queue = VecDeque::from(vec![from]);
+
+	while let Some(state) = queue.pop_front() {
+		for successor in &successors[state] {
+			if parents.contains_key(successor) {
+				continue;
+			}
+
+			parents.insert(*successor, state);
+
+			if target(*successor) {
+				let mut path = vec![*successor];
+
+				while *path.last().unwrap() != from || path.len() == 1 {
+					path.push(parents[path.last().unwrap()]);
+				}
+
+				path.reverse();
+				return Some(path);
+			}
+
+			if within(*successor) {
+				queue.push_back(*successor);
+			}
+		}
+	}
+
+	None
+}
+
+/// Run of the Kripke structure, its last states repeating forever from `cycle` if it is a lasso.
+#[derive(Debug, Clone)]
+pub struct Trace {
+	/// Indexes of the states of the run.
+	pub states: Vec<usize>,
+	/// Index in `states` of the first state of the cycle.
+	pub cycle: Option<usize>,
+}
+
+impl Trace {
+	/// Trace following this one from its last state.
+	fn then(mut self, trace: Trace) -> Trace {
+		let offset = self.states.len() - 1;
+
+		self.states.pop();
+		self.states.extend(trace.states);
+		Trace { states: self.states, cycle: trace.cycle.map(|cycle| cycle + offset) }
+	}
+}
+
+/// Checker of CTL formulas, labelling the states with the subformulas they satisfy.
+struct Labelling<'a> {
+	program: &'a FlowGraph,
+	kripke: &'a Kripke,
+	predecessors: Vec<Vec<usize>>,
+}
+
+impl Labelling<'_> {
+	fn proposition(&self, proposition: &Proposition) -> Vec<bool> {
+		self.kripke.states.iter().map(|state| holds(self.program, state, proposition)).collect()
+	}
+
+	/// States with a path through `a` states to a `b` state.
+	fn exists_until(&self, a: &[bool], b: &[bool]) -> Vec<bool> {
+		let mut labels = b.to_vec();
+		let mut pending: Vec<usize> = (0..labels.len()).filter(|state| labels[*state]).collect();
+
+		while let Some(state) = pending.pop() {
+			for predecessor in &self.predecessors[state] {
+				if !labels[*predecessor] && a[*predecessor] {
+					labels[*predecessor] = true;
+					pending.push(*predecessor);
+				}
+			}
+		}
+
+		labels
+	}
+
+	/// States with an infinite path through `a` states.
+	fn exists_globally(&self, a: &[bool]) -> Vec<bool> {
+		let mut labels = a.to_vec();
+		let mut counts: Vec<usize> = self.kripke.successors.iter().map(|successors| successors.iter().filter(|successor| labels[**successor]).count()).collect();
+		let mut pending: Vec<usize> = (0..labels.len()).filter(|state| labels[*state] && counts[*state] == 0).collect();
+
+		while let Some(state) = pending.pop() {
+			if !labels[state] {
+				continue;
+			}
+
+			labels[state] = false;
+
+			for predecessor in &self.predecessors[state] {
+				counts[*predecessor] -= 1;
+
+				if labels[*predecessor] && counts[*predecessor] == 0 {
+					pending.push(*predecessor);
+				}
+			}
+		}
+
+		labels
+	}
+
+	fn label(&self, formula: &Formula) -> Vec<bool> {
+		use Formula::*;
+
+		let not = |labels: Vec<bool>| labels.into_iter().map(|label| !label).collect::<Vec<bool>>();
+		let all = vec![true; self.kripke.states.len()];
+
+		match formula {
+			Proposition(proposition) => self.proposition(proposition),
+			Not(formula) => not(self.label(formula)),
+			And(formula1, formula2) => self.label(formula1).into_iter().zip(self.label(formula2)).map(|(a, b)| a && b).collect(),
+			Or(formula1, formula2) => self.label(formula1).into_iter().zip(self.label(formula2)).map(|(a, b)| a || b).collect(),
+			Exists(path) | All(path) => {
+				let universal = matches!(formula, All(_));
+
+				match &**path {
+					Next(formula) => {
+						let labels = self.label(formula);
+
+						self.kripke.successors.iter().map(|successors| if universal {
+							successors.iter().all(|successor| labels[*successor])
+						} else {
+							successors.iter().any(|successor| labels[*successor])
+						}).collect()
+					},
+					Finally(formula) if universal => not(self.exists_globally(&not(self.label(formula)))),
+					Finally(formula) => self.exists_until(&all, &self.label(formula)),
+					Globally(formula) if universal => not(self.exists_until(&all, &not(self.label(formula)))),
+					Globally(formula) => self.exists_globally(&self.label(formula)),
+					Until(formula1, formula2) if universal => {
+						let (a, b) = (self.label(formula1), self.label(formula2));
+						let (not_b, neither) = (not(b.clone()), a.iter().zip(&b).map(|(a, b)| !a && !b).collect::<Vec<bool>>());
+						let escapes = self.exists_until(&not_b, &neither);
+
+						self.exists_globally(&not_b).into_iter().zip(escapes).map(|(g, u)| !g && !u).collect()
+					},
+					Until(formula1, formula2) => self.exists_until(&self.label(formula1), &self.label(formula2)),
+					_ => unreachable!("checked CTL formula"),
+				}
+			},
+			_ => unreachable!("checked CTL formula"),
+		}
+	}
+
+	/// Lasso from a state through the states labelled by `labels`, which have an infinite path through them.
+	fn lasso(&self, state: usize, labels: &[bool]) -> Trace {
+		let mut states = vec![state];
+		let mut positions = HashMap::from([(state, 0)]);
+
+		loop {
+			let successor = *self.kripke.successors[*states.last().unwrap()].iter().find(|successor| labels[**successor]).unwrap();
+
+			if let Some(position) = positions.get(&successor) {
+				return Trace { states, cycle: Some(*position) };
+			}
+
+			positions.insert(successor, states.len());
+			states.push(successor);
+		}
+	}
+
+	/// Run from a state showing why the formula holds there, or why it does not if `holds` is false.
+	fn explain(&self, formula: &Formula, state: usize, holds: bool) -> Trace {
+		use Formula::*;
+
+		let here = Trace { states: vec![state], cycle: None };
+		let reach = |labels: &[bool], within: &[bool]| path(&self.kripke.successors, state, |s| within[s], |s| labels[s], false).map(|states| Trace { states, cycle: None });
+
+		match (formula, holds) {
+			(Not(formula), _) => self.explain(formula, state, !holds),
+			(And(formula1, formula2), _) | (Or(formula1, formula2), _) => {
+				// an operand deciding the value is explained, preferably a temporal one which shows more of the run
+				let decides = |formula: &Formula| self.label(formula)[state] == holds;
+				let formula = if decides(formula2) && (!decides(formula1) || formula2.quantified() && !formula1.quantified()) { formula2 } else { formula1 };
+
+				self.explain(formula, state, holds)
+			},
+			(Exists(path) | All(path), _) => {
+				// an existential formula holding or a universal one failing has a witness run
+				if matches!(formula, Exists(_)) != holds {
+					return here;
+				}
+
+				match &**path {
+					Next(formula) => {
+						let labels = self.label(formula);
+
+						match self.kripke.successors[state].iter().find(|successor| labels[**successor] == holds) {
+							Some(successor) => Trace { states: vec![state, *successor], cycle: None }.then(self.explain(formula, *successor, holds)),
+							None => here,
+						}
+					},
+					Finally(formula) | Globally(formula) if matches!(&**path, Finally(_)) == holds => {
+						let labels: Vec<bool> = self.label(formula).into_iter().map(|label| label == holds).collect();
+
+						match reach(&labels, &vec![true; labels.len()]) {
+							Some(trace) => {
+								let last = *trace.states.last().unwrap();
+
+								trace.then(self.explain(formula, last, holds))
+							},
+							None => here,
+						}
+					},
+					Finally(formula) | Globally(formula) => {
+						let labels: Vec<bool> = self.label(formula).into_iter().map(|label| label == holds).collect();
+
+						self.lasso(state, &self.exists_globally(&labels))
+					},
+					Until(formula1, formula2) if holds => {
+						let (a, b) = (self.label(formula1), self.label(formula2));
+
+						match reach(&b, &a) {
+							Some(trace) => {
+								let last = *trace.states.last().unwrap();
+
+								trace.then(self.explain(formula2, last, true))
+							},
+							None => here,
+						}
+					},
+					Until(formula1, formula2) => {
+						let (a, b) = (self.label(formula1), self.label(formula2));
+						let not_b: Vec<bool> = b.iter().map(|b| !b).collect();
+						let globally = self.exists_globally(&not_b);
+
+						if globally[state] {
+							return self.lasso(state, &globally);
+						}
+
+						let neither: Vec<bool> = a.iter().zip(&b).map(|(a, b)| !a && !b).collect();
+						let within: Vec<bool> = a.iter().zip(&b).map(|(a, b)| *a && !b).collect();
+
+						reach(&neither, &within).unwrap_or(here)
+					},
+					_ => here,
+				}
+			},
+			_ => here,
+		}
+	}
+}
+
+/// Whether a proposition holds in a state, the variables not declared yet reading as zero and the conditions failing to evaluate being false.
+fn holds(program: &FlowGraph, state: &State, proposition: &Proposition) -> bool {
+	let entered = |read: bool| match state.entered.map(|edge| &program.0[edge]) {
+		Some(Action::Statement(Statement::Read(_))) => read,
+		Some(Action::Statement(Statement::Write(_))) => !read,
+		_ => false,
+	};
+
+	match proposition {
+		Proposition::Read => entered(true),
+		Proposition::Write => entered(false),
+		Proposition::Condition(boolex) => {
+			let mut memory = state.configuration.state.clone();
+
+			for var in boolex_variables(boolex) {
+				if !memory.arrays.contains_key(&var) {
+					memory.variables.entry(var).or_insert(Value::Int(0));
+				}
+			}
+
+			evaluate(&memory, boolex).unwrap_or(false)
+		},
+	}
+}
+
+/// Formula of LTL in negation normal form, over the propositions numbered in order of appearance.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Ltl {
+	True,
+	False,
+	Proposition(usize),
+	NotProposition(usize),
+	And(Box<Ltl>, Box<Ltl>),
+	Or(Box<Ltl>, Box<Ltl>),
+	Next(Box<Ltl>),
+	Until(Box<Ltl>, Box<Ltl>),
+	Release(Box<Ltl>, Box<Ltl>),
+}
+
+/// Formula in negation normal form, negated if asked, its propositions being numbered in `propositions`.
+fn normalize(formula: &Formula, negated: bool, propositions: &mut Vec<Proposition>) -> Ltl {
+	let mut normalize = |formula: &Formula, negated: bool| Box::new(normalize(formula, negated, propositions));
+
+	match formula {
+		Formula::Proposition(proposition) => {
+			let index = propositions.iter().position(|p| p == proposition).unwrap_or_else(|| {
+				propositions.push(proposition.clone());
+				propositions.len() - 1
+			});
+
+			if negated { Ltl::NotProposition(index) } else { Ltl::Proposition(index) }
+		},
+		Formula::Not(formula) => *normalize(formula, !negated),
+		Formula::And(formula1, formula2) if negated => Ltl::Or(normalize(formula1, true), normalize(formula2, true)),
+		Formula::And(formula1, formula2) => Ltl::And(normalize(formula1, false), normalize(formula2, false)),
+		Formula::Or(formula1, formula2) if negated => Ltl::And(normalize(formula1, true), normalize(formula2, true)),
+		Formula::Or(formula1, formula2) => Ltl::Or(normalize(formula1, false), normalize(formula2, false)),
+		Formula::Next(formula) => Ltl::Next(normalize(formula, negated)),
+		Formula::Finally(formula) if negated => Ltl::Release(Box::new(Ltl::False), normalize(formula, true)),
+		Formula::Finally(formula) => Ltl::Until(Box::new(Ltl::True), normalize(formula, false)),
+		Formula::Globally(formula) if negated => Ltl::Until(Box::new(Ltl::True), normalize(formula, true)),
+		Formula::Globally(formula) => Ltl::Release(Box::new(Ltl::False), normalize(formula, false)),
+		Formula::Until(formula1, formula2) if negated => Ltl::Release(normalize(formula1, true), normalize(formula2, true)),
+		Formula::Until(formula1, formula2) => Ltl::Until(normalize(formula1, false), normalize(formula2, false)),
+		Formula::All(formula) | Formula::Exists(formula) => *normalize(formula, negated),
+	}
+}
+
+/// Node of the tableau of a formula: the nodes it is entered from, the formulas holding in it and the ones holding next.
+#[derive(Debug)]
+struct Node {
+	incoming: BTreeSet<usize>,
+	old: BTreeSet<Ltl>,
+	next: BTreeSet<Ltl>,
+}
+
+/// Incoming node of the initial nodes of the tableau.
+const INIT: usize = usize::MAX;
+
+/// Nodes of the generalized Büchi automaton of a formula, with the tableau construction of Gerth, Peled, Vardi and Wolper.
+fn tableau(formula: Ltl) -> Vec<Node> {
+	let mut nodes: Vec<Node> = vec![];
+	let mut pending = vec![(BTreeSet::from([INIT]), vec![formula], BTreeSet::new(), BTreeSet::new())];
+
+	while let Some((incoming, mut new, mut old, mut next)) = pending.pop() {
+		let formula = match new.pop() {
+			Some(formula) => formula,
+			None => {
+				match nodes.iter_mut().find(|node| node.old == old && node.next == next) {
+					Some(node) => node.incoming.extend(incoming),
+					None => {
+						pending.push((BTreeSet::from([nodes.len()]), next.iter().cloned().collect(), BTreeSet::new(), BTreeSet::new()));
+						nodes.push(Node { incoming, old, next });
+					},
+				}
+
+				continue;
+			},
+		};
+
+		if old.contains(&formula) {
+			pending.push((incoming, new, old, next));
+			continue;
+		}
+
+		match &formula {
+			Ltl::False => continue,
+			Ltl::Proposition(p) if old.contains(&Ltl::NotProposition(*p)) => continue,
+			Ltl::NotProposition(p) if old.contains(&Ltl::Proposition(*p)) => continue,
+			Ltl::True | Ltl::Proposition(_) | Ltl::NotProposition(_) => (),
+			Ltl::And(formula1, formula2) => new.extend([(**formula1).clone(), (**formula2).clone()]),
+			Ltl::Next(formula) => {
+				next.insert((**formula).clone());
+			},
+			Ltl::Or(formula1, formula2) | Ltl::Until(formula1, formula2) | Ltl::Release(formula1, formula2) => {
+				// the formula holds by its first case now, or by its second one
+				let (mut first, mut second, mut later) = (new.clone(), new.clone(), next.clone());
+
+				match &formula {
+					Ltl::Or(..) => {
+						first.push((**formula1).clone());
+						second.push((**formula2).clone());
+					},
+					Ltl::Until(..) => {
+						first.push((**formula1).clone());
+						later.insert(formula.clone());
+						second.push((**formula2).clone());
+					},
+					_ => {
+						first.push((**formula2).clone());
+						later.insert(formula.clone());
+						second.extend([(**formula1).clone(), (**formula2).clone()]);
+					},
+				}
+
+				let mut old = old.clone();
+
+				old.insert(formula.clone());
+				pending.push((incoming.clone(), first, old.clone(), later));
+				pending.push((incoming, second, old, next));
+				continue;
+			},
+		}
+
+		old.insert(formula);
+		pending.push((incoming, new, old, next));
+	}
+
+	nodes
+}
+
+/// Looks for a run of the Kripke structure satisfying the LTL formula, as an accepting lasso of its product with the automaton of the formula.
+fn accepted(program: &FlowGraph, kripke: &Kripke, formula: Ltl, propositions: &[Proposition]) -> Option<Trace> {
+	let untils: BTreeSet<Ltl> = {
+		let mut untils = BTreeSet::new();
+		let mut pending = vec![formula.clone()];
+
+		while let Some(formula) = pending.pop() {
+			match formula {
+				Ltl::And(a, b) | Ltl::Or(a, b) | Ltl::Release(a, b) => pending.extend([*a, *b]),
+				Ltl::Until(ref a, ref b) => {
+					pending.extend([(**a).clone(), (**b).clone()]);
+					untils.insert(formula);
+				},
+				Ltl::Next(a) => pending.push(*a),
+				_ => (),
+			}
+		}
+
+		untils
+	};
+	let nodes = tableau(formula);
+	let labels: Vec<Vec<bool>> = propositions.iter().map(|proposition| kripke.states.iter().map(|state| holds(program, state, proposition)).collect()).collect();
+	let compatible = |state: usize, node: usize| nodes[node].old.iter().all(|formula| match formula {
+		Ltl::Proposition(p) => labels[*p][state],
+		Ltl::NotProposition(p) => !labels[*p][state],
+		_ => true,
+	});
+	// a node is accepting for an until formula when it does not promise it or fulfils it
+	let accepting: Vec<Vec<bool>> = untils.iter().map(|until| nodes.iter().map(|node| match until {
+		Ltl::Until(_, b) => !node.old.contains(until) || node.old.contains(b),
+		_ => true,
+	}).collect()).collect();
+	let following: Vec<Vec<usize>> = (0..nodes.len()).map(|n| (0..nodes.len()).filter(|m| nodes[*m].incoming.contains(&n)).collect()).collect();
+
+	// product of the Kripke structure and the automaton, from the initial state
+	let mut product = DiGraph::<(usize, usize), ()>::new();
+	let mut indexes = HashMap::new();
+	let mut initials = vec![];
+
+	for node in (0..nodes.len()).filter(|node| nodes[*node].incoming.contains(&INIT) && compatible(0, *node)) {
+		let index = product.add_node((0, node));
+
+		indexes.insert((0, node), index);
+		initials.push(index.index());
+	}
+
+	let mut next = 0;
+
+	while next < product.node_count() {
+		let (state, node) = product[NodeIndex::new(next)];
+
+		for successor in &kripke.successors[state] {
+			for follower in following[node].iter().filter(|follower| compatible(*successor, **follower)) {
+				let target = *indexes.entry((*successor, *follower)).or_insert_with(|| product.add_node((*successor, *follower)));
+
+				product.add_edge(NodeIndex::new(next), target, ());
+			}
+		}
+
+		next += 1;
+	}
+
+	let successors: Vec<Vec<usize>> = product.node_indices().map(|index| product.neighbors(index).map(|n| n.index()).collect()).collect();
+
+	for component in tarjan_scc(&product) {
+		let members: BTreeSet<usize> = component.iter().map(|index| index.index()).collect();
+		let entry = *members.iter().next().unwrap();
+		let cyclic = 1 < members.len() || successors[entry].contains(&entry);
+
+		if !cyclic || !accepting.iter().all(|set| members.iter().any(|member| set[product[NodeIndex::new(*member)].1])) {
+			continue;
+		}
+
+		let prefix = path(&successors, initials[0], |_| true, |index| index == entry, false)
+			.or_else(|| initials.iter().find_map(|initial| path(&successors, *initial, |_| true, |index| index == entry, false)))?;
+		let mut cycle = vec![entry];
+
+		// the cycle goes through every acceptance set, then back to its entry
+		for set in &accepting {
+			let last = *cycle.last().unwrap();
+			let part = path(&successors, last, |index| members.contains(&index), |index| members.contains(&index) && set[product[NodeIndex::new(index)].1], false).unwrap();
+
+			cycle.extend(&part[1..]);
+		}
+
+		let back = path(&successors, *cycle.last().unwrap(), |index| members.contains(&index), |index| index == entry, true).unwrap();
+
+		cycle.extend(&back[1..back.len() - 1]);
+
+		let states: Vec<usize> = prefix[..prefix.len() - 1].iter().chain(&cycle).map(|index| product[NodeIndex::new(*index)].0).collect();
+
+		return Some(Trace { states, cycle: Some(prefix.len() - 1) });
+	}
+
+	None
+}
+
+/// Outcome of the check of a property, with the number of states explored.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+	/// Number of states explored.
+	pub states: usize,
+	/// Run violating the property, if it does not hold.
+	pub counterexample: Option<Trace>,
+	/// Kripke states of the counterexample.
+	pub trace: Vec<State>,
+}
+
+/// Checks a property on the program, the integers read having `width` bits.
+pub fn check(program: &FlowGraph, formula: &Formula, width: usize) -> Result<Outcome, String> {
+	let kripke = kripke(program, width)?;
+	let counterexample = if formula.quantified() {
+		let mut predecessors = vec![vec![]; kripke.states.len()];
+
+		for (state, successors) in kripke.successors.iter().enumerate() {
+			successors.iter().for_each(|successor| predecessors[*successor].push(state));
+		}
+
+		let labelling = Labelling { program, kripke: &kripke, predecessors };
+
+		if labelling.label(formula)[0] { None } else { Some(labelling.explain(formula, 0, false)) }
+	} else {
+		let mut propositions = vec![];
+		let negation = normalize(formula, true, &mut propositions);
+
+		accepted(program, &kripke, negation, &propositions)
+	};
+	let trace = counterexample.iter().flat_map(|trace| trace.states.iter().map(|state| kripke.states[*state].clone())).collect();
+
+	Ok(Outcome { states: kripke.states.len(), counterexample, trace })
+}
+
+/// Formats the outcome, with the states of the counterexample and the edges entering them, located with the spans of the edges.
+pub fn report(program: &FlowGraph, property: &str, outcome: &Outcome, spans: &HashMap<EdgeIndex, Span>) -> String {
+	let counterexample = match &outcome.counterexample {
+		Some(counterexample) => counterexample,
+		None => return format!("`{property}` holds, {} states explored", outcome.states),
+	};
+	let mut lines = vec![format!("`{property}` does not hold, {} states explored, counterexample:", outcome.states)];
+
+	for (i, state) in outcome.trace.iter().enumerate() {
+		let node = state.configuration.node.index();
+		let memory = &state.configuration.state;
+		let step = match (state.entered, &state.error) {
+			(Some(edge), _) => {
+				let source = program.0.edge_endpoints(edge).unwrap().0;
+
+				format!("{}q{} -> q{node} `{}` {memory}", locate(spans, edge), source.index(), program.0[edge])
+			},
+			(None, Some(e)) => format!("q{node} stopped on error: {e} {memory}"),
+			(None, None) if state.stopped => format!("q{node} stopped {memory}"),
+			(None, None) => format!("q{node} {memory}"),
+		};
+
+		lines.push(format!("{i}: {step}"));
+	}
+
+	if let Some(cycle) = counterexample.cycle {
+		lines.push(format!("back to {cycle}, forever"));
+	}
+
+	lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Formula, check, report};
+	use crate::{flow_graph::flow, lexer::lex_str, parser::parse};
+	use std::{collections::HashMap, convert::TryFrom};
+
+	fn temporal(source: &str, property: &str, width: usize) -> String {
+		let program = flow(parse(lex_str(source).unwrap()).unwrap());
+		let formula = Formula::try_from(property.to_string()).unwrap();
+
+		report(&program, property, &check(&program, &formula, width).unwrap(), &HashMap::new())
+	}
+
+	#[test]
+	fn properties_parse_into_ctl_or_ltl() {
+		let formula = |property: &str| Formula::try_from(property.to_string()).map(|formula| formula.to_string());
+
+		assert_eq!(formula("AG (x >= 0)"), Ok("AG (x >= 0)".to_string()));
+		assert_eq!(formula("AG (read -> AF (x + 1) * 2 > y & write)"), Ok("AG (!read | (AF ((x + 1) * 2 > y) & write))".to_string()));
+		assert_eq!(formula("G (x == 0 U y < 2) | !F X true"), Ok("(G ((x == 0) U (y < 2)) | !F X (true))".to_string()));
+		assert!(formula("AG F x > 0").unwrap_err().starts_with("Temporal operator without a path quantifier"));
+		assert!(formula("A x > 0").unwrap_err().starts_with("Path quantifier not followed"));
+		assert!(formula("AG x > 0 y").is_err());
+	}
+
+	const ABSOLUTE: &str = "int x;\nread x;\nif x < 0 {\n\tx := 0 - x;\n}\nwrite x;";
+	const ECHO: &str = "int x;\nwhile true {\n\tread x;\n\tif x > 0 {\n\t\twrite x;\n\t}\n}";
+	const COPY: &str = "int x;\nwhile true {\n\tread x;\n\twrite x;\n}";
+
+	#[test]
+	fn ctl_counterexamples_show_failing_runs() {
+		// the opposite of the smallest integer of 3 bits wraps around to itself
+		assert_eq!(temporal(ABSOLUTE, "AG x >= 0", 3), [
+			"`AG x >= 0` does not hold, 32 states explored, counterexample:",
+			"0: q0 {}",
+			"1: q0 -> q2 `int x;` {x: 0}",
+			"2: q2 -> q3 `read x;` {x: -4}",
+		].join("\n"));
+		assert_eq!(temporal(ABSOLUTE, "AF write & EF x == 3", 3), "`AF write & EF x == 3` holds, 32 states explored");
+		assert_eq!(temporal(ECHO, "AG (read -> AF write)", 3), [
+			"`AG (read -> AF write)` does not hold, 29 states explored, counterexample:",
+			"0: q0 {}",
+			"1: q0 -> q2 `int x;` {x: 0}",
+			"2: q2 -> q3 `true` {x: 0}",
+			"3: q3 -> q4 `read x;` {x: -4}",
+			"4: q4 -> q2 `!(x > 0)` {x: -4}",
+			"5: q2 -> q3 `true` {x: -4}",
+			"back to 3, forever",
+		].join("\n"));
+		assert_eq!(temporal(COPY, "AG (read -> AF write)", 3), "`AG (read -> AF write)` holds, 26 states explored");
+	}
+
+	#[test]
+	fn ltl_counterexamples_are_lassos() {
+		assert_eq!(temporal(ECHO, "G (read -> F write)", 3), [
+			"`G (read -> F write)` does not hold, 29 states explored, counterexample:",
+			"0: q0 {}",
+			"1: q0 -> q2 `int x;` {x: 0}",
+			"2: q2 -> q3 `true` {x: 0}",
+			"3: q3 -> q4 `read x;` {x: -4}",
+			"4: q4 -> q2 `!(x > 0)` {x: -4}",
+			"5: q2 -> q3 `true` {x: -4}",
+			"6: q3 -> q4 `read x;` {x: -4}",
+			"back to 4, forever",
+		].join("\n"));
+		assert_eq!(temporal(COPY, "G (read -> F write)", 3), "`G (read -> F write)` holds, 26 states explored");
+		assert!(temporal(COPY, "F G x == 0", 3).ends_with("back to 2, forever"));
+		assert_eq!(temporal(ABSOLUTE, "x == 0 U read", 3), "`x == 0 U read` holds, 32 states explored");
+	}
+}
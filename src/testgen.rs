@@ -0,0 +1,202 @@
+//! Generation of inputs covering the edges of the program graph.
+//!
+//! Inputs are drawn at random, then mutated towards the guards not taken yet: a run reaching the source of such a guard has
+//! one of its inputs replaced by a constant of the guard or moved around its value, until every edge is covered or the budget
+//! of runs is spent.
+
+use crate::flow_graph::{Action, FlowGraph, locate};
+use crate::interpreter::{Value, trace};
+use crate::lexer::Span;
+use crate::microc::{expr::{ArithmeticExpr, ArithmeticLiteral}, visit::{Visitor, walk_arex}};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use std::{collections::{BTreeSet, HashMap, HashSet}, convert::TryFrom};
+
+/// Number of edges a generated run may take, runs going further being considered not to terminate.
+const STEPS: usize = 10_000;
+
+/// Magnitude of the random inputs.
+const RANGE: isize = 100;
+
+/// Values read by a run, in order.
+pub type Inputs = Vec<isize>;
+
+/// Generator of pseudo-random numbers, xorshift being enough to spread the inputs.
+struct Random(u64);
+
+impl Random {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	fn below(&mut self, n: usize) -> usize {
+		(self.next() % n as u64) as usize
+	}
+
+	/// Integer between `-RANGE` and `RANGE`.
+	fn value(&mut self) -> isize {
+		self.below(2 * RANGE as usize + 1) as isize - RANGE
+	}
+}
+
+/// Integer literals of the guards, around which their outcome changes.
+#[derive(Default)]
+struct Constants(BTreeSet<isize>);
+
+impl Visitor for Constants {
+	fn visit_arex(&mut self, arex: &ArithmeticExpr) {
+		if let ArithmeticExpr::Literal(ArithmeticLiteral::Int(n)) = arex {
+			let n = isize::from(*n);
+
+			self.0.extend([n.saturating_sub(1), n, n.saturating_add(1)]);
+		}
+
+		walk_arex(self, arex);
+	}
+}
+
+/// Test suite with the edges it covers.
+#[derive(Debug, Default)]
+pub struct Suite {
+	/// Inputs of every test, one run per test.
+	pub tests: Vec<Inputs>,
+	/// Edges taken by the runs of the tests.
+	pub covered: BTreeSet<EdgeIndex>,
+	/// Number of runs tried.
+	pub runs: usize,
+}
+
+/// Run of the program on inputs, with the ones it read and the nodes and edges it went through.
+struct Run {
+	inputs: Inputs,
+	nodes: HashSet<NodeIndex>,
+	edges: BTreeSet<EdgeIndex>,
+}
+
+/// Runs the program on the given inputs, the ones missing being drawn by `fill`.
+fn execute(program: &FlowGraph, given: &[isize], mut fill: impl FnMut() -> isize) -> Run {
+	let mut inputs = vec![];
+	let mut edges = BTreeSet::new();
+	let read = |_: &str| -> Result<Value, String> {
+		let value = given.get(inputs.len()).copied().unwrap_or_else(&mut fill);
+
+		inputs.push(value);
+		Ok(Value::Int(value))
+	};
+
+	// failing runs still cover the edges taken before they fail
+	let _ = trace(program, STEPS, read, |_| (), |edge| {
+		edges.insert(edge);
+	});
+
+	let nodes = std::iter::once(program.1).chain(edges.iter().map(|edge| program.0.edge_endpoints(*edge).unwrap().1)).collect();
+
+	Run { inputs, nodes, edges }
+}
+
+/// Generates a suite covering the edges of the program graph, with at most `budget` runs drawn from `seed`.
+pub fn generate(program: &FlowGraph, seed: u64, budget: usize) -> Suite {
+	let mut random = Random(seed.max(1));
+	let mut constants = Constants([-1, 0, 1].iter().copied().collect());
+	let guards: Vec<EdgeIndex> = program.0.edge_indices().filter(|edge| matches!(program.0[*edge], Action::Condition(_))).collect();
+
+	for guard in &guards {
+		if let Action::Condition(boolex) = &program.0[*guard] {
+			constants.visit_boolex(boolex);
+		}
+	}
+
+	let constants: Vec<isize> = constants.0.into_iter().collect();
+	let mut suite = Suite::default();
+	let mut runs = Vec::<Run>::new();
+
+	while suite.runs < budget && suite.covered.len() < program.0.edge_count() {
+		suite.runs += 1;
+
+		// guards not taken yet from a node some run reached
+		let frontier: Vec<(EdgeIndex, usize)> = guards.iter().filter(|guard| !suite.covered.contains(guard)).filter_map(|guard| {
+			let source = program.0.edge_endpoints(*guard).unwrap().0;
+
+			runs.iter().position(|run| run.nodes.contains(&source)).map(|i| (*guard, i))
+		}).collect();
+
+		let run = if frontier.is_empty() || suite.runs % 2 == 0 {
+			execute(program, &[], || if random.below(2) == 0 { constants[random.below(constants.len())] } else { random.value() })
+		} else {
+			let (_, i) = frontier[random.below(frontier.len())];
+			let mut inputs = runs[i].inputs.clone();
+
+			if !inputs.is_empty() {
+				let position = random.below(inputs.len());
+
+				inputs[position] = match random.below(3) {
+					0 => constants[random.below(constants.len())],
+					1 => inputs[position].saturating_add(random.below(21) as isize - 10),
+					_ => random.value(),
+				};
+			}
+
+			execute(program, &inputs, || random.value())
+		};
+
+		if !run.edges.is_subset(&suite.covered) {
+			suite.covered.extend(run.edges.iter().copied());
+			suite.tests.push(run.inputs.clone());
+			runs.push(run);
+		}
+	}
+
+	suite
+}
+
+/// Suite as a file read by the `run` pattern: the inputs of a test per line, separated by spaces.
+pub fn file(suite: &Suite) -> String {
+	suite.tests.iter().map(|inputs| inputs.iter().map(isize::to_string).collect::<Vec<String>>().join(" ") + "\n").collect()
+}
+
+/// Inputs of the tests of a suite file, lines starting with `#` being comments.
+pub fn parse(file: &str) -> Result<Vec<Vec<Value>>, String> {
+	file.lines().filter(|line| !line.starts_with('#')).map(|line| line.split_whitespace().map(|value| Value::try_from(value.to_string())).collect()).collect()
+}
+
+/// Formats the tests of the suite and the edges it does not cover, located with the spans of the edges.
+pub fn report(program: &FlowGraph, suite: &Suite, spans: &HashMap<EdgeIndex, Span>) -> String {
+	let mut lines: Vec<String> = suite.tests.iter().enumerate().map(|(i, inputs)| {
+		format!("test {}: {}", i + 1, if inputs.is_empty() { "no inputs".to_string() } else { inputs.iter().map(isize::to_string).collect::<Vec<String>>().join(" ") })
+	}).collect();
+
+	lines.push(format!("{} of {} edges covered by {} tests out of {} runs", suite.covered.len(), program.0.edge_count(), suite.tests.len(), suite.runs));
+	lines.extend(program.0.edge_indices().filter(|edge| !suite.covered.contains(edge)).map(|edge| {
+		let (source, target) = program.0.edge_endpoints(edge).unwrap();
+
+		format!("not covered: {}q{} -> q{} `{}`", locate(spans, edge), source.index(), target.index(), program.0[edge])
+	}));
+	lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{file, generate, parse};
+	use crate::{flow_graph::flow, interpreter::Value, lexer::lex_str, parser::parse as parse_program};
+
+	#[test]
+	fn mutations_reach_narrow_guards() {
+		let source = "int x;\nint y;\nread x;\nif x == 42 {\n\tread y;\n\tif y > x + 50 {\n\t\twrite y;\n\t}\n}\nwhile x < 0 {\n\tx := x + 1;\n}";
+		let program = flow(parse_program(lex_str(source).unwrap()).unwrap());
+		let suite = generate(&program, 1, 1000);
+
+		assert_eq!(suite.covered.len(), program.0.edge_count());
+	}
+
+	#[test]
+	fn suite_files_round_trip() {
+		let program = flow(parse_program(lex_str("int x;\nread x;\nif x > 0 { write x; }").unwrap()).unwrap());
+		let suite = generate(&program, 7, 100);
+		let tests = parse(&format!("# generated\n{}", file(&suite))).unwrap();
+
+		assert_eq!(tests, suite.tests.iter().map(|inputs| inputs.iter().map(|n| Value::Int(*n)).collect::<Vec<Value>>()).collect::<Vec<Vec<Value>>>());
+		assert!(parse("1 x").is_err());
+	}
+}
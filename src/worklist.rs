@@ -1,12 +1,19 @@
+//! Worklists of the nodes whose analysis results are to be recomputed.
+
 use std::collections::VecDeque;
 
+/// Set of pending elements of the worklist algorithm, extracted in an order given by the implementation.
 pub trait Worklist<T: PartialEq>: Default {
+	/// Whether no element is pending.
 	fn empty(&self) -> bool;
 
+	/// Adds an element unless it is pending, giving it back if it was added.
 	fn insert(&mut self, val: T) -> Option<T>;
 
+	/// Removes the next element.
 	fn extract(&mut self) -> Option<T>;
 
+	/// Whether the element is pending.
 	fn contains(&self, val: T) -> bool;
 }
 
@@ -16,6 +23,7 @@ pub struct FiloWorklist<T> {
 	data: VecDeque<T>,
 }
 
+/// Underlying data storage provided by vec::VecDeque.
 #[derive(Debug)]
 pub struct FifoWorklist<T> {
 	data: VecDeque<T>,